@@ -25,16 +25,15 @@ async fn main() -> Result<(), SerialError> {
     let mut connection = devices[0].connect(Duration::from_secs(30))?;
 
     let status = connection
-        .packet_handshake::<GetDeviceStatusReplyPacket>(
-            Duration::from_millis(500),
-            10,
-            GetDeviceStatusPacket::new(()),
-        )
+        .packet_handshake::<GetDeviceStatusReplyPacket>(GetDeviceStatusPacket::new(()))
         .await?
         .try_into_inner()?;
 
     for device in status.devices {
-        info!("{:?} on port: {}", device.device_type, device.port);
+        info!(
+            "{:?} on port {}, firmware {}",
+            device.device_type, device.port, device.firmware.version
+        );
     }
 
     Ok(())
@@ -1,8 +1,8 @@
 use std::{str::FromStr, time::Duration};
 
-use tokio::{fs::File, io::AsyncWriteExt};
+use tokio::fs::File;
 use vex_v5_serial::{
-    commands::file::DownloadFile,
+    commands::{file::DownloadFile, progress::ProgressEvent},
     connection::{
         serial::{self, SerialError},
         Connection,
@@ -35,35 +35,33 @@ async fn main() -> Result<(), SerialError> {
     let mut connection = devices[0].connect(Duration::from_secs(30))?;
 
     connection
-        .packet_handshake::<SelectRadioChannelReplyPacket>(
-            Duration::from_millis(500),
-            10,
-            SelectRadioChannelPacket::new(SelectRadioChannelPayload {
+        .packet_handshake::<SelectRadioChannelReplyPacket>(SelectRadioChannelPacket::new(
+            SelectRadioChannelPayload {
                 channel: RadioChannel::Pit,
-            }),
-        )
+            },
+        ))
         .await?
         .try_into_inner()
         .unwrap();
 
     let file = "slot_3.bin";
 
-    // Download program file
-    let download = connection
+    // Download program file, writing it to disk as it arrives
+    let output = File::create_new(file).await?;
+    connection
         .execute_command(DownloadFile {
             file_name: FixedString::from_str(file).unwrap(),
             size: 2000,
             vendor: FileVendor::User,
             target: Some(FileTransferTarget::Qspi),
             load_addr: 0x03800000,
-            progress_callback: Some(Box::new(move |progress| {
-                log::info!("{}: {:.2}%", file, progress);
-            }) as Box<dyn FnMut(f32) + Send>),
+            checkpoint: None,
+            sink: Some(Box::new(output)),
+            progress: Some(Box::new(move |progress: ProgressEvent| {
+                log::info!("{}: {:.2}%", file, progress.percent());
+            })),
         })
         .await?;
 
-    let mut file = File::create_new(file).await?;
-    file.write_all(&download).await?;
-
     Ok(())
 }
@@ -26,11 +26,7 @@ async fn main() -> Result<(), SerialError> {
     let mut connection = devices[0].connect(Duration::from_secs(30))?;
 
     let response = connection
-        .packet_handshake::<GetSystemVersionReplyPacket>(
-            Duration::from_millis(700),
-            5,
-            GetSystemVersionPacket::new(()),
-        )
+        .packet_handshake::<GetSystemVersionReplyPacket>(GetSystemVersionPacket::new(()))
         .await?;
 
     info!("{:?}", response.payload.product_type);
@@ -29,7 +29,7 @@ async fn main() -> Result<(), SerialError> {
     connection
         .execute_command(ScreenCapture)
         .await?
-        .save("screencap.png")
+        .save_png("screencap.png")
         .unwrap();
 
     connection
@@ -1,16 +1,14 @@
 use std::time::Duration;
 
 use log::{error, info};
-use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 use vex_v5_serial::{
+    commands::competition::{FieldController, MatchTimings},
     connection::{
         serial::{self, SerialError},
         Connection,
     },
-    packets::{
-        match_mode::{MatchMode, SetMatchModePacket, SetMatchModePayload, SetMatchModeReplyPacket},
-        system::{GetSystemVersionPacket, GetSystemVersionReplyPacket},
-    },
+    packets::system::{GetSystemVersionPacket, GetSystemVersionReplyPacket},
 };
 
 #[tokio::main]
@@ -30,11 +28,7 @@ async fn main() -> Result<(), SerialError> {
     let mut connection = devices[0].connect(Duration::from_secs(30))?;
 
     let response = connection
-        .packet_handshake::<GetSystemVersionReplyPacket>(
-            Duration::from_millis(700),
-            5,
-            GetSystemVersionPacket::new(()),
-        )
+        .packet_handshake::<GetSystemVersionReplyPacket>(GetSystemVersionPacket::new(()))
         .await?;
 
     match response.payload.product_type {
@@ -45,45 +39,21 @@ async fn main() -> Result<(), SerialError> {
         vex_v5_serial::packets::system::ProductType::Controller => {}
     }
 
-    info!("Setting match mode to auto");
-    connection
-        .packet_handshake::<SetMatchModeReplyPacket>(
-            Duration::from_millis(500),
-            10,
-            SetMatchModePacket::new(SetMatchModePayload {
-                match_mode: MatchMode::Auto,
-                match_time: 0,
-            }),
-        )
-        .await?;
-
-    sleep(Duration::from_secs(2)).await;
+    let field = FieldController::new(MatchTimings {
+        autonomous: Duration::from_secs(2),
+        pause: Duration::ZERO,
+        driver: Duration::from_secs(2),
+    });
 
-    info!("Setting match mode to driver");
-    connection
-        .packet_handshake::<SetMatchModeReplyPacket>(
-            Duration::from_millis(500),
-            10,
-            SetMatchModePacket::new(SetMatchModePayload {
-                match_mode: MatchMode::Driver,
-                match_time: 2,
-            }),
-        )
-        .await?;
-
-    // 1 minute 45 seconds
-    sleep(Duration::from_secs(2)).await;
+    let mut changes = field.subscribe();
+    tokio::spawn(async move {
+        while let Ok(change) = changes.recv().await {
+            info!("Match mode set to {:?}", change.mode);
+        }
+    });
 
-    info!("Setting match mode to disabled");
-    connection
-        .packet_handshake::<SetMatchModeReplyPacket>(
-            Duration::from_millis(500),
-            10,
-            SetMatchModePacket::new(SetMatchModePayload {
-                match_mode: MatchMode::Disabled,
-                match_time: 4,
-            }),
-        )
+    field
+        .run(&mut connection, &CancellationToken::new())
         .await?;
 
     Ok(())
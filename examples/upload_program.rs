@@ -1,7 +1,10 @@
 use std::time::Duration;
 
 use vex_v5_serial::{
-    commands::file::{ProgramData, UploadProgram},
+    commands::{
+        file::{ProgramData, UploadProgram, UploadStrategy, VerifyMode},
+        progress::ProgressEvent,
+    },
     connection::{
         serial::{self, SerialError},
         Connection,
@@ -13,6 +16,7 @@ use vex_v5_serial::{
             SelectRadioChannelReplyPacket,
         },
     },
+    slot::Slot,
 };
 
 #[tokio::main]
@@ -33,20 +37,12 @@ async fn main() -> Result<(), SerialError> {
     let mut connection = devices[0].connect(Duration::from_secs(30))?;
     let program_data = include_bytes!("./basic.bin").to_vec();
 
-    let callback_generator = |step| {
-        Box::new(move |progress| {
-            log::info!("{}: {:.2}%", step, progress);
-        })
-    };
-
     connection
-        .packet_handshake::<SelectRadioChannelReplyPacket>(
-            Duration::from_millis(500),
-            10,
-            SelectRadioChannelPacket::new(SelectRadioChannelPayload {
+        .packet_handshake::<SelectRadioChannelReplyPacket>(SelectRadioChannelPacket::new(
+            SelectRadioChannelPayload {
                 channel: RadioChannel::Pit,
-            }),
-        )
+            },
+        ))
         .await?
         .try_into_inner()
         .unwrap();
@@ -58,13 +54,18 @@ async fn main() -> Result<(), SerialError> {
             description: "A basic vexide program".to_string(),
             icon: "USER029x.bmp".to_string(),
             program_type: "vexide".to_string(),
-            slot: 4,
+            slot: Slot::new(4)?,
             data: ProgramData::Monolith(program_data),
             compress_program: true,
             after_upload: FileExitAction::RunProgram,
-            ini_callback: Some(callback_generator("INI")),
-            lib_callback: Some(callback_generator("Lib")),
-            bin_callback: Some(callback_generator("Bin")),
+            strategy: UploadStrategy::default(),
+            skip_identical: false,
+            verify: VerifyMode::Crc,
+            dry_run: None,
+            ini_serializer: None,
+            progress: Some(Box::new(|progress: ProgressEvent| {
+                log::info!("{:?}: {:.2}%", progress.stage, progress.percent());
+            })),
         })
         .await?;
 
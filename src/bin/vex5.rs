@@ -0,0 +1,211 @@
+//! `vex5`, a command line tool for working with V5 brains.
+//!
+//! This is built with the `cli` feature:
+//!
+//! ```sh
+//! cargo install vex-v5-serial --features cli
+//! vex5 upload target/program.bin --slot 2 --name "Drive" --run
+//! ```
+
+use std::{error::Error, io::Write as _, path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    select,
+};
+use vex_v5_serial::{
+    commands::{
+        file::{ProgramData, UploadProgram, UploadStrategy, VerifyMode},
+        progress::ProgressEvent,
+        screen::ScreenCapture,
+    },
+    connection::{boxed::BoxedConnection, serial, tcp, Connection, ConnectionType},
+    fs::VexFs,
+    packets::file::FileExitAction,
+    slot::Slot,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "vex5",
+    version,
+    about = "Talk to VEX V5 brains over USB or TCP"
+)]
+struct Cli {
+    /// Connect to a brain shared over TCP at this address, instead of the first USB device.
+    #[arg(long, global = true)]
+    tcp: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Upload a program to a slot.
+    Upload {
+        /// The program's binary.
+        file: PathBuf,
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=8))]
+        slot: u8,
+        /// The program's name, which defaults to the file's name.
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, default_value = "")]
+        description: String,
+        #[arg(long, default_value = "USER029x.bmp")]
+        icon: String,
+        /// The program type shown on the brain.
+        #[arg(long = "type", default_value = "vex5")]
+        program_type: String,
+        /// Upload the program uncompressed.
+        #[arg(long)]
+        no_compress: bool,
+        /// Check the CRC of each file on the brain after uploading it.
+        #[arg(long)]
+        verify: bool,
+        /// Run the program once it has been uploaded.
+        #[arg(long)]
+        run: bool,
+    },
+    /// List the files stored under a vendor, such as `user` or `pros`.
+    Ls {
+        #[arg(default_value = "user")]
+        vendor: String,
+    },
+    /// Delete a file.
+    Rm { path: String },
+    /// Print a file's contents.
+    Cat { path: String },
+    /// Save a screenshot of the brain's screen as a PNG.
+    Screenshot {
+        #[arg(default_value = "screenshot.png")]
+        output: PathBuf,
+    },
+    /// Connect stdin and stdout to the running program.
+    Terminal,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let mut connection = connect(cli.tcp.as_deref()).await?;
+
+    match cli.command {
+        Command::Upload {
+            file,
+            slot,
+            name,
+            description,
+            icon,
+            program_type,
+            no_compress,
+            verify,
+            run,
+        } => {
+            let data = tokio::fs::read(&file).await?;
+            let name = name.unwrap_or_else(|| {
+                file.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+
+            let summary = connection
+                .execute_command(UploadProgram {
+                    name,
+                    description,
+                    icon,
+                    program_type,
+                    slot: Slot::new(slot)?,
+                    compress_program: !no_compress,
+                    data: ProgramData::Monolith(data),
+                    after_upload: if run {
+                        FileExitAction::RunProgram
+                    } else {
+                        FileExitAction::ShowRunScreen
+                    },
+                    strategy: UploadStrategy::default(),
+                    skip_identical: false,
+                    verify: if verify {
+                        VerifyMode::Crc
+                    } else {
+                        VerifyMode::Off
+                    },
+                    dry_run: None,
+                    ini_serializer: None,
+                    progress: Some(Box::new(|progress: ProgressEvent| {
+                        eprint!("\r{:?}: {:.0}%   ", progress.stage, progress.percent());
+                    })),
+                })
+                .await?;
+            eprintln!(
+                "\rUploaded {} bytes at {:.1} KiB/s",
+                summary.bytes_sent(),
+                summary.throughput() / 1024.0
+            );
+        }
+        Command::Ls { vendor } => {
+            for file in VexFs::new(&mut connection).read_dir(&vendor).await? {
+                println!("{:>8}  {}", file.entry.size, file.path);
+            }
+        }
+        Command::Rm { path } => VexFs::new(&mut connection).remove(&path).await?,
+        Command::Cat { path } => {
+            let data = VexFs::new(&mut connection)
+                .read(&path)
+                .await?
+                .ok_or_else(|| format!("{}: no such file", path))?;
+            std::io::stdout().write_all(&data)?;
+        }
+        Command::Screenshot { output } => {
+            connection
+                .execute_command(ScreenCapture)
+                .await?
+                .save_png(&output)?;
+        }
+        Command::Terminal => terminal(&mut connection).await?,
+    }
+
+    connection.shutdown().await?;
+    Ok(())
+}
+
+/// Connects to the brain at `tcp`, or the first one plugged in over USB.
+async fn connect(tcp: Option<&str>) -> Result<BoxedConnection, Box<dyn Error>> {
+    if let Some(address) = tcp {
+        let connection = tcp::connect(address, ConnectionType::Wired).await?;
+        return Ok(BoxedConnection::new(connection));
+    }
+
+    let device = serial::find_devices()?
+        .into_iter()
+        .next()
+        .ok_or("No V5 devices found")?;
+    Ok(BoxedConnection::new(
+        device.connect(Duration::from_secs(30))?,
+    ))
+}
+
+/// Copies program output to stdout and stdin to the program, until stdin is closed.
+async fn terminal(connection: &mut BoxedConnection) -> Result<(), Box<dyn Error>> {
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+    let mut input = [0; 1024];
+    let mut output = [0; 1024];
+
+    loop {
+        select! {
+            read = connection.read_user(&mut output) => {
+                stdout.write_all(&output[..read?]).await?;
+                stdout.flush().await?;
+            }
+            read = stdin.read(&mut input) => match read? {
+                0 => return Ok(()),
+                n => {
+                    connection.write_user(&input[..n]).await?;
+                }
+            },
+        }
+    }
+}
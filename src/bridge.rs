@@ -0,0 +1,123 @@
+//! Serves an open connection over WebSocket.
+//!
+//! The bridge lets a device plugged into one machine be used by tools running elsewhere, such
+//! as a browser-based IDE. Every binary message from a client is a raw device-bound packet,
+//! which is sent to the device as-is. The next packet the device sends back is forwarded to
+//! the client as a binary message.
+//!
+//! Replies are forwarded exactly like a serial port would deliver them: if the device does
+//! not reply in time, the client receives nothing and is expected to retry.
+//!
+//! This is the only network protocol the crate serves. It is not an RFC 2217 server, so
+//! telnet serial clients can't use the bridge, and [`tcp`](crate::connection::tcp) can only
+//! reach brains behind a raw TCP proxy.
+
+use std::io;
+
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tokio_tungstenite::{
+    accept_async,
+    tungstenite::{self, Message},
+};
+
+use crate::connection::{Connection, RawFrame};
+
+/// Accepts WebSocket clients on `listener` and bridges them to `connection`, one at a time.
+///
+/// This only returns if accepting a client fails. Errors from individual clients are
+/// logged and end that client's session.
+pub async fn serve<C: Connection + ?Sized>(
+    connection: &mut C,
+    listener: TcpListener,
+) -> io::Result<()> {
+    loop {
+        let (stream, address) = listener.accept().await?;
+        info!("Bridge client connected from {}", address);
+
+        match serve_client(connection, stream).await {
+            Ok(()) => info!("Bridge client {} disconnected", address),
+            Err(e) => warn!("Bridge client {} disconnected with error: {}", address, e),
+        }
+    }
+}
+
+/// Performs the WebSocket handshake on `stream` and bridges it to `connection` until the
+/// client disconnects.
+pub async fn serve_client<C, S>(connection: &mut C, stream: S) -> Result<(), tungstenite::Error>
+where
+    C: Connection + ?Sized,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut socket = accept_async(stream).await?;
+
+    while let Some(message) = socket.next().await {
+        let packet = match message? {
+            Message::Binary(packet) => packet,
+            Message::Close(_) => break,
+            Message::Text(_) => {
+                warn!("Ignoring text message from bridge client");
+                continue;
+            }
+            _ => continue,
+        };
+
+        let timeout = connection.config().retry_policy_for(&packet).timeout;
+        if let Err(e) = connection.send_packet(packet).await {
+            warn!("Failed to send bridged packet: {}", e);
+            continue;
+        }
+        match connection.receive_packet::<RawFrame>(timeout).await {
+            Ok(RawFrame(reply)) => socket.send(Message::Binary(reply)).await?,
+            Err(e) => debug!("No reply to bridged packet: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio::io::duplex;
+    use tokio_tungstenite::{client_async, tungstenite::Message};
+
+    use super::serve_client;
+    use crate::{
+        connection::{
+            mock::{cdc2_reply, MockConnection},
+            ConnectionType,
+        },
+        encode::Encode,
+        packets::{cdc2::Cdc2Ack, system::GetSystemFlagsPacket},
+    };
+
+    #[tokio::test]
+    async fn forwards_raw_packets() {
+        let request = GetSystemFlagsPacket::new(()).encode().unwrap();
+        let reply = cdc2_reply::<86, 32>(Cdc2Ack::Ack, &[0; 6]);
+
+        let mut device = MockConnection::new(ConnectionType::Wired);
+        device.expect(request.clone(), reply.clone()).unwrap();
+
+        let (server, client) = duplex(1024);
+        let bridge = tokio::spawn(async move {
+            serve_client(&mut device, server).await.unwrap();
+            device
+        });
+
+        let (mut socket, _) = client_async("ws://localhost/", client).await.unwrap();
+        socket.send(Message::Binary(request)).await.unwrap();
+        assert_eq!(
+            socket.next().await.unwrap().unwrap(),
+            Message::Binary(reply)
+        );
+        socket.close(None).await.unwrap();
+
+        assert_eq!(bridge.await.unwrap().remaining(), 0);
+    }
+}
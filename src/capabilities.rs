@@ -0,0 +1,132 @@
+//! Which features a brain supports, based on its VEXos version.
+//!
+//! Older versions of VEXos don't know about some of the things this crate can ask of a brain,
+//! and either reject the packets with a bare NACK or misbehave quietly. [`Capabilities`]
+//! answers these questions up front, so that commands can fail with [`UnsupportedFeature`]
+//! instead.
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::version::Version;
+
+/// A feature that is only available on some versions of VEXos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Feature {
+    /// Uploading gzip compressed files, which the brain decompresses as they are written.
+    ///
+    /// The PROS CLI only compresses uploads to brains running VEXos 1.0.5 or newer
+    /// (`V5Device.write_file`), which is where this version comes from.
+    CompressedUploads,
+}
+impl Feature {
+    /// Every known feature.
+    pub const ALL: &'static [Self] = &[Self::CompressedUploads];
+
+    /// Returns the oldest version of VEXos that supports this feature.
+    pub const fn min_version(self) -> Version {
+        match self {
+            Self::CompressedUploads => Version {
+                major: 1,
+                minor: 0,
+                build: 5,
+                beta: 0,
+            },
+        }
+    }
+}
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::CompressedUploads => "compressed uploads",
+        })
+    }
+}
+
+/// A feature that the brain's version of VEXos doesn't support.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("VEXos {version} does not support {feature}, which needs VEXos {} or newer", feature.min_version())]
+pub struct UnsupportedFeature {
+    pub feature: Feature,
+    /// The version of VEXos the brain is running.
+    pub version: Version,
+}
+
+/// The features supported by a brain running a given version of VEXos.
+///
+/// Beta builds are treated the same as the release they lead up to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    version: Version,
+}
+impl Capabilities {
+    pub fn new(version: Version) -> Self {
+        Self { version }
+    }
+
+    /// Returns the VEXos version these capabilities were decided from.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        let min = feature.min_version();
+        (self.version.major, self.version.minor, self.version.build)
+            >= (min.major, min.minor, min.build)
+    }
+
+    /// Fails with [`UnsupportedFeature`] if `feature` isn't supported.
+    pub fn require(&self, feature: Feature) -> Result<(), UnsupportedFeature> {
+        if self.supports(feature) {
+            Ok(())
+        } else {
+            Err(UnsupportedFeature {
+                feature,
+                version: self.version,
+            })
+        }
+    }
+
+    /// Returns every known feature that is supported.
+    pub fn features(&self) -> impl Iterator<Item = Feature> + '_ {
+        Feature::ALL
+            .iter()
+            .copied()
+            .filter(|feature| self.supports(*feature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, Feature, UnsupportedFeature};
+    use crate::version::Version;
+
+    fn vexos(major: u8, minor: u8, build: u8, beta: u8) -> Capabilities {
+        Capabilities::new(Version {
+            major,
+            minor,
+            build,
+            beta,
+        })
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert!(!vexos(1, 0, 4, 0).supports(Feature::CompressedUploads));
+        assert!(vexos(1, 0, 5, 0).supports(Feature::CompressedUploads));
+        assert!(vexos(1, 0, 5, 2).supports(Feature::CompressedUploads));
+        assert!(vexos(1, 1, 0, 0).supports(Feature::CompressedUploads));
+        assert!(vexos(2, 0, 0, 0).supports(Feature::CompressedUploads));
+
+        assert!(matches!(
+            vexos(0, 9, 9, 0).require(Feature::CompressedUploads),
+            Err(UnsupportedFeature {
+                feature: Feature::CompressedUploads,
+                ..
+            })
+        ));
+        assert_eq!(vexos(0, 9, 9, 0).features().count(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Consistent Overhead Byte Stuffing (COBS), as used to frame messages on the user port.
+//!
+//! Some runtimes, such as PROS, don't write raw text to the user port. Instead, each message
+//! is COBS-encoded so that it contains no zero bytes, and is followed by a zero byte marking
+//! its end. [`CobsDecoder`] splits a stream of such bytes back into messages.
+
+use crate::decode::DecodeError;
+
+/// Encodes a message, without its trailing zero delimiter.
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + data.len() / 254 + 1);
+    // Index of the code byte for the block currently being written.
+    let mut code_index = 0;
+    encoded.push(0);
+
+    for (i, &byte) in data.iter().enumerate() {
+        if byte != 0 {
+            encoded.push(byte);
+        }
+        let block_len = encoded.len() - code_index;
+        if byte == 0 || (block_len == 0xFF && i + 1 < data.len()) {
+            encoded[code_index] = block_len as u8;
+            code_index = encoded.len();
+            encoded.push(0);
+        }
+    }
+    encoded[code_index] = (encoded.len() - code_index) as u8;
+
+    encoded
+}
+
+/// Decodes a single message, without its trailing zero delimiter.
+pub fn decode(frame: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut decoded = Vec::with_capacity(frame.len());
+    let mut rest = frame;
+
+    while let Some((&code, tail)) = rest.split_first() {
+        let block_len = usize::from(code)
+            .checked_sub(1)
+            .ok_or(DecodeError::InvalidCobs)?;
+        if block_len > tail.len() {
+            return Err(DecodeError::InvalidCobs);
+        }
+        let (block, tail) = tail.split_at(block_len);
+        if block.contains(&0) {
+            return Err(DecodeError::InvalidCobs);
+        }
+        decoded.extend_from_slice(block);
+        rest = tail;
+
+        // Blocks of the maximum length aren't followed by an implicit zero, and neither is
+        // the last block of the frame.
+        if code != 0xFF && !rest.is_empty() {
+            decoded.push(0);
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Splits a stream of zero-delimited COBS frames into decoded messages.
+///
+/// Bytes can be pushed in pieces of any size. A frame that is cut off at the end of what has
+/// been pushed so far is held on to until the rest of it arrives.
+#[derive(Debug, Default, Clone)]
+pub struct CobsDecoder {
+    buffer: Vec<u8>,
+}
+impl CobsDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds received bytes to the decoder.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next complete message, or `None` if no full frame has been pushed.
+    ///
+    /// A malformed frame is reported as an error and skipped, so decoding can carry on
+    /// with the frames after it. Empty frames, such as those left by repeated delimiters,
+    /// are ignored.
+    pub fn next_message(&mut self) -> Option<Result<Vec<u8>, DecodeError>> {
+        loop {
+            let end = self.buffer.iter().position(|&byte| byte == 0)?;
+            let frame: Vec<u8> = self.buffer.drain(..=end).collect();
+            let frame = &frame[..end];
+            if !frame.is_empty() {
+                return Some(decode(frame));
+            }
+        }
+    }
+
+    /// Returns the bytes of a frame that hasn't been terminated yet.
+    pub fn pending(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, CobsDecoder};
+
+    #[test]
+    fn round_trips() {
+        let long: Vec<u8> = (1..=255).cycle().take(600).collect();
+        for data in [&[][..], &[0], &[0, 0], &[1, 2, 0, 3], b"sout hello", &long] {
+            assert_eq!(decode(&encode(data)).unwrap(), data);
+        }
+        assert_eq!(
+            encode(&[0x11, 0x22, 0x00, 0x33]),
+            [0x03, 0x11, 0x22, 0x02, 0x33]
+        );
+        assert_eq!(encode(&long[..254]).len(), 255);
+    }
+
+    #[test]
+    fn splits_frames() {
+        let mut decoder = CobsDecoder::new();
+        let mut stream = encode(b"first");
+        stream.extend([0, 0]);
+        stream.extend(encode(b"second"));
+        stream.push(0);
+
+        let (head, tail) = stream.split_at(9);
+        decoder.push(head);
+        assert_eq!(decoder.next_message().unwrap().unwrap(), b"first");
+        assert!(decoder.next_message().is_none());
+
+        decoder.push(tail);
+        assert_eq!(decoder.next_message().unwrap().unwrap(), b"second");
+        assert!(decoder.next_message().is_none());
+        assert!(decoder.pending().is_empty());
+
+        decoder.push(&[5, 1, 0, 1, 1, 1, 0]);
+        assert!(decoder.next_message().unwrap().is_err());
+        assert_eq!(decoder.next_message().unwrap().unwrap(), [0, 0]);
+    }
+}
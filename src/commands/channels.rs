@@ -0,0 +1,173 @@
+//! Reading several user FIFO channels at once.
+//!
+//! The brain's user FIFO is split into numbered channels. Channel [`STDIO`] carries a user
+//! program's output, and other channels can be used by programs for their own data.
+//! [`Channels`] polls any number of them from one connection and hands each channel's data to
+//! its own stream.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::Stream;
+use tokio::sync::mpsc;
+
+use crate::{
+    connection::{
+        time::{interval, MissedTickBehavior},
+        Connection,
+    },
+    packets::controller::{UserFifoPacket, UserFifoPayload, UserFifoReplyPacket},
+};
+
+use super::Command;
+
+/// The user FIFO channel that carries a user program's stdio.
+pub const STDIO: u8 = 1;
+/// The user FIFO channel that stdin writes are sent on.
+pub const STDIN: u8 = 2;
+
+/// How many reads a channel's stream holds before its channel stops being polled.
+const STREAM_BUFFER: usize = 16;
+
+/// Polls a user FIFO channel once.
+///
+/// Returns `None` if nothing has been written to the channel since the last poll.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadChannel {
+    pub channel: u8,
+}
+impl Command for ReadChannel {
+    type Output = Option<Bytes>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let fifo = connection
+            .packet_handshake::<UserFifoReplyPacket>(UserFifoPacket::new(UserFifoPayload {
+                channel: self.channel,
+                write: None,
+            }))
+            .await?
+            .try_into_inner()?;
+
+        Ok(fifo.data.map(|data| Bytes::from(data.into_bytes())))
+    }
+}
+
+/// Polls several user FIFO channels and splits their data into separate streams.
+///
+/// Streams are created with [`subscribe`](Self::subscribe), and are fed while
+/// [`run`](Self::run) is being awaited:
+///
+/// ```no_run
+/// # async fn example(connection: &mut vex_v5_serial::connection::serial::SerialConnection)
+/// # -> Result<(), vex_v5_serial::connection::serial::SerialError> {
+/// use std::time::Duration;
+///
+/// use futures::StreamExt;
+/// use vex_v5_serial::commands::channels::{Channels, STDIO};
+///
+/// let mut channels = Channels::new(connection);
+/// let mut stdio = channels.subscribe(STDIO);
+/// let mut telemetry = channels.subscribe(3);
+///
+/// let printer = async {
+///     loop {
+///         tokio::select! {
+///             Some(data) = stdio.next() => print!("{}", String::from_utf8_lossy(&data)),
+///             Some(data) = telemetry.next() => println!("telemetry: {:?}", data),
+///             else => break,
+///         }
+///     }
+/// };
+/// let (result, _) = tokio::join!(channels.run(Duration::from_millis(10)), printer);
+/// result?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Channels<'a, C: Connection + ?Sized> {
+    connection: &'a mut C,
+    subscribers: Vec<(u8, mpsc::Sender<Bytes>)>,
+}
+impl<'a, C: Connection + ?Sized> Channels<'a, C> {
+    pub fn new(connection: &'a mut C) -> Self {
+        Self {
+            connection,
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Returns a stream of the data read from `channel`.
+    ///
+    /// Each channel has at most one stream, so subscribing to a channel again ends the
+    /// stream returned before.
+    pub fn subscribe(&mut self, channel: u8) -> ChannelStream {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        self.subscribers
+            .retain(|(subscribed, _)| *subscribed != channel);
+        self.subscribers.push((channel, sender));
+        ChannelStream { channel, receiver }
+    }
+
+    /// Polls every subscribed channel once each `poll_interval`, until all of their streams
+    /// have been dropped.
+    ///
+    /// A channel whose stream has fallen behind isn't polled until it catches up, so its
+    /// data waits on the brain instead of piling up on the host. If a read fails, polling
+    /// stops, every stream ends and the error is returned.
+    pub async fn run(self, poll_interval: Duration) -> Result<(), C::Error> {
+        let Self {
+            connection,
+            mut subscribers,
+        } = self;
+        let mut ticker = interval(poll_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            subscribers.retain(|(_, sender)| !sender.is_closed());
+            if subscribers.is_empty() {
+                return Ok(());
+            }
+
+            ticker.tick().await;
+            for (channel, sender) in &subscribers {
+                let Ok(permit) = sender.try_reserve() else {
+                    continue;
+                };
+                let read = connection
+                    .execute_command(ReadChannel { channel: *channel })
+                    .await?;
+                if let Some(data) = read {
+                    permit.send(data);
+                }
+            }
+        }
+    }
+}
+
+/// The data read from one user FIFO channel, created by [`Channels::subscribe`].
+///
+/// The stream ends once its [`Channels`] has stopped running.
+#[derive(Debug)]
+pub struct ChannelStream {
+    channel: u8,
+    receiver: mpsc::Receiver<Bytes>,
+}
+impl ChannelStream {
+    /// Returns the channel this stream reads from.
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+}
+impl Stream for ChannelStream {
+    type Item = Bytes;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Bytes>> {
+        self.receiver.poll_recv(cx)
+    }
+}
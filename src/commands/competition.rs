@@ -0,0 +1,181 @@
+//! Controlling the brain's competition state without a competition switch.
+
+use std::time::Duration;
+
+use log::debug;
+use tokio::{select, sync::broadcast};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    connection::{time::sleep, Connection},
+    packets::match_mode::{
+        MatchMode, SetMatchModePacket, SetMatchModePayload, SetMatchModeReplyPacket,
+    },
+};
+
+use super::Command;
+
+/// Puts the brain into a competition state, like a competition switch or field controller.
+///
+/// This only works when connected through a controller that is linked to the brain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetCompetitionMode {
+    pub mode: MatchMode,
+    /// The match time shown on the controller's screen, rounded down to whole seconds.
+    pub match_time: Duration,
+}
+impl SetCompetitionMode {
+    pub fn disabled() -> Self {
+        Self {
+            mode: MatchMode::Disabled,
+            match_time: Duration::ZERO,
+        }
+    }
+
+    pub fn autonomous(match_time: Duration) -> Self {
+        Self {
+            mode: MatchMode::Auto,
+            match_time,
+        }
+    }
+
+    pub fn driver(match_time: Duration) -> Self {
+        Self {
+            mode: MatchMode::Driver,
+            match_time,
+        }
+    }
+}
+impl Command for SetCompetitionMode {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        debug!("Setting competition mode to {:?}", self.mode);
+        connection
+            .packet_handshake::<SetMatchModeReplyPacket>(SetMatchModePacket::new(
+                SetMatchModePayload {
+                    match_mode: self.mode,
+                    match_time: self.match_time.as_secs() as u32,
+                },
+            ))
+            .await?
+            .try_into_inner()?;
+
+        Ok(())
+    }
+}
+
+/// How long each period of a match lasts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchTimings {
+    pub autonomous: Duration,
+    /// A disabled break between the autonomous and driver periods. Skipped if zero.
+    pub pause: Duration,
+    pub driver: Duration,
+}
+impl Default for MatchTimings {
+    /// The timings of a V5RC match: 15 seconds of autonomous followed by 1:45 of driver control.
+    fn default() -> Self {
+        Self {
+            autonomous: Duration::from_secs(15),
+            pause: Duration::ZERO,
+            driver: Duration::from_secs(105),
+        }
+    }
+}
+
+/// A change in the competition state set by a [`FieldController`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchStateChange {
+    pub mode: MatchMode,
+    /// How long the brain stays in this mode. Zero once the match has ended.
+    pub duration: Duration,
+}
+
+/// Runs timed practice matches, like a field controller.
+///
+/// Every state change is sent to subscribers as a [`MatchStateChange`] once the brain has
+/// acknowledged it.
+#[derive(Debug, Clone)]
+pub struct FieldController {
+    pub timings: MatchTimings,
+    changes: broadcast::Sender<MatchStateChange>,
+}
+impl FieldController {
+    /// How many unreceived changes a subscriber can fall behind by before it starts missing them.
+    const CHANGE_CAPACITY: usize = 16;
+
+    pub fn new(timings: MatchTimings) -> Self {
+        Self {
+            timings,
+            changes: broadcast::channel(Self::CHANGE_CAPACITY).0,
+        }
+    }
+
+    /// Returns a receiver for every state change made after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<MatchStateChange> {
+        self.changes.subscribe()
+    }
+
+    /// Runs a full match, ending with the robot disabled.
+    ///
+    /// If `cancel` is triggered, the robot is disabled immediately and `false` is returned.
+    /// Dropping the future instead leaves the robot in whatever mode it was in.
+    pub async fn run<C: Connection + ?Sized>(
+        &self,
+        connection: &mut C,
+        cancel: &CancellationToken,
+    ) -> Result<bool, C::Error> {
+        let periods = [
+            (MatchMode::Auto, self.timings.autonomous),
+            (MatchMode::Disabled, self.timings.pause),
+            (MatchMode::Driver, self.timings.driver),
+        ];
+
+        for (mode, duration) in periods {
+            if duration.is_zero() {
+                continue;
+            }
+            self.set_mode(connection, mode, duration).await?;
+
+            select! {
+                _ = sleep(duration) => {}
+                _ = cancel.cancelled() => {
+                    debug!("Match cancelled during {:?} period", mode);
+                    self.set_mode(connection, MatchMode::Disabled, Duration::ZERO)
+                        .await?;
+                    return Ok(false);
+                }
+            }
+        }
+
+        self.set_mode(connection, MatchMode::Disabled, Duration::ZERO)
+            .await?;
+        Ok(true)
+    }
+
+    async fn set_mode<C: Connection + ?Sized>(
+        &self,
+        connection: &mut C,
+        mode: MatchMode,
+        duration: Duration,
+    ) -> Result<(), C::Error> {
+        connection
+            .execute_command(SetCompetitionMode {
+                mode,
+                match_time: duration,
+            })
+            .await?;
+        // Sending only fails when nobody is subscribed, which is fine.
+        _ = self.changes.send(MatchStateChange { mode, duration });
+        Ok(())
+    }
+}
+impl Default for FieldController {
+    fn default() -> Self {
+        Self::new(MatchTimings::default())
+    }
+}
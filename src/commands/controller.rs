@@ -0,0 +1,157 @@
+//! Commands that act on the V5 controller itself rather than the brain.
+//!
+//! There is no known packet that reads a controller's joysticks or buttons, or that writes to
+//! its screen. The firmware only gives the running user program access to them, so tools that
+//! need either have to run a program that relays them, for example over user FIFO channels
+//! read with [`Channels`](super::channels::Channels) and written with
+//! [`WriteStdin`](super::terminal::WriteStdin).
+
+use std::time::Duration;
+
+use log::debug;
+
+use crate::{
+    connection::{Connection, ConnectionType},
+    packets::{
+        file::{ExtensionType, FileExitAction, FileMetadata, FileTransferTarget, FileVendor},
+        radio::RadioChannel,
+        system::{ControllerLink, GetSystemFlagsPacket, GetSystemFlagsReplyPacket},
+    },
+    string::FixedString,
+    timestamp::J2000Timestamp,
+    version::Version,
+};
+
+use super::{
+    file::{UploadFile, UploadSummary, VerifyMode},
+    progress::ProgressSink,
+    radio::{GetLinkPath, GetRadioChannel, SwitchRadioChannel},
+    Command,
+};
+
+/// How long to wait for the radio to leave the download channel before flashing.
+const RADIO_SWITCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Flashes new firmware onto a V5 controller.
+///
+/// The controller can be connected over USB, or tethered to a brain that is connected over
+/// USB. If the controller's radio is on the download channel, it is switched back to the pit
+/// channel first so that the link is idle while the controller is being flashed, and this
+/// command leaves it there.
+///
+/// The file name, vendor, load address and exit action that VEXos expects for a firmware
+/// image haven't been verified against a capture of an official update, so they have to be
+/// given by the caller. If the controller restarts once the transfer is closed, its connection
+/// stops responding and has to be reopened.
+pub struct UpdateControllerFirmware<'a> {
+    /// The firmware image to flash.
+    pub image: Vec<u8>,
+    /// The name the image is transferred under.
+    pub file_name: FixedString<23>,
+    pub vendor: FileVendor,
+    /// The controller memory the image is written to, such as [`FileTransferTarget::A1`].
+    pub target: FileTransferTarget,
+    pub load_addr: u32,
+    /// What the controller is told to do once the image has been written.
+    pub after_upload: FileExitAction,
+    /// Receives [`TransferStage::Upload`](super::progress::TransferStage::Upload) progress events.
+    pub progress: Option<Box<dyn ProgressSink + 'a>>,
+}
+impl Command for UpdateControllerFirmware<'_> {
+    type Output = UploadSummary;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        if connection.connection_type() == ConnectionType::Controller {
+            let radio = connection.execute_command(GetRadioChannel).await?;
+            if radio.radio_channel() == RadioChannel::Download {
+                connection
+                    .execute_command(SwitchRadioChannel {
+                        channel: RadioChannel::Pit,
+                        timeout: RADIO_SWITCH_TIMEOUT,
+                    })
+                    .await?;
+            }
+            connection.record_radio_channel(RadioChannel::Pit);
+        }
+
+        debug!(
+            "Flashing {} bytes of controller firmware to {:?}",
+            self.image.len(),
+            self.target
+        );
+        // Uploading directly skips the radio handling of `UploadFile::execute`, which would
+        // move the radio back onto the download channel.
+        let link = connection.execute_command(GetLinkPath).await?;
+        let config = connection.config().tuned_for(link);
+        let summary = UploadFile {
+            filename: self.file_name,
+            metadata: FileMetadata {
+                extension: FixedString::try_from("bin")?,
+                extension_type: ExtensionType::Binary,
+                timestamp: J2000Timestamp::now(),
+                version: Version {
+                    major: 1,
+                    minor: 0,
+                    build: 0,
+                    beta: 0,
+                },
+            },
+            vendor: Some(self.vendor),
+            data: self.image.into(),
+            target: Some(self.target),
+            load_addr: self.load_addr,
+            linked_file: None,
+            after_upload: self.after_upload,
+            skip_identical: false,
+            verify: VerifyMode::Off,
+            checkpoint: None,
+            dry_run: None,
+            progress: self.progress,
+        }
+        .upload(&mut connection.override_config(config), link)
+        .await?;
+        debug!("Controller firmware flashed");
+
+        Ok(summary)
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        true
+    }
+}
+
+/// The battery and link state of the controllers connected to the brain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControllerStatus {
+    /// The primary controller's battery capacity in percent, in steps of 8.
+    pub battery: u8,
+    /// The partner controller's battery capacity, if one is connected.
+    pub partner_battery: Option<u8>,
+    pub link: ControllerLink,
+}
+
+/// Reads the controllers' battery levels and how the primary controller is linked to the brain.
+#[derive(Debug, Clone, Copy)]
+pub struct GetControllerStatus;
+impl Command for GetControllerStatus {
+    type Output = ControllerStatus;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(GetSystemFlagsPacket::new(()))
+            .await?
+            .try_into_inner()?;
+
+        Ok(ControllerStatus {
+            battery: flags.controller_battery_percent(),
+            partner_battery: flags.partner_battery_percent(),
+            link: flags.controller_link(),
+        })
+    }
+}
@@ -1,55 +1,201 @@
-use std::{io::Write, str::FromStr, time::Duration};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, Read, SeekFrom, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use flate2::{Compression, GzBuilder};
-use log::{debug, trace};
-use serde::{Deserialize, Serialize};
+use flate2::{read::GzDecoder, Compression, GzBuilder};
+use log::{debug, trace, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 
 #[cfg(feature = "bluetooth")]
 use crate::connection::bluetooth::BluetoothConnection;
 use crate::{
-    connection::{Connection, ConnectionType},
+    capabilities::Feature,
+    config::{Backoff, Config, LinkPath, RetryPolicy},
+    connection::{time::Instant, Connection, ConnectionType},
     crc::VEX_CRC32,
-    packets::file::{
-        ExitFileTransferPacket, ExitFileTransferReplyPacket, ExtensionType, FileExitAction,
-        FileInitAction, FileInitOption, FileMetadata, FileTransferTarget, FileVendor,
-        InitFileTransferPacket, InitFileTransferPayload, InitFileTransferReplyPacket,
-        LinkFilePacket, LinkFilePayload, LinkFileReplyPacket, ReadFilePacket, ReadFilePayload,
-        ReadFileReplyPacket, WriteFilePacket, WriteFilePayload, WriteFileReplyPacket,
+    decode::DecodeError,
+    encode::{Encode, EncodeError},
+    packets::{
+        cdc2::Cdc2Ack,
+        file::{
+            EraseFilePacket, EraseFilePayload, EraseFileReplyPacket, ExitFileTransferPacket,
+            ExitFileTransferReplyPacket, ExtensionType, FileExitAction, FileFormatConfirmation,
+            FileFormatPacket, FileFormatReplyPacket, FileInitAction, FileInitOption,
+            FileLoadAction, FileMetadata, FileTransferTarget, FileVendor, GetDirectoryEntryPacket,
+            GetDirectoryEntryPayload, GetDirectoryEntryReplyPacket, GetDirectoryEntryReplyPayload,
+            GetDirectoryFileCountPacket, GetDirectoryFileCountPayload,
+            GetDirectoryFileCountReplyPacket, GetFileMetadataPacket, GetFileMetadataPayload,
+            GetFileMetadataReplyPacket, GetFileMetadataReplyPayload, InitFileTransferPacket,
+            InitFileTransferPayload, InitFileTransferReplyPacket, LinkFilePacket, LinkFilePayload,
+            LinkFileReplyPacket, LoadFileActionPacket, LoadFileActionPayload,
+            LoadFileActionReplyPacket, ReadFilePacket, ReadFilePayload, ReadFileReplyPacket,
+            SetFileMetadataPacket, SetFileMetadataPayload, SetFileMetadataReplyPacket,
+            WriteFilePacket, WriteFilePayload, WriteFileReplyPacket,
+        },
     },
+    slot::Slot,
     string::FixedString,
-    timestamp::j2000_timestamp,
+    timestamp::J2000Timestamp,
     version::Version,
 };
 
-use super::Command;
+use super::{
+    progress::{staged, ProgressSink, ProgressTracker, TransferStage},
+    radio::{restore_pit_channel, use_download_channel, GetLinkPath},
+    system::GetCapabilities,
+    Command, CommandError, PacketPlan,
+};
 
+/// Where PROS loads the hot half of a hot/cold program.
 pub const PROS_HOT_BIN_LOAD_ADDR: u32 = 0x7800000;
+/// Where user programs are loaded by default.
 pub const USER_PROGRAM_LOAD_ADDR: u32 = 0x3800000;
-const USER_PROGRAM_CHUNK_SIZE: u16 = 4096;
 
-pub struct DownloadFile {
+#[derive(Debug, Default)]
+struct CheckpointState {
+    offset: u32,
+    downloaded: Vec<u8>,
+    /// The transfer the progress belongs to, set when the checkpoint is first used.
+    transfer: Option<CheckpointedTransfer>,
+    /// The window size the brain gave an upload when it was started.
+    window_size: u16,
+}
+
+/// Identifies the transfer a checkpoint's progress belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CheckpointedTransfer {
+    file_name: String,
+    vendor: FileVendor,
+    size: u32,
+    crc: u32,
+}
+
+/// A record of how far a file transfer got, used to resume it after an interruption.
+///
+/// Pass the same checkpoint to a transfer command again after it fails (for example because
+/// the radio link dropped) and it will continue from the last acknowledged offset instead of
+/// starting over. The checkpoint is reset once a transfer completes.
+///
+/// A checkpoint belongs to the file it was first used with, identified by its name, vendor,
+/// size and CRC. Resuming a different transfer from it fails with
+/// [`CommandError::CheckpointMismatch`].
+///
+/// A resumed upload continues the transfer the brain still has open, without starting a new
+/// one. If the brain has closed it in the meantime, the earlier chunks are gone and the
+/// upload starts over from the beginning.
+#[derive(Debug, Clone, Default)]
+pub struct TransferCheckpoint(Arc<Mutex<CheckpointState>>);
+impl TransferCheckpoint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a checkpoint that resumes an upload from the given byte offset.
+    ///
+    /// The checkpoint belongs to the first transfer it is used with.
+    pub fn resume_from(offset: u32) -> Self {
+        Self(Arc::new(Mutex::new(CheckpointState {
+            offset,
+            ..Default::default()
+        })))
+    }
+
+    /// Returns the offset of the first byte that has not been acknowledged yet.
+    pub fn offset(&self) -> u32 {
+        self.0.lock().unwrap().offset
+    }
+
+    /// Forgets all progress so that the next transfer starts from the beginning.
+    pub fn reset(&self) {
+        *self.0.lock().unwrap() = CheckpointState::default();
+    }
+
+    /// Ties the checkpoint to `transfer`, failing if it holds progress from another one.
+    fn bind(&self, transfer: CheckpointedTransfer) -> Result<(), CommandError> {
+        let mut state = self.0.lock().unwrap();
+        let has_progress = state.offset > 0 || !state.downloaded.is_empty();
+        match &state.transfer {
+            Some(bound) if has_progress && *bound != transfer => {
+                Err(CommandError::CheckpointMismatch {
+                    file_name: transfer.file_name,
+                })
+            }
+            _ => {
+                state.transfer = Some(transfer);
+                Ok(())
+            }
+        }
+    }
+
+    fn window_size(&self) -> u16 {
+        self.0.lock().unwrap().window_size
+    }
+
+    fn set_window_size(&self, window_size: u16) {
+        self.0.lock().unwrap().window_size = window_size;
+    }
+
+    fn set_offset(&self, offset: u32) {
+        self.0.lock().unwrap().offset = offset;
+    }
+
+    fn downloaded(&self) -> Vec<u8> {
+        let state = self.0.lock().unwrap();
+        state.downloaded[..(state.offset as usize).min(state.downloaded.len())].to_vec()
+    }
+
+    fn record_download(&self, chunk: &[u8]) {
+        let mut state = self.0.lock().unwrap();
+        state.downloaded.extend_from_slice(chunk);
+        state.offset = state.downloaded.len() as u32;
+    }
+}
+
+/// A writer that a download can stream into.
+pub trait DownloadWriter: AsyncWrite + Unpin + Send {}
+impl<T: AsyncWrite + Unpin + Send> DownloadWriter for T {}
+
+/// Downloads a file from the device.
+///
+/// Chunks whose CRC doesn't match are requested again. Files in flash are also checked
+/// against the CRC the brain reports for the whole file.
+pub struct DownloadFile<'a> {
     pub file_name: FixedString<23>,
     pub size: u32,
     pub vendor: FileVendor,
     pub target: Option<FileTransferTarget>,
     pub load_addr: u32,
+    /// If set, the download resumes from and records its progress to this checkpoint.
+    ///
+    /// When downloading into a [`sink`](Self::sink), only the offset is recorded, so a resumed
+    /// download must be given a sink that already holds the bytes before it.
+    pub checkpoint: Option<TransferCheckpoint>,
+    /// If set, chunks are written here as they arrive and the command returns no data.
+    ///
+    /// This avoids holding large files in memory.
+    pub sink: Option<Box<dyn DownloadWriter + 'a>>,
 
-    pub progress_callback: Option<Box<dyn FnMut(f32) + Send>>,
+    /// Receives [`TransferStage::Download`] progress events.
+    pub progress: Option<Box<dyn ProgressSink + 'a>>,
 }
-impl Command for DownloadFile {
-    type Output = Vec<u8>;
-
-    async fn execute<C: Connection + ?Sized>(
+impl DownloadFile<'_> {
+    /// Downloads the file with the connection's config as it is, leaving tuning it to the
+    /// link to [`Command::execute`].
+    async fn download<C: Connection + ?Sized>(
         mut self,
         connection: &mut C,
-    ) -> Result<Self::Output, C::Error> {
+    ) -> Result<Vec<u8>, C::Error> {
+        let config = connection.config().clone();
         let target = self.target.unwrap_or(FileTransferTarget::Qspi);
 
         let transfer_response = connection
-            .packet_handshake::<InitFileTransferReplyPacket>(
-                Duration::from_millis(500),
-                5,
-                InitFileTransferPacket::new(InitFileTransferPayload {
+            .packet_handshake::<InitFileTransferReplyPacket>(InitFileTransferPacket::new(
+                InitFileTransferPayload {
                     operation: FileInitAction::Read,
                     target,
                     vendor: self.vendor,
@@ -60,7 +206,7 @@ impl Command for DownloadFile {
                     metadata: FileMetadata {
                         extension: FixedString::from_str("ini").unwrap(),
                         extension_type: ExtensionType::EncryptedBinary,
-                        timestamp: 0,
+                        timestamp: J2000Timestamp::EPOCH,
                         version: Version {
                             major: 1,
                             minor: 0,
@@ -68,76 +214,325 @@ impl Command for DownloadFile {
                             beta: 0,
                         },
                     },
-                    file_name: self.file_name,
-                }),
-            )
+                    file_name: self.file_name.clone(),
+                },
+            ))
             .await?;
         let transfer_response = transfer_response.try_into_inner()?;
 
         let max_chunk_size = if transfer_response.window_size > 0
-            && transfer_response.window_size <= USER_PROGRAM_CHUNK_SIZE
+            && transfer_response.window_size <= config.transfer_chunk_size
         {
             transfer_response.window_size
         } else {
-            USER_PROGRAM_CHUNK_SIZE
+            config.transfer_chunk_size
         };
 
-        let mut data = Vec::with_capacity(transfer_response.file_size as usize);
+        let mut data = Vec::new();
         let mut offset = 0;
-        loop {
-            let read = connection
-                .packet_handshake::<ReadFileReplyPacket>(
-                    Duration::from_millis(500),
-                    5,
-                    ReadFilePacket::new(ReadFilePayload {
-                        address: self.load_addr + offset,
-                        size: max_chunk_size,
-                    }),
-                )
-                .await?;
-
-            let (_, chunk_data) = read.payload.unwrap()?;
-            offset += chunk_data.len() as u32;
-            let progress = (offset as f32 / transfer_response.file_size as f32) * 100.0;
-
-            if let Some(callback) = &mut self.progress_callback {
-                callback(progress);
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.bind(CheckpointedTransfer {
+                file_name: self.file_name.to_string(),
+                vendor: self.vendor,
+                size: transfer_response.file_size,
+                crc: transfer_response.file_crc,
+            })?;
+            if self.sink.is_some() {
+                offset = checkpoint.offset();
+            } else {
+                data = checkpoint.downloaded();
+                offset = data.len() as u32;
+            }
+            if offset > transfer_response.file_size {
+                return Err(CommandError::CheckpointMismatch {
+                    file_name: self.file_name.to_string(),
+                }
+                .into());
             }
+            debug!("Resuming download from offset {}", offset);
+        }
+        if self.sink.is_none() {
+            data.reserve((transfer_response.file_size as usize).saturating_sub(data.len()));
+        }
+
+        // The bytes before a resumed offset are gone when writing to a sink, so its CRC can't
+        // be checked.
+        let mut digest = (self.sink.is_none() || offset == 0).then(|| VEX_CRC32.digest());
+        if let Some(digest) = &mut digest {
+            digest.update(&data);
+        }
 
-            if transfer_response.file_size <= offset {
+        let mut progress = ProgressTracker::new(
+            self.progress.take(),
+            TransferStage::Download,
+            transfer_response.file_size,
+            offset,
+        );
+        while offset < transfer_response.file_size {
+            let mut chunk_data =
+                read_chunk(connection, self.load_addr + offset, max_chunk_size).await?;
+            let chunk_end = offset + chunk_data.len() as u32;
+            if transfer_response.file_size < chunk_end {
                 // Since data is returned in fixed-size chunks read from flash, VEXos will sometimes read
                 // past the end of the file in the last chunk, returning whatever garbled nonsense happens
                 // to be stored next in QSPI. This is a feature™️, and something we need to handle ourselves.
-                let eof = chunk_data.len() - (offset - transfer_response.file_size) as usize;
-                data.extend(&chunk_data[0..eof]);
-                break; // we're done here
+                chunk_data.truncate((transfer_response.file_size - offset) as usize);
+            }
+            offset = chunk_end;
+            progress.report(offset);
+
+            if let Some(digest) = &mut digest {
+                digest.update(&chunk_data);
+            }
+            if let Some(sink) = &mut self.sink {
+                sink.write_all(&chunk_data)
+                    .await
+                    .map_err(CommandError::from)?;
+                if let Some(checkpoint) = &self.checkpoint {
+                    checkpoint.set_offset(offset.min(transfer_response.file_size));
+                }
             } else {
+                if let Some(checkpoint) = &self.checkpoint {
+                    checkpoint.record_download(&chunk_data);
+                }
                 data.extend(chunk_data);
             }
         }
+        if let Some(sink) = &mut self.sink {
+            sink.flush().await.map_err(CommandError::from)?;
+        }
+
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.reset();
+        }
+
+        // Only files stored in flash have a CRC. Other targets, like the screen's framebuffer,
+        // are raw memory.
+        if let Some(digest) = digest.filter(|_| {
+            matches!(target, FileTransferTarget::Qspi) && transfer_response.file_crc != 0
+        }) {
+            let actual = digest.finalize();
+            if actual != transfer_response.file_crc {
+                return Err(CommandError::ChecksumMismatch {
+                    file_name: self.file_name.to_string(),
+                    expected: transfer_response.file_crc,
+                    actual,
+                }
+                .into());
+            }
+        }
 
         Ok(data)
     }
 }
+impl Command for DownloadFile<'_> {
+    type Output = Vec<u8>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let link = connection.execute_command(GetLinkPath).await?;
+        let config = connection.config().tuned_for(link);
+        self.download(&mut connection.override_config(config)).await
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        true
+    }
+}
+
+/// Reads one chunk of a file, re-requesting it if the reply is corrupted.
+///
+/// A chunk is read at most as many times as the read packet's retry policy allows.
+pub(crate) async fn read_chunk<C: Connection + ?Sized>(
+    connection: &mut C,
+    address: u32,
+    size: u16,
+) -> Result<Vec<u8>, C::Error> {
+    let packet = ReadFilePacket::new(ReadFilePayload { address, size });
+    let max_attempts = connection
+        .config()
+        .retry_policy_for(&packet.encode()?)
+        .max_attempts;
+
+    let mut last_error = None;
+    for attempt in 1..=max_attempts.max(1) {
+        let read = connection
+            .packet_handshake::<ReadFileReplyPacket>(packet.clone())
+            .await?;
+        if let Err(e) = read.verify_crc() {
+            warn!(
+                "Chunk at {:#x} is corrupted ({}), attempt {}/{}",
+                address, e, attempt, max_attempts
+            );
+            last_error = Some(e);
+            continue;
+        }
+
+        let (read_address, chunk_data) = read.payload.unwrap()?;
+        if read_address != address {
+            // A late reply to an earlier read.
+            warn!(
+                "Expected chunk at {:#x}, got {:#x}, attempt {}/{}",
+                address, read_address, attempt, max_attempts
+            );
+            last_error = Some(DecodeError::InvalidHeader);
+            continue;
+        }
+        return Ok(chunk_data);
+    }
+
+    Err(last_error.unwrap().into())
+}
 
 #[cfg(feature = "bluetooth")]
-fn max_chunk_size(con_type: ConnectionType, window_size: u16) -> u16 {
+fn max_chunk_size(con_type: ConnectionType, window_size: u16, chunk_size: u16) -> u16 {
     if con_type.is_bluetooth() {
         let max_chunk_size =
             (BluetoothConnection::MAX_PACKET_SIZE as u16).min(window_size / 2) - 14;
         max_chunk_size - (max_chunk_size % 4)
-    } else if window_size > 0 && window_size <= USER_PROGRAM_CHUNK_SIZE {
+    } else if window_size > 0 && window_size <= chunk_size {
         window_size
     } else {
-        USER_PROGRAM_CHUNK_SIZE
+        chunk_size
     }
 }
 #[cfg(not(feature = "bluetooth"))]
-fn max_chunk_size(_con_type: ConnectionType, window_size: u16) -> u16 {
-    if window_size > 0 && window_size <= USER_PROGRAM_CHUNK_SIZE {
+fn max_chunk_size(_con_type: ConnectionType, window_size: u16, chunk_size: u16) -> u16 {
+    if window_size > 0 && window_size <= chunk_size {
         window_size
     } else {
-        USER_PROGRAM_CHUNK_SIZE
+        chunk_size
+    }
+}
+
+/// File writes that have been sent during an upload but not acknowledged yet.
+///
+/// Up to [`Config::write_window`](crate::config::Config::write_window) writes are kept in
+/// flight. The brain replies to writes in the order they were sent, so each reply
+/// acknowledges the oldest outstanding write. A lost or damaged write is sent again along
+/// with every write after it.
+struct WriteWindow {
+    depth: usize,
+    policy: RetryPolicy,
+    /// Each write along with the upload offset reached once it is acknowledged.
+    in_flight: VecDeque<(WriteFilePacket, u32)>,
+}
+impl WriteWindow {
+    fn new(config: &Config) -> Result<Self, EncodeError> {
+        let probe = WriteFilePacket::new(WriteFilePayload {
+            address: 0,
+            chunk_data: Vec::new(),
+        });
+
+        Ok(Self {
+            depth: config.write_window.max(1),
+            policy: *config.retry_policy_for(&probe.encode()?),
+            in_flight: VecDeque::new(),
+        })
+    }
+
+    /// Sends a write, waiting for older writes to be acknowledged if the window is full.
+    ///
+    /// Returns how many times writes had to be resent.
+    async fn send<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+        packet: WriteFilePacket,
+        end_offset: u32,
+        checkpoint: Option<&TransferCheckpoint>,
+    ) -> Result<usize, C::Error> {
+        connection.send_packet(packet.clone()).await?;
+        self.in_flight.push_back((packet, end_offset));
+
+        let mut retries = 0;
+        while self.in_flight.len() >= self.depth {
+            retries += self.acknowledge_oldest(connection, checkpoint).await?;
+        }
+        Ok(retries)
+    }
+
+    /// Waits until every outstanding write has been acknowledged.
+    async fn flush<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+        checkpoint: Option<&TransferCheckpoint>,
+    ) -> Result<usize, C::Error> {
+        let mut retries = 0;
+        while !self.in_flight.is_empty() {
+            retries += self.acknowledge_oldest(connection, checkpoint).await?;
+        }
+        Ok(retries)
+    }
+
+    async fn acknowledge_oldest<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+        checkpoint: Option<&TransferCheckpoint>,
+    ) -> Result<usize, C::Error> {
+        let Some((packet, end_offset)) = self.in_flight.pop_front() else {
+            return Ok(0);
+        };
+
+        let acknowledged = match connection
+            .receive_packet::<WriteFileReplyPacket>(self.policy.timeout)
+            .await
+        {
+            Ok(reply) => match reply.try_into_inner() {
+                Ok(()) => true,
+                Err(Cdc2Ack::NackPacketCrc) => {
+                    warn!("File write was damaged on the way. Resending...");
+                    false
+                }
+                Err(nack) => return Err(nack.into()),
+            },
+            Err(e) => {
+                warn!("No reply to file write: {:?}. Resending...", e);
+                false
+            }
+        };
+        if !acknowledged {
+            self.in_flight.push_front((packet, end_offset));
+            return self.resend(connection, checkpoint).await;
+        }
+
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.set_offset(end_offset);
+        }
+        Ok(0)
+    }
+
+    /// Sends every outstanding write again, one at a time.
+    ///
+    /// Write replies don't say which write they answer, so once one is missing or rejected,
+    /// the replies to the writes sent after it can't be told apart from replies to their
+    /// resends. Those are waited out first. Writes are addressed, so sending one again is
+    /// harmless even if the brain did receive it.
+    async fn resend<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+        checkpoint: Option<&TransferCheckpoint>,
+    ) -> Result<usize, C::Error> {
+        for _ in 1..self.in_flight.len() {
+            _ = connection
+                .receive_packet::<WriteFileReplyPacket>(self.policy.timeout)
+                .await;
+        }
+
+        let mut retries = 0;
+        while let Some((packet, end_offset)) = self.in_flight.pop_front() {
+            let (reply, resends) = connection
+                .packet_handshake_with_policy::<WriteFileReplyPacket>(&self.policy, packet)
+                .await?;
+            reply.try_into_inner()?;
+            retries += resends + 1;
+
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.set_offset(end_offset);
+            }
+        }
+        Ok(retries)
     }
 }
 
@@ -146,212 +541,1069 @@ pub struct LinkedFile {
     pub vendor: Option<FileVendor>,
 }
 
+/// Whether an uploaded file was checked against the brain after the transfer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UploadVerification {
+    /// The file was not read back after uploading.
+    #[default]
+    NotVerified,
+    /// The file on the brain matched the uploaded data.
+    Passed,
+    /// The file on the brain did not match the uploaded data.
+    Failed,
+}
+
+/// How an upload is checked against the brain once the transfer has finished.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// The file is not checked.
+    #[default]
+    Off,
+    /// The CRC the brain reports for the file is compared with the uploaded data's.
+    Crc,
+    /// The file is downloaded again and its CRC compared with the uploaded data's.
+    ///
+    /// This is slower than [`Self::Crc`], but doesn't rely on the brain's own bookkeeping.
+    ReadBack,
+}
+
+/// The outcome of an [`UploadFile`] command.
+#[derive(Debug, Clone)]
+pub struct UploadSummary {
+    pub file_name: String,
+    /// Number of file bytes sent to the brain, including alignment padding.
+    pub bytes_sent: usize,
+    /// Whether the transfer was skipped entirely.
+    pub skipped: bool,
+    pub duration: Duration,
+    /// Number of packets that had to be resent during the transfer.
+    pub retries: usize,
+    pub verification: UploadVerification,
+    /// The route the transfer took to the brain, which its settings were tuned for.
+    pub link: LinkPath,
+    /// The largest chunk of file data sent in a single packet.
+    ///
+    /// This is `0` if the transfer was skipped.
+    pub chunk_size: u16,
+}
+impl UploadSummary {
+    /// Effective throughput of the transfer in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        throughput(self.bytes_sent, self.duration)
+    }
+}
+
+/// The outcome of an [`UploadProgram`] command.
+#[derive(Debug, Clone, Default)]
+pub struct ProgramUploadSummary {
+    /// Summaries of every file that was uploaded, in upload order.
+    pub files: Vec<UploadSummary>,
+    /// Names of program files that were skipped because there was no data for them.
+    pub skipped_files: Vec<String>,
+    pub duration: Duration,
+}
+impl ProgramUploadSummary {
+    /// Total number of bytes sent across all files.
+    pub fn bytes_sent(&self) -> usize {
+        self.files.iter().map(|file| file.bytes_sent).sum()
+    }
+
+    /// Total number of packets that had to be resent across all files.
+    pub fn retries(&self) -> usize {
+        self.files.iter().map(|file| file.retries).sum()
+    }
+
+    /// Effective throughput of the whole upload in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        throughput(self.bytes_sent(), self.duration)
+    }
+
+    /// Combined verification result of every uploaded file.
+    ///
+    /// A single failed file fails the whole program.
+    pub fn verification(&self) -> UploadVerification {
+        let results = self.files.iter().map(|file| file.verification);
+        if results.clone().any(|v| v == UploadVerification::Failed) {
+            UploadVerification::Failed
+        } else if !self.files.is_empty()
+            && results.into_iter().all(|v| v == UploadVerification::Passed)
+        {
+            UploadVerification::Passed
+        } else {
+            UploadVerification::NotVerified
+        }
+    }
+}
+
+fn throughput(bytes: usize, duration: Duration) -> f64 {
+    let secs = duration.as_secs_f64();
+    if secs > 0.0 {
+        bytes as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// A reader that an upload can rewind.
+pub trait UploadReader: AsyncRead + AsyncSeek + Unpin + Send {}
+impl<T: AsyncRead + AsyncSeek + Unpin + Send> UploadReader for T {}
+
+/// The contents of a file to upload.
+pub enum UploadData<'a> {
+    /// The whole file, already in memory.
+    Bytes(Vec<u8>),
+    /// A file that is read one chunk at a time while it is uploaded, so it never has to be
+    /// fully in memory.
+    ///
+    /// The reader is read through once to compute the file's CRC, then rewound to upload it.
+    Reader {
+        reader: Box<dyn UploadReader + 'a>,
+        len: u32,
+    },
+}
+impl<'a> UploadData<'a> {
+    /// Uploads the first `len` bytes of `reader`.
+    pub fn from_reader(reader: impl UploadReader + 'a, len: u32) -> Self {
+        Self::Reader {
+            reader: Box::new(reader),
+            len,
+        }
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn len(&self) -> u32 {
+        match self {
+            Self::Bytes(data) => data.len() as u32,
+            Self::Reader { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    async fn crc32(&mut self) -> io::Result<u32> {
+        let (reader, len) = match self {
+            Self::Bytes(data) => return Ok(VEX_CRC32.checksum(data)),
+            Self::Reader { reader, len } => (reader, *len as usize),
+        };
+
+        reader.seek(SeekFrom::Start(0)).await?;
+        let mut digest = VEX_CRC32.digest();
+        let mut buf = vec![0; 64 * 1024];
+        let mut remaining = len;
+        while remaining > 0 {
+            let size = remaining.min(buf.len());
+            reader.read_exact(&mut buf[..size]).await?;
+            digest.update(&buf[..size]);
+            remaining -= size;
+        }
+        Ok(digest.finalize())
+    }
+
+    /// Reads the chunk of up to `size` bytes starting at `offset`, padding a short last chunk
+    /// to a multiple of four bytes.
+    async fn padded_chunk(&mut self, offset: u32, size: u16) -> Result<Vec<u8>, EncodeError> {
+        let mut chunk = self.read_chunk(offset, size as _).await?;
+        if chunk.len() < size as _ && chunk.len() % 4 != 0 {
+            chunk.resize(chunk.len() + (4 - chunk.len() % 4), 0);
+        }
+        Ok(chunk)
+    }
+
+    /// Reads up to `size` bytes starting at `offset`.
+    async fn read_chunk(&mut self, offset: u32, size: usize) -> io::Result<Vec<u8>> {
+        let size = size.min(self.len().saturating_sub(offset) as usize);
+        match self {
+            Self::Bytes(data) => Ok(data[offset as usize..][..size].to_vec()),
+            Self::Reader { reader, .. } => {
+                reader.seek(SeekFrom::Start(offset.into())).await?;
+                let mut chunk = vec![0; size];
+                reader.read_exact(&mut chunk).await?;
+                Ok(chunk)
+            }
+        }
+    }
+}
+impl From<Vec<u8>> for UploadData<'_> {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Bytes(data)
+    }
+}
+
+/// Uploads a file to the brain.
+///
+/// Over a wireless controller link, the radio is switched to the download channel for the
+/// transfer and back to the pit channel afterwards.
 pub struct UploadFile<'a> {
     pub filename: FixedString<23>,
     pub metadata: FileMetadata,
     pub vendor: Option<FileVendor>,
-    pub data: Vec<u8>,
+    pub data: UploadData<'a>,
     pub target: Option<FileTransferTarget>,
     pub load_addr: u32,
     pub linked_file: Option<LinkedFile>,
     pub after_upload: FileExitAction,
+    /// Skip the transfer if a file with the same size, CRC and load address is already on
+    /// the brain.
+    ///
+    /// Only uploads to [`FileTransferTarget::Qspi`] can be skipped. When a transfer is skipped,
+    /// [`FileExitAction::RunProgram`] still runs the file, but other exit actions are not
+    /// performed.
+    pub skip_identical: bool,
+    /// Checks the file on the brain once the transfer has finished.
+    ///
+    /// A file that doesn't match fails the upload with [`CommandError::VerificationFailed`], or
+    /// [`CommandError::SizeMismatch`] if the brain has a different amount of it.
+    /// Only files stored in flash can be verified, and nothing is checked on a dry run or
+    /// when the transfer was skipped. A [`FileExitAction::RunProgram`] exit action waits
+    /// until the file has passed, so a corrupted program is never run.
+    pub verify: VerifyMode,
+    /// If set, the upload resumes from and records its progress to this checkpoint.
+    ///
+    /// On bluetooth, chunks are not acknowledged individually, so progress is recorded
+    /// as soon as a chunk has been sent. Dry runs plan the whole transfer and leave the
+    /// checkpoint alone.
+    pub checkpoint: Option<TransferCheckpoint>,
+    /// If set, nothing is written to the brain and the packets are recorded to the plan instead.
+    pub dry_run: Option<PacketPlan>,
 
-    pub progress_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
+    /// Receives [`TransferStage::Upload`] progress events.
+    pub progress: Option<Box<dyn ProgressSink + 'a>>,
 }
-impl Command for UploadFile<'_> {
-    type Output = ();
-    async fn execute<C: Connection + ?Sized>(
+impl UploadFile<'_> {
+    /// Starts a new transfer of the file, returning the window size the brain gave it.
+    async fn start<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+        vendor: FileVendor,
+        target: FileTransferTarget,
+        crc: u32,
+        retries: &mut usize,
+    ) -> Result<u16, C::Error> {
+        let init_packet = InitFileTransferPacket::new(InitFileTransferPayload {
+            operation: FileInitAction::Write,
+            target,
+            vendor,
+            options: FileInitOption::Overwrite,
+            file_size: self.data.len(),
+            load_address: self.load_addr,
+            write_file_crc: crc,
+            metadata: self.metadata.clone(),
+            file_name: self.filename.clone(),
+        });
+
+        let window_size = if let Some(plan) = &self.dry_run {
+            plan.record(&init_packet)?;
+            // We never talked to the brain, so assume the largest window it would give us.
+            connection.config().transfer_chunk_size
+        } else {
+            let (transfer_response, init_retries) = connection
+                .packet_handshake_with_retries::<InitFileTransferReplyPacket>(init_packet)
+                .await?;
+            *retries += init_retries;
+            debug!("transfer init responded");
+            transfer_response.try_into_inner()?.window_size
+        };
+
+        if let Some(linked_file) = self.linked_file.take() {
+            let link_packet = LinkFilePacket::new(LinkFilePayload {
+                vendor: linked_file.vendor.unwrap_or(FileVendor::User),
+                option: 0,
+                required_file: linked_file.filename,
+            });
+
+            if let Some(plan) = &self.dry_run {
+                plan.record(&link_packet)?;
+            } else {
+                let (reply, link_retries) = connection
+                    .packet_handshake_with_retries::<LinkFileReplyPacket>(link_packet)
+                    .await?;
+                reply.try_into_inner()?;
+                *retries += link_retries;
+            }
+        }
+
+        Ok(window_size)
+    }
+
+    /// Runs the transfer over `link`, leaving radio channel management and tuning to
+    /// [`Command::execute`].
+    pub(super) async fn upload<C: Connection + ?Sized>(
         mut self,
         connection: &mut C,
-    ) -> Result<Self::Output, C::Error> {
+        link: LinkPath,
+    ) -> Result<UploadSummary, C::Error> {
+        let config = connection.config().clone();
         debug!("Uploading file: {}", self.filename);
+        let start = Instant::now();
+        let mut retries = 0;
         let vendor = self.vendor.unwrap_or(FileVendor::User);
         let target = self.target.unwrap_or(FileTransferTarget::Qspi);
 
-        let crc = VEX_CRC32.checksum(&self.data);
+        let crc = self.data.crc32().await.map_err(EncodeError::from)?;
 
-        let transfer_response = connection
-            .packet_handshake::<InitFileTransferReplyPacket>(
-                Duration::from_millis(500),
-                5,
-                InitFileTransferPacket::new(InitFileTransferPayload {
-                    operation: FileInitAction::Write,
-                    target,
-                    vendor,
-                    options: FileInitOption::Overwrite,
-                    file_size: self.data.len() as u32,
-                    load_address: self.load_addr,
-                    write_file_crc: crc,
-                    metadata: self.metadata,
+        // Only files stored in flash have metadata to compare against.
+        if self.skip_identical && matches!(target, FileTransferTarget::Qspi) {
+            let remote = connection
+                .execute_command(GetFileMetadata {
                     file_name: self.filename.clone(),
-                }),
-            )
-            .await?;
-        debug!("transfer init responded");
-        let transfer_response = transfer_response.try_into_inner()?;
+                    vendor,
+                })
+                .await?;
 
-        if let Some(linked_file) = self.linked_file {
-            connection
-                .packet_handshake::<LinkFileReplyPacket>(
-                    Duration::from_millis(500),
-                    5,
-                    LinkFilePacket::new(LinkFilePayload {
-                        vendor: linked_file.vendor.unwrap_or(FileVendor::User),
-                        option: 0,
-                        required_file: linked_file.filename,
-                    }),
+            let identical = remote.is_some_and(|remote| {
+                let linked_vendor = self
+                    .linked_file
+                    .as_ref()
+                    .map(|linked| linked.vendor.unwrap_or(FileVendor::User));
+
+                remote.size == self.data.len()
+                    && remote.crc32 == crc
+                    && remote.load_address == self.load_addr
+                    && (linked_vendor.is_none() || remote.linked_vendor == linked_vendor)
+            });
+
+            if identical {
+                debug!("Skipping upload of {}: brain already has it", self.filename);
+                if matches!(self.after_upload, FileExitAction::RunProgram) {
+                    let run_packet = LoadFileActionPacket::new(LoadFileActionPayload {
+                        vendor,
+                        action: FileLoadAction::Run,
+                        file_name: self.filename.clone(),
+                    });
+                    if let Some(plan) = &self.dry_run {
+                        plan.record(&run_packet)?;
+                    } else {
+                        connection
+                            .packet_handshake::<LoadFileActionReplyPacket>(run_packet)
+                            .await?
+                            .try_into_inner()?;
+                    }
+                }
+                let total_bytes = self.data.len();
+                ProgressTracker::new(
+                    self.progress.take(),
+                    TransferStage::Upload,
+                    total_bytes,
+                    total_bytes,
                 )
-                .await?
-                .try_into_inner()?;
+                .finish();
+
+                return Ok(UploadSummary {
+                    file_name: self.filename.into_inner(),
+                    bytes_sent: 0,
+                    skipped: true,
+                    duration: start.elapsed(),
+                    retries,
+                    verification: UploadVerification::NotVerified,
+                    link,
+                    chunk_size: 0,
+                });
+            }
+        }
+
+        let mut resume_from = 0;
+        let mut offset = 0;
+        let mut window_size = None;
+        if self.dry_run.is_some() {
+            self.checkpoint = None;
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.bind(CheckpointedTransfer {
+                file_name: self.filename.to_string(),
+                vendor,
+                size: self.data.len(),
+                crc,
+            })?;
+
+            let checkpointed = checkpoint.offset().min(self.data.len());
+            if checkpointed > 0 && checkpointed < self.data.len() {
+                // A checkpoint made with `resume_from` never saw the brain's window size.
+                let resumed_window = match checkpoint.window_size() {
+                    0 => config.transfer_chunk_size,
+                    window_size => window_size,
+                };
+                // Writing the next chunk tells whether the brain still has the transfer open.
+                let chunk_size = max_chunk_size(
+                    connection.connection_type(),
+                    resumed_window,
+                    config.transfer_chunk_size,
+                );
+                let chunk = self.data.padded_chunk(checkpointed, chunk_size).await?;
+                let chunk_len = chunk.len() as u32;
+                let (reply, write_retries) = connection
+                    .packet_handshake_with_retries::<WriteFileReplyPacket>(WriteFilePacket::new(
+                        WriteFilePayload {
+                            address: (self.load_addr + checkpointed) as _,
+                            chunk_data: chunk,
+                        },
+                    ))
+                    .await?;
+                retries += write_retries;
+                match reply.try_into_inner() {
+                    Ok(_) => {
+                        debug!("Resuming upload from offset {}", checkpointed);
+                        resume_from = checkpointed;
+                        offset = checkpointed + chunk_len;
+                        checkpoint.set_offset(offset);
+                        window_size = Some(resumed_window);
+                    }
+                    Err(Cdc2Ack::NackUninitializedTransfer) => {
+                        debug!("The brain closed the interrupted upload, starting over");
+                        checkpoint.set_offset(0);
+                    }
+                    Err(nack) => return Err(nack.into()),
+                }
+            }
         }
 
-        let window_size = transfer_response.window_size;
+        let window_size = match window_size {
+            Some(window_size) => window_size,
+            None => {
+                let window_size = self
+                    .start(connection, vendor, target, crc, &mut retries)
+                    .await?;
+                if let Some(checkpoint) = &self.checkpoint {
+                    checkpoint.set_window_size(window_size);
+                }
+                window_size
+            }
+        };
 
         // The maximum packet size is 244 bytes for bluetooth
-        let max_chunk_size = max_chunk_size(connection.connection_type(), window_size);
+        let max_chunk_size = max_chunk_size(
+            connection.connection_type(),
+            window_size,
+            config.transfer_chunk_size,
+        );
 
         debug!("max_chunk_size: {}", max_chunk_size);
 
-        let mut offset = 0;
-        for chunk in self.data.chunks(max_chunk_size as _) {
-            let chunk = if chunk.len() < max_chunk_size as _ && chunk.len() % 4 != 0 {
-                let mut new_chunk = Vec::new();
-                new_chunk.extend_from_slice(chunk);
-                new_chunk.resize(chunk.len() + (4 - chunk.len() % 4), 0);
-                new_chunk
-            } else {
-                chunk.to_vec()
-            };
+        let mut progress = ProgressTracker::new(
+            self.progress.take(),
+            TransferStage::Upload,
+            self.data.len(),
+            resume_from,
+        );
+        let mut write_window = WriteWindow::new(&config)?;
+        while offset < self.data.len() {
+            let chunk = self.data.padded_chunk(offset, max_chunk_size).await?;
             trace!("sending chunk of size: {}", chunk.len());
-            let progress = (offset as f32 / self.data.len() as f32) * 100.0;
-            if let Some(callback) = &mut self.progress_callback {
-                callback(progress);
-            }
+            progress.report(offset);
 
             let packet = WriteFilePacket::new(WriteFilePayload {
                 address: (self.load_addr + offset) as _,
                 chunk_data: chunk.clone(),
             });
 
+            offset += chunk.len() as u32;
+
             // On bluetooth, we dont wait for the reply
-            if connection.connection_type() == ConnectionType::Bluetooth {
+            if let Some(plan) = &self.dry_run {
+                plan.record(&packet)?;
+            } else if connection.connection_type() == ConnectionType::Bluetooth {
                 connection.send_packet(packet).await?;
             } else {
-                connection
-                    .packet_handshake::<WriteFileReplyPacket>(Duration::from_millis(500), 5, packet)
-                    .await?
-                    .try_into_inner()?;
+                retries += write_window
+                    .send(connection, packet, offset, self.checkpoint.as_ref())
+                    .await?;
+                continue;
             }
 
-            offset += chunk.len() as u32;
+            if let Some(checkpoint) = &self.checkpoint {
+                checkpoint.set_offset(offset);
+            }
+        }
+        retries += write_window
+            .flush(connection, self.checkpoint.as_ref())
+            .await?;
+        progress.finish();
+
+        // Only files stored in flash have a CRC to check against.
+        let verifying = self.dry_run.is_none()
+            && matches!(target, FileTransferTarget::Qspi)
+            && !matches!(self.verify, VerifyMode::Off);
+        // A program that is being verified isn't run until it has passed.
+        let run_after_verifying =
+            verifying && matches!(self.after_upload, FileExitAction::RunProgram);
+        let exit_packet = ExitFileTransferPacket::new(if run_after_verifying {
+            FileExitAction::DoNothing
+        } else {
+            self.after_upload
+        });
+        if let Some(plan) = &self.dry_run {
+            plan.record(&exit_packet)?;
+            debug!("Planned upload of file: {}", self.filename);
+        } else {
+            // Exiting can take a moment while the brain finalizes the file.
+            let mut exit_policy = *config.retry_policy_for(&exit_packet.encode()?);
+            exit_policy.timeout *= 2;
+
+            let (reply, exit_retries) = connection
+                .packet_handshake_with_policy::<ExitFileTransferReplyPacket>(
+                    &exit_policy,
+                    exit_packet,
+                )
+                .await?;
+            reply.try_into_inner()?;
+            retries += exit_retries;
+
+            debug!("Successfully uploaded file: {}", self.filename);
         }
-        if let Some(callback) = &mut self.progress_callback {
-            callback(100.0);
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.reset();
         }
 
-        connection
-            .packet_handshake::<ExitFileTransferReplyPacket>(
-                Duration::from_millis(1000),
-                5,
-                ExitFileTransferPacket::new(self.after_upload),
+        let verification = if verifying {
+            verify_upload(
+                connection,
+                self.verify,
+                &self.filename,
+                vendor,
+                self.load_addr,
+                self.data.len(),
+                crc,
             )
             .await?
-            .try_into_inner()?;
+        } else {
+            UploadVerification::NotVerified
+        };
+        if run_after_verifying {
+            connection
+                .packet_handshake::<LoadFileActionReplyPacket>(LoadFileActionPacket::new(
+                    LoadFileActionPayload {
+                        vendor,
+                        action: FileLoadAction::Run,
+                        file_name: self.filename.clone(),
+                    },
+                ))
+                .await?
+                .try_into_inner()?;
+        }
 
-        debug!("Successfully uploaded file: {}", self.filename.into_inner());
-        Ok(())
+        Ok(UploadSummary {
+            file_name: self.filename.into_inner(),
+            bytes_sent: (offset - resume_from) as usize,
+            skipped: false,
+            duration: start.elapsed(),
+            retries,
+            verification,
+            link,
+            chunk_size: max_chunk_size,
+        })
     }
 }
+impl Command for UploadFile<'_> {
+    type Output = UploadSummary;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let link = connection.execute_command(GetLinkPath).await?;
+        let switched = self.dry_run.is_none() && use_download_channel(connection, link).await?;
+        let config = connection.config().tuned_for(link);
+        let result = self
+            .upload(&mut connection.override_config(config), link)
+            .await;
+        restore_pit_channel(connection, switched, result).await
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        self.dry_run.is_none()
+    }
+}
+
+/// Checks an uploaded file against `expected`, the CRC of the data that was sent.
+async fn verify_upload<C: Connection + ?Sized>(
+    connection: &mut C,
+    mode: VerifyMode,
+    file_name: &FixedString<23>,
+    vendor: FileVendor,
+    load_addr: u32,
+    size: u32,
+    expected: u32,
+) -> Result<UploadVerification, C::Error> {
+    let actual = match mode {
+        VerifyMode::Off => return Ok(UploadVerification::NotVerified),
+        VerifyMode::Crc => connection
+            .execute_command(GetFileMetadata {
+                file_name: file_name.clone(),
+                vendor,
+            })
+            .await?
+            .map(|remote| {
+                // A truncated file could still happen to share the CRC.
+                if remote.size != size {
+                    return Err(CommandError::SizeMismatch {
+                        file_name: file_name.to_string(),
+                        expected: size,
+                        actual: remote.size,
+                    });
+                }
+                Ok(remote.crc32)
+            })
+            .transpose()?,
+        VerifyMode::ReadBack => {
+            // The upload has already tuned the connection to its link.
+            let data = DownloadFile {
+                file_name: file_name.clone(),
+                size,
+                vendor,
+                target: None,
+                load_addr,
+                checkpoint: None,
+                sink: None,
+                progress: None,
+            }
+            .download(connection)
+            .await?;
+            Some(VEX_CRC32.checksum(&data))
+        }
+    };
+
+    if actual != Some(expected) {
+        return Err(CommandError::VerificationFailed {
+            file_name: file_name.to_string(),
+            expected,
+            actual,
+        }
+        .into());
+    }
+    debug!("Verified upload of file: {}", file_name);
+    Ok(UploadVerification::Passed)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ProgramData {
+    #[cfg_attr(feature = "serde_bytes", serde(with = "serde_bytes"))]
+    Monolith(Vec<u8>),
+    HotCold {
+        #[cfg_attr(feature = "serde_bytes", serde(with = "serde_bytes"))]
+        hot: Option<Vec<u8>>,
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum ProgramData {
-    #[cfg_attr(feature = "serde_bytes", serde(with = "serde_bytes"))]
-    Monolith(Vec<u8>),
-    HotCold {
-        #[cfg_attr(feature = "serde_bytes", serde(with = "serde_bytes"))]
-        hot: Option<Vec<u8>>,
-
         #[cfg_attr(feature = "serde_bytes", serde(with = "serde_bytes"))]
         cold: Option<Vec<u8>>,
     },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The longest program name, in bytes, that [`ProgramIniBuilder`] accepts.
+///
+/// The brain reports a program's name with a one byte length that includes its terminator.
+pub const MAX_PROGRAM_NAME_LEN: usize = 254;
+/// The longest program description, in bytes, that [`ProgramIniBuilder`] accepts.
+pub const MAX_PROGRAM_DESCRIPTION_LEN: usize = 255;
+/// The `ide` values written by tools this crate knows about.
+///
+/// [`ProgramIniBuilder`] accepts other values, but logs a warning for them.
+pub const KNOWN_IDES: &[&str] = &["PROS", "vexide", "vex5"];
+
+/// The `[program]` section of a program's ini file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Program {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
     pub name: String,
+    /// The zero-based index of the slot the program is shown in. See [`Slot::index`].
     pub slot: u8,
     pub icon: String,
     pub iconalt: String,
     pub description: String,
+    /// When the program was built, in whatever format the tool that wrote it chose.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
 }
-#[derive(Serialize, Deserialize, Debug)]
+/// The `[project]` section of a program's ini file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Project {
-    // version: String,
+    /// The version of the tool that wrote the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The tool that wrote the file. See [`KNOWN_IDES`].
     pub ide: String,
-    // file: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The ini file uploaded next to a program, which tells the brain how to show it.
+///
+/// Use [`ProgramIniConfig::builder`] to create one with validated fields.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProgramIniConfig {
     pub project: Project,
     pub program: Program,
 }
+impl ProgramIniConfig {
+    /// Starts building the ini file for a program named `name`, written by `ide`.
+    pub fn builder(
+        name: impl Into<String>,
+        slot: Slot,
+        ide: impl Into<String>,
+    ) -> ProgramIniBuilder {
+        ProgramIniBuilder {
+            name: name.into(),
+            slot,
+            ide: ide.into(),
+            ide_version: None,
+            version: None,
+            icon: "USER029x.bmp".to_string(),
+            icon_alt: String::new(),
+            description: String::new(),
+            date: None,
+        }
+    }
 
-pub struct UploadProgram<'a> {
-    pub name: String,
-    pub description: String,
-    pub icon: String,
-    pub program_type: String,
-    /// 0-indexed slot
-    pub slot: u8,
-    pub compress_program: bool,
-    pub data: ProgramData,
-    pub after_upload: FileExitAction,
-
-    /// Called when progress has been made on the ini file.
+    /// Serializes the config into the ini format expected by VEXos.
     ///
-    /// 100.0 should be considered a finished upload.
-    pub ini_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
-    /// Called when progress has been made on the monolith/hot binary
+    /// Returns [`EncodeError::InvalidIni`] if the serializer rejects the config.
+    pub fn to_ini(&self) -> Result<Vec<u8>, EncodeError> {
+        serde_ini::to_vec(self).map_err(|e| EncodeError::InvalidIni(e.to_string()))
+    }
+}
+
+/// Builds a [`ProgramIniConfig`], checking its fields before anything is sent to the brain.
+///
+/// Created with [`ProgramIniConfig::builder`].
+#[derive(Debug, Clone)]
+pub struct ProgramIniBuilder {
+    name: String,
+    slot: Slot,
+    ide: String,
+    ide_version: Option<String>,
+    version: Option<String>,
+    icon: String,
+    icon_alt: String,
+    description: String,
+    date: Option<String>,
+}
+impl ProgramIniBuilder {
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Sets the icon shown for the program. This defaults to `USER029x.bmp`.
     ///
-    /// 100.0 should be considered a finished upload.
-    pub bin_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
-    /// Called when progress has been made on the cold library binary
+    /// See [`UploadProgram::icon`] for which icons can be used.
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.icon = icon.into();
+        self
+    }
+
+    pub fn icon_alt(mut self, icon_alt: impl Into<String>) -> Self {
+        self.icon_alt = icon_alt.into();
+        self
+    }
+
+    /// Sets the version of the tool writing the file.
+    pub fn ide_version(mut self, version: impl Into<String>) -> Self {
+        self.ide_version = Some(version.into());
+        self
+    }
+
+    /// Sets the version of the program itself.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Sets when the program was built.
+    pub fn date(mut self, date: impl Into<String>) -> Self {
+        self.date = Some(date.into());
+        self
+    }
+
+    /// Checks the fields and builds the config.
     ///
-    /// 100.0 should be considered a finished upload.
-    pub lib_callback: Option<Box<dyn FnMut(f32) + Send + 'a>>,
+    /// Fails with [`EncodeError::StringTooLong`] if the name or description is longer than
+    /// [`MAX_PROGRAM_NAME_LEN`] or [`MAX_PROGRAM_DESCRIPTION_LEN`], and with
+    /// [`EncodeError::InvalidIni`] if a value contains a control character. Ini files have no
+    /// way to escape line breaks, so a value containing one would otherwise spill into keys of
+    /// its own.
+    pub fn build(self) -> Result<ProgramIniConfig, EncodeError> {
+        if self.name.len() > MAX_PROGRAM_NAME_LEN
+            || self.description.len() > MAX_PROGRAM_DESCRIPTION_LEN
+        {
+            return Err(EncodeError::StringTooLong);
+        }
+        let values = [
+            ("name", Some(&self.name)),
+            ("description", Some(&self.description)),
+            ("icon", Some(&self.icon)),
+            ("iconalt", Some(&self.icon_alt)),
+            ("ide", Some(&self.ide)),
+            ("project version", self.ide_version.as_ref()),
+            ("program version", self.version.as_ref()),
+            ("date", self.date.as_ref()),
+        ];
+        for (key, value) in values {
+            if value.is_some_and(|value| value.chars().any(char::is_control)) {
+                return Err(EncodeError::InvalidIni(format!(
+                    "{} contains a control character",
+                    key
+                )));
+            }
+        }
+
+        if !KNOWN_IDES.contains(&self.ide.as_str()) {
+            warn!("Unknown IDE {:?} in program ini file", self.ide);
+        }
+
+        Ok(ProgramIniConfig {
+            project: Project {
+                version: self.ide_version,
+                ide: self.ide,
+            },
+            program: Program {
+                version: self.version,
+                name: self.name,
+                slot: self.slot.index(),
+                icon: self.icon,
+                iconalt: self.icon_alt,
+                description: self.description,
+                date: self.date,
+            },
+        })
+    }
 }
-impl Command for UploadProgram<'_> {
-    type Output = ();
+
+/// A hook that turns a program's [`ProgramIniConfig`] into the contents of its ini file.
+///
+/// Different runtimes expect different fields in the project ini, so this can be used
+/// to add extra sections or keys, or to replace the serializer entirely.
+pub type IniSerializer<'a> = Box<dyn FnOnce(&ProgramIniConfig) -> Vec<u8> + Send + 'a>;
+
+/// Reads the metadata of a file on the brain.
+///
+/// Returns `None` if the file does not exist.
+pub struct GetFileMetadata {
+    pub file_name: FixedString<23>,
+    pub vendor: FileVendor,
+}
+impl Command for GetFileMetadata {
+    type Output = Option<GetFileMetadataReplyPayload>;
 
     async fn execute<C: Connection + ?Sized>(
-        mut self,
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let reply = connection
+            .packet_handshake::<GetFileMetadataReplyPacket>(GetFileMetadataPacket::new(
+                GetFileMetadataPayload {
+                    vendor: self.vendor,
+                    option: 0,
+                    file_name: self.file_name,
+                },
+            ))
+            .await?
+            .try_into_inner()?;
+
+        Ok(reply)
+    }
+}
+
+/// Lists every file stored under a vendor.
+///
+/// Each entry includes the file's name, size, CRC, and metadata (including its timestamp).
+pub struct ListFiles {
+    pub vendor: FileVendor,
+}
+impl Command for ListFiles {
+    type Output = Vec<GetDirectoryEntryReplyPayload>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
         connection: &mut C,
     ) -> Result<Self::Output, C::Error> {
-        let base_file_name = format!("slot_{}", self.slot);
+        let count = directory_file_count(connection, self.vendor).await?;
+        debug!("Listing {} files for vendor {:?}", count, self.vendor);
 
-        debug!("Uploading program ini file");
+        let mut files = Vec::with_capacity(count as usize);
+        for file_index in 0..count {
+            // Entries can disappear if a file is erased while listing.
+            if let Some(entry) = directory_entry(connection, file_index).await? {
+                files.push(entry);
+            }
+        }
 
-        let ini = ProgramIniConfig {
-            program: Program {
-                description: self.description,
-                icon: self.icon,
-                iconalt: String::new(),
-                slot: self.slot,
-                name: self.name,
-            },
-            project: Project {
-                ide: self.program_type,
+        Ok(files)
+    }
+}
+
+/// Returns how many files are stored under a vendor.
+///
+/// The brain builds the listing that [`directory_entry`] reads from when the count is
+/// requested, so this always has to come first.
+pub(crate) async fn directory_file_count<C: Connection + ?Sized>(
+    connection: &mut C,
+    vendor: FileVendor,
+) -> Result<u16, C::Error> {
+    Ok(connection
+        .packet_handshake::<GetDirectoryFileCountReplyPacket>(GetDirectoryFileCountPacket::new(
+            GetDirectoryFileCountPayload { vendor, option: 0 },
+        ))
+        .await?
+        .try_into_inner()?)
+}
+
+/// Returns an entry from the listing built by [`directory_file_count`], or `None` if it has
+/// disappeared since.
+pub(crate) async fn directory_entry<C: Connection + ?Sized>(
+    connection: &mut C,
+    file_index: u16,
+) -> Result<Option<GetDirectoryEntryReplyPayload>, C::Error> {
+    Ok(connection
+        .packet_handshake::<GetDirectoryEntryReplyPacket>(GetDirectoryEntryPacket::new(
+            GetDirectoryEntryPayload {
+                file_index: file_index as u8,
+                unknown: 0,
             },
+        ))
+        .await?
+        .try_into_inner()?)
+}
+
+/// A file found by [`ListVendorFiles`], along with the vendor it is stored under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VendorFileEntry {
+    pub vendor: FileVendor,
+    pub entry: GetDirectoryEntryReplyPayload,
+}
+
+/// Lists the files stored under several vendors.
+///
+/// Programs uploaded by different tools live under different vendors, so a [`ListFiles`] of
+/// [`FileVendor::User`] alone misses programs uploaded with PROS, for example.
+#[derive(Debug, Clone)]
+pub struct ListVendorFiles {
+    pub vendors: Vec<FileVendor>,
+}
+impl Default for ListVendorFiles {
+    /// Lists the vendors that programs are commonly uploaded under.
+    fn default() -> Self {
+        Self {
+            vendors: vec![FileVendor::User, FileVendor::PROS, FileVendor::MW],
+        }
+    }
+}
+impl Command for ListVendorFiles {
+    type Output = Vec<VendorFileEntry>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let mut files = Vec::new();
+        for vendor in self.vendors {
+            let entries = connection.execute_command(ListFiles { vendor }).await?;
+            files.extend(
+                entries
+                    .into_iter()
+                    .map(|entry| VendorFileEntry { vendor, entry }),
+            );
+        }
+        Ok(files)
+    }
+}
+
+/// The sections of an ini file, each mapping keys to values.
+pub type IniSections = BTreeMap<String, BTreeMap<String, String>>;
+
+/// An ini file stored on the brain, parsed into `T`.
+///
+/// By default the file is parsed into untyped [`IniSections`], but any type that can be
+/// (de)serialized with `serde_ini` can be used instead.
+#[derive(Debug, Clone)]
+pub struct IniFile<T = IniSections> {
+    pub file_name: FixedString<23>,
+    pub vendor: FileVendor,
+    pub contents: T,
+    /// The metadata of the file when it was downloaded, reused when writing it back.
+    pub file_metadata: Option<GetFileMetadataReplyPayload>,
+}
+impl<T: Serialize + DeserializeOwned> IniFile<T> {
+    /// Creates an ini file that does not exist on the brain yet.
+    pub fn new(file_name: FixedString<23>, vendor: FileVendor, contents: T) -> Self {
+        Self {
+            file_name,
+            vendor,
+            contents,
+            file_metadata: None,
+        }
+    }
+
+    /// Downloads and parses an ini file from the brain.
+    ///
+    /// Returns `None` if the file does not exist.
+    pub async fn download<C: Connection + ?Sized>(
+        connection: &mut C,
+        file_name: FixedString<23>,
+        vendor: FileVendor,
+    ) -> Result<Option<Self>, C::Error> {
+        let Some(file_metadata) = connection
+            .execute_command(GetFileMetadata {
+                file_name: file_name.clone(),
+                vendor,
+            })
+            .await?
+        else {
+            return Ok(None);
         };
 
-        connection
-            .execute_command(UploadFile {
-                filename: FixedString::new(format!("{}.ini", base_file_name))?,
-                metadata: FileMetadata {
+        let data = connection
+            .execute_command(DownloadFile {
+                file_name: file_name.clone(),
+                size: file_metadata.size,
+                vendor,
+                target: None,
+                load_addr: file_metadata.load_address,
+                checkpoint: None,
+                sink: None,
+                progress: None,
+            })
+            .await?;
+
+        let mut ini = Self::from_bytes(file_name, vendor, data)?;
+        ini.file_metadata = Some(file_metadata);
+        Ok(Some(ini))
+    }
+
+    /// Parses the contents of an ini file that has already been downloaded.
+    ///
+    /// The result has no [`file_metadata`](Self::file_metadata), so writing it back uploads
+    /// it like a new file.
+    pub fn from_bytes(
+        file_name: FixedString<23>,
+        vendor: FileVendor,
+        data: Vec<u8>,
+    ) -> Result<Self, DecodeError> {
+        let text = String::from_utf8(data).map_err(|e| DecodeError::from(e.utf8_error()))?;
+        let contents =
+            serde_ini::from_str(&text).map_err(|e| DecodeError::InvalidIni(e.to_string()))?;
+        Ok(Self::new(file_name, vendor, contents))
+    }
+
+    /// Serializes the contents and writes them back to the brain, replacing the file.
+    pub async fn upload<C: Connection + ?Sized>(
+        &self,
+        connection: &mut C,
+    ) -> Result<UploadSummary, C::Error> {
+        let data = serde_ini::to_vec(&self.contents)
+            .map_err(|e| EncodeError::InvalidIni(e.to_string()))?;
+
+        let (load_addr, metadata) = match &self.file_metadata {
+            Some(existing) => (
+                existing.load_address,
+                FileMetadata {
+                    timestamp: J2000Timestamp::now(),
+                    ..existing.metadata.clone()
+                },
+            ),
+            None => (
+                USER_PROGRAM_LOAD_ADDR,
+                FileMetadata {
                     extension: FixedString::new("ini".to_string())?,
                     extension_type: ExtensionType::default(),
-                    timestamp: j2000_timestamp(),
+                    timestamp: J2000Timestamp::now(),
                     version: Version {
                         major: 1,
                         minor: 0,
@@ -359,26 +1611,249 @@ impl Command for UploadProgram<'_> {
                         beta: 0,
                     },
                 },
-                vendor: None,
-                data: serde_ini::to_vec(&ini).unwrap(),
+            ),
+        };
+
+        connection
+            .execute_command(UploadFile {
+                filename: self.file_name.clone(),
+                metadata,
+                vendor: Some(self.vendor),
+                data: data.into(),
                 target: None,
-                load_addr: USER_PROGRAM_LOAD_ADDR,
+                load_addr,
                 linked_file: None,
                 after_upload: FileExitAction::DoNothing,
-                progress_callback: self.ini_callback.take(),
+                skip_identical: false,
+                verify: VerifyMode::Off,
+                checkpoint: None,
+                dry_run: None,
+                progress: None,
             })
-            .await?;
+            .await
+    }
+}
+
+/// Determines the name and vendor of the cold library that hot/cold programs are linked against.
+#[derive(Debug, Clone, Default)]
+pub enum LinkStrategy {
+    /// `slot_N_lib.bin` in the user vendor.
+    #[default]
+    SlotSuffix,
+    /// PROS-style `slotN_lib.bin` in the user vendor.
+    Pros,
+    /// A single library shared between every slot.
+    Shared {
+        file_name: String,
+        vendor: FileVendor,
+    },
+    /// A per-slot library name, where `{slot}` in the template is replaced with the slot number.
+    Custom {
+        template: String,
+        vendor: FileVendor,
+    },
+}
+impl LinkStrategy {
+    /// Returns the file name of the library linked to the program in the given slot.
+    pub fn library_file_name(&self, slot: Slot) -> String {
+        match self {
+            Self::SlotSuffix => format!("slot_{slot}_lib.bin"),
+            Self::Pros => format!("slot{slot}_lib.bin"),
+            Self::Shared { file_name, .. } => file_name.clone(),
+            Self::Custom { template, .. } => template.replace("{slot}", &slot.to_string()),
+        }
+    }
+
+    /// Returns the vendor that the library is stored under.
+    pub fn vendor(&self) -> FileVendor {
+        match self {
+            Self::SlotSuffix | Self::Pros => FileVendor::User,
+            Self::Shared { vendor, .. } | Self::Custom { vendor, .. } => *vendor,
+        }
+    }
+}
+
+/// Where a program's binaries are loaded, and how its library is named, following the
+/// conventions of the runtime that built it.
+#[derive(Debug, Clone, Default)]
+pub enum UploadStrategy {
+    /// PROS. Monolithic programs are loaded at [`USER_PROGRAM_LOAD_ADDR`]. Hot/cold programs
+    /// load their cold library, `slotN_lib.bin`, there instead, and the hot program at
+    /// [`PROS_HOT_BIN_LOAD_ADDR`].
+    Pros,
+    /// VEXcode, which builds monolithic programs. This is the same as [`Self::Monolith`].
+    Vexcode,
+    /// vexide, and the layout this crate has always used. Programs are loaded at
+    /// [`USER_PROGRAM_LOAD_ADDR`], and hot/cold programs load their library, `slot_N_lib.bin`,
+    /// at [`PROS_HOT_BIN_LOAD_ADDR`].
+    #[default]
+    Vexide,
+    /// A single binary loaded at [`USER_PROGRAM_LOAD_ADDR`], with no library.
+    Monolith,
+    /// Anything else.
+    Custom {
+        program_load_addr: u32,
+        library_load_addr: u32,
+        link_strategy: LinkStrategy,
+    },
+}
+impl UploadStrategy {
+    /// Returns where the program binary is loaded, depending on whether it has a library.
+    pub fn program_load_addr(&self, linked: bool) -> u32 {
+        match self {
+            Self::Pros if linked => PROS_HOT_BIN_LOAD_ADDR,
+            Self::Custom {
+                program_load_addr, ..
+            } => *program_load_addr,
+            _ => USER_PROGRAM_LOAD_ADDR,
+        }
+    }
+
+    /// Returns how a program's library is named and where it is loaded, or `None` if this
+    /// strategy has no libraries.
+    pub fn library(&self) -> Option<(LinkStrategy, u32)> {
+        match self {
+            Self::Pros => Some((LinkStrategy::Pros, USER_PROGRAM_LOAD_ADDR)),
+            Self::Vexide => Some((LinkStrategy::SlotSuffix, PROS_HOT_BIN_LOAD_ADDR)),
+            Self::Vexcode | Self::Monolith => None,
+            Self::Custom {
+                library_load_addr,
+                link_strategy,
+                ..
+            } => Some((link_strategy.clone(), *library_load_addr)),
+        }
+    }
+}
+
+/// Uploads a program's ini file and binaries to a slot.
+///
+/// Like [`UploadFile`], this moves a wireless controller link onto the download channel and
+/// tunes the connection's config to the link. Both happen once, for the whole upload.
+///
+/// The ini file is built with [`ProgramIniBuilder`], so an invalid name, description or slot
+/// fails the upload before anything is sent.
+pub struct UploadProgram<'a> {
+    pub name: String,
+    pub description: String,
+    /// The icon shown for the program, such as `USER029x.bmp`.
+    ///
+    /// This must be one of the icons built into VEXos. The brain only keeps the number in
+    /// the icon's name (see [`Slot::icon_number`](crate::packets::program::Slot::icon_number)),
+    /// so there is no way to give a program an icon of its own.
+    pub icon: String,
+    /// The tool that built the program, written to the ini file's `ide` key.
+    pub program_type: String,
+    pub slot: Slot,
+    /// Compress the binaries with gzip before uploading them.
+    ///
+    /// Fails with [`CommandError::Unsupported`] if the brain's VEXos is too old to decompress
+    /// them.
+    pub compress_program: bool,
+    pub data: ProgramData,
+    pub after_upload: FileExitAction,
+    /// Where the binaries are loaded, and the naming and vendor of the cold library for
+    /// hot/cold programs.
+    ///
+    /// Uploading a [`ProgramData::HotCold`] program with a strategy that has no libraries
+    /// fails with [`EncodeError::LibraryNotSupported`], before anything is sent.
+    pub strategy: UploadStrategy,
+    /// Skip uploading files that are already on the brain. See [`UploadFile::skip_identical`].
+    pub skip_identical: bool,
+    /// Checks each file on the brain after it is uploaded. See [`UploadFile::verify`].
+    pub verify: VerifyMode,
+    /// If set, nothing is written to the brain and the packets are recorded to the plan instead.
+    pub dry_run: Option<PacketPlan>,
+    /// Overrides how the program's ini file is generated.
+    ///
+    /// If this is `None`, [`ProgramIniConfig::to_ini`] is used.
+    pub ini_serializer: Option<IniSerializer<'a>>,
+
+    /// Receives progress events for each file, labeled [`TransferStage::ProgramIni`],
+    /// [`TransferStage::ProgramLibrary`] or [`TransferStage::ProgramBinary`].
+    pub progress: Option<Box<dyn ProgressSink + 'a>>,
+}
+impl UploadProgram<'_> {
+    /// Uploads the ini file and binaries over `link`, leaving radio channel management and
+    /// tuning the connection to the link to [`Command::execute`].
+    async fn upload<C: Connection + ?Sized>(
+        mut self,
+        connection: &mut C,
+        link: LinkPath,
+    ) -> Result<ProgramUploadSummary, C::Error> {
+        let start = Instant::now();
+        let mut summary = ProgramUploadSummary::default();
+        let base_file_name = self.slot.file_stem();
+
+        if self.compress_program {
+            connection
+                .execute_command(GetCapabilities)
+                .await?
+                .require(Feature::CompressedUploads)
+                .map_err(CommandError::from)?;
+        }
+
+        let library = match self.data {
+            ProgramData::Monolith(_) => None,
+            ProgramData::HotCold { .. } => Some(
+                self.strategy
+                    .library()
+                    .ok_or(EncodeError::LibraryNotSupported)?,
+            ),
+        };
+
+        debug!("Uploading program ini file");
+
+        let ini = ProgramIniConfig::builder(self.name, self.slot, self.program_type)
+            .description(self.description)
+            .icon(self.icon)
+            .build()?;
+
+        let ini_data = match self.ini_serializer.take() {
+            Some(serializer) => serializer(&ini),
+            None => ini.to_ini()?,
+        };
+
+        let ini_summary = UploadFile {
+            filename: FixedString::new(format!("{}.ini", base_file_name))?,
+            metadata: FileMetadata {
+                extension: FixedString::new("ini".to_string())?,
+                extension_type: ExtensionType::default(),
+                timestamp: J2000Timestamp::now(),
+                version: Version {
+                    major: 1,
+                    minor: 0,
+                    build: 0,
+                    beta: 0,
+                },
+            },
+            vendor: None,
+            data: ini_data.into(),
+            target: None,
+            load_addr: USER_PROGRAM_LOAD_ADDR,
+            linked_file: None,
+            after_upload: FileExitAction::DoNothing,
+            skip_identical: self.skip_identical,
+            verify: self.verify,
+            checkpoint: None,
+            dry_run: self.dry_run.clone(),
+            progress: staged(&mut self.progress, TransferStage::ProgramIni),
+        }
+        .upload(connection, link)
+        .await?;
+        summary.files.push(ini_summary);
 
         let program_bin_name = format!("{base_file_name}.bin");
-        let program_lib_name = format!("{base_file_name}_lib.bin");
+        let program_load_addr = self.strategy.program_load_addr(library.is_some());
 
-        let is_monolith = matches!(self.data, ProgramData::Monolith(_));
+        let is_monolith = library.is_none();
         let (program_data, library_data) = match self.data {
             ProgramData::HotCold { hot, cold } => (hot, cold),
             ProgramData::Monolith(data) => (Some(data), None),
         };
 
-        if let Some(mut library_data) = library_data {
+        if let (Some(mut library_data), Some((link_strategy, library_load_addr))) =
+            (library_data, &library)
+        {
             debug!("Uploading cold library binary");
 
             // Compress the file to improve upload times
@@ -389,34 +1864,43 @@ impl Command for UploadProgram<'_> {
                 debug!("Compression complete");
             }
 
-            connection
-                .execute_command(UploadFile {
-                    filename: FixedString::new(program_lib_name.clone())?,
-                    metadata: FileMetadata {
-                        extension: FixedString::new("bin".to_string())?,
-                        extension_type: ExtensionType::default(),
-                        timestamp: j2000_timestamp(),
-                        version: Version {
-                            major: 1,
-                            minor: 0,
-                            build: 0,
-                            beta: 0,
-                        },
-                    },
-                    vendor: None,
-                    data: library_data,
-                    target: None,
-                    load_addr: PROS_HOT_BIN_LOAD_ADDR,
-                    linked_file: None,
-                    after_upload: if is_monolith {
-                        self.after_upload
-                    } else {
-                        // we are still uploading, so the post-upload action should not yet be performed
-                        FileExitAction::DoNothing
+            let lib_summary = UploadFile {
+                filename: FixedString::new(link_strategy.library_file_name(self.slot))?,
+                metadata: FileMetadata {
+                    extension: FixedString::new("bin".to_string())?,
+                    extension_type: ExtensionType::default(),
+                    timestamp: J2000Timestamp::now(),
+                    version: Version {
+                        major: 1,
+                        minor: 0,
+                        build: 0,
+                        beta: 0,
                     },
-                    progress_callback: self.lib_callback.take(),
-                })
-                .await?;
+                },
+                vendor: Some(link_strategy.vendor()),
+                data: library_data.into(),
+                target: None,
+                load_addr: *library_load_addr,
+                linked_file: None,
+                after_upload: if is_monolith {
+                    self.after_upload
+                } else {
+                    // we are still uploading, so the post-upload action should not yet be performed
+                    FileExitAction::DoNothing
+                },
+                skip_identical: self.skip_identical,
+                verify: self.verify,
+                checkpoint: None,
+                dry_run: self.dry_run.clone(),
+                progress: staged(&mut self.progress, TransferStage::ProgramLibrary),
+            }
+            .upload(connection, link)
+            .await?;
+            summary.files.push(lib_summary);
+        } else if let Some((link_strategy, _)) = &library {
+            summary
+                .skipped_files
+                .push(link_strategy.library_file_name(self.slot));
         }
 
         if let Some(mut program_data) = program_data {
@@ -430,48 +1914,572 @@ impl Command for UploadProgram<'_> {
 
             // Only ask the brain to link to a library if the program expects it.
             // Monolith programs don't have libraries.
-            let linked_file = if is_monolith {
-                None
-            } else {
-                debug!("Program will be linked to cold library: {program_lib_name:?}");
-                Some(LinkedFile {
-                    filename: FixedString::new(program_lib_name)?,
-                    vendor: None,
-                })
+            let linked_file = match &library {
+                None => None,
+                Some((link_strategy, _)) => {
+                    let program_lib_name = link_strategy.library_file_name(self.slot);
+                    debug!("Program will be linked to cold library: {program_lib_name:?}");
+                    Some(LinkedFile {
+                        filename: FixedString::new(program_lib_name)?,
+                        vendor: Some(link_strategy.vendor()),
+                    })
+                }
             };
 
-            connection
-                .execute_command(UploadFile {
-                    filename: FixedString::new(program_bin_name)?,
-                    metadata: FileMetadata {
-                        extension: FixedString::new("bin".to_string())?,
-                        extension_type: ExtensionType::default(),
-                        timestamp: j2000_timestamp(),
-                        version: Version {
-                            major: 1,
-                            minor: 0,
-                            build: 0,
-                            beta: 0,
-                        },
+            let bin_summary = UploadFile {
+                filename: FixedString::new(program_bin_name)?,
+                metadata: FileMetadata {
+                    extension: FixedString::new("bin".to_string())?,
+                    extension_type: ExtensionType::default(),
+                    timestamp: J2000Timestamp::now(),
+                    version: Version {
+                        major: 1,
+                        minor: 0,
+                        build: 0,
+                        beta: 0,
                     },
-                    vendor: None,
-                    data: program_data,
-                    target: None,
-                    load_addr: USER_PROGRAM_LOAD_ADDR,
-                    linked_file,
-                    after_upload: self.after_upload,
-                    progress_callback: self.bin_callback.take(),
+                },
+                vendor: None,
+                data: program_data.into(),
+                target: None,
+                load_addr: program_load_addr,
+                linked_file,
+                after_upload: self.after_upload,
+                skip_identical: self.skip_identical,
+                verify: self.verify,
+                checkpoint: None,
+                dry_run: self.dry_run.clone(),
+                progress: staged(&mut self.progress, TransferStage::ProgramBinary),
+            }
+            .upload(connection, link)
+            .await?;
+            summary.files.push(bin_summary);
+        } else {
+            summary.skipped_files.push(program_bin_name);
+        }
+
+        summary.duration = start.elapsed();
+        Ok(summary)
+    }
+}
+impl Command for UploadProgram<'_> {
+    type Output = ProgramUploadSummary;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let link = connection.execute_command(GetLinkPath).await?;
+        let switched = self.dry_run.is_none() && use_download_channel(connection, link).await?;
+        let config = connection.config().tuned_for(link);
+        let result = self
+            .upload(&mut connection.override_config(config), link)
+            .await;
+        restore_pit_channel(connection, switched, result).await
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        self.dry_run.is_none()
+    }
+}
+
+/// A program read back from the brain by [`DownloadProgram`].
+#[derive(Debug)]
+pub struct DownloadedProgram {
+    /// The program's parsed ini file.
+    ///
+    /// This is `None` if the program has no ini file, or if it couldn't be parsed, for example
+    /// because the tool that wrote it left out keys. [`ini`](Self::ini) still holds it then.
+    pub config: Option<ProgramIniConfig>,
+    /// The raw contents of the program's ini file, if it has one.
+    pub ini: Option<Vec<u8>>,
+    /// The program's binaries.
+    ///
+    /// Programs with a linked library are returned as [`ProgramData::HotCold`].
+    pub data: ProgramData,
+}
+
+/// Downloads a program from a slot on the brain. This is the inverse of [`UploadProgram`].
+///
+/// Binaries that were compressed during upload are decompressed.
+/// Returns `None` if there is no program in the slot.
+pub struct DownloadProgram<'a> {
+    pub slot: Slot,
+    /// Naming and vendor of the cold library for hot/cold programs.
+    pub link_strategy: LinkStrategy,
+
+    /// Receives progress events for each binary, labeled [`TransferStage::ProgramBinary`] or
+    /// [`TransferStage::ProgramLibrary`].
+    pub progress: Option<Box<dyn ProgressSink + 'a>>,
+}
+impl Command for DownloadProgram<'_> {
+    type Output = Option<DownloadedProgram>;
+
+    async fn execute<C: Connection + ?Sized>(
+        mut self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let base_file_name = self.slot.file_stem();
+
+        let Some(program) = download_if_exists(
+            connection,
+            FixedString::new(format!("{base_file_name}.bin"))?,
+            FileVendor::User,
+            staged(&mut self.progress, TransferStage::ProgramBinary),
+        )
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let ini_name = FixedString::new(format!("{base_file_name}.ini"))?;
+        let ini = download_if_exists(connection, ini_name.clone(), FileVendor::User, None).await?;
+        let config = ini.clone().and_then(|data| {
+            IniFile::<ProgramIniConfig>::from_bytes(ini_name, FileVendor::User, data)
+                .inspect_err(|e| {
+                    warn!("Ignoring unreadable ini file for slot {}: {}", self.slot, e)
+                })
+                .ok()
+                .map(|ini| ini.contents)
+        });
+
+        let library = download_if_exists(
+            connection,
+            FixedString::new(self.link_strategy.library_file_name(self.slot))?,
+            self.link_strategy.vendor(),
+            staged(&mut self.progress, TransferStage::ProgramLibrary),
+        )
+        .await?;
+
+        let data = match library {
+            Some(library) => ProgramData::HotCold {
+                hot: Some(program),
+                cold: Some(library),
+            },
+            None => ProgramData::Monolith(program),
+        };
+
+        Ok(Some(DownloadedProgram { config, ini, data }))
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        true
+    }
+}
+
+/// Downloads and decompresses a file, returning `None` if it does not exist.
+async fn download_if_exists<C: Connection + ?Sized>(
+    connection: &mut C,
+    file_name: FixedString<23>,
+    vendor: FileVendor,
+    progress: Option<Box<dyn ProgressSink + '_>>,
+) -> Result<Option<Vec<u8>>, C::Error> {
+    let Some(metadata) = connection
+        .execute_command(GetFileMetadata {
+            file_name: file_name.clone(),
+            vendor,
+        })
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    debug!("Downloading {} ({} bytes)", file_name, metadata.size);
+    let mut data = connection
+        .execute_command(DownloadFile {
+            file_name,
+            size: metadata.size,
+            vendor,
+            target: None,
+            load_addr: metadata.load_address,
+            checkpoint: None,
+            sink: None,
+            progress,
+        })
+        .await?;
+    decompress(&mut data);
+
+    Ok(Some(data))
+}
+
+/// Deletes a file from the brain.
+///
+/// VEXos treats an erase as the start of a file transfer, so the transfer is closed again
+/// afterwards.
+pub struct DeleteFile {
+    pub file_name: FixedString<23>,
+    pub vendor: FileVendor,
+    /// Also erase the files linked to this one, such as a program's cold library.
+    pub erase_all_linked: bool,
+    /// If set, nothing is erased and the packets are recorded to the plan instead.
+    pub dry_run: Option<PacketPlan>,
+}
+impl Command for DeleteFile {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let packet = EraseFilePacket::new(EraseFilePayload {
+            vendor: self.vendor,
+            option: if self.erase_all_linked {
+                EraseFilePayload::ERASE_ALL_LINKED
+            } else {
+                0
+            },
+            file_name: self.file_name,
+        });
+        let exit_packet = ExitFileTransferPacket::new(FileExitAction::DoNothing);
+
+        if let Some(plan) = &self.dry_run {
+            plan.record(&packet)?;
+            plan.record(&exit_packet)?;
+            return Ok(());
+        }
+
+        connection
+            .packet_handshake::<EraseFileReplyPacket>(packet)
+            .await?
+            .try_into_inner()?;
+        connection
+            .packet_handshake::<ExitFileTransferReplyPacket>(exit_packet)
+            .await?
+            .try_into_inner()?;
+
+        Ok(())
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        self.dry_run.is_none()
+    }
+}
+
+/// Copies a file on the brain.
+///
+/// VEXos has no packet for copying files, so the file is downloaded and uploaded again under
+/// the new name with the same metadata. Links to other files are not copied.
+#[derive(Debug, Clone)]
+pub struct CopyFile {
+    pub source: FixedString<23>,
+    pub destination: FixedString<23>,
+    pub vendor: FileVendor,
+    /// Replace the destination if it already exists. Otherwise, copying onto an existing file
+    /// fails with [`Cdc2Ack::NackFileAlreadyExists`].
+    pub overwrite: bool,
+}
+impl Command for CopyFile {
+    /// `false` if the source file does not exist.
+    type Output = bool;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let Some(source) = connection
+            .execute_command(GetFileMetadata {
+                file_name: self.source.clone(),
+                vendor: self.vendor,
+            })
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        if !self.overwrite
+            && connection
+                .execute_command(GetFileMetadata {
+                    file_name: self.destination.clone(),
+                    vendor: self.vendor,
+                })
+                .await?
+                .is_some()
+        {
+            return Err(Cdc2Ack::NackFileAlreadyExists.into());
+        }
+
+        debug!("Copying {} to {}", self.source, self.destination);
+        let data = connection
+            .execute_command(DownloadFile {
+                file_name: self.source,
+                size: source.size,
+                vendor: self.vendor,
+                target: None,
+                load_addr: source.load_address,
+                checkpoint: None,
+                sink: None,
+                progress: None,
+            })
+            .await?;
+
+        connection
+            .execute_command(UploadFile {
+                filename: self.destination,
+                metadata: source.metadata,
+                vendor: Some(self.vendor),
+                data: data.into(),
+                target: None,
+                load_addr: source.load_address,
+                linked_file: None,
+                after_upload: FileExitAction::DoNothing,
+                skip_identical: false,
+                verify: VerifyMode::Off,
+                checkpoint: None,
+                dry_run: None,
+                progress: None,
+            })
+            .await?;
+
+        Ok(true)
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        true
+    }
+}
+
+/// Renames a file on the brain.
+///
+/// This is a [`CopyFile`] followed by deleting the original, so it takes as long as
+/// transferring the file twice. Renaming a file to its own name does nothing.
+#[derive(Debug, Clone)]
+pub struct RenameFile {
+    pub from: FixedString<23>,
+    pub to: FixedString<23>,
+    pub vendor: FileVendor,
+    /// Replace the destination if it already exists.
+    pub overwrite: bool,
+}
+impl Command for RenameFile {
+    /// `false` if the file does not exist.
+    type Output = bool;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        // Copying a file onto itself and deleting the original would lose it.
+        if self.from == self.to {
+            let existing = connection
+                .execute_command(GetFileMetadata {
+                    file_name: self.from,
+                    vendor: self.vendor,
                 })
                 .await?;
+            return Ok(existing.is_some());
+        }
+
+        let copied = connection
+            .execute_command(CopyFile {
+                source: self.from.clone(),
+                destination: self.to,
+                vendor: self.vendor,
+                overwrite: self.overwrite,
+            })
+            .await?;
+        if !copied {
+            return Ok(false);
+        }
+
+        // The original is only deleted once the copy has been written completely. Files it
+        // links to, such as a shared library, are left alone since the copy still needs them.
+        connection
+            .execute_command(DeleteFile {
+                file_name: self.from,
+                vendor: self.vendor,
+                erase_all_linked: false,
+                dry_run: None,
+            })
+            .await?;
+        Ok(true)
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        true
+    }
+}
+
+/// Erases every user file on the brain.
+pub struct FormatFilesystem {
+    /// If set, nothing is erased and the packets are recorded to the plan instead.
+    pub dry_run: Option<PacketPlan>,
+}
+impl Command for FormatFilesystem {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let packet = FileFormatPacket::new(FileFormatConfirmation {
+            confirmation_code: [0x44, 0x43, 0x42, 0x41],
+        });
+
+        if let Some(plan) = &self.dry_run {
+            plan.record(&packet)?;
+            return Ok(());
+        }
+
+        // Formatting takes quite a while, so be generous with the timeout.
+        let policy = connection
+            .config()
+            .retry_override_for(&packet.encode()?)
+            .copied()
+            .unwrap_or(RetryPolicy {
+                max_attempts: 1,
+                timeout: Duration::from_secs(5),
+                backoff: Backoff::None,
+            });
+        connection
+            .packet_handshake_with_policy::<FileFormatReplyPacket>(&policy, packet)
+            .await?
+            .0
+            .try_into_inner()?;
+
+        Ok(())
+    }
+}
+
+/// Replaces the metadata of a file that is already on the brain.
+pub struct SetFileMetadata {
+    pub file_name: FixedString<23>,
+    pub vendor: FileVendor,
+    pub load_address: u32,
+    pub metadata: FileMetadata,
+    /// If set, nothing is written and the packets are recorded to the plan instead.
+    pub dry_run: Option<PacketPlan>,
+}
+impl Command for SetFileMetadata {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let packet = SetFileMetadataPacket::new(SetFileMetadataPayload {
+            vendor: self.vendor,
+            option: 0,
+            load_address: self.load_address,
+            metadata: self.metadata,
+            file_name: self.file_name,
+        });
+
+        if let Some(plan) = &self.dry_run {
+            plan.record(&packet)?;
+            return Ok(());
         }
 
+        connection
+            .packet_handshake::<SetFileMetadataReplyPacket>(packet)
+            .await?
+            .try_into_inner()?;
+
         Ok(())
     }
 }
 
+/// Undo gzip compression applied by [`compress`], leaving uncompressed data untouched.
+fn decompress(data: &mut Vec<u8>) {
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return;
+    }
+
+    let mut decompressed = Vec::new();
+    match GzDecoder::new(data.as_slice()).read_to_end(&mut decompressed) {
+        Ok(_) => *data = decompressed,
+        Err(e) => debug!(
+            "Data looked compressed but could not be decompressed: {}",
+            e
+        ),
+    }
+}
+
 /// Apply gzip compression to the given data
 fn compress(data: &mut Vec<u8>) {
     let mut encoder = GzBuilder::new().write(Vec::new(), Compression::default());
     encoder.write_all(data).unwrap();
     *data = encoder.finish().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ProgramIniConfig, MAX_PROGRAM_DESCRIPTION_LEN, MAX_PROGRAM_NAME_LEN};
+    use crate::{encode::EncodeError, slot::Slot};
+
+    fn slot(number: u8) -> Slot {
+        Slot::new(number).unwrap()
+    }
+
+    #[test]
+    fn builds_program_ini_defaults() {
+        let ini = ProgramIniConfig::builder("demo", slot(3), "PROS")
+            .build()
+            .unwrap();
+        assert_eq!(ini.project.ide, "PROS");
+        assert_eq!(ini.project.version, None);
+        assert_eq!(ini.program.name, "demo");
+        assert_eq!(ini.program.slot, 2, "the ini file stores a zero-based slot");
+        assert_eq!(ini.program.icon, "USER029x.bmp");
+        assert_eq!(ini.program.iconalt, "");
+        assert_eq!(ini.program.description, "");
+        assert_eq!(ini.program.version, None);
+        assert_eq!(ini.program.date, None);
+    }
+
+    #[test]
+    fn rejects_invalid_program_ini_values() {
+        assert!(matches!(Slot::new(9), Err(EncodeError::SlotOutOfRange(9))));
+
+        let longest_name = "a".repeat(MAX_PROGRAM_NAME_LEN);
+        assert!(ProgramIniConfig::builder(&*longest_name, slot(1), "PROS")
+            .build()
+            .is_ok());
+        assert!(matches!(
+            ProgramIniConfig::builder(longest_name + "a", slot(1), "PROS").build(),
+            Err(EncodeError::StringTooLong)
+        ));
+        assert!(matches!(
+            ProgramIniConfig::builder("demo", slot(1), "PROS")
+                .description("a".repeat(MAX_PROGRAM_DESCRIPTION_LEN + 1))
+                .build(),
+            Err(EncodeError::StringTooLong)
+        ));
+
+        for builder in [
+            ProgramIniConfig::builder("two\nlines", slot(1), "PROS"),
+            ProgramIniConfig::builder("demo", slot(1), "PROS").icon("USER029x.bmp\r"),
+            ProgramIniConfig::builder("demo", slot(1), "PROS").date("2024\n[project]"),
+        ] {
+            assert!(matches!(builder.build(), Err(EncodeError::InvalidIni(_))));
+        }
+
+        // Unknown IDEs are only warned about.
+        assert!(ProgramIniConfig::builder("demo", slot(1), "my-tool")
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn encodes_program_ini_files() {
+        let ini = ProgramIniConfig::builder("demo", slot(8), "vexide")
+            .description("A demo")
+            .ide_version("0.5.0")
+            .build()
+            .unwrap();
+        let encoded = String::from_utf8(ini.to_ini().unwrap()).unwrap();
+        assert_eq!(
+            encoded.lines().collect::<Vec<_>>(),
+            [
+                "[project]",
+                "version=0.5.0",
+                "ide=vexide",
+                "[program]",
+                "name=demo",
+                "slot=7",
+                "icon=USER029x.bmp",
+                "iconalt=",
+                "description=A demo",
+            ]
+        );
+    }
+}
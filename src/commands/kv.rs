@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use log::trace;
+use tokio::sync::broadcast;
+
+use crate::{
+    connection::Connection,
+    packets::kv::{
+        ReadKeyValuePacket, ReadKeyValueReplyPacket, WriteKeyValuePacket, WriteKeyValuePayload,
+        WriteKeyValueReplyPacket,
+    },
+    string::FixedString,
+};
+
+use super::Command;
+
+/// Reads a value from the brain's global key-value store.
+#[derive(Debug, Clone)]
+pub struct ReadKeyValue {
+    pub key: FixedString<31>,
+}
+impl Command for ReadKeyValue {
+    type Output = String;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let value = connection
+            .packet_handshake::<ReadKeyValueReplyPacket>(ReadKeyValuePacket::new(self.key))
+            .await?
+            .try_into_inner()?;
+
+        Ok(value.into_inner())
+    }
+}
+
+/// Writes a value to the brain's global key-value store.
+#[derive(Debug, Clone)]
+pub struct WriteKeyValue {
+    pub key: FixedString<31>,
+    pub value: FixedString<255>,
+}
+impl Command for WriteKeyValue {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        connection
+            .packet_handshake::<WriteKeyValueReplyPacket>(WriteKeyValuePacket::new(
+                WriteKeyValuePayload {
+                    key: self.key,
+                    value: self.value,
+                },
+            ))
+            .await?
+            .try_into_inner()?;
+
+        Ok(())
+    }
+}
+
+/// A change to a value tracked by a [`KeyValueStore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValueChange {
+    pub key: String,
+    /// The previously known value, or `None` if the key had not been seen yet.
+    pub old_value: Option<String>,
+    pub new_value: String,
+}
+
+/// A host-side mirror of the brain's key-value store.
+///
+/// Values are recorded whenever they are read or written through the store, and a
+/// [`KeyValueChange`] is sent to every subscriber when a recorded value changes.
+/// This lets UIs stay in sync after a write without polling every key again.
+///
+/// The store is cheap to clone; clones share the same values and subscribers.
+#[derive(Debug, Clone)]
+pub struct KeyValueStore {
+    values: Arc<Mutex<HashMap<String, String>>>,
+    changes: broadcast::Sender<KeyValueChange>,
+}
+impl KeyValueStore {
+    /// How many unreceived changes a subscriber can fall behind by before it starts missing them.
+    const CHANGE_CAPACITY: usize = 64;
+
+    pub fn new() -> Self {
+        Self {
+            values: Default::default(),
+            changes: broadcast::channel(Self::CHANGE_CAPACITY).0,
+        }
+    }
+
+    /// Returns a receiver for every change recorded after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<KeyValueChange> {
+        self.changes.subscribe()
+    }
+
+    /// Returns the last known value of a key without talking to the brain.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.values.lock().unwrap().get(key).cloned()
+    }
+
+    /// Returns every key and value recorded so far.
+    pub fn snapshot(&self) -> HashMap<String, String> {
+        self.values.lock().unwrap().clone()
+    }
+
+    /// Records a value, notifying subscribers if it differs from the last known value.
+    pub fn record(&self, key: &str, value: &str) {
+        let old_value = self
+            .values
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+
+        if old_value.as_deref() != Some(value) {
+            trace!("KV {key} changed from {old_value:?} to {value:?}");
+            // Sending only fails when nobody is subscribed, which is fine.
+            _ = self.changes.send(KeyValueChange {
+                key: key.to_string(),
+                old_value,
+                new_value: value.to_string(),
+            });
+        }
+    }
+
+    /// Reads a value from the brain and records it.
+    pub async fn read<C: Connection + ?Sized>(
+        &self,
+        connection: &mut C,
+        key: FixedString<31>,
+    ) -> Result<String, C::Error> {
+        let value = connection
+            .execute_command(ReadKeyValue { key: key.clone() })
+            .await?;
+        self.record(key.as_ref(), &value);
+        Ok(value)
+    }
+
+    /// Writes a value to the brain and records it once the brain acknowledges the write.
+    pub async fn write<C: Connection + ?Sized>(
+        &self,
+        connection: &mut C,
+        key: FixedString<31>,
+        value: FixedString<255>,
+    ) -> Result<(), C::Error> {
+        connection
+            .execute_command(WriteKeyValue {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .await?;
+        self.record(key.as_ref(), value.as_ref());
+        Ok(())
+    }
+}
+impl Default for KeyValueStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
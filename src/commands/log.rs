@@ -0,0 +1,96 @@
+//! Reading the brain's event log.
+
+use std::time::Duration;
+
+use log::debug;
+
+use crate::{
+    connection::Connection,
+    packets::log::{
+        GetLogCountPacket, GetLogCountReplyPacket, Log, ReadLogPagePacket, ReadLogPagePayload,
+        ReadLogPageReplyPacket,
+    },
+};
+
+use super::Command;
+
+/// How many entries are requested from the brain at once.
+const LOG_PAGE_SIZE: u32 = 32;
+
+/// An entry in the brain's event log.
+///
+/// VEXos does not document its log entries, and what each byte of one means hasn't been
+/// verified. The codes are exposed as the raw values the brain sends, under the same names
+/// as the fields of [`Log`], which are guesses too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventLogEntry {
+    /// How long after the brain powered on the event happened.
+    pub timestamp: Duration,
+    /// The first byte of the entry. See [`Log::code`].
+    pub code: u8,
+    /// The second byte of the entry. See [`Log::log_type`].
+    pub log_type: u8,
+    /// The third byte of the entry. See [`Log::description`].
+    pub description: u8,
+    /// The fourth byte of the entry. See [`Log::spare`].
+    pub spare: u8,
+}
+impl From<Log> for EventLogEntry {
+    fn from(log: Log) -> Self {
+        Self {
+            timestamp: Duration::from_millis(log.time as u64),
+            code: log.code,
+            log_type: log.log_type,
+            description: log.description,
+            spare: log.spare,
+        }
+    }
+}
+
+/// Reads entries from the brain's event log, oldest first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadEventLog {
+    /// If set, only this many of the most recent entries are read.
+    pub limit: Option<u32>,
+}
+impl Command for ReadEventLog {
+    type Output = Vec<EventLogEntry>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let total = connection
+            .packet_handshake::<GetLogCountReplyPacket>(GetLogCountPacket::new(()))
+            .await?
+            .try_into_inner()?
+            .count;
+        let wanted = self.limit.map_or(total, |limit| limit.min(total));
+        debug!("Reading {} of {} event log entries", wanted, total);
+
+        let mut entries = Vec::with_capacity(wanted as usize);
+        // Pages are addressed by how far from the newest entry they start.
+        let mut remaining = wanted;
+        while remaining > 0 {
+            let count = remaining.min(LOG_PAGE_SIZE);
+            let page = connection
+                .packet_handshake::<ReadLogPageReplyPacket>(ReadLogPagePacket::new(
+                    ReadLogPagePayload {
+                        offset: remaining,
+                        count,
+                    },
+                ))
+                .await?
+                .try_into_inner()?;
+
+            // The log can shrink while it's being read, in which case the page comes back short.
+            if page.entries.is_empty() {
+                break;
+            }
+            entries.extend(page.entries.into_iter().map(EventLogEntry::from));
+            remaining -= count;
+        }
+
+        Ok(entries)
+    }
+}
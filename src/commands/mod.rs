@@ -1,10 +1,32 @@
-use std::future::Future;
+use std::{
+    future::Future,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use crate::connection::Connection;
+use thiserror::Error;
 
+use crate::{
+    capabilities::UnsupportedFeature,
+    connection::Connection,
+    encode::{Encode, EncodeError},
+};
+
+pub mod channels;
+pub mod competition;
+pub mod controller;
 pub mod file;
+pub mod kv;
+pub mod log;
+pub mod program;
+pub mod progress;
+pub mod radio;
 #[cfg(feature = "screen-command")]
 pub mod screen;
+pub mod settings;
+pub mod system;
+pub mod terminal;
 
 pub trait Command {
     type Output;
@@ -13,4 +35,93 @@ pub trait Command {
         self,
         connection: &mut C,
     ) -> impl Future<Output = Result<Self::Output, C::Error>>;
+
+    /// Returns whether this command opens a file transfer on the brain.
+    ///
+    /// If a command that returns `true` is cancelled, the transfer is closed with an
+    /// `ExitFileTransferPacket` so that the brain is not left waiting for the rest of it.
+    fn opens_file_transfer(&self) -> bool {
+        false
+    }
+}
+
+/// A failure of a command that goes beyond a single packet, such as a file transfer that
+/// didn't check out or a program that never started.
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error(
+        "{file_name} did not match its checksum: expected {expected:#x}, computed {actual:#x}"
+    )]
+    ChecksumMismatch {
+        file_name: String,
+        expected: u32,
+        actual: u32,
+    },
+    #[error(
+        "{file_name} did not match the uploaded data: expected CRC {expected:#x}, found {}",
+        actual.map_or("no file".to_string(), |crc| format!("{crc:#x}"))
+    )]
+    VerificationFailed {
+        file_name: String,
+        expected: u32,
+        actual: Option<u32>,
+    },
+    #[error(
+        "{file_name} did not match the uploaded data: expected {expected} bytes, found {actual}"
+    )]
+    SizeMismatch {
+        file_name: String,
+        expected: u32,
+        actual: u32,
+    },
+    #[error("Checkpoint does not match the transfer of {file_name}")]
+    CheckpointMismatch { file_name: String },
+    #[error("Program did not start within {0:?}")]
+    ProgramStartTimeout(Duration),
+    #[error("Program did not stop within {0:?}")]
+    ProgramStopTimeout(Duration),
+    #[error("Could not write received data: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Unsupported(#[from] UnsupportedFeature),
+}
+
+/// A packet that a mutating command would have sent if it were not running as a dry run.
+#[derive(Debug, Clone)]
+pub struct PlannedPacket {
+    /// The Rust type of the packet.
+    pub packet_type: &'static str,
+    /// The exact bytes that would have been sent.
+    pub bytes: Vec<u8>,
+}
+
+/// A shared record of the packets planned by commands running as a dry run.
+///
+/// [`UploadFile`](file::UploadFile), [`UploadProgram`](file::UploadProgram),
+/// [`DeleteFile`](file::DeleteFile), [`FormatFilesystem`](file::FormatFilesystem) and
+/// [`SetFileMetadata`](file::SetFileMetadata) take an `Option<PacketPlan>` in their `dry_run`
+/// field. When it is set, all queries and validation still happen, but every packet that would
+/// modify the brain is encoded and recorded here instead of being sent. Other commands that
+/// modify the brain have no dry run.
+#[derive(Debug, Clone, Default)]
+pub struct PacketPlan(Arc<Mutex<Vec<PlannedPacket>>>);
+impl PacketPlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encodes a packet and adds it to the plan.
+    pub fn record<P: Encode>(&self, packet: &P) -> Result<(), EncodeError> {
+        let bytes = packet.encode()?;
+        self.0.lock().unwrap().push(PlannedPacket {
+            packet_type: std::any::type_name::<P>(),
+            bytes,
+        });
+        Ok(())
+    }
+
+    /// Returns every packet recorded so far, in the order they would have been sent.
+    pub fn packets(&self) -> Vec<PlannedPacket> {
+        self.0.lock().unwrap().clone()
+    }
 }
@@ -0,0 +1,344 @@
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::{
+    connection::{
+        time::{interval, sleep, Instant, Interval, MissedTickBehavior},
+        Connection,
+    },
+    packets::{
+        file::{
+            FileLoadAction, FileVendor, LoadFileActionPacket, LoadFileActionPayload,
+            LoadFileActionReplyPacket,
+        },
+        system::{GetSystemFlagsPacket, GetSystemFlagsReplyPacket},
+    },
+    slot::Slot,
+    string::FixedString,
+    timestamp::J2000Timestamp,
+};
+
+use super::{
+    file::{DownloadFile, IniFile, IniSections, ListFiles},
+    Command, CommandError,
+};
+
+/// Reads which program is currently running on the brain.
+///
+/// Returns `None` if no program is running, otherwise the raw program number reported by
+/// VEXos. User slots are numbered from 1, the built-in ClawBot program is 129, and the
+/// built-in driver program is 145.
+#[derive(Debug, Clone, Copy)]
+pub struct GetRunningProgram;
+impl Command for GetRunningProgram {
+    type Output = Option<u8>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(GetSystemFlagsPacket::new(()))
+            .await?
+            .try_into_inner()?;
+
+        Ok(flags.is_program_running().then_some(flags.current_program))
+    }
+}
+
+/// How often the brain is polled while waiting for a program to start or stop.
+const RUN_STATE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Polls the running program until `done` returns true, failing with `timed_out` once
+/// `timeout` elapses.
+async fn wait_for_running_program<C: Connection + ?Sized>(
+    connection: &mut C,
+    timeout: Duration,
+    done: impl Fn(Option<u8>) -> bool,
+    timed_out: CommandError,
+) -> Result<(), C::Error> {
+    let start = Instant::now();
+    loop {
+        if done(connection.execute_command(GetRunningProgram).await?) {
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(timed_out.into());
+        }
+        sleep(RUN_STATE_POLL_INTERVAL).await;
+    }
+}
+
+/// Runs the program stored in a slot.
+///
+/// If `wait` is set, this polls the brain until the slot's program is reported as running,
+/// failing with [`CommandError::ProgramStartTimeout`] if it hasn't started once the duration
+/// has elapsed.
+#[derive(Debug, Clone, Copy)]
+pub struct RunProgram {
+    pub slot: Slot,
+    pub wait: Option<Duration>,
+}
+impl Command for RunProgram {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        debug!("Running program in slot {}", self.slot);
+        connection
+            .packet_handshake::<LoadFileActionReplyPacket>(LoadFileActionPacket::new(
+                LoadFileActionPayload {
+                    vendor: FileVendor::User,
+                    action: FileLoadAction::Run,
+                    file_name: FixedString::new(format!("{}.bin", self.slot.file_stem()))?,
+                },
+            ))
+            .await?
+            .try_into_inner()?;
+
+        if let Some(timeout) = self.wait {
+            let number = self.slot.number();
+            wait_for_running_program(
+                connection,
+                timeout,
+                |program| program == Some(number),
+                CommandError::ProgramStartTimeout(timeout),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Stops the program running on the brain, if there is one.
+///
+/// If `wait` is set, this polls the brain until no program is reported as running, failing
+/// with [`CommandError::ProgramStopTimeout`] if one is still running once the duration has
+/// elapsed.
+#[derive(Debug, Clone, Copy)]
+pub struct StopProgram {
+    pub wait: Option<Duration>,
+}
+impl Command for StopProgram {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        debug!("Stopping running program");
+        connection
+            .packet_handshake::<LoadFileActionReplyPacket>(LoadFileActionPacket::new(
+                LoadFileActionPayload {
+                    vendor: FileVendor::User,
+                    action: FileLoadAction::Stop,
+                    file_name: FixedString::new(String::new())?,
+                },
+            ))
+            .await?
+            .try_into_inner()?;
+
+        if let Some(timeout) = self.wait {
+            wait_for_running_program(
+                connection,
+                timeout,
+                |program| program.is_none(),
+                CommandError::ProgramStopTimeout(timeout),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A change in which program is running on the brain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramEvent {
+    /// A program started running.
+    Started { program: u8 },
+    /// A program stopped running.
+    ///
+    /// VEXos does not distinguish a program exiting from one being stopped or crashing,
+    /// so all of these are reported as a stop.
+    Stopped { program: u8 },
+}
+
+/// Polls the brain's system flags and reports programs starting and stopping.
+///
+/// This lets terminal tools reattach their stdout stream whenever a program is restarted.
+/// A program that is replaced by another between two polls is reported as a stop followed
+/// by a start.
+pub struct ProgramStateStream {
+    ticker: Interval,
+    running: Option<Option<u8>>,
+    pending: Option<ProgramEvent>,
+}
+impl ProgramStateStream {
+    pub fn new(poll_interval: Duration) -> Self {
+        let mut ticker = interval(poll_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        Self {
+            ticker,
+            running: None,
+            pending: None,
+        }
+    }
+
+    /// Returns the running program as of the last poll, or `None` if nothing has been polled yet.
+    pub fn running(&self) -> Option<Option<u8>> {
+        self.running
+    }
+
+    /// Waits for the next program event.
+    ///
+    /// The first poll only records the current state; a program that is already running
+    /// when the stream is created does not produce a [`ProgramEvent::Started`] event.
+    pub async fn next<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<ProgramEvent, C::Error> {
+        if let Some(event) = self.pending.take() {
+            return Ok(event);
+        }
+
+        loop {
+            self.ticker.tick().await;
+            let now = connection.execute_command(GetRunningProgram).await?;
+
+            let Some(before) = self.running.replace(now) else {
+                continue;
+            };
+            if before == now {
+                continue;
+            }
+            debug!("Running program changed from {:?} to {:?}", before, now);
+
+            let started = now.map(|program| ProgramEvent::Started { program });
+            match before {
+                Some(program) => {
+                    self.pending = started;
+                    return Ok(ProgramEvent::Stopped { program });
+                }
+                None => return Ok(started.unwrap()),
+            }
+        }
+    }
+}
+
+/// A program stored in one of the brain's program slots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramSlot {
+    /// The slot the program is stored in.
+    pub slot: Slot,
+    /// The program's name from its ini file.
+    pub name: Option<String>,
+    /// The program's description from its ini file.
+    pub description: Option<String>,
+    /// The icon shown for the program, such as `USER029x.bmp`.
+    pub icon: Option<String>,
+    /// The size of the program binary in bytes. Compressed binaries report their compressed size.
+    pub binary_size: u32,
+    /// When the binary was uploaded.
+    pub upload_time: Option<J2000Timestamp>,
+}
+
+/// Lists the programs stored on the brain, sorted by slot.
+///
+/// Every `slot_N.bin` in the user vendor whose `N` is a valid [`Slot`] is reported as a
+/// program. Its name, description and icon are read from the matching `slot_N.ini` if there is
+/// one; they are left as `None` if the ini file is missing or can't be parsed.
+#[derive(Debug, Clone, Copy)]
+pub struct ListPrograms;
+impl Command for ListPrograms {
+    type Output = Vec<ProgramSlot>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let files = connection
+            .execute_command(ListFiles {
+                vendor: FileVendor::User,
+            })
+            .await?;
+
+        let mut programs = Vec::new();
+        for binary in &files {
+            let Some(slot) = binary
+                .file_name
+                .strip_prefix("slot_")
+                .and_then(|name| name.strip_suffix(".bin"))
+                .and_then(|slot| slot.parse::<u8>().ok())
+                .and_then(|number| Slot::new(number).ok())
+            else {
+                continue;
+            };
+
+            let ini_name = format!("{}.ini", slot.file_stem());
+            let mut program = (None, None, None);
+            if let Some(ini) = files.iter().find(|file| file.file_name == ini_name) {
+                let file_name = FixedString::new(ini_name)?;
+                let data = connection
+                    .execute_command(DownloadFile {
+                        file_name: file_name.clone(),
+                        size: ini.size,
+                        vendor: FileVendor::User,
+                        target: None,
+                        load_addr: ini.load_address,
+                        checkpoint: None,
+                        sink: None,
+                        progress: None,
+                    })
+                    .await?;
+                program = parse_program_section(file_name, data, slot);
+            }
+            let (name, description, icon) = program;
+
+            programs.push(ProgramSlot {
+                slot,
+                name,
+                description,
+                icon,
+                binary_size: binary.size,
+                upload_time: binary.metadata.as_ref().map(|metadata| metadata.timestamp),
+            });
+        }
+        programs.sort_by_key(|program| program.slot);
+
+        Ok(programs)
+    }
+
+    fn opens_file_transfer(&self) -> bool {
+        true
+    }
+}
+
+/// Reads the name, description and icon from the `[program]` section of a slot's ini file.
+///
+/// The section is parsed loosely, since different tools write different sets of keys.
+fn parse_program_section(
+    file_name: FixedString<23>,
+    data: Vec<u8>,
+    slot: Slot,
+) -> (Option<String>, Option<String>, Option<String>) {
+    let mut ini = match IniFile::<IniSections>::from_bytes(file_name, FileVendor::User, data) {
+        Ok(ini) => ini,
+        Err(err) => {
+            warn!("Ignoring unreadable ini file for slot {}: {}", slot, err);
+            return (None, None, None);
+        }
+    };
+
+    let mut program = ini.contents.remove("program").unwrap_or_default();
+    (
+        program.remove("name"),
+        program.remove("description"),
+        program.remove("icon"),
+    )
+}
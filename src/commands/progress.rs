@@ -0,0 +1,136 @@
+//! Progress reporting for long-running transfers.
+
+use std::time::Duration;
+
+use crate::connection::time::Instant;
+
+/// The part of a transfer that a [`ProgressEvent`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferStage {
+    /// A single file being uploaded.
+    Upload,
+    /// A single file being downloaded.
+    Download,
+    /// The ini file of a program.
+    ProgramIni,
+    /// The cold library of a hot/cold program.
+    ProgramLibrary,
+    /// The monolith or hot binary of a program.
+    ProgramBinary,
+}
+
+/// How far a transfer has progressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    pub stage: TransferStage,
+    /// How many bytes have been transferred so far.
+    pub bytes_done: u32,
+    /// The total size of the transfer in bytes.
+    pub total_bytes: u32,
+    /// The estimated time left, extrapolated from the transfer rate so far.
+    ///
+    /// This is `None` until some data has been transferred.
+    pub eta: Option<Duration>,
+}
+impl ProgressEvent {
+    /// Returns the progress as a percentage, where 100.0 is a finished transfer.
+    pub fn percent(&self) -> f32 {
+        if self.total_bytes == 0 {
+            100.0
+        } else {
+            self.bytes_done as f32 / self.total_bytes as f32 * 100.0
+        }
+    }
+
+    /// Returns whether this is the last event of its stage.
+    pub fn is_finished(&self) -> bool {
+        self.bytes_done >= self.total_bytes
+    }
+}
+
+/// Receives progress events from a transfer command.
+///
+/// This is implemented for every `FnMut(ProgressEvent)`, so a closure can be used as a sink.
+pub trait ProgressSink: Send {
+    fn progress(&mut self, event: ProgressEvent);
+}
+impl<F: FnMut(ProgressEvent) + Send> ProgressSink for F {
+    fn progress(&mut self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+/// Forwards events to another sink, relabeling them with a different stage.
+struct StagedSink<'s, 'a> {
+    stage: TransferStage,
+    sink: &'s mut (dyn ProgressSink + 'a),
+}
+impl ProgressSink for StagedSink<'_, '_> {
+    fn progress(&mut self, event: ProgressEvent) {
+        self.sink.progress(ProgressEvent {
+            stage: self.stage,
+            ..event
+        });
+    }
+}
+
+/// Borrows a sink for one transfer of a multi-file command, relabeling its events with `stage`.
+pub(crate) fn staged<'s>(
+    sink: &'s mut Option<Box<dyn ProgressSink + '_>>,
+    stage: TransferStage,
+) -> Option<Box<dyn ProgressSink + 's>> {
+    sink.as_deref_mut()
+        .map(|sink| Box::new(StagedSink { stage, sink }) as Box<dyn ProgressSink + 's>)
+}
+
+/// Turns byte counts into [`ProgressEvent`]s for an optional sink.
+pub(crate) struct ProgressTracker<'a> {
+    sink: Option<Box<dyn ProgressSink + 'a>>,
+    stage: TransferStage,
+    total_bytes: u32,
+    start: Instant,
+    /// Bytes that were already transferred when tracking started, such as when resuming.
+    initial_bytes: u32,
+}
+impl<'a> ProgressTracker<'a> {
+    pub fn new(
+        sink: Option<Box<dyn ProgressSink + 'a>>,
+        stage: TransferStage,
+        total_bytes: u32,
+        initial_bytes: u32,
+    ) -> Self {
+        Self {
+            sink,
+            stage,
+            total_bytes,
+            start: Instant::now(),
+            initial_bytes,
+        }
+    }
+
+    pub fn report(&mut self, bytes_done: u32) {
+        let Some(sink) = &mut self.sink else {
+            return;
+        };
+        let bytes_done = bytes_done.min(self.total_bytes);
+
+        let transferred = bytes_done.saturating_sub(self.initial_bytes);
+        let eta = (transferred > 0).then(|| {
+            let remaining = self.total_bytes - bytes_done;
+            self.start
+                .elapsed()
+                .mul_f64(remaining as f64 / transferred as f64)
+        });
+
+        sink.progress(ProgressEvent {
+            stage: self.stage,
+            bytes_done,
+            total_bytes: self.total_bytes,
+            eta,
+        });
+    }
+
+    pub fn finish(&mut self) {
+        self.report(self.total_bytes);
+    }
+}
@@ -0,0 +1,267 @@
+use std::time::Duration;
+
+use log::{debug, trace, warn};
+
+use crate::{
+    config::LinkPath,
+    connection::{
+        time::{sleep, Instant},
+        Connection, ConnectionType,
+    },
+    packets::{
+        cdc2::Cdc2Ack,
+        radio::{
+            GetRadioStatusPacket, GetRadioStatusReplyPacket, RadioChannel,
+            SelectRadioChannelPacket, SelectRadioChannelPayload, SelectRadioChannelReplyPacket,
+        },
+        system::{GetSystemFlagsPacket, GetSystemFlagsReplyPacket, ProductFlags, SystemFlags},
+    },
+};
+
+use super::{system::GetSystemVersion, Command};
+
+/// How long a file transfer waits for the radio to re-link after switching channels.
+const TRANSFER_CHANNEL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The radio channel state of a controller link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadioChannelInfo {
+    /// The raw channel number reported by the radio.
+    pub channel: i8,
+
+    /// Whether the radio is currently on the high-bandwidth download channel.
+    pub download_active: bool,
+}
+impl RadioChannelInfo {
+    /// Returns the [`RadioChannel`] that the link is currently using.
+    pub fn radio_channel(&self) -> RadioChannel {
+        if self.download_active {
+            RadioChannel::Download
+        } else {
+            RadioChannel::Pit
+        }
+    }
+}
+
+/// Queries which radio channel the controller link is currently using.
+#[derive(Debug, Clone, Copy)]
+pub struct GetRadioChannel;
+impl Command for GetRadioChannel {
+    type Output = RadioChannelInfo;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection
+            .packet_handshake::<GetRadioStatusReplyPacket>(GetRadioStatusPacket::new(()))
+            .await?
+            .try_into_inner()?;
+
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(GetSystemFlagsPacket::new(()))
+            .await?
+            .try_into_inner()?;
+
+        Ok(RadioChannelInfo {
+            channel: status.channel,
+            download_active: flags.is_radio_data_mode(),
+        })
+    }
+}
+
+/// The state and quality of the radio link between a controller and the brain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadioStatusInfo {
+    pub channel: RadioChannelInfo,
+    /// Whether the radio is linked to a controller.
+    pub connected: bool,
+    /// Link quality, from 0 to 100.
+    pub quality: u16,
+    /// Signal strength in dBm.
+    pub strength: i16,
+}
+impl RadioStatusInfo {
+    /// The link quality below which [`Self::is_transfer_quality`] reports the link as too weak.
+    ///
+    /// This is a heuristic picked for this crate, not a threshold that VEXos uses or documents.
+    pub const MIN_TRANSFER_QUALITY: u16 = 50;
+
+    /// Returns whether the radio is linked with a quality of at least
+    /// [`Self::MIN_TRANSFER_QUALITY`].
+    ///
+    /// This is only a rough guess at whether a wireless file transfer will go smoothly. Callers
+    /// that need their own cutoff should compare [`Self::quality`] directly.
+    pub fn is_transfer_quality(&self) -> bool {
+        self.connected && self.quality >= Self::MIN_TRANSFER_QUALITY
+    }
+}
+
+/// Queries the channel, link state and signal quality of the radio.
+#[derive(Debug, Clone, Copy)]
+pub struct GetRadioStatus;
+impl Command for GetRadioStatus {
+    type Output = RadioStatusInfo;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection
+            .packet_handshake::<GetRadioStatusReplyPacket>(GetRadioStatusPacket::new(()))
+            .await?
+            .try_into_inner()?;
+
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(GetSystemFlagsPacket::new(()))
+            .await?
+            .try_into_inner()?;
+
+        Ok(RadioStatusInfo {
+            channel: RadioChannelInfo {
+                channel: status.channel,
+                download_active: flags.is_radio_data_mode(),
+            },
+            connected: flags.flags.contains(SystemFlags::RADIO_CONNECTED),
+            quality: status.quality,
+            strength: status.strength,
+        })
+    }
+}
+
+/// Switches the controller's radio to another channel and waits until the
+/// brain reports that the switch actually happened.
+///
+/// The link drops while the radio changes channels, so failed queries are
+/// retried until `timeout` has elapsed, after which a [`Cdc2Ack::Timeout`] is returned.
+#[derive(Debug, Clone, Copy)]
+pub struct SwitchRadioChannel {
+    pub channel: RadioChannel,
+    pub timeout: Duration,
+}
+impl Command for SwitchRadioChannel {
+    type Output = RadioChannelInfo;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        debug!("Switching radio to {:?} channel", self.channel);
+        connection
+            .packet_handshake::<SelectRadioChannelReplyPacket>(SelectRadioChannelPacket::new(
+                SelectRadioChannelPayload {
+                    channel: self.channel,
+                },
+            ))
+            .await?
+            .try_into_inner()?;
+
+        let start = Instant::now();
+        while start.elapsed() < self.timeout {
+            // Give the radio some time to re-link before polling it.
+            sleep(Duration::from_millis(250)).await;
+
+            match connection.execute_command(GetRadioChannel).await {
+                Ok(info) if info.radio_channel() == self.channel => {
+                    debug!("Radio switched to channel {}", info.channel);
+                    connection.record_radio_channel(self.channel);
+                    return Ok(info);
+                }
+                Ok(info) => trace!("Radio is still on channel {}", info.channel),
+                Err(e) => trace!("Radio is not responding yet: {:?}", e),
+            }
+        }
+
+        Err(Cdc2Ack::Timeout.into())
+    }
+}
+
+/// Finds out which route packets take to the brain.
+///
+/// Only controller connections need to ask the controller whether it is tethered or
+/// wireless. Other connections are answered without sending anything.
+#[derive(Debug, Clone, Copy)]
+pub struct GetLinkPath;
+impl Command for GetLinkPath {
+    type Output = LinkPath;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        Ok(match connection.connection_type() {
+            ConnectionType::Wired => LinkPath::Direct,
+            ConnectionType::Bluetooth => LinkPath::Bluetooth,
+            ConnectionType::Controller => {
+                let version = connection.execute_command(GetSystemVersion).await?;
+                if version.flags.contains(ProductFlags::CONNECTED_WIRELESS) {
+                    LinkPath::Wireless
+                } else {
+                    LinkPath::TetheredController
+                }
+            }
+        })
+    }
+}
+
+/// Moves a wireless controller link onto the download channel before a file transfer.
+///
+/// Returns whether the channel was switched, in which case [`restore_pit_channel`] switches
+/// it back once the transfer is over. Other links and wireless links that are already on
+/// the download channel are left alone.
+pub(crate) async fn use_download_channel<C: Connection + ?Sized>(
+    connection: &mut C,
+    link: LinkPath,
+) -> Result<bool, C::Error> {
+    if link != LinkPath::Wireless {
+        return Ok(false);
+    }
+    if connection
+        .execute_command(GetRadioChannel)
+        .await?
+        .download_active
+    {
+        return Ok(false);
+    }
+
+    connection
+        .execute_command(SwitchRadioChannel {
+            channel: RadioChannel::Download,
+            timeout: TRANSFER_CHANNEL_TIMEOUT,
+        })
+        .await?;
+    Ok(true)
+}
+
+/// Switches the radio back to the pit channel if [`use_download_channel`] switched it, and
+/// returns the result of the transfer that ran in between.
+///
+/// If both the transfer and switching back fail, the transfer's error is returned. The radio
+/// is then still restored when the connection is shut down.
+pub(crate) async fn restore_pit_channel<C: Connection + ?Sized, T>(
+    connection: &mut C,
+    switched: bool,
+    result: Result<T, C::Error>,
+) -> Result<T, C::Error> {
+    if !switched {
+        return result;
+    }
+
+    let restored = connection
+        .execute_command(SwitchRadioChannel {
+            channel: RadioChannel::Pit,
+            timeout: TRANSFER_CHANNEL_TIMEOUT,
+        })
+        .await;
+    match (result, restored) {
+        (Ok(_), Err(e)) => Err(e),
+        (Err(e), Err(restore_error)) => {
+            warn!(
+                "Could not switch the radio back to the pit channel: {}",
+                restore_error
+            );
+            Err(e)
+        }
+        (result, Ok(_)) => result,
+    }
+}
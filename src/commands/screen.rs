@@ -1,7 +1,8 @@
-use std::time::Duration;
-
 use log::info;
 
+pub use crate::packets::capture::{
+    Screenshot, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH, SCREEN_WIDTH,
+};
 use crate::{
     connection::Connection,
     packets::{
@@ -15,12 +16,16 @@ use crate::{
     string::FixedString,
 };
 
-use super::{file::DownloadFile, Command};
+use super::{file::DownloadFile, progress::ProgressEvent, Command};
 
+/// Captures the brain's raw framebuffer.
+///
+/// The output is [`FRAMEBUFFER_WIDTH`] by [`FRAMEBUFFER_HEIGHT`] pixels, stored row by row as
+/// 4 byte little-endian `0x00RRGGBB` words. Only the first [`SCREEN_WIDTH`] columns are visible.
 #[derive(Debug, Clone, Copy)]
-pub struct ScreenCapture;
-impl Command for ScreenCapture {
-    type Output = image::RgbImage;
+pub struct RawScreenCapture;
+impl Command for RawScreenCapture {
+    type Output = Vec<u8>;
 
     async fn execute<C: Connection + ?Sized>(
         self,
@@ -28,44 +33,40 @@ impl Command for ScreenCapture {
     ) -> Result<Self::Output, C::Error> {
         // Tell the brain we want to take a screenshot
         connection
-            .packet_handshake::<ScreenCaptureReplyPacket>(
-                Duration::from_millis(100),
-                5,
-                ScreenCapturePacket::new(()),
-            )
-            .await?;
+            .packet_handshake::<ScreenCaptureReplyPacket>(ScreenCapturePacket::new(()))
+            .await?
+            .try_into_inner()?;
 
         // Grab the image data
-        let cap = connection
+        connection
             .execute_command(DownloadFile {
                 file_name: FixedString::new("screen".to_string()).unwrap(),
                 vendor: FileVendor::Sys,
                 target: Some(FileTransferTarget::Cbuf),
                 load_addr: 0,
-                size: 512 * 272 * 4,
-                progress_callback: Some(Box::new(|progress| {
-                    info!("Downloading screen: {:.2}%", progress)
+                checkpoint: None,
+                sink: None,
+                size: FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT * 4,
+                progress: Some(Box::new(|progress: ProgressEvent| {
+                    info!("Downloading screen: {:.2}%", progress.percent())
                 })),
             })
             .await
-            .unwrap();
+    }
+}
 
-        let colors = cap
-            .chunks(4)
-            .filter_map(|p| {
-                if p.len() == 4 {
-                    // little endian
-                    let color = [p[2], p[1], p[0]];
-                    Some(color)
-                } else {
-                    None
-                }
-            })
-            .flatten()
-            .collect::<Vec<_>>();
+/// Captures the visible part of the brain's screen.
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenCapture;
+impl Command for ScreenCapture {
+    type Output = Screenshot;
 
-        let image = image::RgbImage::from_vec(512, 272, colors).unwrap();
-        Ok(image::GenericImageView::view(&image, 0, 0, 480, 272).to_image())
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let cap = connection.execute_command(RawScreenCapture).await?;
+        Ok(Screenshot::from_raw(&cap)?)
     }
 }
 
@@ -83,15 +84,13 @@ impl Command for MockTouch {
         connection: &mut C,
     ) -> Result<Self::Output, C::Error> {
         connection
-            .packet_handshake::<SendDashTouchReplyPacket>(
-                Duration::from_millis(100),
-                5,
-                SendDashTouchPacket::new(SendDashTouchPayload {
+            .packet_handshake::<SendDashTouchReplyPacket>(SendDashTouchPacket::new(
+                SendDashTouchPayload {
                     x: self.x,
                     y: self.y,
                     pressing: if self.pressed { 1 } else { 0 },
-                }),
-            )
+                },
+            ))
             .await?;
         Ok(())
     }
@@ -139,14 +138,10 @@ impl Command for OpenDashScreen {
         connection: &mut C,
     ) -> Result<Self::Output, C::Error> {
         connection
-            .packet_handshake::<SelectDashReplyPacket>(
-                Duration::from_millis(100),
-                5,
-                SelectDashPacket::new(SelectDashPayload {
-                    screen: self.dash,
-                    port: 0,
-                }),
-            )
+            .packet_handshake::<SelectDashReplyPacket>(SelectDashPacket::new(SelectDashPayload {
+                screen: self.dash,
+                port: 0,
+            }))
             .await?;
 
         Ok(())
@@ -0,0 +1,192 @@
+//! Robot settings stored in the brain's key-value store.
+
+use crate::{connection::Connection, encode::EncodeError, string::FixedString};
+
+use super::{
+    kv::{ReadKeyValue, WriteKeyValue},
+    Command,
+};
+
+/// The key-value store key holding the team number.
+pub const TEAM_NUMBER_KEY: &str = "teamnumber";
+/// The key-value store key holding the robot name.
+pub const ROBOT_NAME_KEY: &str = "robotname";
+
+/// The longest team number, in bytes, that the brain displays in full.
+pub const MAX_TEAM_NUMBER_LEN: usize = 8;
+/// The longest robot name, in bytes, that the brain displays in full.
+pub const MAX_ROBOT_NAME_LEN: usize = 16;
+
+async fn read_setting<C: Connection + ?Sized>(
+    connection: &mut C,
+    key: &str,
+) -> Result<String, C::Error> {
+    connection
+        .execute_command(ReadKeyValue {
+            key: FixedString::try_from(key)?,
+        })
+        .await
+}
+
+async fn write_setting<C: Connection + ?Sized>(
+    connection: &mut C,
+    key: &str,
+    value: &str,
+    max_len: usize,
+) -> Result<(), C::Error> {
+    if value.len() > max_len {
+        return Err(EncodeError::StringTooLong.into());
+    }
+
+    connection
+        .execute_command(WriteKeyValue {
+            key: FixedString::try_from(key)?,
+            value: FixedString::try_from(value)?,
+        })
+        .await
+}
+
+/// Reads the team number shown on the brain.
+#[derive(Debug, Clone, Copy)]
+pub struct GetTeamNumber;
+impl Command for GetTeamNumber {
+    type Output = String;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        read_setting(connection, TEAM_NUMBER_KEY).await
+    }
+}
+
+/// Sets the team number shown on the brain.
+///
+/// Fails with [`EncodeError::StringTooLong`] if the team number is longer than
+/// [`MAX_TEAM_NUMBER_LEN`], without sending anything.
+#[derive(Debug, Clone)]
+pub struct SetTeamNumber {
+    pub team_number: String,
+}
+impl Command for SetTeamNumber {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        write_setting(
+            connection,
+            TEAM_NUMBER_KEY,
+            &self.team_number,
+            MAX_TEAM_NUMBER_LEN,
+        )
+        .await
+    }
+}
+
+/// Reads the robot name shown on the brain.
+#[derive(Debug, Clone, Copy)]
+pub struct GetRobotName;
+impl Command for GetRobotName {
+    type Output = String;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        read_setting(connection, ROBOT_NAME_KEY).await
+    }
+}
+
+/// Sets the robot name shown on the brain.
+///
+/// Fails with [`EncodeError::StringTooLong`] if the name is longer than
+/// [`MAX_ROBOT_NAME_LEN`], without sending anything.
+#[derive(Debug, Clone)]
+pub struct SetRobotName {
+    pub name: String,
+}
+impl Command for SetRobotName {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        write_setting(connection, ROBOT_NAME_KEY, &self.name, MAX_ROBOT_NAME_LEN).await
+    }
+}
+
+/// A key in the brain's key-value store.
+///
+/// The brain stores every value as a string, and the keys this crate knows about all hold
+/// text, so values are read and written as strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KVKey {
+    TeamNumber,
+    RobotName,
+    /// A key this crate does not know about.
+    Other(String),
+}
+impl KVKey {
+    /// The key's name in the key-value store.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::TeamNumber => TEAM_NUMBER_KEY,
+            Self::RobotName => ROBOT_NAME_KEY,
+            Self::Other(name) => name,
+        }
+    }
+
+    /// The longest value, in bytes, that can be written to this key.
+    pub fn max_len(&self) -> usize {
+        match self {
+            Self::TeamNumber => MAX_TEAM_NUMBER_LEN,
+            Self::RobotName => MAX_ROBOT_NAME_LEN,
+            Self::Other(_) => 255,
+        }
+    }
+}
+impl From<&str> for KVKey {
+    fn from(name: &str) -> Self {
+        match name {
+            TEAM_NUMBER_KEY => Self::TeamNumber,
+            ROBOT_NAME_KEY => Self::RobotName,
+            _ => Self::Other(name.to_string()),
+        }
+    }
+}
+
+/// Access to the brain's settings through its key-value store, by [`KVKey`].
+pub struct Settings<'a, C: Connection + ?Sized> {
+    connection: &'a mut C,
+}
+impl<'a, C: Connection + ?Sized> Settings<'a, C> {
+    pub fn new(connection: &'a mut C) -> Self {
+        Self { connection }
+    }
+
+    /// Reads a setting.
+    pub async fn get(&mut self, key: &KVKey) -> Result<String, C::Error> {
+        self.get_raw(key.name()).await
+    }
+
+    /// Writes a setting.
+    ///
+    /// Fails with [`EncodeError::StringTooLong`] without sending anything if the value is
+    /// longer than the key's [`max_len`](KVKey::max_len).
+    pub async fn set(&mut self, key: &KVKey, value: &str) -> Result<(), C::Error> {
+        write_setting(self.connection, key.name(), value, key.max_len()).await
+    }
+
+    /// Reads the raw string stored under any key.
+    pub async fn get_raw(&mut self, key: &str) -> Result<String, C::Error> {
+        read_setting(self.connection, key).await
+    }
+
+    /// Writes a raw string under any key.
+    pub async fn set_raw(&mut self, key: &str, value: &str) -> Result<(), C::Error> {
+        write_setting(self.connection, key, value, KVKey::from(key).max_len()).await
+    }
+}
@@ -0,0 +1,136 @@
+//! Queries about the state of the brain itself.
+//!
+//! The serial protocol has no known command for setting the brain's clock. Files written over
+//! it are stamped with the host's time instead, through the `timestamp` in their
+//! [`FileMetadata`](crate::packets::file::FileMetadata).
+
+use crate::{
+    capabilities::Capabilities,
+    connection::Connection,
+    decode::DecodeError,
+    packets::system::{
+        GetSystemFlagsPacket, GetSystemFlagsReplyPacket, GetSystemStatusPacket,
+        GetSystemStatusReplyPacket, GetSystemVersionPacket, GetSystemVersionReplyPacket,
+        GetSystemVersionReplyPayload, Query1Packet, Query1ReplyPacket, Query1ReplyPayload,
+        SystemFlags,
+    },
+};
+
+use super::Command;
+
+/// The state of the V5 battery connected to the brain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryStatus {
+    /// The remaining capacity in percent. The brain reports this in steps of 8%.
+    pub capacity: u8,
+    pub charging: bool,
+}
+
+/// Reads the capacity of the brain's battery.
+///
+/// VEXos only reports the battery's capacity over the serial protocol. Its voltage, current
+/// and temperature are only available to user programs running on the brain.
+#[derive(Debug, Clone, Copy)]
+pub struct GetBatteryStatus;
+impl Command for GetBatteryStatus {
+    type Output = BatteryStatus;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let flags = connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(GetSystemFlagsPacket::new(()))
+            .await?
+            .try_into_inner()?;
+
+        Ok(BatteryStatus {
+            capacity: flags.battery_percent(),
+            charging: flags.flags.contains(SystemFlags::BATTERY_CHARGING),
+        })
+    }
+}
+
+/// Reads the brain's unique hardware ID, as found in [`SystemDetails::unique_id`].
+///
+/// Unlike port names, which change as devices are plugged in and out, this identifies the
+/// same physical brain every time it is connected.
+///
+/// [`SystemDetails::unique_id`]: crate::packets::system::SystemDetails::unique_id
+#[derive(Debug, Clone, Copy)]
+pub struct GetUniqueId;
+impl Command for GetUniqueId {
+    type Output = u32;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection
+            .packet_handshake::<GetSystemStatusReplyPacket>(GetSystemStatusPacket::new(()))
+            .await?
+            .try_into_inner()?;
+        Ok(status.details.ok_or(DecodeError::PacketTooShort)?.unique_id)
+    }
+}
+
+/// Reads the brain's VEXos version and returns the features it supports.
+///
+/// The version comes from the brain's system status rather than [`GetSystemVersion`], which a
+/// controller answers with its own version when the brain is connected over radio.
+#[derive(Debug, Clone, Copy)]
+pub struct GetCapabilities;
+impl Command for GetCapabilities {
+    type Output = Capabilities;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let status = connection
+            .packet_handshake::<GetSystemStatusReplyPacket>(GetSystemStatusPacket::new(()))
+            .await?
+            .try_into_inner()?;
+        Ok(Capabilities::new(status.system_version))
+    }
+}
+
+/// Reads the VEXos version and product type of the connected device.
+///
+/// This is a simple CDC command, answered by the device itself rather than forwarded to a
+/// brain, so its [`product_type`](GetSystemVersionReplyPayload::product_type) tells brains
+/// and controllers apart.
+#[derive(Debug, Clone, Copy)]
+pub struct GetSystemVersion;
+impl Command for GetSystemVersion {
+    type Output = GetSystemVersionReplyPayload;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let reply = connection
+            .packet_handshake::<GetSystemVersionReplyPacket>(GetSystemVersionPacket::new(()))
+            .await?;
+        Ok(reply.payload)
+    }
+}
+
+/// Sends the simple CDC `Query1` command.
+///
+/// Much of its reply is still undocumented. See [`Query1ReplyPayload`].
+#[derive(Debug, Clone, Copy)]
+pub struct Query1;
+impl Command for Query1 {
+    type Output = Query1ReplyPayload;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let reply = connection
+            .packet_handshake::<Query1ReplyPacket>(Query1Packet::new(()))
+            .await?;
+        Ok(reply.payload)
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+
+use crate::{
+    cobs::CobsDecoder,
+    connection::{
+        time::{interval, MissedTickBehavior},
+        Connection,
+    },
+    decode::DecodeError,
+    encode::EncodeError,
+    packets::controller::{UserFifoPacket, UserFifoPayload, UserFifoReplyPacket},
+    string::FixedString,
+};
+
+use super::{
+    channels::{ReadChannel, STDIN, STDIO},
+    Command,
+};
+
+/// The maximum number of bytes in a single user FIFO write.
+const MAX_WRITE_SIZE: usize = 224;
+
+/// Polls the user program's stdout once.
+///
+/// Returns `None` if the program has not written anything since the last poll.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadStdout;
+impl Command for ReadStdout {
+    type Output = Option<Bytes>;
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        connection
+            .execute_command(ReadChannel { channel: STDIO })
+            .await
+    }
+}
+
+/// Writes to the user program's stdin.
+///
+/// The data is split into as many user FIFO writes as needed, and each write is acknowledged
+/// by the brain before the next is sent. Since the brain expects text, `data` must be valid
+/// UTF-8; writes are never split in the middle of a character.
+#[derive(Debug, Clone)]
+pub struct WriteStdin {
+    pub data: Bytes,
+}
+impl Command for WriteStdin {
+    type Output = ();
+
+    async fn execute<C: Connection + ?Sized>(
+        self,
+        connection: &mut C,
+    ) -> Result<Self::Output, C::Error> {
+        let mut text = std::str::from_utf8(&self.data).map_err(EncodeError::from)?;
+
+        while !text.is_empty() {
+            let mut split = text.len().min(MAX_WRITE_SIZE);
+            while !text.is_char_boundary(split) {
+                split -= 1;
+            }
+            let (chunk, rest) = text.split_at(split);
+
+            connection
+                .packet_handshake::<UserFifoReplyPacket>(UserFifoPacket::new(UserFifoPayload {
+                    channel: STDIN,
+                    write: Some(FixedString::new(chunk.to_string())?),
+                }))
+                .await?
+                .try_into_inner()?;
+            text = rest;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns a stream of the user program's stdout, polled through the system channel.
+///
+/// The brain is polled every `poll_interval` while the stream is being read. Polling only
+/// happens when the consumer asks for the next item, so a slow consumer naturally applies
+/// backpressure: output stays buffered on the brain instead of piling up on the host.
+///
+/// The stream ends after the first error.
+pub fn stdout_stream<C: Connection + ?Sized>(
+    connection: &mut C,
+    poll_interval: Duration,
+) -> impl Stream<Item = Result<Bytes, C::Error>> + '_ {
+    let mut ticker = interval(poll_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    stream::unfold(Some((connection, ticker)), |state| async move {
+        let (connection, mut ticker) = state?;
+        loop {
+            ticker.tick().await;
+            match connection.execute_command(ReadStdout).await {
+                Ok(Some(data)) => return Some((Ok(data), Some((connection, ticker)))),
+                Ok(None) => continue,
+                Err(e) => return Some((Err(e), None)),
+            }
+        }
+    })
+}
+
+/// Decodes a stream of user port output, such as [`stdout_stream`], as zero-delimited
+/// [COBS](crate::cobs) frames.
+///
+/// This is how PROS frames its output, and yields one item per message instead of whatever
+/// pieces the bytes happened to arrive in. Malformed frames are yielded as errors without
+/// ending the stream, but errors from `output` end it.
+pub fn cobs_messages<S, E>(output: S) -> impl Stream<Item = Result<Vec<u8>, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: From<DecodeError>,
+{
+    let output = Box::pin(output);
+    stream::unfold(Some((output, CobsDecoder::new())), |state| async move {
+        let (mut output, mut decoder) = state?;
+        loop {
+            if let Some(message) = decoder.next_message() {
+                let message = message.map_err(E::from);
+                return Some((message, Some((output, decoder))));
+            }
+            match output.next().await? {
+                Ok(data) => decoder.push(&data),
+                Err(e) => return Some((Err(e), None)),
+            }
+        }
+    })
+}
@@ -0,0 +1,330 @@
+//! Tuning knobs shared by connections and commands.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::LevelFilter;
+
+use crate::packets::CommandId;
+
+/// Crate-wide configuration for a connection.
+///
+/// A config is attached to a connection when it is opened and is read by every
+/// [`Command`](crate::commands::Command) executed on it. It can be overridden for a single
+/// command using [`Connection::override_config`](crate::connection::Connection::override_config).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// How packet handshakes wait for replies and resend packets.
+    pub retry: RetryPolicy,
+
+    /// Retry policies that replace [`Config::retry`] for specific packets.
+    ///
+    /// The first matching override is used.
+    pub packet_retry_overrides: Vec<PacketRetryOverride>,
+
+    /// The largest chunk of file data sent or requested in a single packet.
+    ///
+    /// The brain may negotiate a smaller window than this during a transfer.
+    pub transfer_chunk_size: u16,
+
+    /// How many file writes can be waiting for a reply at once during an upload.
+    ///
+    /// With a depth of 1, each chunk is only sent once the previous one has been acknowledged.
+    /// Deeper windows keep the link busy while replies are in flight, which mostly helps on
+    /// high-latency wireless links.
+    pub write_window: usize,
+
+    /// The most verbose level at which raw packet bytes are logged.
+    pub packet_log_level: LevelFilter,
+
+    /// Whether file transfers adjust this config to the [`LinkPath`] they run over.
+    ///
+    /// See [`Config::tuned_for`].
+    pub tune_for_link: bool,
+}
+
+impl Config {
+    /// The default configuration, tuned for a wired connection.
+    pub const DEFAULT: Self = Self {
+        retry: RetryPolicy::DEFAULT,
+        packet_retry_overrides: Vec::new(),
+        transfer_chunk_size: 4096,
+        write_window: 1,
+        packet_log_level: LevelFilter::Trace,
+        tune_for_link: true,
+    };
+
+    /// Returns the retry policy for an encoded device-bound packet.
+    pub fn retry_policy_for(&self, encoded: &[u8]) -> &RetryPolicy {
+        self.retry_override_for(encoded).unwrap_or(&self.retry)
+    }
+
+    /// Returns the overridden retry policy for an encoded device-bound packet, if there is one.
+    ///
+    /// Commands that need a different policy than [`Config::retry`] by default use this so
+    /// that a user's override still takes precedence.
+    pub fn retry_override_for(&self, encoded: &[u8]) -> Option<&RetryPolicy> {
+        self.packet_retry_overrides
+            .iter()
+            .find(|o| o.matches(encoded))
+            .map(|o| &o.policy)
+    }
+
+    /// Returns this config adjusted for file transfers over `link`.
+    ///
+    /// The defaults are tuned for a direct connection, so slower links get longer timeouts,
+    /// and wireless links also get smaller chunks and more attempts. Settings that are already
+    /// more forgiving than the link needs are kept, as are [`packet_retry_overrides`].
+    ///
+    /// [`packet_retry_overrides`]: Self::packet_retry_overrides
+    pub fn tuned_for(&self, link: LinkPath) -> Self {
+        let mut config = self.clone();
+        if !self.tune_for_link {
+            return config;
+        }
+
+        let retry = &mut config.retry;
+        match link {
+            LinkPath::Direct => {}
+            LinkPath::TetheredController | LinkPath::Bluetooth => {
+                retry.timeout = retry.timeout.max(Duration::from_secs(1));
+            }
+            LinkPath::Wireless => {
+                retry.timeout = retry.timeout.max(Duration::from_millis(1500));
+                retry.max_attempts = retry.max_attempts.max(8);
+                if retry.backoff == Backoff::None {
+                    retry.backoff = Backoff::ExponentialJitter {
+                        initial: Duration::from_millis(100),
+                        max: Duration::from_secs(1),
+                    };
+                }
+                config.transfer_chunk_size = config.transfer_chunk_size.min(1024);
+            }
+        }
+        config
+    }
+
+    /// Returns whether raw packets should be logged at the given level.
+    pub fn logs_packets_at(&self, level: log::Level) -> bool {
+        level <= self.packet_log_level
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub(crate) static DEFAULT_CONFIG: Config = Config::DEFAULT;
+
+/// The route that packets take between the host and the brain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkPath {
+    /// The brain is plugged straight into the host.
+    Direct,
+    /// A controller plugged into the host is connected to the brain by a cable.
+    TetheredController,
+    /// A controller plugged into the host is connected to the brain over VEXlink radio.
+    Wireless,
+    /// The host talks to the brain over Bluetooth.
+    Bluetooth,
+}
+
+/// How long to wait before resending a packet that got no reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// Resend immediately.
+    None,
+    /// Wait the same amount of time before every resend.
+    Fixed(Duration),
+    /// Start at `initial` and double the wait after every resend, up to `max`.
+    Exponential { initial: Duration, max: Duration },
+    /// Like [`Exponential`](Self::Exponential), but each wait is a random duration between half
+    /// of the exponential delay and all of it.
+    ///
+    /// The randomness keeps retries from lining up with a device that is busy on a fixed
+    /// schedule, such as a brain that has just rebooted or a congested radio link.
+    ExponentialJitter { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// Returns how long to wait after the given failed attempt, counting from zero.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        match *self {
+            Self::None => Duration::ZERO,
+            Self::Fixed(delay) => delay,
+            Self::Exponential { initial, max } => exponential_delay(initial, max, attempt),
+            Self::ExponentialJitter { initial, max } => {
+                let delay = exponential_delay(initial, max, attempt);
+                delay / 2 + (delay / 2).mul_f64(random_fraction())
+            }
+        }
+    }
+}
+
+fn exponential_delay(initial: Duration, max: Duration, attempt: usize) -> Duration {
+    initial
+        .checked_mul(1 << attempt.min(31))
+        .map_or(max, |delay| delay.min(max))
+}
+
+/// Returns a random number in `[0, 1)`, which is only meant for spreading out retries.
+fn random_fraction() -> f64 {
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    let bits = RandomState::new().hash_one(CALLS.fetch_add(1, Ordering::Relaxed));
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// How a packet handshake waits for replies and resends packets.
+///
+/// Wireless links through a controller usually need a longer timeout and more attempts
+/// than a wired connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a packet is sent before the handshake gives up. A packet is always
+    /// sent at least once.
+    pub max_attempts: usize,
+    /// How long to wait for a reply to each attempt.
+    pub timeout: Duration,
+    /// How long to wait between attempts, in addition to the timeout.
+    pub backoff: Backoff,
+}
+
+impl RetryPolicy {
+    /// The default policy, tuned for a wired connection.
+    pub const DEFAULT: Self = Self {
+        max_attempts: 5,
+        timeout: Duration::from_millis(500),
+        backoff: Backoff::None,
+    };
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A retry policy that applies only to packets with a specific command ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketRetryOverride {
+    /// The packet's command ID.
+    pub id: u8,
+    /// The packet's extended command ID.
+    ///
+    /// `None` matches every packet with [`id`](Self::id), including simple CDC packets,
+    /// which have no extended ID.
+    pub ext_id: Option<u8>,
+    pub policy: RetryPolicy,
+}
+
+impl PacketRetryOverride {
+    /// Creates an override for every packet of type `P`.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use vex_v5_serial::{config::{PacketRetryOverride, RetryPolicy}, packets::file::InitFileTransferPacket};
+    /// let slow_transfers = PacketRetryOverride::for_packet::<InitFileTransferPacket>(RetryPolicy {
+    ///     timeout: Duration::from_secs(2),
+    ///     ..RetryPolicy::DEFAULT
+    /// });
+    /// ```
+    pub fn for_packet<P: CommandId>(policy: RetryPolicy) -> Self {
+        Self {
+            id: P::ID,
+            ext_id: P::EXT_ID,
+            policy,
+        }
+    }
+
+    fn matches(&self, encoded: &[u8]) -> bool {
+        // Both kinds of device-bound packet put their IDs right after the 4 byte header.
+        encoded.get(4) == Some(&self.id)
+            && self
+                .ext_id
+                .is_none_or(|ext_id| encoded.get(5) == Some(&ext_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Backoff, Config, LinkPath, PacketRetryOverride, RetryPolicy};
+    use crate::packets::file::WriteFilePacket;
+
+    #[test]
+    fn retry_policy_overrides() {
+        let slow = RetryPolicy {
+            max_attempts: 10,
+            timeout: Duration::from_secs(2),
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(50),
+                max: Duration::from_millis(300),
+            },
+        };
+        let config = Config {
+            packet_retry_overrides: vec![PacketRetryOverride::for_packet::<WriteFilePacket>(slow)],
+            ..Config::DEFAULT
+        };
+
+        // Write file packets use the override; other CDC2 packets use the default.
+        let header = [0xC9, 0x36, 0xB8, 0x47];
+        assert_eq!(
+            config.retry_policy_for(&[&header[..], &[0x56, 0x13]].concat()),
+            &slow
+        );
+        assert_eq!(
+            config.retry_policy_for(&[&header[..], &[0x56, 0x12]].concat()),
+            &RetryPolicy::DEFAULT
+        );
+
+        assert_eq!(slow.backoff.delay(0), Duration::from_millis(50));
+        assert_eq!(slow.backoff.delay(2), Duration::from_millis(200));
+        assert_eq!(slow.backoff.delay(64), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn tunes_for_links() {
+        let wireless = Config::DEFAULT.tuned_for(LinkPath::Wireless);
+        assert_eq!(wireless.transfer_chunk_size, 1024);
+        assert_eq!(wireless.retry.max_attempts, 8);
+        assert_eq!(Config::DEFAULT.tuned_for(LinkPath::Direct), Config::DEFAULT);
+
+        let patient = Config {
+            retry: RetryPolicy {
+                timeout: Duration::from_secs(5),
+                ..RetryPolicy::DEFAULT
+            },
+            ..Config::DEFAULT
+        };
+        assert_eq!(
+            patient.tuned_for(LinkPath::Wireless).retry.timeout,
+            Duration::from_secs(5)
+        );
+
+        let untuned = Config {
+            tune_for_link: false,
+            ..Config::DEFAULT
+        };
+        assert_eq!(untuned.tuned_for(LinkPath::Wireless), untuned);
+    }
+
+    #[test]
+    fn jittered_backoff_stays_in_range() {
+        let backoff = Backoff::ExponentialJitter {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        for _ in 0..100 {
+            let delay = backoff.delay(1);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+            assert!(backoff.delay(10) <= Duration::from_secs(1));
+        }
+    }
+}
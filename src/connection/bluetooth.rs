@@ -1,38 +1,38 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    time::{Duration, Instant},
+};
 
 use btleplug::api::{
-    Central, CentralEvent, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
+    Central, CentralEvent, Characteristic, Manager as _, Peripheral as _, ScanFilter,
+    ValueNotification, WriteType,
 };
 use btleplug::platform::{Manager, Peripheral};
-use log::{debug, trace, warn};
+use log::{debug, trace, warn, Level};
 use thiserror::Error;
 use tokio::select;
 use tokio::time::sleep;
-use tokio_stream::StreamExt;
+use tokio_stream::{Stream, StreamExt};
 use uuid::Uuid;
 
+use crate::commands::CommandError;
+use crate::config::Config;
 use crate::connection::trim_packets;
 use crate::decode::{Decode, DecodeError};
 use crate::encode::{Encode, EncodeError};
 use crate::packets::cdc2::Cdc2Ack;
-
-use super::{Connection, ConnectionType, RawPacket};
-
-/// The BLE GATT Service that V5 Brains provide
-pub const V5_SERVICE: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_8757_72f6faeb13d5);
-
-/// User port GATT characteristic
-pub const CHARACTERISTIC_SYSTEM_TX: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_8757_72f6faeb1306); // WRITE_WITHOUT_RESPONSE | NOTIFY | INDICATE
-pub const CHARACTERISTIC_SYSTEM_RX: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_8757_72f6faeb13f5); // WRITE_WITHOUT_RESPONSE | WRITE | NOTIFY
-
-/// System port GATT characteristic
-pub const CHARACTERISTIC_USER_TX: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_8757_72f6faeb1316); // WRITE_WITHOUT_RESPONSE | NOTIFY | INDICATE
-pub const CHARACTERISTIC_USER_RX: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_8757_72f6faeb1326); // WRITE_WITHOUT_RESPONSE | WRITE | NOTIF
-
-/// PIN authentication characteristic
-pub const CHARACTERISTIC_PAIRING: Uuid = Uuid::from_u128(0x08590f7e_db05_467e_8757_72f6faeb13e5); // READ | WRITE_WITHOUT_RESPONSE | WRITE
-
-pub const UNPAIRED_MAGIC: u32 = 0xdeadface;
+use crate::packets::radio::RadioChannel;
+
+pub use super::gatt::*;
+use super::{
+    first_shutdown_error, instrument,
+    reconnect::{is_link_lost_io, LinkError},
+    restore_radio_channel,
+    stats::ConnectionStats,
+    transport::FrameDecoder,
+    Connection, ConnectionType, RawPacket,
+};
 
 #[derive(Debug, Clone)]
 pub struct BluetoothDevice(pub Peripheral);
@@ -41,6 +41,14 @@ impl BluetoothDevice {
     pub async fn connect(&self) -> Result<BluetoothConnection, BluetoothError> {
         BluetoothConnection::open(self.clone()).await
     }
+
+    /// Connects to the device using the given [`Config`].
+    pub async fn connect_with_config(
+        &self,
+        config: Config,
+    ) -> Result<BluetoothConnection, BluetoothError> {
+        BluetoothConnection::open_with_config(self.clone(), config).await
+    }
 }
 
 /// Discover and locate bluetooth-compatible V5 peripherals.
@@ -124,13 +132,27 @@ pub struct BluetoothConnection {
     pub user_rx: Characteristic,
     pub pairing: Characteristic,
 
+    notifications: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+    decoder: FrameDecoder,
     incoming_packets: Vec<RawPacket>,
+    user_buffer: VecDeque<u8>,
+    config: Config,
+    radio_channel: Option<RadioChannel>,
+    stats: ConnectionStats,
 }
 
 impl BluetoothConnection {
     pub const MAX_PACKET_SIZE: usize = 244;
 
     pub async fn open(device: BluetoothDevice) -> Result<Self, BluetoothError> {
+        Self::open_with_config(device, Config::default()).await
+    }
+
+    /// Opens a connection to the device using the given [`Config`].
+    pub async fn open_with_config(
+        device: BluetoothDevice,
+        config: Config,
+    ) -> Result<Self, BluetoothError> {
         let peripheral = device.0;
 
         if !peripheral.is_connected().await? {
@@ -168,24 +190,31 @@ impl BluetoothConnection {
             }
         }
 
-        let connection = Self {
+        let system_tx = system_tx.ok_or(BluetoothError::MissingCharacteristic)?;
+        let user_tx = user_tx.ok_or(BluetoothError::MissingCharacteristic)?;
+
+        peripheral.subscribe(&system_tx).await?;
+        peripheral.subscribe(&user_tx).await?;
+        // Notifications are only delivered to streams that exist when they arrive, so the
+        // stream is kept for the lifetime of the connection.
+        let notifications = peripheral.notifications().await?;
+
+        Ok(Self {
             peripheral,
-            system_tx: system_tx.ok_or(BluetoothError::MissingCharacteristic)?,
+            system_tx,
             system_rx: system_rx.ok_or(BluetoothError::MissingCharacteristic)?,
-            user_tx: user_tx.ok_or(BluetoothError::MissingCharacteristic)?,
+            user_tx,
             user_rx: user_rx.ok_or(BluetoothError::MissingCharacteristic)?,
             pairing: pairing.ok_or(BluetoothError::MissingCharacteristic)?,
 
+            notifications,
+            decoder: FrameDecoder::new(),
             incoming_packets: Vec::new(),
-        };
-
-        connection
-            .peripheral
-            .subscribe(&connection.system_tx)
-            .await?;
-        connection.peripheral.subscribe(&connection.user_tx).await?;
-
-        Ok(connection)
+            user_buffer: VecDeque::new(),
+            config,
+            radio_channel: None,
+            stats: ConnectionStats::default(),
+        })
     }
 
     pub async fn is_paired(&self) -> Result<bool, BluetoothError> {
@@ -220,24 +249,38 @@ impl BluetoothConnection {
         Ok(())
     }
 
-    async fn receive_one_packet(&mut self) -> Result<(), BluetoothError> {
-        //TODO: get notifications and store it rather than creating it every time this method is called
-        let mut notifs = self.peripheral.notifications().await?;
-
-        loop {
-            let Some(notification) = notifs.next().await else {
-                return Err(BluetoothError::NoResponse);
-            };
-
-            if notification.uuid == CHARACTERISTIC_SYSTEM_TX {
-                let data = notification.value;
-                debug!("Received packet: {:x?}", data);
-                let packet = RawPacket::new(data);
-                self.incoming_packets.push(packet);
-                break;
+    /// Waits for a single notification and routes it to either the incoming packet queue or
+    /// the user output buffer. Returns the UUID of the characteristic that sent it.
+    async fn receive_one_notification(&mut self) -> Result<Uuid, BluetoothError> {
+        let Some(notification) = self.notifications.next().await else {
+            return Err(BluetoothError::NoResponse);
+        };
+
+        match notification.uuid {
+            CHARACTERISTIC_SYSTEM_TX => {
+                // Large replies can be split across several notifications.
+                self.decoder.push(&notification.value);
+                while let Some(data) = self.decoder.next_frame() {
+                    if self.config.logs_packets_at(Level::Debug) {
+                        debug!("Received packet: {:x?}", data);
+                    }
+                    self.stats.packet_received(&data);
+                    self.incoming_packets.push(RawPacket::new(data));
+                }
+            }
+            CHARACTERISTIC_USER_TX => {
+                trace!("Received {} bytes of user output", notification.value.len());
+                self.user_buffer.extend(notification.value);
             }
+            uuid => trace!("Ignoring notification from {}", uuid),
         }
 
+        Ok(notification.uuid)
+    }
+
+    /// Receives a single packet from the system characteristic and adds it to the queue of incoming packets.
+    async fn receive_one_packet(&mut self) -> Result<(), BluetoothError> {
+        while self.receive_one_notification().await? != CHARACTERISTIC_SYSTEM_TX {}
         Ok(())
     }
 }
@@ -249,6 +292,44 @@ impl Connection for BluetoothConnection {
         ConnectionType::Bluetooth
     }
 
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.radio_channel = (channel != RadioChannel::Pit).then_some(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        Some(&mut self.stats)
+    }
+
+    async fn shutdown(mut self) -> Result<(), BluetoothError> {
+        let mut errors = Vec::new();
+
+        if self.radio_channel.take().is_some() {
+            if let Err(e) = restore_radio_channel(&mut self).await {
+                errors.push(e);
+            }
+        }
+
+        for characteristic in [&self.system_tx, &self.user_tx] {
+            if let Err(e) = self.peripheral.unsubscribe(characteristic).await {
+                errors.push(e.into());
+            }
+        }
+        if let Err(e) = self.peripheral.disconnect().await {
+            errors.push(e.into());
+        }
+        debug!("Bluetooth connection closed");
+
+        first_shutdown_error(errors)
+    }
+
     async fn send_packet(&mut self, packet: impl Encode) -> Result<(), BluetoothError> {
         if !self.is_paired().await? {
             return Err(BluetoothError::PairingRequired);
@@ -257,7 +338,11 @@ impl Connection for BluetoothConnection {
         // Encode the packet
         let encoded = packet.encode()?;
 
-        trace!("Sending packet: {:x?}", encoded);
+        if self.config.logs_packets_at(Level::Trace) {
+            trace!("Sending packet: {:x?}", encoded);
+        }
+        instrument::packet_sent(&encoded);
+        self.stats.packet_sent(&encoded);
 
         // Write the packet to the system rx characteristic.
         self.peripheral
@@ -286,12 +371,27 @@ impl Connection for BluetoothConnection {
         }
     }
 
-    async fn read_user(&mut self, _buf: &mut [u8]) -> Result<usize, BluetoothError> {
-        todo!();
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, BluetoothError> {
+        while self.user_buffer.is_empty() {
+            self.receive_one_notification().await?;
+        }
+
+        let len = self.user_buffer.len().min(buf.len());
+        for (dest, byte) in buf.iter_mut().zip(self.user_buffer.drain(..len)) {
+            *dest = byte;
+        }
+
+        Ok(len)
     }
 
-    async fn write_user(&mut self, _buf: &[u8]) -> Result<usize, BluetoothError> {
-        todo!();
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, BluetoothError> {
+        for chunk in buf.chunks(Self::MAX_PACKET_SIZE) {
+            self.peripheral
+                .write(&self.user_rx, chunk, WriteType::WithoutResponse)
+                .await?;
+        }
+
+        Ok(buf.len())
     }
 }
 
@@ -303,6 +403,8 @@ pub enum BluetoothError {
     EncodeError(#[from] EncodeError),
     #[error("Packet decoding error: {0}")]
     DecodeError(#[from] DecodeError),
+    #[error("Command failed: {0}")]
+    CommandError(#[from] CommandError),
     #[error("Packet timeout")]
     Timeout,
     #[error("NACK received: {0:?}")]
@@ -320,3 +422,12 @@ pub enum BluetoothError {
     #[error("Pairing is required")]
     PairingRequired,
 }
+impl LinkError for BluetoothError {
+    fn is_link_lost(&self) -> bool {
+        match self {
+            Self::IoError(e) => is_link_lost_io(e),
+            Self::Btleplug(btleplug::Error::NotConnected | btleplug::Error::DeviceNotFound) => true,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Type-erased connections.
+//!
+//! [`Connection`] has generic methods, so it can't be used as a trait object. A
+//! [`BoxedConnection`] wraps any connection behind a [`DynConnection`] that only moves raw
+//! packet bytes, and implements [`Connection`] itself by decoding those bytes. This lets an
+//! application hold serial, Bluetooth, and TCP connections in one type.
+
+use std::time::Duration;
+
+use futures::{future::LocalBoxFuture, FutureExt};
+use thiserror::Error;
+
+use crate::{
+    commands::CommandError,
+    config::Config,
+    decode::{Decode, DecodeError},
+    encode::{Encode, EncodeError},
+    packets::{cdc2::Cdc2Ack, radio::RadioChannel},
+};
+
+use super::{
+    stats::ConnectionStats, time::Instant, trim_packets, Connection, ConnectionType, RawFrame,
+    RawPacket,
+};
+
+/// An object-safe view of a [`Connection`] that sends and receives undecoded packets.
+///
+/// This is implemented for every connection whose error type can be boxed.
+pub trait DynConnection {
+    fn connection_type(&self) -> ConnectionType;
+
+    fn config(&self) -> &Config;
+
+    fn record_radio_channel(&mut self, channel: RadioChannel);
+
+    fn stats(&self) -> Option<&ConnectionStats>;
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats>;
+
+    /// Gracefully closes the connection. See [`Connection::shutdown`].
+    fn shutdown_boxed(self: Box<Self>) -> LocalBoxFuture<'static, Result<(), BoxedError>>;
+
+    /// Sends an encoded packet.
+    fn send_raw(&mut self, packet: Vec<u8>) -> LocalBoxFuture<'_, Result<(), BoxedError>>;
+
+    /// Receives the next packet from the device, whatever it is.
+    fn receive_raw(&mut self, timeout: Duration)
+        -> LocalBoxFuture<'_, Result<Vec<u8>, BoxedError>>;
+
+    fn read_user<'a>(
+        &'a mut self,
+        buf: &'a mut [u8],
+    ) -> LocalBoxFuture<'a, Result<usize, BoxedError>>;
+
+    fn write_user<'a>(&'a mut self, buf: &'a [u8])
+        -> LocalBoxFuture<'a, Result<usize, BoxedError>>;
+}
+impl<C> DynConnection for C
+where
+    C: Connection + 'static,
+    C::Error: Send + Sync + 'static,
+{
+    fn connection_type(&self) -> ConnectionType {
+        Connection::connection_type(self)
+    }
+
+    fn config(&self) -> &Config {
+        Connection::config(self)
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        Connection::record_radio_channel(self, channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        Connection::stats(self)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        Connection::stats_mut(self)
+    }
+
+    fn shutdown_boxed(self: Box<Self>) -> LocalBoxFuture<'static, Result<(), BoxedError>> {
+        async move { Connection::shutdown(*self).await.map_err(BoxedError::new) }.boxed_local()
+    }
+
+    fn send_raw(&mut self, packet: Vec<u8>) -> LocalBoxFuture<'_, Result<(), BoxedError>> {
+        async move {
+            Connection::send_packet(self, packet)
+                .await
+                .map_err(BoxedError::new)
+        }
+        .boxed_local()
+    }
+
+    fn receive_raw(
+        &mut self,
+        timeout: Duration,
+    ) -> LocalBoxFuture<'_, Result<Vec<u8>, BoxedError>> {
+        async move {
+            Connection::receive_packet::<RawFrame>(self, timeout)
+                .await
+                .map(|frame| frame.0)
+                .map_err(BoxedError::new)
+        }
+        .boxed_local()
+    }
+
+    fn read_user<'a>(
+        &'a mut self,
+        buf: &'a mut [u8],
+    ) -> LocalBoxFuture<'a, Result<usize, BoxedError>> {
+        async move {
+            Connection::read_user(self, buf)
+                .await
+                .map_err(BoxedError::new)
+        }
+        .boxed_local()
+    }
+
+    fn write_user<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+    ) -> LocalBoxFuture<'a, Result<usize, BoxedError>> {
+        async move {
+            Connection::write_user(self, buf)
+                .await
+                .map_err(BoxedError::new)
+        }
+        .boxed_local()
+    }
+}
+
+/// A [`Connection`] of any type.
+///
+/// The futures returned by a boxed connection are not `Send`, so it has to be used from a
+/// single task.
+pub struct BoxedConnection {
+    inner: Box<dyn DynConnection>,
+    incoming_packets: Vec<RawPacket>,
+}
+impl BoxedConnection {
+    pub fn new(connection: impl DynConnection + 'static) -> Self {
+        Self::from(Box::new(connection) as Box<dyn DynConnection>)
+    }
+}
+impl From<Box<dyn DynConnection>> for BoxedConnection {
+    fn from(inner: Box<dyn DynConnection>) -> Self {
+        Self {
+            inner,
+            incoming_packets: Vec::new(),
+        }
+    }
+}
+impl Connection for BoxedConnection {
+    type Error = BoxedError;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    fn config(&self) -> &Config {
+        self.inner.config()
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.inner.record_radio_channel(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        self.inner.stats_mut()
+    }
+
+    async fn shutdown(self) -> Result<(), BoxedError> {
+        self.inner.shutdown_boxed().await
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), BoxedError> {
+        self.inner.send_raw(packet.encode()?).await
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, BoxedError> {
+        // Packets that don't decode as `P` are kept around for a later call, like the
+        // inner connection would have done.
+        let deadline = Instant::now() + timeout;
+        loop {
+            for packet in self.incoming_packets.iter_mut() {
+                if let Ok(decoded) = packet.decode_and_use::<P>() {
+                    trim_packets(&mut self.incoming_packets);
+                    return Ok(decoded);
+                }
+            }
+            trim_packets(&mut self.incoming_packets);
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let frame = self.inner.receive_raw(remaining).await?;
+            self.incoming_packets.push(RawPacket::forwarded(frame));
+        }
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, BoxedError> {
+        self.inner.read_user(buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, BoxedError> {
+        self.inner.write_user(buf).await
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BoxedError {
+    #[error(transparent)]
+    Connection(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Packet encoding error: {0}")]
+    EncodeError(#[from] EncodeError),
+    #[error("Packet decoding error: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("Command failed: {0}")]
+    CommandError(#[from] CommandError),
+    #[error("NACK received: {0:?}")]
+    Nack(#[from] Cdc2Ack),
+}
+impl BoxedError {
+    fn new(error: impl std::error::Error + Send + Sync + 'static) -> Self {
+        Self::Connection(Box::new(error))
+    }
+
+    /// Returns the inner connection's error, if it is of type `E`.
+    pub fn downcast_ref<E: std::error::Error + 'static>(&self) -> Option<&E> {
+        match self {
+            Self::Connection(error) => error.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BoxedConnection;
+    use crate::{
+        commands::terminal::ReadStdout,
+        connection::{
+            mock::{cdc2_reply, stdout_exchange, MockConnection, MockError},
+            Connection, ConnectionType,
+        },
+        packets::cdc2::Cdc2Ack,
+    };
+
+    #[tokio::test]
+    async fn forwards_packets() {
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        let (poll, reply) = stdout_exchange("hi");
+        // A stale reply to another command is skipped, not lost.
+        mock.expect_replies(poll, vec![cdc2_reply::<86, 88>(Cdc2Ack::Ack, &[]), reply])
+            .unwrap();
+
+        let mut connection = BoxedConnection::new(mock);
+        let output = connection.execute_command(ReadStdout).await.unwrap();
+        assert_eq!(output.as_deref(), Some(&b"hi"[..]));
+
+        let error = connection.execute_command(ReadStdout).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<MockError>(),
+            Some(MockError::UnexpectedPacket { .. })
+        ));
+    }
+}
@@ -0,0 +1,72 @@
+//! Sending the same command to several devices at once.
+
+use std::time::Duration;
+
+use futures::future::join_all;
+use log::debug;
+
+use crate::commands::Command;
+
+use super::{
+    time::{sleep_until, Instant},
+    Connection,
+};
+
+/// The outcome of a command sent to one device by [`fan_out`].
+#[derive(Debug)]
+pub struct FanOutResult<T, E> {
+    /// The index of the connection in the slice passed to [`fan_out`].
+    pub index: usize,
+    /// How long after the shared start time this device's command actually started.
+    pub start_skew: Duration,
+    /// How long the command took to complete on this device.
+    pub elapsed: Duration,
+    pub result: Result<T, E>,
+}
+
+/// Executes a clone of `command` on every connection concurrently.
+///
+/// Every device waits until a shared start time `lead` from now before its command is
+/// executed, so that the commands go out as close together as possible. This is best-effort:
+/// all commands run on the calling task, so the achievable alignment depends on how quickly
+/// each connection can send its first packet.
+///
+/// Results are returned in the same order as `connections`.
+pub async fn fan_out<C, Cmd>(
+    connections: &mut [C],
+    command: Cmd,
+    lead: Duration,
+) -> Vec<FanOutResult<Cmd::Output, C::Error>>
+where
+    C: Connection,
+    Cmd: Command + Clone,
+{
+    let start_at = Instant::now() + lead;
+    debug!(
+        "Fanning out {} to {} devices",
+        std::any::type_name::<Cmd>(),
+        connections.len()
+    );
+
+    join_all(
+        connections
+            .iter_mut()
+            .enumerate()
+            .map(|(index, connection)| {
+                let command = command.clone();
+                async move {
+                    sleep_until(start_at.into()).await;
+                    let started = Instant::now();
+                    let result = connection.execute_command(command).await;
+
+                    FanOutResult {
+                        index,
+                        start_skew: started.saturating_duration_since(start_at),
+                        elapsed: started.elapsed(),
+                        result,
+                    }
+                }
+            }),
+    )
+    .await
+}
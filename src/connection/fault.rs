@@ -0,0 +1,268 @@
+//! Deterministic fault injection for testing retry and resync logic.
+//!
+//! [`FaultInjector`] wraps any [`Connection`] and drops, corrupts, duplicates, or delays
+//! frames according to a [`FaultPolicy`]. Faults are chosen by a seeded generator, so
+//! the same policy always produces the same sequence of faults for the same traffic.
+
+use std::time::{Duration, Instant};
+
+use log::debug;
+use tokio::time::sleep;
+
+use crate::{
+    config::Config,
+    decode::Decode,
+    encode::Encode,
+    packets::{cdc2::Cdc2Ack, radio::RadioChannel},
+};
+
+use super::{stats::ConnectionStats, Connection, ConnectionType};
+
+/// How often each kind of fault is injected.
+///
+/// Rates are probabilities between `0.0` and `1.0`. Outgoing frames get at most one fault each,
+/// checked in the order drop, corrupt, duplicate, delay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultPolicy {
+    /// The seed for the fault generator.
+    pub seed: u64,
+
+    /// The chance that an outgoing frame is never sent.
+    pub drop_rate: f64,
+    /// The chance that a single bit of an outgoing frame is flipped.
+    pub corrupt_rate: f64,
+    /// The chance that an outgoing frame is sent twice.
+    pub duplicate_rate: f64,
+    /// The chance that an outgoing frame is held back for [`FaultPolicy::delay`].
+    pub delay_rate: f64,
+    /// How long delayed frames are held back.
+    pub delay: Duration,
+
+    /// The chance that an incoming reply is discarded before the caller sees it.
+    pub drop_reply_rate: f64,
+}
+impl Default for FaultPolicy {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            drop_rate: 0.0,
+            corrupt_rate: 0.0,
+            duplicate_rate: 0.0,
+            delay_rate: 0.0,
+            delay: Duration::from_millis(50),
+            drop_reply_rate: 0.0,
+        }
+    }
+}
+
+/// A fault that was injected into the traffic of a [`FaultInjector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// An outgoing frame was dropped.
+    Drop,
+    /// The bit `bit` of byte `index` in an outgoing frame was flipped.
+    Corrupt { index: usize, bit: u8 },
+    /// An outgoing frame was sent twice.
+    Duplicate,
+    /// An outgoing frame was delayed.
+    Delay(Duration),
+    /// An incoming reply was dropped.
+    DropReply,
+}
+
+/// Chooses faults from a [`FaultPolicy`] using a seeded xorshift generator.
+#[derive(Debug, Clone)]
+struct FaultSchedule {
+    policy: FaultPolicy,
+    state: u64,
+}
+impl FaultSchedule {
+    fn new(policy: FaultPolicy) -> Self {
+        // Xorshift gets stuck on zero, so mix the seed with an odd constant.
+        let state = policy.seed ^ 0x9E37_79B9_7F4A_7C15;
+        Self { policy, state }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        let sample = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        sample < rate
+    }
+
+    fn outgoing(&mut self, frame_len: usize) -> Option<Fault> {
+        if self.chance(self.policy.drop_rate) {
+            Some(Fault::Drop)
+        } else if frame_len > 0 && self.chance(self.policy.corrupt_rate) {
+            let index = (self.next_u64() % frame_len as u64) as usize;
+            let bit = (self.next_u64() % 8) as u8;
+            Some(Fault::Corrupt { index, bit })
+        } else if self.chance(self.policy.duplicate_rate) {
+            Some(Fault::Duplicate)
+        } else if self.chance(self.policy.delay_rate) {
+            Some(Fault::Delay(self.policy.delay))
+        } else {
+            None
+        }
+    }
+
+    fn incoming(&mut self) -> Option<Fault> {
+        self.chance(self.policy.drop_reply_rate)
+            .then_some(Fault::DropReply)
+    }
+}
+
+/// A [`Connection`] that injects faults into the traffic of another connection.
+pub struct FaultInjector<C: Connection> {
+    inner: C,
+    schedule: FaultSchedule,
+    injected: Vec<Fault>,
+}
+impl<C: Connection> FaultInjector<C> {
+    pub fn new(inner: C, policy: FaultPolicy) -> Self {
+        Self {
+            inner,
+            schedule: FaultSchedule::new(policy),
+            injected: Vec::new(),
+        }
+    }
+
+    /// Returns every fault injected so far, in order.
+    pub fn injected(&self) -> &[Fault] {
+        &self.injected
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn record(&mut self, fault: Fault) {
+        debug!("Injecting fault: {:?}", fault);
+        self.injected.push(fault);
+    }
+}
+impl<C: Connection> Connection for FaultInjector<C> {
+    type Error = C::Error;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    fn config(&self) -> &Config {
+        self.inner.config()
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.inner.record_radio_channel(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        self.inner.stats_mut()
+    }
+
+    async fn shutdown(self) -> Result<(), Self::Error> {
+        self.inner.shutdown().await
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), Self::Error> {
+        let mut frame = packet.encode()?;
+
+        match self.schedule.outgoing(frame.len()) {
+            None => self.inner.send_packet(frame).await,
+            Some(fault) => {
+                self.record(fault);
+                match fault {
+                    Fault::Drop => Ok(()),
+                    Fault::Corrupt { index, bit } => {
+                        frame[index] ^= 1 << bit;
+                        self.inner.send_packet(frame).await
+                    }
+                    Fault::Duplicate => {
+                        self.inner.send_packet(frame.clone()).await?;
+                        self.inner.send_packet(frame).await
+                    }
+                    Fault::Delay(delay) => {
+                        sleep(delay).await;
+                        self.inner.send_packet(frame).await
+                    }
+                    Fault::DropReply => unreachable!(),
+                }
+            }
+        }
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, Self::Error> {
+        let start = Instant::now();
+        loop {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return Err(Cdc2Ack::Timeout.into());
+            }
+
+            let packet = self.inner.receive_packet::<P>(remaining).await?;
+            match self.schedule.incoming() {
+                Some(fault) => self.record(fault),
+                None => return Ok(packet),
+            }
+        }
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.inner.read_user(buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.inner.write_user(buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fault, FaultPolicy, FaultSchedule};
+
+    #[test]
+    fn schedule_is_deterministic() {
+        let policy = FaultPolicy {
+            seed: 1234,
+            drop_rate: 0.1,
+            corrupt_rate: 0.1,
+            duplicate_rate: 0.1,
+            delay_rate: 0.1,
+            drop_reply_rate: 0.2,
+            ..Default::default()
+        };
+
+        let run = || {
+            let mut schedule = FaultSchedule::new(policy.clone());
+            (0..256)
+                .map(|_| (schedule.outgoing(16), schedule.incoming()))
+                .collect::<Vec<_>>()
+        };
+        let faults = run();
+        assert_eq!(faults, run());
+        assert!(faults.iter().any(|(fault, _)| fault.is_some()));
+        assert!(faults.iter().all(
+            |(fault, _)| !matches!(fault, Some(Fault::Corrupt { index, .. }) if *index >= 16)
+        ));
+
+        let mut quiet = FaultSchedule::new(FaultPolicy::default());
+        assert!((0..256).all(|_| quiet.outgoing(16).is_none() && quiet.incoming().is_none()));
+    }
+}
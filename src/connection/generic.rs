@@ -1,15 +1,18 @@
 use crate::{
-    connection::{bluetooth, serial, Connection, ConnectionType},
+    commands::CommandError,
+    config::Config,
+    connection::{bluetooth, serial, stats::ConnectionStats, Connection, ConnectionType},
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
-    packets::cdc2::Cdc2Ack,
+    packets::{cdc2::Cdc2Ack, radio::RadioChannel},
 };
 use futures::{try_join, TryFutureExt};
 use std::time::Duration;
 use thiserror::Error;
 
-use super::{bluetooth::BluetoothError, serial::SerialError};
+use super::{bluetooth::BluetoothError, reconnect::LinkError, serial::SerialError};
 
+#[allow(clippy::large_enum_variant)]
 pub enum GenericConnection {
     Bluetooth(bluetooth::BluetoothConnection),
     Serial(serial::SerialConnection),
@@ -24,6 +27,42 @@ impl Connection for GenericConnection {
         }
     }
 
+    fn config(&self) -> &Config {
+        match self {
+            GenericConnection::Bluetooth(c) => c.config(),
+            GenericConnection::Serial(s) => s.config(),
+        }
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        match self {
+            GenericConnection::Bluetooth(c) => c.record_radio_channel(channel),
+            GenericConnection::Serial(s) => s.record_radio_channel(channel),
+        }
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        match self {
+            GenericConnection::Bluetooth(c) => c.stats(),
+            GenericConnection::Serial(s) => s.stats(),
+        }
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        match self {
+            GenericConnection::Bluetooth(c) => c.stats_mut(),
+            GenericConnection::Serial(s) => s.stats_mut(),
+        }
+    }
+
+    async fn shutdown(self) -> Result<(), GenericError> {
+        match self {
+            GenericConnection::Bluetooth(c) => c.shutdown().await?,
+            GenericConnection::Serial(s) => s.shutdown().await?,
+        };
+        Ok(())
+    }
+
     async fn send_packet(&mut self, packet: impl Encode) -> Result<(), GenericError> {
         match self {
             GenericConnection::Bluetooth(c) => c.send_packet(packet).await?,
@@ -123,6 +162,32 @@ impl GenericDevice {
             GenericDevice::Serial(d) => Ok(GenericConnection::Serial(d.connect(timeout)?)),
         }
     }
+
+    /// Connects to the device using the given [`Config`].
+    pub async fn connect_with_config(
+        &self,
+        timeout: Duration,
+        config: Config,
+    ) -> Result<GenericConnection, GenericError> {
+        match self.clone() {
+            GenericDevice::Bluetooth(d) => Ok(GenericConnection::Bluetooth(
+                d.connect_with_config(config).await?,
+            )),
+            GenericDevice::Serial(d) => Ok(GenericConnection::Serial(
+                d.connect_with_config(timeout, config)?,
+            )),
+        }
+    }
+
+    /// Returns the USB serial number, location and other details of a serial device.
+    ///
+    /// Bluetooth devices have no USB details, so this returns `None` for them.
+    pub fn usb_info(&self) -> Option<&serial::UsbDeviceInfo> {
+        match self {
+            GenericDevice::Bluetooth(_) => None,
+            GenericDevice::Serial(d) => Some(d.usb_info()),
+        }
+    }
 }
 impl From<serial::SerialDevice> for GenericDevice {
     fn from(d: serial::SerialDevice) -> Self {
@@ -140,7 +205,7 @@ pub async fn find_devices() -> Result<Vec<GenericDevice>, GenericError> {
         bluetooth_devices().map_err(GenericError::BluetoothError),
         serial_devices().map_err(GenericError::SerialError),
     }
-    .map(|(bluetooth, serial)| bluetooth.into_iter().chain(serial.into_iter()).collect())?;
+    .map(|(bluetooth, serial)| bluetooth.into_iter().chain(serial).collect())?;
     Ok(res)
 }
 
@@ -167,8 +232,19 @@ pub enum GenericError {
     EncodeError(#[from] EncodeError),
     #[error("Packet decoding error: {0}")]
     DecodeError(#[from] DecodeError),
+    #[error("Command failed: {0}")]
+    CommandError(#[from] CommandError),
     #[error("NACK received: {0:?}")]
     Nack(#[from] Cdc2Ack),
     #[error("Pairing is not supported over any connection other than Bluetooth")]
     PairingNotSupported,
 }
+impl LinkError for GenericError {
+    fn is_link_lost(&self) -> bool {
+        match self {
+            Self::SerialError(e) => e.is_link_lost(),
+            Self::BluetoothError(e) => e.is_link_lost(),
+            _ => false,
+        }
+    }
+}
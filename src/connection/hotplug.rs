@@ -0,0 +1,91 @@
+//! Native hotplug notifications for [`watch_devices`](super::serial::watch_devices).
+//!
+//! Only Linux is supported. A udev monitor reports tty devices being added and removed. The
+//! monitor's socket can't be moved between threads, so it is read on a thread of its own,
+//! which wakes the watcher through a channel.
+//!
+//! There is no IOKit or `WM_DEVICECHANGE` backend, so macOS and Windows keep polling even
+//! with the `hotplug` feature enabled.
+
+use std::{io, os::fd::AsRawFd, sync, thread};
+
+use log::{debug, warn};
+use tokio::sync::mpsc;
+
+/// How long the monitor thread waits for events before checking if the watcher was dropped.
+const CLOSE_CHECK_INTERVAL_MS: i32 = 1000;
+
+/// Starts watching for tty devices being added or removed.
+///
+/// The returned receiver gets a message after each batch of udev events. The monitor thread
+/// exits once the receiver is dropped.
+pub(crate) fn tty_changes() -> io::Result<mpsc::UnboundedReceiver<()>> {
+    let (ready_sender, ready) = sync::mpsc::channel();
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    thread::Builder::new()
+        .name("vex-hotplug".to_string())
+        .spawn(move || {
+            let socket = match listen() {
+                Ok(socket) => {
+                    _ = ready_sender.send(Ok(()));
+                    socket
+                }
+                Err(e) => {
+                    _ = ready_sender.send(Err(e));
+                    return;
+                }
+            };
+            forward_events(socket, sender);
+        })?;
+
+    ready
+        .recv()
+        .map_err(|_| io::Error::other("udev monitor thread exited"))??;
+    Ok(receiver)
+}
+
+/// Opens a udev monitor for tty devices.
+fn listen() -> io::Result<libudev::MonitorSocket> {
+    let context = libudev::Context::new()?;
+    let mut monitor = libudev::Monitor::new(&context)?;
+    monitor.match_subsystem("tty")?;
+    Ok(monitor.listen()?)
+}
+
+/// Wakes `sender` for each batch of events on `socket`, until `sender` is closed.
+fn forward_events(mut socket: libudev::MonitorSocket, sender: mpsc::UnboundedSender<()>) {
+    loop {
+        let mut fd = libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `fd` is a single valid pollfd, and the socket outlives the call.
+        let ready = unsafe { libc::poll(&mut fd, 1, CLOSE_CHECK_INTERVAL_MS) };
+        if sender.is_closed() {
+            return;
+        }
+        if ready < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            warn!("Stopped watching udev events: {}", error);
+            return;
+        }
+
+        let mut changed = false;
+        while let Some(event) = socket.receive_event() {
+            debug!(
+                "udev {} event for {:?}",
+                event.event_type(),
+                event.devnode()
+            );
+            changed = true;
+        }
+        if changed && sender.send(()).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! `tracing` instrumentation for packets and commands.
+//!
+//! Without the `tracing` feature every function here compiles down to nothing.
+
+use std::future::Future;
+
+use crate::encode::Encode;
+
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
+
+/// Returns the command ID and, for CDC2 packets, the extended command ID of an encoded packet.
+#[cfg(feature = "tracing")]
+fn packet_ids(encoded: &[u8]) -> (Option<u8>, Option<u8>) {
+    // Both kinds of packet put their IDs right after the 4 byte device-bound header.
+    // CDC2 packets are the ones sent under the 0x56 and 0x58 command IDs.
+    let id = encoded.get(4).copied();
+    let ext_id = match id {
+        Some(0x56 | 0x58) => encoded.get(5).copied(),
+        _ => None,
+    };
+    (id, ext_id)
+}
+
+/// Records an encoded packet being sent to the device.
+pub(crate) fn packet_sent(encoded: &[u8]) {
+    #[cfg(feature = "tracing")]
+    {
+        let (id, ext_id) = packet_ids(encoded);
+        tracing::trace!(id, ext_id, size = encoded.len(), "sent packet");
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = encoded;
+}
+
+/// Records raw bytes of a packet being received from the device.
+pub(crate) fn packet_received(bytes: &[u8]) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        id = bytes.get(2).copied(),
+        size = bytes.len(),
+        "received packet"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = bytes;
+}
+
+/// Records a received packet being decoded as a reply of type `D`.
+pub(crate) fn packet_decoded<D>() {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(reply = std::any::type_name::<D>(), "decoded packet");
+}
+
+/// Runs a command's future inside a span named after the command.
+#[cfg(feature = "tracing")]
+pub(crate) fn command<C, F: Future>(future: F) -> impl Future<Output = F::Output> {
+    future.instrument(tracing::debug_span!(
+        "command",
+        command = std::any::type_name::<C>()
+    ))
+}
+#[cfg(not(feature = "tracing"))]
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn command<C, F: Future>(future: F) -> F {
+    future
+}
+
+/// Runs a packet handshake inside a span describing the packet being sent.
+#[cfg(feature = "tracing")]
+pub(crate) fn handshake<D, F: Future>(
+    packet: &impl Encode,
+    future: F,
+) -> impl Future<Output = F::Output> {
+    let encoded = packet.encode().unwrap_or_default();
+    let (id, ext_id) = packet_ids(&encoded);
+    future.instrument(tracing::trace_span!(
+        "handshake",
+        id,
+        ext_id,
+        size = encoded.len(),
+        reply = std::any::type_name::<D>()
+    ))
+}
+#[cfg(not(feature = "tracing"))]
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn handshake<D, F: Future>(_packet: &impl Encode, future: F) -> F {
+    future
+}
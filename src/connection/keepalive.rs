@@ -0,0 +1,270 @@
+//! Noticing a dead link while a connection is otherwise idle.
+
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::sync::broadcast;
+
+use crate::{
+    config::{Config, RetryPolicy},
+    decode::Decode,
+    encode::Encode,
+    packets::{
+        radio::RadioChannel,
+        system::{GetSystemVersionPacket, GetSystemVersionReplyPacket},
+    },
+};
+
+use super::{
+    stats::ConnectionStats,
+    time::{sleep_until, Instant},
+    Connection, ConnectionType,
+};
+
+/// Whether a [`KeepaliveConnection`]'s device is still answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    /// Too many keepalive queries in a row went unanswered.
+    Unhealthy,
+}
+
+/// How often a [`KeepaliveConnection`] checks on its device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepalivePolicy {
+    /// How long the connection can go without a reply before a keepalive query is sent.
+    pub interval: Duration,
+    /// How many keepalive queries in a row can fail before the connection is unhealthy.
+    pub max_failures: usize,
+}
+impl Default for KeepalivePolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(2),
+            max_failures: 3,
+        }
+    }
+}
+
+/// A [`Connection`] that periodically checks that its device is still answering.
+///
+/// While [`run`](Self::run) is being awaited, a system version query is sent whenever the
+/// connection has gone [`KeepalivePolicy::interval`] without receiving a packet. Changes in
+/// the connection's [`Health`] are sent to every receiver from [`subscribe`](Self::subscribe).
+/// Any reply, including one to an ordinary command, counts as a sign of life.
+///
+/// `run` never returns, so it is meant to be raced against the rest of an application's work:
+///
+/// ```no_run
+/// # async fn example(
+/// #     connection: vex_v5_serial::connection::serial::SerialConnection,
+/// #     mut requests: tokio::sync::mpsc::Receiver<()>,
+/// # ) {
+/// use vex_v5_serial::{
+///     commands::system::GetBatteryStatus,
+///     connection::{keepalive::KeepaliveConnection, Connection},
+/// };
+///
+/// let mut connection = KeepaliveConnection::new(connection, Default::default());
+/// loop {
+///     tokio::select! {
+///         Some(()) = requests.recv() => {
+///             println!("{:?}", connection.execute_command(GetBatteryStatus).await);
+///         }
+///         _ = connection.run() => {}
+///     }
+/// }
+/// # }
+/// ```
+///
+/// A keepalive query interrupted this way is simply abandoned. If its reply arrives later, it
+/// is discarded like any other stale packet.
+pub struct KeepaliveConnection<C> {
+    inner: C,
+    policy: KeepalivePolicy,
+    health: Health,
+    failures: usize,
+    next_check: Instant,
+    events: broadcast::Sender<Health>,
+}
+impl<C: Connection> KeepaliveConnection<C> {
+    /// How many unreceived events a subscriber can fall behind by before it starts missing them.
+    const EVENT_CAPACITY: usize = 16;
+
+    pub fn new(inner: C, policy: KeepalivePolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            health: Health::Healthy,
+            failures: 0,
+            next_check: Instant::now() + policy.interval,
+            events: broadcast::channel(Self::EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Returns a receiver for every change in health after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<Health> {
+        self.events.subscribe()
+    }
+
+    pub fn health(&self) -> Health {
+        self.health
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Sends keepalive queries whenever the connection is idle. This never returns.
+    pub async fn run(&mut self) {
+        loop {
+            sleep_until(self.next_check.into()).await;
+            // A reply may have arrived while this was cancelled and awaited again.
+            if Instant::now() >= self.next_check {
+                self.check().await;
+            }
+        }
+    }
+
+    /// Sends a single keepalive query and returns the connection's health afterwards.
+    pub async fn check(&mut self) -> Health {
+        // A lost query is retried on the next check, so only one attempt is made.
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            ..self.inner.config().retry
+        };
+        let result = self
+            .inner
+            .packet_handshake_with_policy::<GetSystemVersionReplyPacket>(
+                &policy,
+                GetSystemVersionPacket::new(()),
+            )
+            .await;
+
+        match result {
+            Ok(_) => self.alive(),
+            Err(e) => {
+                self.failures += 1;
+                self.next_check = Instant::now() + self.policy.interval;
+                warn!("Keepalive query failed ({} in a row): {}", self.failures, e);
+                if self.failures >= self.policy.max_failures {
+                    self.set_health(Health::Unhealthy);
+                }
+            }
+        }
+        self.health
+    }
+
+    fn alive(&mut self) {
+        self.failures = 0;
+        self.next_check = Instant::now() + self.policy.interval;
+        self.set_health(Health::Healthy);
+    }
+
+    fn set_health(&mut self, health: Health) {
+        if self.health != health {
+            info!("Connection is now {:?}", health);
+            self.health = health;
+            // Sending only fails when nobody is subscribed, which is fine.
+            _ = self.events.send(health);
+        }
+    }
+}
+impl<C: Connection> Connection for KeepaliveConnection<C> {
+    type Error = C::Error;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    fn config(&self) -> &Config {
+        self.inner.config()
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.inner.record_radio_channel(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        self.inner.stats_mut()
+    }
+
+    async fn shutdown(self) -> Result<(), Self::Error> {
+        self.inner.shutdown().await
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), Self::Error> {
+        self.inner.send_packet(packet).await
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, Self::Error> {
+        let received = self.inner.receive_packet(timeout).await?;
+        self.alive();
+        Ok(received)
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.inner.read_user(buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.inner.write_user(buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Health, KeepaliveConnection, KeepalivePolicy};
+    use crate::{
+        connection::{
+            mock::{cdc_reply, MockConnection},
+            ConnectionType,
+        },
+        packets::system::GetSystemVersionPacket,
+    };
+
+    #[tokio::test]
+    async fn reports_health_changes() {
+        let version = cdc_reply::<164>(&[1, 1, 4, 0, 0x00, 0x10, 0x00]);
+        let mut mock = MockConnection::new(ConnectionType::Wired);
+        mock.expect(GetSystemVersionPacket::new(()), version.clone())
+            .unwrap();
+        // Two unanswered queries, then an answer.
+        mock.expect(GetSystemVersionPacket::new(()), Vec::new())
+            .unwrap();
+        mock.expect(GetSystemVersionPacket::new(()), Vec::new())
+            .unwrap();
+        mock.expect(GetSystemVersionPacket::new(()), version)
+            .unwrap();
+
+        let mut connection = KeepaliveConnection::new(
+            mock,
+            KeepalivePolicy {
+                max_failures: 2,
+                ..Default::default()
+            },
+        );
+        let mut events = connection.subscribe();
+
+        assert_eq!(connection.check().await, Health::Healthy);
+        assert_eq!(connection.check().await, Health::Healthy);
+        assert_eq!(connection.check().await, Health::Unhealthy);
+        assert_eq!(connection.check().await, Health::Healthy);
+
+        assert_eq!(events.try_recv(), Ok(Health::Unhealthy));
+        assert_eq!(events.try_recv(), Ok(Health::Healthy));
+        assert!(events.try_recv().is_err());
+    }
+}
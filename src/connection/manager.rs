@@ -0,0 +1,200 @@
+//! Managing connections to several devices at once.
+
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::commands::{system::GetUniqueId, Command};
+
+use super::{fan_out::fan_out, time::Instant, Connection};
+
+/// The state of a device as of the last command executed on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceState {
+    /// No command has been executed yet.
+    Idle,
+    /// The last command succeeded.
+    Ok,
+    /// The last command failed with the given error.
+    Failed(String),
+}
+
+/// Information about a device held by a [`DeviceManager`].
+#[derive(Debug, Clone)]
+pub struct ManagedDevice {
+    /// The brain's unique hardware ID, used to address the device.
+    pub serial_number: u32,
+    pub state: DeviceState,
+    /// When a command last succeeded on the device.
+    pub last_success: Option<Instant>,
+}
+impl ManagedDevice {
+    fn record<T, E: std::error::Error>(&mut self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => {
+                self.state = DeviceState::Ok;
+                self.last_success = Some(Instant::now());
+            }
+            Err(e) => {
+                warn!("Command failed on device {:08x}: {}", self.serial_number, e);
+                self.state = DeviceState::Failed(e.to_string());
+            }
+        }
+    }
+}
+
+/// Holds connections to several devices and routes commands to them by serial number.
+///
+/// This is meant for tooling that works with many robots at once, such as flashing every
+/// brain in a classroom.
+pub struct DeviceManager<C: Connection> {
+    // Kept separate from the device info so that they can be passed to `fan_out` as a slice.
+    connections: Vec<C>,
+    devices: Vec<ManagedDevice>,
+}
+impl<C: Connection> DeviceManager<C> {
+    pub fn new() -> Self {
+        Self {
+            connections: Vec::new(),
+            devices: Vec::new(),
+        }
+    }
+
+    /// Adds a connection, identifying it by the unique ID the brain reports.
+    ///
+    /// Returns the device's serial number. If a device with the same serial number was
+    /// already added, its connection is replaced.
+    pub async fn add(&mut self, mut connection: C) -> Result<u32, C::Error> {
+        let serial_number = connection.execute_command(GetUniqueId).await?;
+
+        self.insert(serial_number, connection);
+        Ok(serial_number)
+    }
+
+    /// Adds a connection with an already known serial number.
+    ///
+    /// If a device with the same serial number was already added, its connection is replaced.
+    pub fn insert(&mut self, serial_number: u32, connection: C) {
+        debug!("Managing device {:08x}", serial_number);
+        let device = ManagedDevice {
+            serial_number,
+            state: DeviceState::Idle,
+            last_success: None,
+        };
+
+        match self.index_of(serial_number) {
+            Some(index) => {
+                self.connections[index] = connection;
+                self.devices[index] = device;
+            }
+            None => {
+                self.connections.push(connection);
+                self.devices.push(device);
+            }
+        }
+    }
+
+    /// Stops managing a device, returning its connection.
+    pub fn remove(&mut self, serial_number: u32) -> Option<C> {
+        let index = self.index_of(serial_number)?;
+        self.devices.remove(index);
+        Some(self.connections.remove(index))
+    }
+
+    /// Returns information about every managed device.
+    pub fn devices(&self) -> &[ManagedDevice] {
+        &self.devices
+    }
+
+    /// Returns information about the device with the given serial number.
+    pub fn device(&self, serial_number: u32) -> Option<&ManagedDevice> {
+        self.devices
+            .iter()
+            .find(|device| device.serial_number == serial_number)
+    }
+
+    /// Returns the connection to the device with the given serial number.
+    pub fn connection_mut(&mut self, serial_number: u32) -> Option<&mut C> {
+        let index = self.index_of(serial_number)?;
+        Some(&mut self.connections[index])
+    }
+
+    /// Executes a command on the device with the given serial number.
+    ///
+    /// Returns `None` if no such device is managed.
+    pub async fn execute<Cmd: Command>(
+        &mut self,
+        serial_number: u32,
+        command: Cmd,
+    ) -> Option<Result<Cmd::Output, C::Error>> {
+        let index = self.index_of(serial_number)?;
+        let result = self.connections[index].execute_command(command).await;
+        self.devices[index].record(&result);
+        Some(result)
+    }
+
+    /// Executes a clone of a command on every managed device concurrently.
+    ///
+    /// See [`fan_out`] for how `lead` is used. Results are paired with each device's
+    /// serial number, in the same order as [`DeviceManager::devices`].
+    pub async fn execute_all<Cmd: Command + Clone>(
+        &mut self,
+        command: Cmd,
+        lead: Duration,
+    ) -> Vec<(u32, Result<Cmd::Output, C::Error>)> {
+        let results = fan_out(&mut self.connections, command, lead).await;
+
+        results
+            .into_iter()
+            .map(|result| {
+                let device = &mut self.devices[result.index];
+                device.record(&result.result);
+                (device.serial_number, result.result)
+            })
+            .collect()
+    }
+
+    fn index_of(&self, serial_number: u32) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|device| device.serial_number == serial_number)
+    }
+}
+impl<C: Connection> Default for DeviceManager<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceManager, DeviceState};
+    use crate::{
+        commands::terminal::ReadStdout,
+        connection::{
+            mock::{stdout_exchange, MockConnection},
+            ConnectionType,
+        },
+    };
+
+    #[tokio::test]
+    async fn routes_by_serial_number() {
+        let mut responsive = MockConnection::new(ConnectionType::Wired);
+        let (poll, reply) = stdout_exchange("");
+        responsive.expect(poll, reply).unwrap();
+
+        let mut manager = DeviceManager::new();
+        manager.insert(1, responsive);
+        manager.insert(2, MockConnection::new(ConnectionType::Wired));
+
+        assert_eq!(manager.execute(1, ReadStdout).await.unwrap().unwrap(), None);
+        assert!(manager.execute(2, ReadStdout).await.unwrap().is_err());
+        assert!(manager.execute(3, ReadStdout).await.is_none());
+
+        assert_eq!(manager.device(1).unwrap().state, DeviceState::Ok);
+        assert!(matches!(
+            manager.device(2).unwrap().state,
+            DeviceState::Failed(_)
+        ));
+    }
+}
@@ -0,0 +1,479 @@
+//! An in-memory [`Connection`] for testing commands without a physical device.
+//!
+//! A [`MockConnection`] is loaded with the packets a test expects a command to send, along
+//! with the raw replies the device would have sent back. Replies can be built with
+//! [`cdc_reply`] and [`cdc2_reply`], and [`stdout_exchange`] scripts a whole command.
+
+use std::{collections::VecDeque, time::Duration};
+
+use log::{debug, trace, Level};
+use thiserror::Error;
+
+use crate::{
+    commands::CommandError,
+    config::Config,
+    decode::{Decode, DecodeError},
+    encode::{Encode, EncodeError},
+    packets::{
+        cdc2::Cdc2Ack,
+        controller::{UserFifoPacket, UserFifoPayload},
+    },
+};
+
+use super::{
+    instrument, reconnect::LinkError, reply, stats::ConnectionStats, trim_packets, Connection,
+    ConnectionType, RawPacket,
+};
+
+/// Builds the raw bytes of a simple CDC reply packet.
+pub fn cdc_reply<const ID: u8>(payload: &[u8]) -> Vec<u8> {
+    reply::cdc_reply(ID, payload)
+}
+
+/// Builds the raw bytes of a CDC2 reply packet, including its CRC.
+pub fn cdc2_reply<const ID: u8, const EXT_ID: u8>(ack: Cdc2Ack, payload: &[u8]) -> Vec<u8> {
+    reply::cdc2_reply(ID, EXT_ID, ack, payload)
+}
+
+/// Returns the user FIFO poll sent by [`ReadStdout`], along with the raw reply of a brain
+/// whose program printed `output`.
+///
+/// This scripts the simplest command there is, for tests of connections that pass commands
+/// through to another one.
+///
+/// [`ReadStdout`]: crate::commands::terminal::ReadStdout
+pub fn stdout_exchange(output: &str) -> (UserFifoPacket, Vec<u8>) {
+    let poll = UserFifoPacket::new(UserFifoPayload {
+        channel: 1,
+        write: None,
+    });
+    let mut payload = vec![1];
+    if !output.is_empty() {
+        payload.extend(output.as_bytes());
+        payload.push(0);
+    }
+    (poll, cdc2_reply::<86, 39>(Cdc2Ack::Ack, &payload))
+}
+
+/// A packet that a [`MockConnection`] expects to be sent, and what it replies with.
+#[derive(Debug, Clone)]
+struct Expectation {
+    request: Vec<u8>,
+    replies: Vec<Vec<u8>>,
+}
+
+/// A [`Connection`] that replays scripted replies to expected packets.
+///
+/// Every packet sent must match the next expectation exactly, or sending fails with
+/// [`MockError::UnexpectedPacket`]. Receiving never waits: if no queued reply decodes as the
+/// requested packet, it fails immediately with [`MockError::Timeout`].
+#[derive(Debug)]
+pub struct MockConnection {
+    connection_type: ConnectionType,
+    config: Config,
+    expectations: VecDeque<Expectation>,
+    incoming_packets: Vec<RawPacket>,
+    sent: Vec<Vec<u8>>,
+    user_output: VecDeque<u8>,
+    user_input: Vec<u8>,
+    disconnected: bool,
+    stats: ConnectionStats,
+}
+impl MockConnection {
+    pub fn new(connection_type: ConnectionType) -> Self {
+        Self::with_config(connection_type, Config::default())
+    }
+
+    pub fn with_config(connection_type: ConnectionType, config: Config) -> Self {
+        Self {
+            connection_type,
+            config,
+            expectations: VecDeque::new(),
+            incoming_packets: Vec::new(),
+            sent: Vec::new(),
+            user_output: VecDeque::new(),
+            user_input: Vec::new(),
+            disconnected: false,
+            stats: ConnectionStats::default(),
+        }
+    }
+
+    /// Expects `request` to be sent next, and replies to it with the raw packet `reply`.
+    pub fn expect(&mut self, request: impl Encode, reply: Vec<u8>) -> Result<(), EncodeError> {
+        self.expect_replies(request, vec![reply])
+    }
+
+    /// Expects `request` to be sent next, and replies to it with every packet in `replies`.
+    ///
+    /// An empty list simulates a dropped reply.
+    pub fn expect_replies(
+        &mut self,
+        request: impl Encode,
+        replies: Vec<Vec<u8>>,
+    ) -> Result<(), EncodeError> {
+        self.expectations.push_back(Expectation {
+            request: request.encode()?,
+            replies,
+        });
+        Ok(())
+    }
+
+    /// Queues a raw packet as if the device had sent it unprompted.
+    pub fn push_reply(&mut self, reply: Vec<u8>) {
+        self.stats.packet_received(&reply);
+        self.incoming_packets.push(RawPacket::new(reply));
+    }
+
+    /// Queues user program output to be returned by [`Connection::read_user`].
+    pub fn push_user_output(&mut self, output: &[u8]) {
+        self.user_output.extend(output);
+    }
+
+    /// Returns everything written with [`Connection::write_user`] so far.
+    pub fn user_input(&self) -> &[u8] {
+        &self.user_input
+    }
+
+    /// Returns every packet sent so far, encoded.
+    pub fn sent(&self) -> &[Vec<u8>] {
+        &self.sent
+    }
+
+    /// Simulates the device being unplugged, failing everything after this call with
+    /// [`MockError::Disconnected`].
+    pub fn disconnect(&mut self) {
+        self.disconnected = true;
+    }
+
+    /// Returns how many expected packets have not been sent yet.
+    pub fn remaining(&self) -> usize {
+        self.expectations.len()
+    }
+}
+impl Connection for MockConnection {
+    type Error = MockError;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.connection_type
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        Some(&mut self.stats)
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), MockError> {
+        if self.disconnected {
+            return Err(MockError::Disconnected);
+        }
+        let encoded = packet.encode()?;
+
+        if self.config.logs_packets_at(Level::Trace) {
+            trace!("Sending packet: {:x?}", encoded);
+        }
+        instrument::packet_sent(&encoded);
+        self.stats.packet_sent(&encoded);
+        self.sent.push(encoded.clone());
+
+        let expectation = self.expectations.pop_front();
+        match expectation {
+            Some(expectation) if expectation.request == encoded => {
+                for reply in expectation.replies {
+                    if self.config.logs_packets_at(Level::Debug) {
+                        debug!("received packet: {:x?}", reply);
+                    }
+                    self.stats.packet_received(&reply);
+                    self.incoming_packets.push(RawPacket::new(reply));
+                }
+                Ok(())
+            }
+            expectation => Err(MockError::UnexpectedPacket {
+                expected: expectation.map(|e| e.request),
+                actual: encoded,
+            }),
+        }
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, _timeout: Duration) -> Result<P, MockError> {
+        if self.disconnected {
+            return Err(MockError::Disconnected);
+        }
+        for packet in self.incoming_packets.iter_mut() {
+            if let Ok(decoded) = packet.decode_and_use::<P>() {
+                trim_packets(&mut self.incoming_packets);
+                return Ok(decoded);
+            }
+        }
+        trim_packets(&mut self.incoming_packets);
+        Err(MockError::Timeout)
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, MockError> {
+        if self.disconnected {
+            return Err(MockError::Disconnected);
+        }
+        let len = buf.len().min(self.user_output.len());
+        for (dst, src) in buf.iter_mut().zip(self.user_output.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, MockError> {
+        if self.disconnected {
+            return Err(MockError::Disconnected);
+        }
+        self.user_input.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MockError {
+    #[error("Packet encoding error: {0}")]
+    EncodeError(#[from] EncodeError),
+    #[error("Packet decoding error: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("Command failed: {0}")]
+    CommandError(#[from] CommandError),
+    #[error("Packet timeout")]
+    Timeout,
+    #[error("NACK received: {0:?}")]
+    Nack(#[from] Cdc2Ack),
+    #[error("Sent unexpected packet {actual:x?}, expected {expected:x?}")]
+    UnexpectedPacket {
+        expected: Option<Vec<u8>>,
+        actual: Vec<u8>,
+    },
+    #[error("Device disconnected")]
+    Disconnected,
+}
+impl LinkError for MockError {
+    fn is_link_lost(&self) -> bool {
+        matches!(self, Self::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::{cdc2_reply, stdout_exchange, MockConnection, MockError};
+    use crate::{
+        commands::{
+            file::{DeleteFile, UploadFile, VerifyMode},
+            settings::{KVKey, Settings},
+            terminal::ReadStdout,
+            PacketPlan,
+        },
+        config::{Backoff, Config, RetryPolicy},
+        connection::{Connection, ConnectionType},
+        encode::{Encode, EncodeError},
+        packets::{
+            cdc2::Cdc2Ack,
+            controller::UserFifoReplyPacket,
+            file::{
+                EraseFilePacket, EraseFilePayload, ExitFileTransferPacket, ExtensionType,
+                FileExitAction, FileMetadata, FileVendor, InitFileTransferPacket, WriteFilePacket,
+                WriteFilePayload,
+            },
+            kv::{ReadKeyValuePacket, WriteKeyValuePacket, WriteKeyValuePayload},
+        },
+        string::FixedString,
+        timestamp::J2000Timestamp,
+        version::Version,
+    };
+
+    #[tokio::test]
+    async fn replays_expected_packets() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        let (poll, reply) = stdout_exchange("hi");
+        connection.expect(poll, reply).unwrap();
+
+        let output = connection.execute_command(ReadStdout).await.unwrap();
+        assert_eq!(output.as_deref(), Some(&b"hi"[..]));
+        assert_eq!(connection.remaining(), 0);
+
+        assert!(matches!(
+            connection.execute_command(ReadStdout).await,
+            Err(MockError::UnexpectedPacket { expected: None, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn sends_at_least_once() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        let (poll, _) = stdout_exchange("");
+        connection.expect_replies(poll.clone(), Vec::new()).unwrap();
+
+        let policy = RetryPolicy {
+            max_attempts: 0,
+            ..RetryPolicy::DEFAULT
+        };
+        let result = connection
+            .packet_handshake_with_policy::<UserFifoReplyPacket>(&policy, poll)
+            .await;
+        assert!(matches!(result, Err(MockError::Timeout)));
+        assert_eq!(connection.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancels_before_a_transfer_is_opened() {
+        let mut connection = MockConnection::with_config(
+            ConnectionType::Wired,
+            Config {
+                retry: RetryPolicy {
+                    max_attempts: 2,
+                    backoff: Backoff::Fixed(Duration::from_secs(60)),
+                    ..RetryPolicy::DEFAULT
+                },
+                ..Config::default()
+            },
+        );
+        let file_name = FixedString::new("slot_1.bin".to_string()).unwrap();
+        // The erase is never answered, so the delete is still waiting to resend it.
+        connection
+            .expect_replies(
+                EraseFilePacket::new(EraseFilePayload {
+                    vendor: FileVendor::User,
+                    option: 0,
+                    file_name: file_name.clone(),
+                }),
+                Vec::new(),
+            )
+            .unwrap();
+        connection
+            .expect(
+                ExitFileTransferPacket::new(FileExitAction::DoNothing),
+                cdc2_reply::<86, 18>(Cdc2Ack::NackUninitializedTransfer, &[]),
+            )
+            .unwrap();
+
+        let cancel = CancellationToken::new();
+        let (result, ()) = tokio::join!(
+            connection.execute_command_cancellable(
+                DeleteFile {
+                    file_name,
+                    vendor: FileVendor::User,
+                    erase_all_linked: false,
+                    dry_run: None,
+                },
+                &cancel,
+            ),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                cancel.cancel();
+            }
+        );
+        assert!(matches!(result, Ok(None)));
+        assert_eq!(connection.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn plans_uploads_without_sending() {
+        // With no expectations, any packet that reaches the connection fails the upload.
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        let plan = PacketPlan::new();
+        let data = vec![0xAB; 8];
+
+        connection
+            .execute_command(UploadFile {
+                filename: FixedString::new("slot_1.bin".to_string()).unwrap(),
+                metadata: FileMetadata {
+                    extension: FixedString::new("bin".to_string()).unwrap(),
+                    extension_type: ExtensionType::Binary,
+                    timestamp: J2000Timestamp(0),
+                    version: Version {
+                        major: 1,
+                        minor: 0,
+                        build: 0,
+                        beta: 0,
+                    },
+                },
+                vendor: None,
+                data: data.clone().into(),
+                target: None,
+                load_addr: 0x03800000,
+                linked_file: None,
+                after_upload: FileExitAction::DoNothing,
+                skip_identical: false,
+                verify: VerifyMode::Crc,
+                checkpoint: None,
+                dry_run: Some(plan.clone()),
+                progress: None,
+            })
+            .await
+            .unwrap();
+
+        let planned = plan.packets();
+        assert_eq!(
+            planned.iter().map(|p| p.packet_type).collect::<Vec<_>>(),
+            [
+                std::any::type_name::<InitFileTransferPacket>(),
+                std::any::type_name::<WriteFilePacket>(),
+                std::any::type_name::<ExitFileTransferPacket>(),
+            ]
+        );
+        assert_eq!(
+            planned[1].bytes,
+            WriteFilePacket::new(WriteFilePayload {
+                address: 0x03800000,
+                chunk_data: data,
+            })
+            .encode()
+            .unwrap()
+        );
+        assert_eq!(
+            planned[2].bytes,
+            ExitFileTransferPacket::new(FileExitAction::DoNothing)
+                .encode()
+                .unwrap()
+        );
+        assert!(connection.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn round_trips_settings() {
+        for key in [KVKey::TeamNumber, KVKey::RobotName, KVKey::from("custom")] {
+            assert_eq!(KVKey::from(key.name()), key);
+        }
+
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        let key = FixedString::new("robotname".to_string()).unwrap();
+        connection
+            .expect(
+                WriteKeyValuePacket::new(WriteKeyValuePayload {
+                    key: key.clone(),
+                    value: FixedString::new("Clawbot".to_string()).unwrap(),
+                }),
+                cdc2_reply::<86, 47>(Cdc2Ack::Ack, &[]),
+            )
+            .unwrap();
+        connection
+            .expect(
+                ReadKeyValuePacket::new(key),
+                cdc2_reply::<86, 46>(Cdc2Ack::Ack, b"Clawbot\0"),
+            )
+            .unwrap();
+
+        let mut settings = Settings::new(&mut connection);
+        settings.set(&KVKey::RobotName, "Clawbot").await.unwrap();
+        assert_eq!(settings.get(&KVKey::RobotName).await.unwrap(), "Clawbot");
+
+        // Too long for the brain to display, so it is never sent.
+        assert!(matches!(
+            settings.set(&KVKey::TeamNumber, "123456789").await,
+            Err(MockError::EncodeError(EncodeError::StringTooLong))
+        ));
+        assert_eq!(connection.remaining(), 0);
+    }
+}
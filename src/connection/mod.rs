@@ -1,23 +1,80 @@
 //! Implements functions and structures for interacting with vex devices.
 
-use std::{future::Future, time::Instant};
+use std::future::Future;
 
-use log::{error, trace, warn};
+use log::{debug, error, trace, warn};
 use std::time::Duration;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     commands::Command,
+    commands::CommandError,
+    config::{Config, RetryPolicy, DEFAULT_CONFIG},
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
-    packets::cdc2::Cdc2Ack,
+    packets::{
+        cdc2::Cdc2Ack,
+        file::{ExitFileTransferPacket, ExitFileTransferReplyPacket, FileExitAction},
+        radio::{
+            RadioChannel, SelectRadioChannelPacket, SelectRadioChannelPayload,
+            SelectRadioChannelReplyPacket,
+        },
+    },
+};
+
+use self::{
+    stats::ConnectionStats,
+    time::{sleep, Instant},
 };
 
 #[cfg(feature = "bluetooth")]
 pub mod bluetooth;
+pub mod boxed;
+pub mod fan_out;
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+#[cfg(any(feature = "bluetooth", feature = "wasm"))]
+mod gatt;
 #[cfg(all(feature = "serial", feature = "bluetooth"))]
 pub mod generic;
+#[cfg(all(feature = "hotplug", target_os = "linux"))]
+mod hotplug;
+pub(crate) mod instrument;
+pub mod keepalive;
+pub mod manager;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod reconnect;
+#[cfg(any(test, feature = "record"))]
+pub mod record;
+#[cfg(any(test, feature = "mock", feature = "simulator"))]
+pub(crate) mod reply;
 #[cfg(feature = "serial")]
 pub mod serial;
+pub mod stats;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tcp;
+pub(crate) mod time;
+pub mod transport;
+#[cfg(feature = "wasm")]
+pub mod web_bluetooth;
+#[cfg(feature = "wasm")]
+pub mod web_serial;
+
+/// The USB venddor ID for VEX devices
+pub const VEX_USB_VID: u16 = 0x2888;
+
+/// The USB PID of the V5 Brain
+pub const V5_BRAIN_USB_PID: u16 = 0x0501;
+
+/// The USB PID of the EXP Brain
+pub const EXP_BRAIN_USB_PID: u16 = 0x600;
+
+/// The USB PID of the V5 Controller
+pub const V5_CONTROLLER_USB_PID: u16 = 0x0503;
+
+pub const V5_SERIAL_BAUDRATE: u32 = 115200;
 
 #[derive(Debug, Clone)]
 pub(crate) struct RawPacket {
@@ -27,6 +84,16 @@ pub(crate) struct RawPacket {
 }
 impl RawPacket {
     pub fn new(bytes: Vec<u8>) -> Self {
+        instrument::packet_received(&bytes);
+        Self {
+            bytes,
+            used: false,
+            timestamp: Instant::now(),
+        }
+    }
+
+    /// Wraps a packet that was already received by another connection.
+    pub fn forwarded(bytes: Vec<u8>) -> Self {
         Self {
             bytes,
             used: false,
@@ -44,10 +111,20 @@ impl RawPacket {
     /// This function will **NOT** fail if the packet has already been used.
     pub fn decode_and_use<D: Decode>(&mut self) -> Result<D, DecodeError> {
         let decoded = D::decode(self.bytes.clone())?;
+        instrument::packet_decoded::<D>();
         self.used = true;
         Ok(decoded)
     }
 }
+
+/// A host-bound packet of any kind, kept as raw bytes.
+pub(crate) struct RawFrame(pub Vec<u8>);
+impl Decode for RawFrame {
+    fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
+        Ok(Self(data.into_iter().collect()))
+    }
+}
+
 /// Removes old and used packets from the incoming packets buffer.
 pub(crate) fn trim_packets(packets: &mut Vec<RawPacket>) {
     trace!("Trimming packets. Length before: {}", packets.len());
@@ -61,10 +138,61 @@ pub(crate) fn trim_packets(packets: &mut Vec<RawPacket>) {
 /// Represents an open connection to a V5 peripheral.
 #[allow(async_fn_in_trait)]
 pub trait Connection {
-    type Error: std::error::Error + From<EncodeError> + From<DecodeError> + From<Cdc2Ack>;
+    type Error: std::error::Error
+        + From<EncodeError>
+        + From<DecodeError>
+        + From<CommandError>
+        + From<Cdc2Ack>;
 
     fn connection_type(&self) -> ConnectionType;
 
+    /// Returns the configuration used by commands executed on this connection.
+    fn config(&self) -> &Config {
+        &DEFAULT_CONFIG
+    }
+
+    /// Temporarily overrides this connection's configuration.
+    ///
+    /// The returned connection borrows this one and can be used to execute
+    /// commands with different timeouts, retries, or chunk sizes.
+    fn override_config(&mut self, config: Config) -> ConfigOverride<'_, Self> {
+        ConfigOverride {
+            connection: self,
+            config,
+        }
+    }
+
+    /// Records that a command switched the radio to another channel, so that
+    /// [`Connection::shutdown`] can switch it back to [`RadioChannel::Pit`].
+    fn record_radio_channel(&mut self, _channel: RadioChannel) {}
+
+    /// Returns the packet and handshake counters of this connection.
+    ///
+    /// Returns `None` for connections that don't keep statistics.
+    fn stats(&self) -> Option<&ConnectionStats> {
+        None
+    }
+
+    /// Returns the counters that packet handshakes record their round trips in.
+    ///
+    /// Connections that keep statistics return the same counters as [`Connection::stats`].
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        None
+    }
+
+    /// Gracefully closes the connection.
+    ///
+    /// Unlike dropping the connection, this can still talk to the device: the radio is
+    /// switched back to the pit channel if a command changed it, pending writes are flushed,
+    /// and only then are the ports closed. Every step is attempted even if an earlier one
+    /// fails, and the first error encountered is returned.
+    async fn shutdown(self) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+
     /// Sends a packet.
     fn send_packet(&mut self, packet: impl Encode)
         -> impl Future<Output = Result<(), Self::Error>>;
@@ -83,44 +211,196 @@ pub trait Connection {
 
     /// Executes a [`Command`].
     async fn execute_command<C: Command>(&mut self, command: C) -> Result<C::Output, Self::Error> {
-        command.execute(self).await
+        instrument::command::<C, _>(command.execute(self)).await
+    }
+
+    /// Executes a [`Command`], aborting it if `cancel` is triggered before it completes.
+    ///
+    /// Returns `None` if the command was cancelled. If the command may have had a file transfer
+    /// open, the transfer is exited before returning so that the brain is ready for the next
+    /// one. A brain that never opened it is left as it is.
+    async fn execute_command_cancellable<C: Command>(
+        &mut self,
+        command: C,
+        cancel: &CancellationToken,
+    ) -> Result<Option<C::Output>, Self::Error> {
+        let opens_file_transfer = command.opens_file_transfer();
+
+        select! {
+            result = command.execute(self) => return result.map(Some),
+            _ = cancel.cancelled() => {}
+        }
+
+        debug!("Cancelled {}", std::any::type_name::<C>());
+        if opens_file_transfer {
+            let reply = self
+                .packet_handshake::<ExitFileTransferReplyPacket>(ExitFileTransferPacket::new(
+                    FileExitAction::DoNothing,
+                ))
+                .await?;
+            match reply.try_into_inner() {
+                // The command was cancelled before its transfer was opened.
+                Ok(_) | Err(Cdc2Ack::NackUninitializedTransfer) => {}
+                Err(nack) => return Err(nack.into()),
+            }
+        }
+
+        Ok(None)
     }
 
     /// Sends a packet and waits for a response.
     ///
-    /// This function will retry the handshake `retries` times
-    /// before giving up and erroring with the error thrown on the last retry.
+    /// The packet is resent according to the connection's [`RetryPolicy`] for it
+    /// (see [`Config::retry_policy_for`]) before giving up and erroring with the error
+    /// thrown on the last attempt.
     ///
     /// # Note
     ///
     /// This function will fail immediately if the given packet fails to encode.
     async fn packet_handshake<D: Decode>(
         &mut self,
-        timeout: Duration,
-        retries: usize,
         packet: impl Encode + Clone,
     ) -> Result<D, Self::Error> {
-        let mut last_error = None;
-
-        for _ in 0..retries {
-            self.send_packet(packet.clone()).await?;
-            match self.receive_packet::<D>(timeout).await {
-                Ok(decoded) => return Ok(decoded),
-                Err(e) => {
-                    warn!(
-                        "Handshake failed while waiting for {}: {:?}. Retrying...",
-                        std::any::type_name::<D>(),
-                        e
-                    );
-                    last_error = Some(e);
+        self.packet_handshake_with_retries(packet)
+            .await
+            .map(|(decoded, _)| decoded)
+    }
+
+    /// Sends a packet and waits for a response, also returning how many
+    /// times the packet had to be resent before a response was received.
+    ///
+    /// See [`Connection::packet_handshake`] for details.
+    async fn packet_handshake_with_retries<D: Decode>(
+        &mut self,
+        packet: impl Encode + Clone,
+    ) -> Result<(D, usize), Self::Error> {
+        let policy = *self.config().retry_policy_for(&packet.encode()?);
+        self.packet_handshake_with_policy(&policy, packet).await
+    }
+
+    /// Sends a packet and waits for a response using the given [`RetryPolicy`],
+    /// ignoring the connection's configuration.
+    ///
+    /// The packet is always sent at least once, even if the policy allows no attempts.
+    ///
+    /// See [`Connection::packet_handshake_with_retries`] for details.
+    async fn packet_handshake_with_policy<D: Decode>(
+        &mut self,
+        policy: &RetryPolicy,
+        packet: impl Encode + Clone,
+    ) -> Result<(D, usize), Self::Error> {
+        instrument::handshake::<D, _>(&packet, async {
+            let max_attempts = policy.max_attempts.max(1);
+            let mut attempt = 0;
+            loop {
+                if attempt > 0 {
+                    sleep(policy.backoff.delay(attempt - 1)).await;
+                    if let Some(stats) = self.stats_mut() {
+                        stats.retransmits += 1;
+                    }
                 }
+
+                self.send_packet(packet.clone()).await?;
+                let sent = Instant::now();
+                match self.receive_packet::<D>(policy.timeout).await {
+                    Ok(decoded) => {
+                        if let Some(stats) = self.stats_mut() {
+                            stats.handshake_completed(sent.elapsed());
+                        }
+                        return Ok((decoded, attempt));
+                    }
+                    Err(e) if attempt + 1 < max_attempts => {
+                        warn!(
+                            "Handshake failed while waiting for {}: {:?}. Retrying...",
+                            std::any::type_name::<D>(),
+                            e
+                        );
+                    }
+                    Err(e) => {
+                        error!(
+                            "Handshake failed after {} attempts with error: {:?}",
+                            max_attempts, e
+                        );
+                        return Err(e);
+                    }
+                }
+                attempt += 1;
             }
-        }
-        error!(
-            "Handshake failed after {} retries with error: {:?}",
-            retries, last_error
-        );
-        Err(last_error.unwrap())
+        })
+        .await
+    }
+}
+
+/// Switches the radio back to the pit channel while a connection is shutting down.
+pub(crate) async fn restore_radio_channel<C: Connection + ?Sized>(
+    connection: &mut C,
+) -> Result<(), C::Error> {
+    debug!("Restoring radio to pit channel");
+    connection
+        .packet_handshake::<SelectRadioChannelReplyPacket>(SelectRadioChannelPacket::new(
+            SelectRadioChannelPayload {
+                channel: RadioChannel::Pit,
+            },
+        ))
+        .await?
+        .try_into_inner()?;
+    Ok(())
+}
+
+/// Returns the first of the errors collected during a shutdown, logging the rest.
+pub(crate) fn first_shutdown_error<E: std::error::Error>(errors: Vec<E>) -> Result<(), E> {
+    let mut errors = errors.into_iter();
+    let first = errors.next();
+    for error in errors {
+        warn!("Additional error while shutting down connection: {}", error);
+    }
+    first.map_or(Ok(()), Err)
+}
+
+/// A borrowed connection with an overridden [`Config`].
+///
+/// Created by [`Connection::override_config`].
+pub struct ConfigOverride<'a, C: Connection + ?Sized> {
+    connection: &'a mut C,
+    config: Config,
+}
+impl<C: Connection + ?Sized> Connection for ConfigOverride<'_, C> {
+    type Error = C::Error;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.connection.connection_type()
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.connection.record_radio_channel(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        self.connection.stats()
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        self.connection.stats_mut()
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), Self::Error> {
+        self.connection.send_packet(packet).await
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, Self::Error> {
+        self.connection.receive_packet(timeout).await
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.connection.read_user(buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.connection.write_user(buf).await
     }
 }
 
@@ -0,0 +1,286 @@
+//! Reopening connections after the link to the device is lost.
+
+use std::{future::Future, io, time::Duration};
+
+use log::{info, warn};
+use tokio::sync::broadcast;
+
+use crate::{
+    config::{Backoff, Config},
+    decode::Decode,
+    encode::Encode,
+    packets::radio::RadioChannel,
+};
+
+use super::{stats::ConnectionStats, time::sleep, Connection, ConnectionType};
+
+/// An error that can tell whether the link to the device was lost.
+pub trait LinkError {
+    /// Returns whether the device was unplugged, went out of range, or otherwise stopped
+    /// being reachable, as opposed to a single packet failing.
+    fn is_link_lost(&self) -> bool;
+}
+
+/// Returns whether an IO error means that the underlying port or socket is gone.
+pub(crate) fn is_link_lost_io(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotFound
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// A change in the state of a [`ReconnectingConnection`]'s link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// The link was lost and a new connection is about to be opened.
+    Lost,
+    /// Opening a new connection is being attempted, counting from zero.
+    Reconnecting { attempt: usize },
+    /// A new connection was opened.
+    Reconnected,
+    /// Every attempt to reconnect failed.
+    GaveUp,
+}
+
+/// How a [`ReconnectingConnection`] tries to open a new connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// How many times opening a new connection is attempted before giving up.
+    pub max_attempts: usize,
+    /// How long to wait between attempts.
+    pub backoff: Backoff,
+}
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(250),
+                max: Duration::from_secs(5),
+            },
+        }
+    }
+}
+
+/// A [`Connection`] that reopens itself when the link to the device is lost.
+///
+/// When sending a packet fails because the link was lost, a new connection is opened with
+/// `connect` and the packet is sent again. When receiving fails, the connection is reopened
+/// and the error is returned, so that the packet handshake in progress resends its packet on
+/// its next attempt.
+///
+/// The device forgets any in-progress file transfer when the link drops, so a transfer
+/// interrupted by a reconnect will still fail.
+pub struct ReconnectingConnection<C, F> {
+    inner: C,
+    connect: F,
+    policy: ReconnectPolicy,
+    events: broadcast::Sender<LinkEvent>,
+}
+impl<C, F, Fut> ReconnectingConnection<C, F>
+where
+    C: Connection,
+    C::Error: LinkError,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<C, C::Error>>,
+{
+    /// How many unreceived events a subscriber can fall behind by before it starts missing them.
+    const EVENT_CAPACITY: usize = 16;
+
+    /// Opens the first connection with `connect`, which is called again whenever the
+    /// link is lost.
+    pub async fn connect(mut connect: F, policy: ReconnectPolicy) -> Result<Self, C::Error> {
+        let inner = connect().await?;
+        Ok(Self::new(inner, connect, policy))
+    }
+
+    /// Wraps an already open connection.
+    pub fn new(inner: C, connect: F, policy: ReconnectPolicy) -> Self {
+        Self {
+            inner,
+            connect,
+            policy,
+            events: broadcast::channel(Self::EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Returns a receiver for every link event after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<LinkEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Replaces the connection with a newly opened one.
+    ///
+    /// The old connection is dropped without being shut down, since its link is already gone.
+    /// At least one attempt is made, even if the policy allows none.
+    pub async fn reconnect(&mut self) -> Result<(), C::Error> {
+        // Sending only fails when nobody is subscribed, which is fine.
+        _ = self.events.send(LinkEvent::Lost);
+
+        let mut last_error = None;
+        for attempt in 0..self.policy.max_attempts.max(1) {
+            if attempt > 0 {
+                sleep(self.policy.backoff.delay(attempt - 1)).await;
+            }
+            _ = self.events.send(LinkEvent::Reconnecting { attempt });
+
+            match (self.connect)().await {
+                Ok(connection) => {
+                    info!("Reconnected after {} attempts", attempt + 1);
+                    self.inner = connection;
+                    _ = self.events.send(LinkEvent::Reconnected);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("Failed to reconnect: {}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        _ = self.events.send(LinkEvent::GaveUp);
+        Err(last_error.unwrap())
+    }
+
+    async fn handle_link_loss(&mut self, error: &C::Error) -> Result<(), C::Error> {
+        warn!("Link to device lost: {}", error);
+        self.reconnect().await
+    }
+}
+impl<C, F, Fut> Connection for ReconnectingConnection<C, F>
+where
+    C: Connection,
+    C::Error: LinkError,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<C, C::Error>>,
+{
+    type Error = C::Error;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    fn config(&self) -> &Config {
+        self.inner.config()
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.inner.record_radio_channel(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        self.inner.stats_mut()
+    }
+
+    async fn shutdown(self) -> Result<(), Self::Error> {
+        self.inner.shutdown().await
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), Self::Error> {
+        let encoded = packet.encode()?;
+        match self.inner.send_packet(encoded.clone()).await {
+            Err(e) if e.is_link_lost() => {
+                self.handle_link_loss(&e).await?;
+                self.inner.send_packet(encoded).await
+            }
+            result => result,
+        }
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, Self::Error> {
+        match self.inner.receive_packet(timeout).await {
+            // The reply was lost with the link, so the packet has to be resent.
+            Err(e) if e.is_link_lost() => {
+                self.handle_link_loss(&e).await?;
+                Err(e)
+            }
+            result => result,
+        }
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self.inner.read_user(buf).await {
+            Err(e) if e.is_link_lost() => {
+                self.handle_link_loss(&e).await?;
+                self.inner.read_user(buf).await
+            }
+            result => result,
+        }
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        match self.inner.write_user(buf).await {
+            Err(e) if e.is_link_lost() => {
+                self.handle_link_loss(&e).await?;
+                self.inner.write_user(buf).await
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkEvent, ReconnectPolicy, ReconnectingConnection};
+    use crate::{
+        commands::terminal::ReadStdout,
+        config::Backoff,
+        connection::{
+            mock::{stdout_exchange, MockConnection, MockError},
+            Connection, ConnectionType,
+        },
+    };
+
+    fn scripted() -> MockConnection {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        let (poll, reply) = stdout_exchange("hi");
+        connection.expect(poll, reply).unwrap();
+        connection
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_link_loss() {
+        let mut unplugged = MockConnection::new(ConnectionType::Wired);
+        unplugged.disconnect();
+        let policy = ReconnectPolicy {
+            max_attempts: 1,
+            backoff: Backoff::None,
+        };
+        let mut connection = ReconnectingConnection::new(
+            unplugged,
+            || async { Ok::<_, MockError>(scripted()) },
+            policy,
+        );
+        let mut events = connection.subscribe();
+
+        let output = connection.execute_command(ReadStdout).await.unwrap();
+        assert_eq!(output.as_deref(), Some(&b"hi"[..]));
+        assert_eq!(events.try_recv(), Ok(LinkEvent::Lost));
+        assert_eq!(
+            events.try_recv(),
+            Ok(LinkEvent::Reconnecting { attempt: 0 })
+        );
+        assert_eq!(events.try_recv(), Ok(LinkEvent::Reconnected));
+    }
+}
@@ -0,0 +1,306 @@
+//! Recording packet traffic and replaying it later.
+//!
+//! [`PacketRecorder`] wraps any [`Connection`] and writes every frame it sends and receives
+//! to a recording, one frame per line:
+//!
+//! ```text
+//! > 12 c936b8475621...
+//! < 80 aa55562...
+//! ```
+//!
+//! Each line holds the direction (`>` for device-bound, `<` for host-bound), the time since
+//! recording started in microseconds, and the frame as hex. Recordings can be fed back
+//! through [`replay`], which turns them into a [`MockConnection`] that answers the same
+//! requests with the same replies.
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+use log::warn;
+
+use crate::{
+    config::Config,
+    decode::{Decode, DecodeError},
+    encode::Encode,
+    packets::radio::RadioChannel,
+};
+
+use super::{
+    mock::MockConnection, stats::ConnectionStats, time::Instant, Connection, ConnectionType,
+};
+
+/// Which way a recorded frame was travelling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    /// Sent from the host to the device.
+    DeviceBound,
+    /// Sent from the device to the host.
+    HostBound,
+}
+
+/// A single frame in a recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    pub direction: FrameDirection,
+    /// The time since recording started.
+    pub timestamp: Duration,
+    pub bytes: Vec<u8>,
+}
+impl Display for RecordedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.direction {
+            FrameDirection::DeviceBound => '>',
+            FrameDirection::HostBound => '<',
+        };
+        write!(f, "{} {} ", direction, self.timestamp.as_micros())?;
+        for byte in &self.bytes {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for RecordedFrame {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid frame: {s}"));
+
+        let mut fields = s.split_whitespace();
+        let direction = match fields.next() {
+            Some(">") => FrameDirection::DeviceBound,
+            Some("<") => FrameDirection::HostBound,
+            _ => return Err(invalid()),
+        };
+        let timestamp = fields
+            .next()
+            .and_then(|micros| micros.parse().ok())
+            .map(Duration::from_micros)
+            .ok_or_else(invalid)?;
+        let hex = fields.next().unwrap_or_default();
+        if !hex.len().is_multiple_of(2) || fields.next().is_some() {
+            return Err(invalid());
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+
+        Ok(Self {
+            direction,
+            timestamp,
+            bytes,
+        })
+    }
+}
+
+/// Reads every frame from a recording.
+pub fn read_recording(reader: impl BufRead) -> io::Result<Vec<RecordedFrame>> {
+    reader
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|line| line?.parse())
+        .collect()
+}
+
+/// Builds a [`MockConnection`] that plays back a recording.
+///
+/// Every device-bound frame becomes an expected request, answered with the host-bound
+/// frames that followed it. Timestamps are ignored, so replies arrive immediately.
+pub fn replay(
+    frames: impl IntoIterator<Item = RecordedFrame>,
+    connection_type: ConnectionType,
+) -> MockConnection {
+    let mut connection = MockConnection::new(connection_type);
+    let mut request: Option<(Vec<u8>, Vec<Vec<u8>>)> = None;
+
+    for frame in frames {
+        match (frame.direction, &mut request) {
+            (FrameDirection::HostBound, Some((_, replies))) => replies.push(frame.bytes),
+            (FrameDirection::HostBound, None) => connection.push_reply(frame.bytes),
+            (FrameDirection::DeviceBound, _) => {
+                if let Some((request, replies)) = request.replace((frame.bytes, Vec::new())) {
+                    // Raw frames always encode successfully.
+                    connection.expect_replies(request, replies).unwrap();
+                }
+            }
+        }
+    }
+    if let Some((request, replies)) = request {
+        connection.expect_replies(request, replies).unwrap();
+    }
+
+    connection
+}
+
+/// Opens a recording file and builds a [`MockConnection`] that plays it back.
+///
+/// See [`replay`] for details.
+pub fn replay_file(
+    path: impl AsRef<Path>,
+    connection_type: ConnectionType,
+) -> io::Result<MockConnection> {
+    let frames = read_recording(BufReader::new(File::open(path)?))?;
+    Ok(replay(frames, connection_type))
+}
+
+/// A host-bound packet decoded alongside the raw frame it was decoded from.
+struct Recorded<P> {
+    bytes: Vec<u8>,
+    packet: P,
+}
+impl<P: Decode> Decode for Recorded<P> {
+    fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
+        let bytes: Vec<u8> = data.into_iter().collect();
+        let packet = P::decode(bytes.iter().copied())?;
+        Ok(Self { bytes, packet })
+    }
+}
+
+/// A [`Connection`] that records every frame sent and received by another connection.
+///
+/// Recording is best-effort: if writing to the recording fails, a warning is logged, the
+/// connection keeps working, and the error is returned by [`PacketRecorder::finish`].
+pub struct PacketRecorder<C, W: Write = BufWriter<File>> {
+    inner: C,
+    writer: W,
+    start: Instant,
+    error: Option<io::Error>,
+}
+impl<C: Connection> PacketRecorder<C> {
+    /// Records `inner` to a new file at `path`, replacing any existing file.
+    pub fn create(inner: C, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(inner, BufWriter::new(File::create(path)?)))
+    }
+}
+impl<C: Connection, W: Write> PacketRecorder<C, W> {
+    pub fn new(inner: C, writer: W) -> Self {
+        Self {
+            inner,
+            writer,
+            start: Instant::now(),
+            error: None,
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Flushes the recording and returns the inner connection and writer.
+    ///
+    /// Fails with the first error that occurred while recording, if any.
+    pub fn finish(mut self) -> io::Result<(C, W)> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok((self.inner, self.writer))
+    }
+
+    fn record(&mut self, direction: FrameDirection, bytes: Vec<u8>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let frame = RecordedFrame {
+            direction,
+            timestamp: self.start.elapsed(),
+            bytes,
+        };
+        if let Err(error) = writeln!(self.writer, "{}", frame) {
+            warn!("Failed to record frame, recording stopped: {}", error);
+            self.error = Some(error);
+        }
+    }
+}
+impl<C: Connection, W: Write> Connection for PacketRecorder<C, W> {
+    type Error = C::Error;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    fn config(&self) -> &Config {
+        self.inner.config()
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.inner.record_radio_channel(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        self.inner.stats_mut()
+    }
+
+    async fn shutdown(mut self) -> Result<(), Self::Error> {
+        if let Err(error) = self.writer.flush() {
+            warn!("Failed to flush recording: {}", error);
+        }
+        self.inner.shutdown().await
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), Self::Error> {
+        let frame = packet.encode()?;
+        self.record(FrameDirection::DeviceBound, frame.clone());
+        self.inner.send_packet(frame).await
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, Self::Error> {
+        let recorded = self.inner.receive_packet::<Recorded<P>>(timeout).await?;
+        self.record(FrameDirection::HostBound, recorded.bytes);
+        Ok(recorded.packet)
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.inner.read_user(buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.inner.write_user(buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_recording, replay, PacketRecorder};
+    use crate::{
+        commands::terminal::ReadStdout,
+        connection::{
+            mock::{stdout_exchange, MockConnection},
+            Connection, ConnectionType,
+        },
+    };
+
+    #[tokio::test]
+    async fn recording_replays() {
+        let mut device = MockConnection::new(ConnectionType::Wired);
+        let (poll, reply) = stdout_exchange("hi");
+        device.expect(poll, reply).unwrap();
+
+        let mut recorder = PacketRecorder::new(device, Vec::new());
+        let live = recorder.execute_command(ReadStdout).await.unwrap();
+        let (_, recording) = recorder.finish().unwrap();
+
+        let frames = read_recording(&recording[..]).unwrap();
+        assert_eq!(frames.len(), 2);
+
+        let mut replayed = replay(frames, ConnectionType::Wired);
+        assert_eq!(replayed.execute_command(ReadStdout).await.unwrap(), live);
+        assert_eq!(replayed.remaining(), 0);
+    }
+}
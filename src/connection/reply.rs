@@ -0,0 +1,36 @@
+//! Builders for the raw bytes of host-bound replies, shared by the mock connection and the
+//! simulated brain.
+
+use crate::{
+    crc::VEX_CRC16,
+    encode::Encode,
+    packets::{cdc2::Cdc2Ack, HOST_BOUND_HEADER},
+    varint::VarU16,
+};
+
+/// Builds the raw bytes of a simple CDC reply packet.
+pub(crate) fn cdc_reply(id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::from(HOST_BOUND_HEADER);
+    packet.push(id);
+    packet.extend(VarU16::new(payload.len() as u16).encode().unwrap());
+    packet.extend(payload);
+    packet
+}
+
+/// Builds the raw bytes of a reply whose size covers a trailing CRC, which ends the reply.
+pub(crate) fn crc_reply(id: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::from(HOST_BOUND_HEADER);
+    packet.push(id);
+    packet.extend(VarU16::new(body.len() as u16 + 2).encode().unwrap());
+    packet.extend(body);
+    let crc = VEX_CRC16.checksum(&packet);
+    packet.extend(crc.to_be_bytes());
+    packet
+}
+
+/// Builds the raw bytes of a CDC2 reply packet, including its CRC.
+pub(crate) fn cdc2_reply(id: u8, ext_id: u8, ack: Cdc2Ack, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![ext_id, ack as u8];
+    body.extend(payload);
+    crc_reply(id, &body)
+}
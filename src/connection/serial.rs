@@ -1,44 +1,47 @@
 //! Implements discovering, opening, and interacting with vex devices connected over USB. This module does not have async support.
 
-use log::{debug, error, trace, warn};
+use futures::{stream, Stream};
+use log::{debug, trace, warn, Level};
 use serialport::{SerialPortInfo, SerialPortType};
-use std::time::Duration;
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+#[cfg(target_os = "linux")]
+use std::{fs, path::Path};
 use thiserror::Error;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufRead, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf},
     select,
-    time::sleep,
+    task::spawn_blocking,
+    time::{interval, sleep, Interval, MissedTickBehavior},
 };
 use tokio_serial::SerialStream;
 
-use super::{Connection, ConnectionType};
+use super::{
+    first_shutdown_error, instrument,
+    reconnect::{is_link_lost_io, LinkError},
+    restore_radio_channel,
+    stats::ConnectionStats,
+    transport::{read_frame, read_user_fifo, write_user_fifo, FrameDecoder},
+    Connection, ConnectionType,
+};
+pub use super::{
+    EXP_BRAIN_USB_PID, V5_BRAIN_USB_PID, V5_CONTROLLER_USB_PID, V5_SERIAL_BAUDRATE, VEX_USB_VID,
+};
 use crate::{
+    commands::system::GetUniqueId,
+    commands::CommandError,
+    config::Config,
     connection::{trim_packets, RawPacket},
     decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
-    packets::{
-        cdc2::Cdc2Ack,
-        controller::{UserFifoPacket, UserFifoPayload, UserFifoReplyPacket},
-        HOST_BOUND_HEADER,
-    },
-    string::FixedString,
-    varint::VarU16,
+    packets::{cdc2::Cdc2Ack, radio::RadioChannel},
 };
 
-/// The USB venddor ID for VEX devices
-pub const VEX_USB_VID: u16 = 0x2888;
-
-/// The USB PID of the V5 Brain
-pub const V5_BRAIN_USB_PID: u16 = 0x0501;
-
-/// The USB PID of the EXP Brain
-pub const EXP_BRAIN_USB_PID: u16 = 0x600;
-
-/// The USB PID of the V5 Controller
-pub const V5_CONTROLLER_USB_PID: u16 = 0x0503;
-
-pub const V5_SERIAL_BAUDRATE: u32 = 115200;
-
 /// The information of a generic vex serial port
 #[derive(Clone, Debug)]
 pub struct VexSerialPort {
@@ -53,8 +56,15 @@ pub enum VexSerialPortType {
     Controller,
 }
 
-/// Assigns port types by port location.
-/// This does not appear to work on windows due to its shitty serial device drivers from 2006.
+/// Assigns port types by the USB interface number of each port.
+///
+/// A brain is a composite USB device whose system and user ports are separate interfaces, so
+/// this doesn't depend on the product strings reported by the OS. Returns `None` if the
+/// interface number of any brain port is unknown, so that a fallback can be used instead.
+///
+/// The interface numbers come from `serialport`'s port listing. There is no separate USB
+/// enumeration path that reads the brain's descriptors directly, so when the OS doesn't
+/// report them, ports are classified by their names instead.
 fn types_by_location(ports: &[SerialPortInfo]) -> Option<Vec<VexSerialPort>> {
     debug!("Attempting to infer serial port types by port location.");
     let mut vex_ports = Vec::new();
@@ -81,29 +91,28 @@ fn types_by_location(ports: &[SerialPortInfo]) -> Option<Vec<VexSerialPort>> {
                 port_type: VexSerialPortType::Controller,
             }),
             V5_BRAIN_USB_PID | EXP_BRAIN_USB_PID => {
-                // Check the product name for identifying information
-                // This will not work on windows
-                if let Some(mut location) = info.interface {
-                    if cfg!(target_os = "macos") {
-                        location -= 1; // macOS is 1-indexed
-                    }
+                let mut location = info.interface?;
+                if cfg!(target_os = "macos") {
+                    location = location.checked_sub(1)?; // macOS is 1-indexed
+                }
 
-                    match location {
-                        0 => {
-                            debug!("Found a 'system' serial port over a Brain connection.");
-                            vex_ports.push(VexSerialPort {
+                match location {
+                    0 => {
+                        debug!("Found a 'system' serial port over a Brain connection.");
+                        vex_ports.push(VexSerialPort {
                             port_info: port.clone(),
                             port_type: VexSerialPortType::System,
-                        })},
-                        1 => warn!("Found a controller serial port over a Brain connection! Things are most likely broken."),
-                        2 => {
-                            debug!("Found a 'user' serial port over a Brain connection.");
-                            vex_ports.push(VexSerialPort {
+                        })
+                    }
+                    1 => warn!("Found a controller serial port over a Brain connection! Things are most likely broken."),
+                    2 => {
+                        debug!("Found a 'user' serial port over a Brain connection.");
+                        vex_ports.push(VexSerialPort {
                             port_info: port.clone(),
                             port_type: VexSerialPortType::User,
-                        })},
-                        _ => warn!("Unknown location for V5 device: {}", location),
+                        })
                     }
+                    _ => warn!("Unknown location for V5 device: {}", location),
                 }
             }
             // Unknown product
@@ -117,6 +126,8 @@ fn types_by_location(ports: &[SerialPortInfo]) -> Option<Vec<VexSerialPort>> {
 /// Assign port types based on the last character of the port name.
 /// This is the fallback option for macOS.
 /// This is a band-aid solution and will become obsolete once serialport correctly gets the interface number.
+// The platform is asserted at runtime, since this is still compiled on other platforms.
+#[allow(clippy::assertions_on_constants)]
 fn types_by_name_darwin(ports: &[SerialPortInfo]) -> Option<Vec<VexSerialPort>> {
     assert!(cfg!(target_os = "macos"));
 
@@ -241,6 +252,10 @@ fn find_ports() -> Result<Vec<VexSerialPort>, SerialError> {
 }
 
 /// Finds all connected V5 devices.
+///
+/// A brain's system and user ports are told apart by their USB interface numbers. If the OS
+/// doesn't report those, this falls back to guessing from the port names, which can pair or
+/// classify the ports wrongly.
 pub fn find_devices() -> Result<Vec<SerialDevice>, SerialError> {
     // Find all vex ports, iterate using peekable.
     let mut ports = find_ports()?.into_iter().peekable();
@@ -263,11 +278,13 @@ pub fn find_devices() -> Result<Vec<SerialDevice>, SerialError> {
                     devices.push(SerialDevice::Brain {
                         system_port: port_name,
                         user_port: ports.next().unwrap().port_info.port_name.clone(),
+                        usb: UsbDeviceInfo::from_port(&port.port_info),
                     });
                 } else {
                     // If there is only a system device, add a unknown V5 device
                     devices.push(SerialDevice::Unknown {
                         system_port: port_name,
+                        usb: UsbDeviceInfo::from_port(&port.port_info),
                     });
                 }
             }
@@ -277,14 +294,17 @@ pub fn find_devices() -> Result<Vec<SerialDevice>, SerialError> {
                     Some(p) => p.port_type == VexSerialPortType::System,
                     _ => false,
                 } {
+                    let system_port = ports.next().unwrap().port_info;
                     devices.push(SerialDevice::Brain {
-                        system_port: ports.next().unwrap().port_info.port_name.clone(),
+                        usb: UsbDeviceInfo::from_port(&system_port),
+                        system_port: system_port.port_name,
                         user_port: port.port_info.port_name.clone(),
                     });
                 }
             }
             VexSerialPortType::Controller => devices.push(SerialDevice::Controller {
                 system_port: port.port_info.port_name.clone(),
+                usb: UsbDeviceInfo::from_port(&port.port_info),
             }),
         }
     }
@@ -293,8 +313,228 @@ pub fn find_devices() -> Result<Vec<SerialDevice>, SerialError> {
     Ok(devices)
 }
 
+/// Finds the connected V5 devices that match `filter`.
+///
+/// This is useful for picking out one brain when several are plugged in, since the order
+/// of [`find_devices`] isn't stable.
+pub fn find_devices_matching(filter: &DeviceFilter) -> Result<Vec<SerialDevice>, SerialError> {
+    let mut devices = find_devices()?;
+    devices.retain(|device| filter.matches(device));
+    Ok(devices)
+}
+
+/// Selects devices for [`find_devices_matching`].
+///
+/// A device matches if it passes every filter that is set, so the default filter matches
+/// every device.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    /// The device's USB serial number.
+    pub serial_number: Option<String>,
+    /// The name of either of the device's ports, such as `/dev/ttyACM0` or `COM3`.
+    pub port_name: Option<String>,
+    pub device_type: Option<SerialDeviceType>,
+}
+impl DeviceFilter {
+    /// Returns whether `device` passes this filter.
+    pub fn matches(&self, device: &SerialDevice) -> bool {
+        let serial_number_matches = self
+            .serial_number
+            .as_ref()
+            .is_none_or(|serial| device.usb_info().serial_number.as_ref() == Some(serial));
+        let port_name_matches = self.port_name.as_ref().is_none_or(|name| {
+            device.system_port() == *name || device.user_port().as_ref() == Some(name)
+        });
+        let type_matches = self
+            .device_type
+            .is_none_or(|device_type| device.device_type() == device_type);
+
+        serial_number_matches && port_name_matches && type_matches
+    }
+}
+
+/// Identifying information about the USB device behind a [`SerialDevice`].
+///
+/// Unlike port names, which can change each time a device is plugged in, the serial number
+/// stays the same, so it can be used to recognize a device later.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct UsbDeviceInfo {
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub pid: u16,
+    /// The path of USB buses and ports the device is plugged into, such as `1-2.4`.
+    ///
+    /// This is only available on Linux.
+    pub location: Option<String>,
+}
+impl UsbDeviceInfo {
+    fn from_port(port: &SerialPortInfo) -> Self {
+        let SerialPortType::UsbPort(info) = &port.port_type else {
+            return Self::default();
+        };
+
+        Self {
+            serial_number: info.serial_number.clone(),
+            manufacturer: info.manufacturer.clone(),
+            product: info.product.clone(),
+            pid: info.pid,
+            location: usb_location(&port.port_name),
+        }
+    }
+}
+
+/// Finds the USB location of a serial port from sysfs.
+#[cfg(target_os = "linux")]
+fn usb_location(port_name: &str) -> Option<String> {
+    let name = Path::new(port_name).file_name()?;
+    // The tty belongs to a USB interface such as `1-2.4:1.0`, which is named after the
+    // location of its device.
+    let interface = fs::canonicalize(Path::new("/sys/class/tty").join(name).join("device")).ok()?;
+    let interface = interface.file_name()?.to_str()?;
+    Some(interface.split(':').next()?.to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn usb_location(_port_name: &str) -> Option<String> {
+    None
+}
+
+/// How often [`watch_devices`] checks for devices being plugged in or unplugged, when it
+/// isn't notified of them by the OS.
+pub const DEVICE_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long [`watch_devices`] waits after a hotplug notification before listing devices, so
+/// that both ports of a brain have been set up.
+#[cfg(all(feature = "hotplug", target_os = "linux"))]
+const HOTPLUG_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// A V5 device being plugged in or unplugged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Connected(SerialDevice),
+    Disconnected(SerialDevice),
+}
+
+/// Returns a stream that reports V5 devices as they are plugged in and unplugged.
+///
+/// Devices that are already plugged in are reported as connected first. Errors while listing
+/// devices are yielded without ending the stream, so a port that is still being set up by the
+/// OS does not stop the watcher.
+///
+/// With the `hotplug` feature on Linux, ports are listed when udev reports a tty device
+/// being added or removed. Otherwise, or if udev can't be reached, the available ports are
+/// checked every [`DEVICE_WATCH_INTERVAL`]. The feature has no effect on macOS and Windows,
+/// which are always polled. Ports are listed on Tokio's blocking thread pool.
+pub fn watch_devices() -> impl Stream<Item = Result<DeviceEvent, SerialError>> {
+    let state = (
+        DeviceChanges::new(),
+        Vec::<SerialDevice>::new(),
+        VecDeque::new(),
+    );
+
+    stream::unfold(state, |(mut changes, mut known, mut pending)| async move {
+        loop {
+            if let Some(event) = pending.pop_front() {
+                return Some((event, (changes, known, pending)));
+            }
+            changes.wait().await;
+
+            // Listing ports can block on the OS, so it is kept off the async runtime.
+            let devices = match spawn_blocking(find_devices)
+                .await
+                .unwrap_or_else(|e| Err(io::Error::from(e).into()))
+            {
+                Ok(devices) => devices,
+                Err(e) => return Some((Err(e), (changes, known, pending))),
+            };
+            pending.extend(diff_devices(&mut known, devices).into_iter().map(Ok));
+        }
+    })
+}
+
+/// Updates `known` to the listed `devices`, returning an event for each device that was
+/// unplugged or plugged in since it was last updated.
+fn diff_devices(known: &mut Vec<SerialDevice>, devices: Vec<SerialDevice>) -> Vec<DeviceEvent> {
+    let mut events = Vec::new();
+    known.retain(|device| {
+        let present = devices.contains(device);
+        if !present {
+            debug!("Device disconnected: {:?}", device);
+            events.push(DeviceEvent::Disconnected(device.clone()));
+        }
+        present
+    });
+    for device in devices {
+        if !known.contains(&device) {
+            debug!("Device connected: {:?}", device);
+            events.push(DeviceEvent::Connected(device.clone()));
+            known.push(device);
+        }
+    }
+    events
+}
+
+/// What wakes [`watch_devices`] up to list devices again.
+enum DeviceChanges {
+    Polling(Interval),
+    #[cfg(all(feature = "hotplug", target_os = "linux"))]
+    Hotplug {
+        notifications: tokio::sync::mpsc::UnboundedReceiver<()>,
+        listed: bool,
+    },
+}
+impl DeviceChanges {
+    fn new() -> Self {
+        #[cfg(all(feature = "hotplug", target_os = "linux"))]
+        match super::hotplug::tty_changes() {
+            Ok(notifications) => {
+                return Self::Hotplug {
+                    notifications,
+                    listed: false,
+                }
+            }
+            Err(e) => warn!("Could not monitor udev events, polling instead: {}", e),
+        }
+        Self::polling()
+    }
+
+    fn polling() -> Self {
+        let mut ticker = interval(DEVICE_WATCH_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self::Polling(ticker)
+    }
+
+    /// Waits until the connected devices may have changed.
+    async fn wait(&mut self) {
+        match self {
+            Self::Polling(ticker) => {
+                ticker.tick().await;
+            }
+            #[cfg(all(feature = "hotplug", target_os = "linux"))]
+            Self::Hotplug {
+                notifications,
+                listed,
+            } => {
+                // Devices that are already plugged in are listed straight away.
+                if !std::mem::replace(listed, true) {
+                    return;
+                }
+                if notifications.recv().await.is_none() {
+                    warn!("Stopped receiving udev events, polling instead");
+                    *self = Self::polling();
+                    return;
+                }
+
+                sleep(HOTPLUG_SETTLE_TIME).await;
+                while notifications.try_recv().is_ok() {}
+            }
+        }
+    }
+}
+
 /// Represents a V5 device that can be connected to over serial.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SerialDevice {
     /// V5 Brain
     ///
@@ -302,12 +542,16 @@ pub enum SerialDevice {
     Brain {
         user_port: String,
         system_port: String,
+        usb: UsbDeviceInfo,
     },
 
     /// V5 Controller
     ///
     /// Has a system port, but no user port.
-    Controller { system_port: String },
+    Controller {
+        system_port: String,
+        usb: UsbDeviceInfo,
+    },
 
     /// Unknown V5 Peripheral.
     ///
@@ -315,44 +559,75 @@ pub enum SerialDevice {
     /// *Probably doesn't even exist. How'd you even get this to happen?*
     ///
     /// Has a system port and no user port but __is not a controller__.
-    Unknown { system_port: String },
+    Unknown {
+        system_port: String,
+        usb: UsbDeviceInfo,
+    },
+}
+
+/// The kinds of [`SerialDevice`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SerialDeviceType {
+    Brain,
+    Controller,
+    Unknown,
 }
 
 impl SerialDevice {
+    pub fn device_type(&self) -> SerialDeviceType {
+        match self {
+            Self::Brain { .. } => SerialDeviceType::Brain,
+            Self::Controller { .. } => SerialDeviceType::Controller,
+            Self::Unknown { .. } => SerialDeviceType::Unknown,
+        }
+    }
+
     pub fn connect(&self, timeout: Duration) -> Result<SerialConnection, SerialError> {
         SerialConnection::open(self.clone(), timeout)
     }
 
+    /// Connects to the device using the given [`Config`].
+    pub fn connect_with_config(
+        &self,
+        timeout: Duration,
+        config: Config,
+    ) -> Result<SerialConnection, SerialError> {
+        SerialConnection::open_with_config(self.clone(), timeout, config)
+    }
+
+    /// Connects to the device just long enough to read the brain's unique hardware ID.
+    ///
+    /// See [`GetUniqueId`].
+    pub async fn unique_id(&self, timeout: Duration) -> Result<u32, SerialError> {
+        let mut connection = self.connect(timeout)?;
+        let unique_id = connection.execute_command(GetUniqueId).await;
+        connection.shutdown().await?;
+        unique_id
+    }
+
     pub fn system_port(&self) -> String {
         match &self {
-            Self::Brain {
-                system_port,
-                user_port: _,
-            }
-            | Self::Controller { system_port }
-            | Self::Unknown { system_port } => system_port.clone(),
+            Self::Brain { system_port, .. }
+            | Self::Controller { system_port, .. }
+            | Self::Unknown { system_port, .. } => system_port.clone(),
         }
     }
 
     pub fn user_port(&self) -> Option<String> {
         match &self {
-            Self::Brain {
-                system_port: _,
-                user_port,
-            } => Some(user_port.clone()),
+            Self::Brain { user_port, .. } => Some(user_port.clone()),
             _ => None,
         }
     }
-}
 
-/// Decodes a [`HostBoundPacket`]'s header sequence.
-fn decode_header(data: impl IntoIterator<Item = u8>) -> Result<[u8; 2], DecodeError> {
-    let mut data = data.into_iter();
-    let header = Decode::decode(&mut data)?;
-    if header != HOST_BOUND_HEADER {
-        return Err(DecodeError::InvalidHeader);
+    /// Returns the USB serial number, location and other details of the device.
+    pub fn usb_info(&self) -> &UsbDeviceInfo {
+        match self {
+            Self::Brain { usb, .. } | Self::Controller { usb, .. } | Self::Unknown { usb, .. } => {
+                usb
+            }
+        }
     }
-    Ok(header)
 }
 
 /// An open serial connection to a V5 device.
@@ -360,12 +635,114 @@ fn decode_header(data: impl IntoIterator<Item = u8>) -> Result<[u8; 2], DecodeEr
 pub struct SerialConnection {
     system_port: SerialStream,
     user_port: Option<BufReader<SerialStream>>,
+    decoder: FrameDecoder,
     incoming_packets: Vec<RawPacket>,
+    config: Config,
+    radio_channel: Option<RadioChannel>,
+    stats: ConnectionStats,
+}
+
+/// Advanced options for the serial ports opened by a [`SerialConnection`].
+///
+/// These tune how the operating system's serial driver buffers data. They are currently only
+/// applied on Windows, where the default COM port settings can hold back small packets; on
+/// other platforms they are ignored.
+///
+/// VEX devices use the generic USB CDC driver (`usbser.sys`), which has no latency timer to
+/// configure, unlike USB-serial adapters such as FTDI chips.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SerialOptions {
+    /// The recommended size of the driver's receive buffer in bytes.
+    pub rx_buffer_size: Option<u32>,
+    /// The recommended size of the driver's transmit buffer in bytes.
+    pub tx_buffer_size: Option<u32>,
+    /// The longest gap allowed between two received bytes before a pending read completes.
+    ///
+    /// Lower values deliver small packets sooner. Rounded to whole milliseconds.
+    pub read_interval_timeout: Option<Duration>,
+    /// The longest time a single write may take before it fails.
+    pub write_timeout: Option<Duration>,
+}
+impl SerialOptions {
+    #[cfg(windows)]
+    fn apply(&self, port: &SerialStream) -> Result<(), SerialError> {
+        use std::os::windows::io::AsRawHandle;
+        use winapi::um::{
+            commapi::{GetCommTimeouts, SetCommTimeouts, SetupComm},
+            winbase::COMMTIMEOUTS,
+        };
+
+        let handle = port.as_raw_handle() as winapi::um::winnt::HANDLE;
+
+        if self.rx_buffer_size.is_some() || self.tx_buffer_size.is_some() {
+            // SAFETY: The handle is a valid open COM port for the lifetime of `port`.
+            let ok = unsafe {
+                SetupComm(
+                    handle,
+                    self.rx_buffer_size.unwrap_or(4096),
+                    self.tx_buffer_size.unwrap_or(4096),
+                )
+            };
+            if ok == 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        if self.read_interval_timeout.is_some() || self.write_timeout.is_some() {
+            let mut timeouts: COMMTIMEOUTS = unsafe { std::mem::zeroed() };
+            // SAFETY: The handle is valid and `timeouts` is a valid out pointer.
+            if unsafe { GetCommTimeouts(handle, &mut timeouts) } == 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            if let Some(interval) = self.read_interval_timeout {
+                // 0 disables the interval timeout entirely, so never go below 1ms.
+                timeouts.ReadIntervalTimeout = (interval.as_millis() as u32).max(1);
+            }
+            if let Some(write_timeout) = self.write_timeout {
+                timeouts.WriteTotalTimeoutMultiplier = 0;
+                timeouts.WriteTotalTimeoutConstant = write_timeout.as_millis() as u32;
+            }
+
+            // SAFETY: The handle is valid and `timeouts` is fully initialized.
+            if unsafe { SetCommTimeouts(handle, &mut timeouts) } == 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        debug!("Applied serial options: {:?}", self);
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    fn apply(&self, _port: &SerialStream) -> Result<(), SerialError> {
+        Ok(())
+    }
 }
 
 impl SerialConnection {
     /// Opens a new serial connection to a V5 Brain.
     pub fn open(device: SerialDevice, timeout: Duration) -> Result<Self, SerialError> {
+        Self::open_with_config(device, timeout, Config::default())
+    }
+
+    /// Opens a new serial connection to a V5 Brain using the given [`Config`].
+    pub fn open_with_config(
+        device: SerialDevice,
+        timeout: Duration,
+        config: Config,
+    ) -> Result<Self, SerialError> {
+        Self::open_with_options(device, timeout, config, SerialOptions::default())
+    }
+
+    /// Opens a new serial connection to a V5 Brain using the given [`Config`] and
+    /// advanced port [`SerialOptions`].
+    pub fn open_with_options(
+        device: SerialDevice,
+        timeout: Duration,
+        config: Config,
+        options: SerialOptions,
+    ) -> Result<Self, SerialError> {
         // Open the system port
         let system_port = match tokio_serial::SerialStream::open(
             &tokio_serial::new(device.system_port(), 115200)
@@ -376,6 +753,7 @@ impl SerialConnection {
             Ok(v) => Ok(v),
             Err(e) => Err(SerialError::SerialportError(e)),
         }?;
+        options.apply(&system_port)?;
 
         // Open the user port (if it exists)
         let user_port = if let Some(port) = &device.user_port() {
@@ -385,7 +763,10 @@ impl SerialConnection {
                     .timeout(timeout)
                     .stop_bits(tokio_serial::StopBits::One),
             ) {
-                Ok(v) => Ok(BufReader::new(v)),
+                Ok(v) => {
+                    options.apply(&v)?;
+                    Ok(BufReader::new(v))
+                }
                 Err(e) => Err(SerialError::SerialportError(e)),
             }?)
         } else {
@@ -395,61 +776,92 @@ impl SerialConnection {
         Ok(Self {
             system_port,
             user_port,
+            decoder: FrameDecoder::new(),
             incoming_packets: Default::default(),
+            config,
+            radio_channel: None,
+            stats: ConnectionStats::default(),
         })
     }
 
     /// Receives a single packet from the serial port and adds it to the queue of incoming packets.
     async fn receive_one_packet(&mut self) -> Result<(), SerialError> {
-        // Read the header into an array
-        let mut header = [0u8; 2];
-        self.system_port.read_exact(&mut header).await?;
-
-        // Verify that the header is valid
-        if let Err(e) = decode_header(header) {
-            warn!(
-                "Skipping packet with invalid header: {:x?}. Error: {}",
-                header, e
-            );
-            return Ok(());
+        let packet = read_frame::<_, SerialError>(&mut self.system_port, &mut self.decoder).await?;
+        if self.config.logs_packets_at(Level::Debug) {
+            debug!("received packet: {:x?}", packet);
         }
 
-        // Create a buffer to store the entire packet
-        let mut packet = Vec::from(header);
-
-        // Push the command's ID
-        packet.push(self.system_port.read_u8().await?);
-
-        // Get the size of the packet
-        // We do some extra logic to make sure we only read the necessary amount of bytes
-        let first_size_byte = self.system_port.read_u8().await?;
-        let size = if VarU16::check_wide(first_size_byte) {
-            let second_size_byte = self.system_port.read_u8().await?;
-            packet.extend([first_size_byte, second_size_byte]);
-
-            // Decode the size of the packet
-            VarU16::decode(vec![first_size_byte, second_size_byte])?
-        } else {
-            packet.push(first_size_byte);
+        // Push the packet to the incoming packets buffer
+        self.stats.packet_received(&packet);
+        self.incoming_packets.push(RawPacket::new(packet));
 
-            // Decode the size of the packet
-            VarU16::decode(vec![first_size_byte])?
-        }
-        .into_inner() as usize;
+        Ok(())
+    }
 
-        // Read the rest of the packet
-        let mut payload = vec![0; size];
-        self.system_port.read_exact(&mut payload).await?;
+    /// Switches to raw passthrough on the user port, for talking to user programs that use
+    /// their own protocol instead of plain text.
+    ///
+    /// The returned [`RawUserPort`] reads and writes the user port's bytes exactly as they
+    /// are, and borrows the connection so that no packets can be sent until it is given back
+    /// with [`RawUserPort::exit_raw`] or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SerialError::NoUserPort`] for controllers, which only pass user program data
+    /// through system packets.
+    pub fn enter_raw(&mut self) -> Result<RawUserPort<'_>, SerialError> {
+        let port = self.user_port.as_mut().ok_or(SerialError::NoUserPort)?;
+        debug!("Entered raw user port passthrough");
+        Ok(RawUserPort { port })
+    }
+}
 
-        // Completely fill the packet
-        packet.extend(payload);
+/// Raw access to a brain's user port, created by [`SerialConnection::enter_raw`].
+#[derive(Debug)]
+pub struct RawUserPort<'a> {
+    port: &'a mut BufReader<SerialStream>,
+}
+impl RawUserPort<'_> {
+    /// Flushes anything written and hands the connection back.
+    pub async fn exit_raw(self) -> Result<(), SerialError> {
+        self.port.flush().await?;
+        debug!("Exited raw user port passthrough");
+        Ok(())
+    }
+}
+impl AsyncRead for RawUserPort<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.port).poll_read(cx, buf)
+    }
+}
+impl AsyncBufRead for RawUserPort<'_> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        Pin::new(&mut *self.get_mut().port).poll_fill_buf(cx)
+    }
 
-        debug!("received packet: {:x?}", packet);
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut *self.port).consume(amt)
+    }
+}
+impl AsyncWrite for RawUserPort<'_> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut *self.port).poll_write(cx, buf)
+    }
 
-        // Push the packet to the incoming packets buffer
-        self.incoming_packets.push(RawPacket::new(packet));
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.port).poll_flush(cx)
+    }
 
-        Ok(())
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.port).poll_shutdown(cx)
     }
 }
 
@@ -464,11 +876,58 @@ impl Connection for SerialConnection {
         }
     }
 
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.radio_channel = (channel != RadioChannel::Pit).then_some(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        Some(&mut self.stats)
+    }
+
+    async fn shutdown(mut self) -> Result<(), SerialError> {
+        let mut errors = Vec::new();
+
+        if self.radio_channel.take().is_some() {
+            if let Err(e) = restore_radio_channel(&mut self).await {
+                errors.push(e);
+            }
+        }
+
+        if let Some(user_port) = &mut self.user_port {
+            if let Err(e) = user_port.flush().await {
+                errors.push(e.into());
+            }
+        }
+        if let Err(e) = self.system_port.flush().await {
+            errors.push(e.into());
+        }
+
+        // Close the user port before the system port so that the brain never sees
+        // user output after its system channel has gone away.
+        drop(self.user_port.take());
+        drop(self.system_port);
+        debug!("Serial connection closed");
+
+        first_shutdown_error(errors)
+    }
+
     async fn send_packet(&mut self, packet: impl Encode) -> Result<(), SerialError> {
         // Encode the packet
         let encoded = packet.encode()?;
 
-        trace!("Sending packet: {:x?}", encoded);
+        if self.config.logs_packets_at(Level::Trace) {
+            trace!("Sending packet: {:x?}", encoded);
+        }
+        instrument::packet_sent(&encoded);
+        self.stats.packet_sent(&encoded);
 
         // Write the packet to the serial port
         match self.system_port.write_all(&encoded).await {
@@ -507,57 +966,15 @@ impl Connection for SerialConnection {
         if let Some(user_port) = &mut self.user_port {
             Ok(user_port.read(buf).await?)
         } else {
-            let mut data = Vec::new();
-            loop {
-                let fifo = self
-                    .packet_handshake::<UserFifoReplyPacket>(
-                        Duration::from_millis(100),
-                        1,
-                        UserFifoPacket::new(UserFifoPayload {
-                            channel: 1, // stdio channel
-                            write: None,
-                        }),
-                    )
-                    .await?
-                    .try_into_inner()?;
-                if let Some(read) = fifo.data {
-                    data.extend(read.as_bytes());
-                    break;
-                }
-            }
-
-            let len = data.len().min(buf.len());
-            buf[..len].copy_from_slice(&data[..len]);
-
-            Ok(len)
+            read_user_fifo(self, buf).await
         }
     }
 
-    async fn write_user(&mut self, mut buf: &[u8]) -> Result<usize, SerialError> {
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, SerialError> {
         if let Some(user_port) = &mut self.user_port {
             Ok(user_port.write(buf).await?)
         } else {
-            let buf_len = buf.len();
-            while !buf.is_empty() {
-                let (chunk, rest) = buf.split_at(std::cmp::min(224, buf.len()));
-                _ = self
-                    .packet_handshake::<UserFifoReplyPacket>(
-                        Duration::from_millis(100),
-                        1,
-                        UserFifoPacket::new(UserFifoPayload {
-                            channel: 2, // stdio channel
-                            write: Some(
-                                FixedString::new(String::from_utf8(chunk.to_vec()).unwrap())
-                                    .unwrap(),
-                            ),
-                        }),
-                    )
-                    .await?
-                    .try_into_inner()?;
-                buf = rest;
-            }
-
-            Ok(buf_len)
+            write_user_fifo(self, buf).await
         }
     }
 }
@@ -570,6 +987,8 @@ pub enum SerialError {
     EncodeError(#[from] EncodeError),
     #[error("Packet decoding error: {0}")]
     DecodeError(#[from] DecodeError),
+    #[error("Command failed: {0}")]
+    CommandError(#[from] CommandError),
     #[error("Packet timeout")]
     Timeout,
     #[error("NACK received: {0:?}")]
@@ -578,4 +997,76 @@ pub enum SerialError {
     SerialportError(#[from] tokio_serial::Error),
     #[error("Could not infer serial port types")]
     CouldntInferTypes,
+    #[error("This device has no user port")]
+    NoUserPort,
+}
+impl LinkError for SerialError {
+    fn is_link_lost(&self) -> bool {
+        match self {
+            Self::IoError(e) => is_link_lost_io(e),
+            Self::SerialportError(e) => e.kind() == tokio_serial::ErrorKind::NoDevice,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        diff_devices, DeviceEvent, DeviceFilter, SerialDevice, SerialDeviceType, UsbDeviceInfo,
+    };
+
+    #[test]
+    fn filters_devices() {
+        let brain = SerialDevice::Brain {
+            user_port: "/dev/ttyACM1".to_string(),
+            system_port: "/dev/ttyACM0".to_string(),
+            usb: UsbDeviceInfo {
+                serial_number: Some("ABC123".to_string()),
+                ..Default::default()
+            },
+        };
+
+        assert!(DeviceFilter::default().matches(&brain));
+        assert!(DeviceFilter {
+            serial_number: Some("ABC123".to_string()),
+            port_name: Some("/dev/ttyACM1".to_string()),
+            device_type: Some(SerialDeviceType::Brain),
+        }
+        .matches(&brain));
+        assert!(!DeviceFilter {
+            serial_number: Some("ABC123".to_string()),
+            device_type: Some(SerialDeviceType::Controller),
+            ..Default::default()
+        }
+        .matches(&brain));
+    }
+
+    #[test]
+    fn diffs_listed_devices() {
+        let brain = SerialDevice::Brain {
+            user_port: "/dev/ttyACM1".to_string(),
+            system_port: "/dev/ttyACM0".to_string(),
+            usb: UsbDeviceInfo::default(),
+        };
+        let controller = SerialDevice::Controller {
+            system_port: "/dev/ttyACM2".to_string(),
+            usb: UsbDeviceInfo::default(),
+        };
+
+        let mut known = Vec::new();
+        assert_eq!(
+            diff_devices(&mut known, vec![brain.clone()]),
+            [DeviceEvent::Connected(brain.clone())]
+        );
+        assert!(diff_devices(&mut known, vec![brain.clone()]).is_empty());
+        assert_eq!(
+            diff_devices(&mut known, vec![controller.clone()]),
+            [
+                DeviceEvent::Disconnected(brain.clone()),
+                DeviceEvent::Connected(controller.clone()),
+            ]
+        );
+        assert_eq!(known, [controller]);
+    }
 }
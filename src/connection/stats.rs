@@ -0,0 +1,129 @@
+//! Counting the packets that pass through a connection.
+
+use std::time::Duration;
+
+use crate::packets::cdc2::Cdc2Ack;
+
+/// Packet and handshake counters for a connection, returned by
+/// [`Connection::stats`](super::Connection::stats).
+///
+/// Every counter starts at zero when the connection is opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    /// The encoded size of every packet sent, including headers and checksums.
+    pub bytes_sent: u64,
+    /// The size of every packet received, including headers and checksums.
+    pub bytes_received: u64,
+    /// How many CDC2 replies carried a negative acknowledgement.
+    pub nacks: u64,
+    /// How many times a packet handshake resent its packet after getting no usable reply.
+    pub retransmits: u64,
+    /// How many packet handshakes got a reply.
+    pub handshakes: u64,
+    /// The round trip times of every successful handshake added together, measured from
+    /// the last time its packet was sent.
+    pub total_rtt: Duration,
+}
+impl ConnectionStats {
+    /// Returns the average round trip time of a successful handshake.
+    pub fn average_rtt(&self) -> Option<Duration> {
+        (self.handshakes > 0).then(|| {
+            self.total_rtt
+                .checked_div(self.handshakes.min(u32::MAX as u64) as u32)
+                .unwrap_or_default()
+        })
+    }
+
+    pub(crate) fn packet_sent(&mut self, encoded: &[u8]) {
+        self.packets_sent += 1;
+        self.bytes_sent += encoded.len() as u64;
+    }
+
+    pub(crate) fn packet_received(&mut self, bytes: &[u8]) {
+        self.packets_received += 1;
+        self.bytes_received += bytes.len() as u64;
+        if cdc2_ack(bytes).is_some_and(|ack| ack != Cdc2Ack::Ack as u8) {
+            self.nacks += 1;
+        }
+    }
+
+    pub(crate) fn handshake_completed(&mut self, rtt: Duration) {
+        self.handshakes += 1;
+        self.total_rtt += rtt;
+    }
+}
+
+/// Returns the acknowledgement byte of a raw CDC2 reply.
+fn cdc2_ack(bytes: &[u8]) -> Option<u8> {
+    // CDC2 replies are the ones sent under the 0x56 and 0x58 command IDs.
+    if !matches!(bytes.get(2)?, 0x56 | 0x58) {
+        return None;
+    }
+    // The size after the ID takes two bytes if its top bit is set.
+    let ext_id_index = if bytes.get(3)? & 0x80 != 0 { 5 } else { 4 };
+    bytes.get(ext_id_index + 1).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ConnectionStats;
+    use crate::{
+        connection::{
+            mock::{cdc_reply, MockConnection},
+            Connection, ConnectionType,
+        },
+        packets::{
+            cdc2::Cdc2Ack,
+            system::{GetSystemVersionPacket, GetSystemVersionReplyPacket},
+        },
+    };
+
+    #[test]
+    fn counts_packets() {
+        let mut stats = ConnectionStats::default();
+        stats.packet_sent(&[0xC9, 0x36, 0xB8, 0x47, 0xA4]);
+        // A simple CDC reply, an ACK and a NACK with a two byte size.
+        stats.packet_received(&[0xAA, 0x55, 0xA4, 0x00]);
+        stats.packet_received(&[0xAA, 0x55, 0x56, 0x04, 0x22, Cdc2Ack::Ack as u8, 0, 0]);
+        stats.packet_received(&[0xAA, 0x55, 0x56, 0x80, 0x04, 0x13, 0xD1, 0, 0]);
+        stats.handshake_completed(Duration::from_millis(10));
+        stats.handshake_completed(Duration::from_millis(30));
+
+        assert_eq!(stats.packets_sent, 1);
+        assert_eq!(stats.bytes_sent, 5);
+        assert_eq!(stats.packets_received, 3);
+        assert_eq!(stats.bytes_received, 21);
+        assert_eq!(stats.nacks, 1);
+        assert_eq!(stats.average_rtt(), Some(Duration::from_millis(20)));
+        assert_eq!(ConnectionStats::default().average_rtt(), None);
+    }
+
+    #[tokio::test]
+    async fn counts_retransmits() {
+        let mut connection = MockConnection::new(ConnectionType::Wired);
+        connection
+            .expect_replies(GetSystemVersionPacket::new(()), Vec::new())
+            .unwrap();
+        connection
+            .expect(
+                GetSystemVersionPacket::new(()),
+                cdc_reply::<164>(&[1, 1, 4, 0, 0x00, 0x10, 0x00]),
+            )
+            .unwrap();
+
+        connection
+            .packet_handshake::<GetSystemVersionReplyPacket>(GetSystemVersionPacket::new(()))
+            .await
+            .unwrap();
+
+        let stats = connection.stats().unwrap();
+        assert_eq!(stats.packets_sent, 2);
+        assert_eq!(stats.packets_received, 1);
+        assert_eq!(stats.retransmits, 1);
+        assert_eq!(stats.handshakes, 1);
+    }
+}
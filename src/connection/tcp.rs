@@ -0,0 +1,99 @@
+//! Connecting to devices exposed over TCP.
+//!
+//! This is meant for brains plugged into another machine and shared over the network by a
+//! serial-over-TCP proxy such as ser2net. The proxy must forward the raw byte stream: telnet
+//! (RFC 2217) mode escapes bytes and is not supported.
+
+use std::io;
+
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::config::Config;
+
+use super::{
+    transport::{TransportConnection, TransportError},
+    ConnectionType,
+};
+
+/// A connection to a device through a TCP socket.
+pub type TcpConnection = TransportConnection<TcpStream>;
+/// Errors that can occur on a [`TcpConnection`].
+pub type TcpError = TransportError;
+
+/// Connects to a device exposed at `address`, such as `"192.168.1.20:3333"`.
+///
+/// `connection_type` describes how the device is attached to the proxy, since it cannot be
+/// detected over the network.
+///
+/// The proxy has to serve the port in raw mode, such as ser2net's `raw` accepter. RFC 2217 is
+/// not implemented, so a telnet port's option negotiation and doubled `0xFF` bytes would be
+/// read as part of the device's packets and corrupt them.
+pub async fn connect(
+    address: impl ToSocketAddrs,
+    connection_type: ConnectionType,
+) -> io::Result<TcpConnection> {
+    connect_with_config(address, connection_type, Config::default()).await
+}
+
+/// Connects to a device exposed at `address` with a custom configuration.
+///
+/// See [`connect`] for details.
+pub async fn connect_with_config(
+    address: impl ToSocketAddrs,
+    connection_type: ConnectionType,
+    config: Config,
+) -> io::Result<TcpConnection> {
+    let stream = TcpStream::connect(address).await?;
+    // Packets are small and each one is waited on, so don't let them sit in Nagle's buffer.
+    stream.set_nodelay(true)?;
+
+    Ok(TransportConnection::with_config(
+        stream,
+        connection_type,
+        config,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::connect;
+    use crate::{
+        connection::{mock::cdc2_reply, Connection, ConnectionType},
+        encode::Encode,
+        packets::{
+            cdc2::Cdc2Ack,
+            system::{GetSystemFlagsPacket, GetSystemFlagsReplyPacket},
+        },
+    };
+
+    #[tokio::test]
+    async fn handshakes_over_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let request = GetSystemFlagsPacket::new(()).encode().unwrap();
+        let reply = cdc2_reply::<86, 32>(Cdc2Ack::Ack, &[0; 7]);
+        let proxy = tokio::spawn({
+            let request = request.clone();
+            async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = vec![0; request.len()];
+                socket.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, request);
+                socket.write_all(&reply).await.unwrap();
+            }
+        });
+
+        let mut connection = connect(address, ConnectionType::Wired).await.unwrap();
+        connection
+            .packet_handshake::<GetSystemFlagsReplyPacket>(GetSystemFlagsPacket::new(()))
+            .await
+            .unwrap();
+        proxy.await.unwrap();
+    }
+}
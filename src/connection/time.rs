@@ -0,0 +1,15 @@
+//! Timers that work both natively and in the browser.
+//!
+//! `std::time::Instant` panics and tokio's timer has no driver on `wasm32-unknown-unknown`,
+//! so code shared with the browser backends should get its clock and timers from here.
+
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+pub(crate) use std::time::Instant;
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+pub(crate) use tokio::time::{interval, sleep, sleep_until, Interval, MissedTickBehavior};
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub(crate) use wasmtimer::{
+    std::Instant,
+    tokio::{interval, sleep, sleep_until, Interval, MissedTickBehavior},
+};
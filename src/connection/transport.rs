@@ -0,0 +1,401 @@
+//! Byte-level transports and a [`Connection`] built on top of them.
+//!
+//! A [`Transport`] only has to move bytes. [`TransportConnection`] adds everything else:
+//! packet framing, buffering of incoming packets, handshakes, and user program I/O over
+//! the system channel. New backends can implement [`Transport`] instead of [`Connection`].
+
+use std::{future::Future, io, time::Duration};
+
+use bytes::Bytes;
+use log::{debug, trace, warn, Level};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    select,
+};
+
+use crate::{
+    commands::CommandError,
+    commands::{channels::STDIO, terminal::WriteStdin},
+    config::{Backoff, Config, RetryPolicy},
+    decode::{Decode, DecodeError},
+    encode::{Encode, EncodeError},
+    packets::{
+        cdc2::Cdc2Ack,
+        controller::{UserFifoPacket, UserFifoPayload, UserFifoReplyPacket},
+        HOST_BOUND_HEADER,
+    },
+    varint::VarU16,
+};
+
+use super::{
+    instrument,
+    reconnect::{is_link_lost_io, LinkError},
+    stats::ConnectionStats,
+    time::sleep,
+    trim_packets, Connection, ConnectionType, RawPacket,
+};
+
+/// A bidirectional byte stream to a V5 device.
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    /// Reads some bytes into `buf`, returning how many were read.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> impl Future<Output = io::Result<usize>>;
+
+    /// Writes all of `buf`.
+    fn write_bytes(&mut self, buf: &[u8]) -> impl Future<Output = io::Result<()>>;
+
+    /// Flushes any buffered writes.
+    fn flush(&mut self) -> impl Future<Output = io::Result<()>>;
+
+    /// Fills `buf` completely.
+    async fn read_exact_bytes(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_bytes(buf).await? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> Transport for T {
+    async fn read_bytes(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read(buf).await
+    }
+
+    async fn write_bytes(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.write_all(buf).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        AsyncWriteExt::flush(self).await
+    }
+}
+
+/// Splits a stream of host-bound bytes into packet frames.
+///
+/// Bytes can be pushed in pieces of any size, so a frame split across several reads from the
+/// OS driver is only returned once all of it has arrived. Bytes that do not belong to a frame
+/// are skipped until the next packet header.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds received bytes to the decoder.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns how many bytes are waiting to form a complete frame.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the next complete frame, or `None` if more bytes are needed.
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        // Skip to the next header
+        let start = self
+            .buffer
+            .windows(HOST_BOUND_HEADER.len())
+            .position(|window| window == HOST_BOUND_HEADER)
+            .unwrap_or_else(|| {
+                // Keep a trailing first header byte, since the rest of the header may follow
+                let partial = self.buffer.last() == Some(&HOST_BOUND_HEADER[0]);
+                self.buffer.len() - usize::from(partial)
+            });
+        if start > 0 {
+            warn!(
+                "Skipping {} bytes without a valid header: {:x?}",
+                start,
+                &self.buffer[..start]
+            );
+            self.buffer.drain(..start);
+        }
+
+        // The header is followed by the command's ID and the size of the rest of the packet.
+        // We do some extra logic to make sure we only read the necessary amount of bytes
+        let size_start = HOST_BOUND_HEADER.len() + 1;
+        let first_size_byte = *self.buffer.get(size_start)?;
+        let size_end = size_start
+            + if VarU16::check_wide(first_size_byte) {
+                2
+            } else {
+                1
+            };
+        let size_bytes = self.buffer.get(size_start..size_end)?;
+        let size = VarU16::decode(size_bytes.iter().copied())
+            .ok()?
+            .into_inner() as usize;
+
+        if self.buffer.len() < size_end + size {
+            return None;
+        }
+        Some(self.buffer.drain(..size_end + size).collect())
+    }
+}
+
+/// Reads a single host-bound packet from a transport.
+///
+/// Bytes are read into `decoder` as soon as they arrive, so no data is lost if this future
+/// is cancelled before a complete frame has been read.
+pub(crate) async fn read_frame<T, E>(
+    transport: &mut T,
+    decoder: &mut FrameDecoder,
+) -> Result<Vec<u8>, E>
+where
+    T: Transport + ?Sized,
+    E: From<io::Error>,
+{
+    let mut buf = [0u8; 1024];
+    loop {
+        if let Some(frame) = decoder.next_frame() {
+            return Ok(frame);
+        }
+
+        match transport.read_bytes(&mut buf).await? {
+            0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            n => decoder.push(&buf[..n]),
+        }
+    }
+}
+
+/// Reads user program output through the system channel's user FIFO.
+///
+/// This is used by devices that have no dedicated user port, such as controllers.
+pub(crate) async fn read_user_fifo<C: Connection + ?Sized>(
+    connection: &mut C,
+    buf: &mut [u8],
+) -> Result<usize, C::Error> {
+    let packet = UserFifoPacket::new(UserFifoPayload {
+        channel: STDIO,
+        write: None,
+    });
+    // Polling is repeated anyway, so a lost reply isn't worth resending by default.
+    let policy = connection
+        .config()
+        .retry_override_for(&packet.encode()?)
+        .copied()
+        .unwrap_or(RetryPolicy {
+            max_attempts: 1,
+            timeout: Duration::from_millis(100),
+            backoff: Backoff::None,
+        });
+
+    let mut data = Vec::new();
+    loop {
+        let fifo = connection
+            .packet_handshake_with_policy::<UserFifoReplyPacket>(&policy, packet.clone())
+            .await?
+            .0
+            .try_into_inner()?;
+        if let Some(read) = fifo.data {
+            data.extend(read.as_bytes());
+            break;
+        }
+    }
+
+    let len = data.len().min(buf.len());
+    buf[..len].copy_from_slice(&data[..len]);
+
+    Ok(len)
+}
+
+/// Writes to user program stdin through the system channel's user FIFO.
+///
+/// This is used by devices that have no dedicated user port, such as controllers.
+pub(crate) async fn write_user_fifo<C: Connection + ?Sized>(
+    connection: &mut C,
+    buf: &[u8],
+) -> Result<usize, C::Error> {
+    connection
+        .execute_command(WriteStdin {
+            data: Bytes::copy_from_slice(buf),
+        })
+        .await?;
+    Ok(buf.len())
+}
+
+/// A [`Connection`] that frames packets over any [`Transport`].
+///
+/// User program I/O goes through the system channel, since a transport only carries one stream.
+pub struct TransportConnection<T: Transport> {
+    transport: T,
+    connection_type: ConnectionType,
+    decoder: FrameDecoder,
+    incoming_packets: Vec<RawPacket>,
+    config: Config,
+    stats: ConnectionStats,
+}
+impl<T: Transport> TransportConnection<T> {
+    pub fn new(transport: T, connection_type: ConnectionType) -> Self {
+        Self::with_config(transport, connection_type, Config::default())
+    }
+
+    pub fn with_config(transport: T, connection_type: ConnectionType, config: Config) -> Self {
+        Self {
+            transport,
+            connection_type,
+            decoder: FrameDecoder::new(),
+            incoming_packets: Vec::new(),
+            config,
+            stats: ConnectionStats::default(),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+
+    /// Receives a single packet from the transport and adds it to the queue of incoming packets.
+    async fn receive_one_packet(&mut self) -> Result<(), TransportError> {
+        let packet =
+            read_frame::<_, TransportError>(&mut self.transport, &mut self.decoder).await?;
+        if self.config.logs_packets_at(Level::Debug) {
+            debug!("received packet: {:x?}", packet);
+        }
+        self.stats.packet_received(&packet);
+        self.incoming_packets.push(RawPacket::new(packet));
+        Ok(())
+    }
+}
+impl<T: Transport> Connection for TransportConnection<T> {
+    type Error = TransportError;
+
+    fn connection_type(&self) -> ConnectionType {
+        self.connection_type
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        Some(&mut self.stats)
+    }
+
+    async fn shutdown(mut self) -> Result<(), TransportError> {
+        self.transport.flush().await?;
+        Ok(())
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), TransportError> {
+        let encoded = packet.encode()?;
+
+        if self.config.logs_packets_at(Level::Trace) {
+            trace!("Sending packet: {:x?}", encoded);
+        }
+        instrument::packet_sent(&encoded);
+        self.stats.packet_sent(&encoded);
+
+        self.transport.write_bytes(&encoded).await?;
+        self.transport.flush().await?;
+
+        Ok(())
+    }
+
+    async fn receive_packet<P: Decode>(&mut self, timeout: Duration) -> Result<P, TransportError> {
+        // Return an error if the right packet is not received within the timeout
+        select! {
+            result = async {
+                loop {
+                    for packet in self.incoming_packets.iter_mut() {
+                        if let Ok(decoded) = packet.decode_and_use::<P>() {
+                            trim_packets(&mut self.incoming_packets);
+                            return Ok(decoded);
+                        }
+                    }
+                    trim_packets(&mut self.incoming_packets);
+                    self.receive_one_packet().await?;
+                }
+            } => result,
+            _ = sleep(timeout) => Err(TransportError::Timeout)
+        }
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        read_user_fifo(self, buf).await
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, TransportError> {
+        write_user_fifo(self, buf).await
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TransportError {
+    #[error("IO Error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Packet encoding error: {0}")]
+    EncodeError(#[from] EncodeError),
+    #[error("Packet decoding error: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("Command failed: {0}")]
+    CommandError(#[from] CommandError),
+    #[error("Packet timeout")]
+    Timeout,
+    #[error("NACK received: {0:?}")]
+    Nack(#[from] Cdc2Ack),
+}
+impl LinkError for TransportError {
+    fn is_link_lost(&self) -> bool {
+        matches!(self, Self::IoError(e) if is_link_lost_io(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    use super::{read_frame, FrameDecoder, TransportError};
+
+    #[tokio::test]
+    async fn frames_packets() {
+        let (mut device, mut host) = duplex(64);
+        device
+            .write_all(&[0x00, 0x00, 0xAA, 0x55, 0x21, 0x02, 0x01, 0x02])
+            .await
+            .unwrap();
+
+        // A bad header is skipped without losing the packet after it.
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(
+            read_frame::<_, TransportError>(&mut host, &mut decoder)
+                .await
+                .unwrap(),
+            vec![0xAA, 0x55, 0x21, 0x02, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn decodes_partial_frames() {
+        let frame = [0xAA, 0x55, 0x56, 0x80, 0x03, 0x14, 0x01, 0x02];
+        let mut decoder = FrameDecoder::new();
+
+        // Split the frame through the header and wide size.
+        decoder.push(&[0x13, 0xAA]);
+        assert_eq!(decoder.next_frame(), None);
+        decoder.push(&frame[1..4]);
+        assert_eq!(decoder.next_frame(), None);
+        decoder.push(&frame[4..]);
+        assert_eq!(decoder.next_frame(), Some(frame.to_vec()));
+        assert_eq!(decoder.buffered(), 0);
+    }
+}
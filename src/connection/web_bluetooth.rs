@@ -0,0 +1,491 @@
+//! Connecting to brains through the browser's WebBluetooth API.
+//!
+//! Like [`bluetooth`](super::bluetooth), this talks to the brain's BLE radio directly, so the
+//! brain's radio must be in Bluetooth mode and the connection must be paired with the code
+//! shown on the brain's screen before packets can be sent.
+//!
+//! WebBluetooth is only available in Chromium-based browsers, in secure contexts.
+
+use std::{collections::VecDeque, time::Duration};
+
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    StreamExt,
+};
+use js_sys::{Array, DataView, Function, Object, Promise, Reflect, Uint8Array};
+use log::{debug, trace, Level};
+use thiserror::Error;
+use tokio::select;
+use uuid::Uuid;
+use wasm_bindgen::{
+    prelude::{wasm_bindgen, Closure},
+    JsCast, JsValue,
+};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    commands::CommandError,
+    config::Config,
+    decode::{Decode, DecodeError},
+    encode::{Encode, EncodeError},
+    packets::{cdc2::Cdc2Ack, radio::RadioChannel},
+};
+
+pub use super::gatt::*;
+use super::{
+    first_shutdown_error, instrument, restore_radio_channel, stats::ConnectionStats, time::sleep,
+    transport::FrameDecoder, trim_packets, Connection, ConnectionType, RawPacket,
+};
+
+// web-sys only exposes WebBluetooth behind `--cfg=web_sys_unstable_apis`, so we bind the
+// small part of it we need ourselves.
+#[wasm_bindgen]
+extern "C" {
+    type Bluetooth;
+    #[wasm_bindgen(method, js_name = requestDevice)]
+    fn request_device(this: &Bluetooth, options: &JsValue) -> Promise;
+
+    #[derive(Debug, Clone)]
+    type BluetoothDevice;
+    #[wasm_bindgen(method, getter)]
+    fn name(this: &BluetoothDevice) -> Option<String>;
+    #[wasm_bindgen(method, getter)]
+    fn gatt(this: &BluetoothDevice) -> Option<BluetoothRemoteGattServer>;
+
+    type BluetoothRemoteGattServer;
+    #[wasm_bindgen(method)]
+    fn connect(this: &BluetoothRemoteGattServer) -> Promise;
+    #[wasm_bindgen(method)]
+    fn disconnect(this: &BluetoothRemoteGattServer);
+    #[wasm_bindgen(method, js_name = getPrimaryService)]
+    fn get_primary_service(this: &BluetoothRemoteGattServer, uuid: &str) -> Promise;
+
+    type BluetoothRemoteGattService;
+    #[wasm_bindgen(method, js_name = getCharacteristic)]
+    fn get_characteristic(this: &BluetoothRemoteGattService, uuid: &str) -> Promise;
+
+    #[derive(Clone)]
+    type BluetoothRemoteGattCharacteristic;
+    #[wasm_bindgen(method, getter)]
+    fn value(this: &BluetoothRemoteGattCharacteristic) -> Option<DataView>;
+    #[wasm_bindgen(method, js_name = readValue)]
+    fn read_value(this: &BluetoothRemoteGattCharacteristic) -> Promise;
+    #[wasm_bindgen(method, js_name = writeValueWithoutResponse)]
+    fn write_value_without_response(
+        this: &BluetoothRemoteGattCharacteristic,
+        value: &Uint8Array,
+    ) -> Promise;
+    #[wasm_bindgen(method, js_name = startNotifications)]
+    fn start_notifications(this: &BluetoothRemoteGattCharacteristic) -> Promise;
+    #[wasm_bindgen(method, js_name = stopNotifications)]
+    fn stop_notifications(this: &BluetoothRemoteGattCharacteristic) -> Promise;
+    #[wasm_bindgen(method, js_name = addEventListener)]
+    fn add_event_listener(
+        this: &BluetoothRemoteGattCharacteristic,
+        event: &str,
+        listener: &Function,
+    );
+    #[wasm_bindgen(method, js_name = removeEventListener)]
+    fn remove_event_listener(
+        this: &BluetoothRemoteGattCharacteristic,
+        event: &str,
+        listener: &Function,
+    );
+}
+
+const VALUE_CHANGED: &str = "characteristicvaluechanged";
+
+fn js_error(value: JsValue) -> WebBluetoothError {
+    WebBluetoothError::JsError(format!("{:?}", value))
+}
+
+fn uuid_string(uuid: Uuid) -> String {
+    uuid.hyphenated().to_string()
+}
+
+fn data_view_bytes(view: &DataView) -> Vec<u8> {
+    Uint8Array::new_with_byte_offset_and_length(
+        &view.buffer(),
+        view.byte_offset() as u32,
+        view.byte_length() as u32,
+    )
+    .to_vec()
+}
+
+/// Returns `navigator.bluetooth`, if the browser supports it.
+fn bluetooth() -> Result<Bluetooth, WebBluetoothError> {
+    let navigator = Reflect::get(&js_sys::global(), &"navigator".into()).map_err(js_error)?;
+    let bluetooth = Reflect::get(&navigator, &"bluetooth".into()).map_err(js_error)?;
+    if bluetooth.is_undefined() {
+        return Err(WebBluetoothError::Unsupported);
+    }
+    Ok(bluetooth.unchecked_into())
+}
+
+/// Shows the browser's device picker, listing only V5 brains.
+///
+/// This must be called in response to a user gesture, such as a click.
+pub async fn request_device() -> Result<WebBluetoothDevice, WebBluetoothError> {
+    let filter = Object::new();
+    let services = Array::of1(&uuid_string(V5_SERVICE).into());
+    Reflect::set(&filter, &"services".into(), &services).map_err(js_error)?;
+    let options = Object::new();
+    Reflect::set(&options, &"filters".into(), &Array::of1(&filter)).map_err(js_error)?;
+
+    let device = JsFuture::from(bluetooth()?.request_device(&options))
+        .await
+        .map_err(js_error)?;
+    Ok(WebBluetoothDevice(device.unchecked_into()))
+}
+
+/// A brain the user has given this page access to.
+#[derive(Debug, Clone)]
+pub struct WebBluetoothDevice(BluetoothDevice);
+impl WebBluetoothDevice {
+    /// The device's advertised name, if known.
+    pub fn name(&self) -> Option<String> {
+        self.0.name()
+    }
+
+    pub async fn connect(&self) -> Result<WebBluetoothConnection, WebBluetoothError> {
+        WebBluetoothConnection::open(self.clone()).await
+    }
+
+    /// Connects to the device using the given [`Config`].
+    pub async fn connect_with_config(
+        &self,
+        config: Config,
+    ) -> Result<WebBluetoothConnection, WebBluetoothError> {
+        WebBluetoothConnection::open_with_config(self.clone(), config).await
+    }
+}
+
+/// The characteristic a notification came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    System,
+    User,
+}
+
+/// A subscription to a characteristic's notifications.
+struct Subscription {
+    characteristic: BluetoothRemoteGattCharacteristic,
+    listener: Closure<dyn FnMut()>,
+}
+impl Subscription {
+    async fn start(
+        characteristic: BluetoothRemoteGattCharacteristic,
+        channel: Channel,
+        sender: UnboundedSender<(Channel, Vec<u8>)>,
+    ) -> Result<Self, WebBluetoothError> {
+        let listener = Closure::<dyn FnMut()>::new({
+            let characteristic = characteristic.clone();
+            move || {
+                if let Some(value) = characteristic.value() {
+                    // The receiver is only gone once the connection is dropped.
+                    _ = sender.unbounded_send((channel, data_view_bytes(&value)));
+                }
+            }
+        });
+        characteristic.add_event_listener(VALUE_CHANGED, listener.as_ref().unchecked_ref());
+        JsFuture::from(characteristic.start_notifications())
+            .await
+            .map_err(js_error)?;
+
+        Ok(Self {
+            characteristic,
+            listener,
+        })
+    }
+
+    async fn stop(&self) -> Result<(), WebBluetoothError> {
+        self.characteristic
+            .remove_event_listener(VALUE_CHANGED, self.listener.as_ref().unchecked_ref());
+        JsFuture::from(self.characteristic.stop_notifications())
+            .await
+            .map_err(js_error)?;
+        Ok(())
+    }
+}
+
+pub struct WebBluetoothConnection {
+    server: BluetoothRemoteGattServer,
+    system_rx: BluetoothRemoteGattCharacteristic,
+    user_rx: BluetoothRemoteGattCharacteristic,
+    pairing: BluetoothRemoteGattCharacteristic,
+
+    subscriptions: [Subscription; 2],
+    notifications: UnboundedReceiver<(Channel, Vec<u8>)>,
+    decoder: FrameDecoder,
+    incoming_packets: Vec<RawPacket>,
+    user_buffer: VecDeque<u8>,
+    config: Config,
+    radio_channel: Option<RadioChannel>,
+    stats: ConnectionStats,
+}
+impl WebBluetoothConnection {
+    pub const MAX_PACKET_SIZE: usize = 244;
+
+    pub async fn open(device: WebBluetoothDevice) -> Result<Self, WebBluetoothError> {
+        Self::open_with_config(device, Config::default()).await
+    }
+
+    /// Opens a connection to the device using the given [`Config`].
+    pub async fn open_with_config(
+        device: WebBluetoothDevice,
+        config: Config,
+    ) -> Result<Self, WebBluetoothError> {
+        let server = device.0.gatt().ok_or(WebBluetoothError::Unsupported)?;
+        JsFuture::from(server.connect()).await.map_err(js_error)?;
+
+        let service: BluetoothRemoteGattService =
+            JsFuture::from(server.get_primary_service(&uuid_string(V5_SERVICE)))
+                .await
+                .map_err(|_| WebBluetoothError::MissingCharacteristic)?
+                .unchecked_into();
+        let characteristic = |uuid| {
+            let request = service.get_characteristic(&uuid_string(uuid));
+            async move {
+                JsFuture::from(request)
+                    .await
+                    .map(JsCast::unchecked_into::<BluetoothRemoteGattCharacteristic>)
+                    .map_err(|_| WebBluetoothError::MissingCharacteristic)
+            }
+        };
+
+        let system_tx = characteristic(CHARACTERISTIC_SYSTEM_TX).await?;
+        let system_rx = characteristic(CHARACTERISTIC_SYSTEM_RX).await?;
+        let user_tx = characteristic(CHARACTERISTIC_USER_TX).await?;
+        let user_rx = characteristic(CHARACTERISTIC_USER_RX).await?;
+        let pairing = characteristic(CHARACTERISTIC_PAIRING).await?;
+
+        let (sender, notifications) = unbounded();
+        let subscriptions = [
+            Subscription::start(system_tx, Channel::System, sender.clone()).await?,
+            Subscription::start(user_tx, Channel::User, sender).await?,
+        ];
+        debug!("Connected to {:?} over WebBluetooth", device.name());
+
+        Ok(Self {
+            server,
+            system_rx,
+            user_rx,
+            pairing,
+
+            subscriptions,
+            notifications,
+            decoder: FrameDecoder::new(),
+            incoming_packets: Vec::new(),
+            user_buffer: VecDeque::new(),
+            config,
+            radio_channel: None,
+            stats: ConnectionStats::default(),
+        })
+    }
+
+    async fn write(
+        characteristic: &BluetoothRemoteGattCharacteristic,
+        data: &[u8],
+    ) -> Result<(), WebBluetoothError> {
+        JsFuture::from(characteristic.write_value_without_response(&Uint8Array::from(data)))
+            .await
+            .map_err(js_error)?;
+        Ok(())
+    }
+
+    async fn read_pairing(&self) -> Result<Vec<u8>, WebBluetoothError> {
+        let value: DataView = JsFuture::from(self.pairing.read_value())
+            .await
+            .map_err(js_error)?
+            .unchecked_into();
+        Ok(data_view_bytes(&value))
+    }
+
+    pub async fn is_paired(&self) -> Result<bool, WebBluetoothError> {
+        let auth_bytes = self.read_pairing().await?;
+        let auth_bytes = auth_bytes
+            .get(0..4)
+            .ok_or(DecodeError::PacketTooShort)?
+            .try_into()
+            .unwrap();
+
+        Ok(u32::from_be_bytes(auth_bytes) != UNPAIRED_MAGIC)
+    }
+
+    pub async fn request_pairing(&mut self) -> Result<(), WebBluetoothError> {
+        Self::write(&self.pairing, &[0xFF, 0xFF, 0xFF, 0xFF]).await
+    }
+
+    pub async fn authenticate_pairing(&mut self, pin: [u8; 4]) -> Result<(), WebBluetoothError> {
+        Self::write(&self.pairing, &pin).await?;
+
+        if self.read_pairing().await? != pin {
+            return Err(WebBluetoothError::IncorrectPin);
+        }
+
+        Ok(())
+    }
+
+    /// Waits for a single notification and routes it to either the incoming packet queue or
+    /// the user output buffer. Returns the channel that sent it.
+    async fn receive_one_notification(&mut self) -> Result<Channel, WebBluetoothError> {
+        let Some((channel, value)) = self.notifications.next().await else {
+            return Err(WebBluetoothError::NoResponse);
+        };
+
+        match channel {
+            Channel::System => {
+                // Large replies can be split across several notifications.
+                self.decoder.push(&value);
+                while let Some(data) = self.decoder.next_frame() {
+                    if self.config.logs_packets_at(Level::Debug) {
+                        debug!("Received packet: {:x?}", data);
+                    }
+                    self.stats.packet_received(&data);
+                    self.incoming_packets.push(RawPacket::new(data));
+                }
+            }
+            Channel::User => {
+                trace!("Received {} bytes of user output", value.len());
+                self.user_buffer.extend(value);
+            }
+        }
+
+        Ok(channel)
+    }
+
+    /// Receives a single packet from the system characteristic and adds it to the queue of incoming packets.
+    async fn receive_one_packet(&mut self) -> Result<(), WebBluetoothError> {
+        while self.receive_one_notification().await? != Channel::System {}
+        Ok(())
+    }
+}
+
+impl Connection for WebBluetoothConnection {
+    type Error = WebBluetoothError;
+
+    fn connection_type(&self) -> ConnectionType {
+        ConnectionType::Bluetooth
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn record_radio_channel(&mut self, channel: RadioChannel) {
+        self.radio_channel = (channel != RadioChannel::Pit).then_some(channel);
+    }
+
+    fn stats(&self) -> Option<&ConnectionStats> {
+        Some(&self.stats)
+    }
+
+    fn stats_mut(&mut self) -> Option<&mut ConnectionStats> {
+        Some(&mut self.stats)
+    }
+
+    async fn shutdown(mut self) -> Result<(), WebBluetoothError> {
+        let mut errors = Vec::new();
+
+        if self.radio_channel.take().is_some() {
+            if let Err(e) = restore_radio_channel(&mut self).await {
+                errors.push(e);
+            }
+        }
+
+        for subscription in &self.subscriptions {
+            if let Err(e) = subscription.stop().await {
+                errors.push(e);
+            }
+        }
+        self.server.disconnect();
+        debug!("WebBluetooth connection closed");
+
+        first_shutdown_error(errors)
+    }
+
+    async fn send_packet(&mut self, packet: impl Encode) -> Result<(), WebBluetoothError> {
+        if !self.is_paired().await? {
+            return Err(WebBluetoothError::PairingRequired);
+        }
+
+        let encoded = packet.encode()?;
+
+        if self.config.logs_packets_at(Level::Trace) {
+            trace!("Sending packet: {:x?}", encoded);
+        }
+        instrument::packet_sent(&encoded);
+        self.stats.packet_sent(&encoded);
+
+        Self::write(&self.system_rx, &encoded).await
+    }
+
+    async fn receive_packet<P: Decode>(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<P, WebBluetoothError> {
+        // Return an error if the right packet is not received within the timeout
+        select! {
+            result = async {
+                loop {
+                    for packet in self.incoming_packets.iter_mut() {
+                        if let Ok(decoded) = packet.decode_and_use::<P>() {
+                            trim_packets(&mut self.incoming_packets);
+                            return Ok(decoded);
+                        }
+                    }
+                    trim_packets(&mut self.incoming_packets);
+                    self.receive_one_packet().await?;
+                }
+            } => result,
+            _ = sleep(timeout) => Err(WebBluetoothError::Timeout)
+        }
+    }
+
+    async fn read_user(&mut self, buf: &mut [u8]) -> Result<usize, WebBluetoothError> {
+        while self.user_buffer.is_empty() {
+            self.receive_one_notification().await?;
+        }
+
+        let len = self.user_buffer.len().min(buf.len());
+        for (dest, byte) in buf.iter_mut().zip(self.user_buffer.drain(..len)) {
+            *dest = byte;
+        }
+
+        Ok(len)
+    }
+
+    async fn write_user(&mut self, buf: &[u8]) -> Result<usize, WebBluetoothError> {
+        for chunk in buf.chunks(Self::MAX_PACKET_SIZE) {
+            Self::write(&self.user_rx, chunk).await?;
+        }
+
+        Ok(buf.len())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WebBluetoothError {
+    #[error("Packet encoding error: {0}")]
+    EncodeError(#[from] EncodeError),
+    #[error("Packet decoding error: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("Command failed: {0}")]
+    CommandError(#[from] CommandError),
+    #[error("Packet timeout")]
+    Timeout,
+    #[error("NACK received: {0:?}")]
+    Nack(#[from] Cdc2Ack),
+    #[error("JavaScript error: {0}")]
+    JsError(String),
+    #[error("WebBluetooth is not supported by this browser")]
+    Unsupported,
+    #[error("No response received over bluetooth")]
+    NoResponse,
+    #[error("Expected a Bluetooth characteristic that didn't exist")]
+    MissingCharacteristic,
+    #[error("Authentication PIN code was incorrect")]
+    IncorrectPin,
+    #[error("Pairing is required")]
+    PairingRequired,
+}
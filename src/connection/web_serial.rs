@@ -0,0 +1,256 @@
+//! Connecting to devices through the browser's WebSerial API.
+//!
+//! Browsers only expose serial ports the user has picked in a permission prompt, so there is
+//! no scanning: [`request_device`] shows the prompt and [`find_devices`] returns ports that
+//! were granted earlier. A brain shows up as two ports with the same product ID, and only the
+//! system port (usually listed first) responds to packets. User program I/O goes through the
+//! system channel, as with any [`TransportConnection`].
+//!
+//! WebSerial is only available in Chromium-based browsers, in secure contexts.
+
+use std::io;
+
+use js_sys::{Array, Object, Promise, Reflect, Uint8Array};
+use log::debug;
+use thiserror::Error;
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::config::Config;
+
+use super::{
+    transport::{Transport, TransportConnection},
+    ConnectionType, V5_CONTROLLER_USB_PID, V5_SERIAL_BAUDRATE, VEX_USB_VID,
+};
+
+// web-sys only exposes WebSerial behind `--cfg=web_sys_unstable_apis`, so we bind the
+// small part of it we need ourselves.
+#[wasm_bindgen]
+extern "C" {
+    type Serial;
+    #[wasm_bindgen(method, js_name = requestPort)]
+    fn request_port(this: &Serial, options: &JsValue) -> Promise;
+    #[wasm_bindgen(method, js_name = getPorts)]
+    fn get_ports(this: &Serial) -> Promise;
+
+    #[derive(Debug, Clone)]
+    type SerialPort;
+    #[wasm_bindgen(method)]
+    fn open(this: &SerialPort, options: &JsValue) -> Promise;
+    #[wasm_bindgen(method)]
+    fn close(this: &SerialPort) -> Promise;
+    #[wasm_bindgen(method, getter)]
+    fn readable(this: &SerialPort) -> ReadableStream;
+    #[wasm_bindgen(method, getter)]
+    fn writable(this: &SerialPort) -> WritableStream;
+    #[wasm_bindgen(method, js_name = getInfo)]
+    fn get_info(this: &SerialPort) -> SerialPortInfo;
+
+    type SerialPortInfo;
+    #[wasm_bindgen(method, getter, js_name = usbVendorId)]
+    fn usb_vendor_id(this: &SerialPortInfo) -> Option<u16>;
+    #[wasm_bindgen(method, getter, js_name = usbProductId)]
+    fn usb_product_id(this: &SerialPortInfo) -> Option<u16>;
+
+    type ReadableStream;
+    #[wasm_bindgen(method, js_name = getReader)]
+    fn get_reader(this: &ReadableStream) -> ReadableStreamDefaultReader;
+
+    type ReadableStreamDefaultReader;
+    #[wasm_bindgen(method)]
+    fn read(this: &ReadableStreamDefaultReader) -> Promise;
+    #[wasm_bindgen(method)]
+    fn cancel(this: &ReadableStreamDefaultReader) -> Promise;
+    #[wasm_bindgen(method, js_name = releaseLock)]
+    fn release_lock(this: &ReadableStreamDefaultReader);
+
+    type WritableStream;
+    #[wasm_bindgen(method, js_name = getWriter)]
+    fn get_writer(this: &WritableStream) -> WritableStreamDefaultWriter;
+
+    type WritableStreamDefaultWriter;
+    #[wasm_bindgen(method)]
+    fn write(this: &WritableStreamDefaultWriter, chunk: &Uint8Array) -> Promise;
+    #[wasm_bindgen(method, js_name = releaseLock)]
+    fn release_lock(this: &WritableStreamDefaultWriter);
+}
+
+fn js_error(value: JsValue) -> WebSerialError {
+    WebSerialError::JsError(format!("{:?}", value))
+}
+
+fn io_error(value: JsValue) -> io::Error {
+    io::Error::other(js_error(value))
+}
+
+/// Returns `navigator.serial`, if the browser supports it.
+fn serial() -> Result<Serial, WebSerialError> {
+    let navigator = Reflect::get(&js_sys::global(), &"navigator".into()).map_err(js_error)?;
+    let serial = Reflect::get(&navigator, &"serial".into()).map_err(js_error)?;
+    if serial.is_undefined() {
+        return Err(WebSerialError::Unsupported);
+    }
+    Ok(serial.unchecked_into())
+}
+
+/// Shows the browser's port picker, listing only VEX devices.
+///
+/// This must be called in response to a user gesture, such as a click.
+pub async fn request_device() -> Result<WebSerialDevice, WebSerialError> {
+    let filter = Object::new();
+    Reflect::set(&filter, &"usbVendorId".into(), &VEX_USB_VID.into()).map_err(js_error)?;
+    let options = Object::new();
+    Reflect::set(&options, &"filters".into(), &Array::of1(&filter)).map_err(js_error)?;
+
+    let port = JsFuture::from(serial()?.request_port(&options))
+        .await
+        .map_err(js_error)?;
+    Ok(WebSerialDevice {
+        port: port.unchecked_into(),
+    })
+}
+
+/// Returns the VEX devices this page was given access to earlier.
+pub async fn find_devices() -> Result<Vec<WebSerialDevice>, WebSerialError> {
+    let ports: Array = JsFuture::from(serial()?.get_ports())
+        .await
+        .map_err(js_error)?
+        .unchecked_into();
+
+    Ok(ports
+        .iter()
+        .map(|port| WebSerialDevice {
+            port: port.unchecked_into(),
+        })
+        .filter(|device| device.port.get_info().usb_vendor_id() == Some(VEX_USB_VID))
+        .collect())
+}
+
+/// A serial port the user has given this page access to.
+#[derive(Debug, Clone)]
+pub struct WebSerialDevice {
+    port: SerialPort,
+}
+impl WebSerialDevice {
+    /// The USB product ID of the device, if it is a USB device.
+    pub fn usb_product_id(&self) -> Option<u16> {
+        self.port.get_info().usb_product_id()
+    }
+
+    pub fn connection_type(&self) -> ConnectionType {
+        if self.usb_product_id() == Some(V5_CONTROLLER_USB_PID) {
+            ConnectionType::Controller
+        } else {
+            ConnectionType::Wired
+        }
+    }
+
+    /// Opens the port.
+    pub async fn connect(&self) -> Result<WebSerialConnection, WebSerialError> {
+        self.connect_with_config(Config::default()).await
+    }
+
+    /// Opens the port with a custom configuration.
+    pub async fn connect_with_config(
+        &self,
+        config: Config,
+    ) -> Result<WebSerialConnection, WebSerialError> {
+        let options = Object::new();
+        Reflect::set(&options, &"baudRate".into(), &V5_SERIAL_BAUDRATE.into()).map_err(js_error)?;
+        JsFuture::from(self.port.open(&options))
+            .await
+            .map_err(js_error)?;
+        debug!("Opened WebSerial port {:?}", self.usb_product_id());
+
+        let transport = WebSerialTransport {
+            reader: self.port.readable().get_reader(),
+            writer: self.port.writable().get_writer(),
+            port: self.port.clone(),
+            pending_read: None,
+            buffer: Vec::new(),
+        };
+        Ok(TransportConnection::with_config(
+            transport,
+            self.connection_type(),
+            config,
+        ))
+    }
+}
+
+/// A connection to a device through WebSerial.
+pub type WebSerialConnection = TransportConnection<WebSerialTransport>;
+
+/// An open WebSerial port.
+pub struct WebSerialTransport {
+    port: SerialPort,
+    reader: ReadableStreamDefaultReader,
+    writer: WritableStreamDefaultWriter,
+    // Kept across calls so that a read cancelled by a timeout does not lose its chunk.
+    pending_read: Option<JsFuture>,
+    buffer: Vec<u8>,
+}
+impl WebSerialTransport {
+    /// Closes the port so that it can be opened again.
+    pub async fn close(self) -> Result<(), WebSerialError> {
+        // The reader stays locked while a read is pending, so cancel it first.
+        JsFuture::from(self.reader.cancel())
+            .await
+            .map_err(js_error)?;
+        self.reader.release_lock();
+        self.writer.release_lock();
+        JsFuture::from(self.port.close()).await.map_err(js_error)?;
+        Ok(())
+    }
+}
+impl Transport for WebSerialTransport {
+    async fn read_bytes(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            let reader = &self.reader;
+            let read = self
+                .pending_read
+                .get_or_insert_with(|| JsFuture::from(reader.read()));
+            let result = read.await;
+            self.pending_read = None;
+
+            let result = result.map_err(io_error)?;
+            if Reflect::get(&result, &"done".into())
+                .map_err(io_error)?
+                .is_truthy()
+            {
+                return Ok(0);
+            }
+            let chunk: Uint8Array = Reflect::get(&result, &"value".into())
+                .map_err(io_error)?
+                .unchecked_into();
+            self.buffer = chunk.to_vec();
+        }
+
+        let len = self.buffer.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.buffer[..len]);
+        self.buffer.drain(..len);
+        Ok(len)
+    }
+
+    async fn write_bytes(&mut self, buf: &[u8]) -> io::Result<()> {
+        JsFuture::from(self.writer.write(&Uint8Array::from(buf)))
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        // Writes resolve once the data has been handed to the port.
+        Ok(())
+    }
+}
+
+/// Errors that can occur while finding or opening a WebSerial port.
+///
+/// Errors on an open connection are [`TransportError`](super::transport::TransportError)s.
+#[derive(Error, Debug)]
+pub enum WebSerialError {
+    #[error("WebSerial is not supported by this browser")]
+    Unsupported,
+    #[error("JavaScript error: {0}")]
+    JsError(String),
+}
@@ -13,13 +13,18 @@ pub enum DecodeError {
     InvalidStringContents(#[from] Utf8Error),
     #[error("Could not decode byte with unexpected value. Found {value:x}, expected one of: {expected:x?}")]
     UnexpectedValue { value: u8, expected: &'static [u8] },
-    #[error("Attempted to decode a choice, but neither choice was successful: left: {left}, right: {right}")]
-    BothChoicesFailed {
-        left: Box<DecodeError>,
-        right: Box<DecodeError>,
-    },
+    #[error("Could not parse ini file: {0}")]
+    InvalidIni(String),
+    #[error("Checksum mismatch: expected {expected:#x}, computed {actual:#x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[error("Malformed COBS frame")]
+    InvalidCobs,
 }
 
+/// Decodes a value from the bytes of a received packet.
+///
+/// Decoding works over a byte iterator and every decoded value owns its data, so strings and
+/// file chunks are copied out of the packet rather than borrowed from it.
 pub trait Decode {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError>
     where
@@ -86,7 +91,7 @@ impl Decode for i32 {
 }
 impl<D: Decode> Decode for Option<D> {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
-        Ok(D::decode(data).map(|decoded| Some(decoded))?)
+        D::decode(data).map(Some)
     }
 }
 impl<D: Decode + Default, const N: usize> Decode for [D; N] {
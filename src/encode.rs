@@ -1,3 +1,5 @@
+use std::{io, str::Utf8Error};
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +8,18 @@ pub enum EncodeError {
     StringTooLong,
     #[error("Value too large for variable length u16")]
     VarShortTooLarge,
+    #[error("Could not serialize ini file: {0}")]
+    InvalidIni(String),
+    #[error("String is not valid UTF-8: {0}")]
+    InvalidStringContents(#[from] Utf8Error),
+    #[error("Program slot {0} does not exist, slots are numbered from 1 to 8")]
+    SlotOutOfRange(u8),
+    #[error("The upload strategy does not support programs with a linked library")]
+    LibraryNotSupported,
+    #[error("Invalid file path: {0}")]
+    InvalidPath(String),
+    #[error("Could not read data to send: {0}")]
+    Io(#[from] io::Error),
 }
 
 /// A trait that allows for encoding a structure into a byte sequence.
@@ -0,0 +1,88 @@
+//! Explicit little-endian wire types.
+//!
+//! Almost every multi-byte integer in the V5 protocol is sent little-endian. Rather than
+//! sprinkling `to_le_bytes` calls across every payload, encoders should go through these
+//! types so that the byte order is decided in exactly one place.
+
+use crate::decode::{Decode, DecodeError};
+use crate::encode::{Encode, EncodeError};
+
+macro_rules! le_wire_type {
+    ($(#[$meta:meta])* $name:ident, $inner:ty) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub $inner);
+
+        impl $name {
+            /// Returns the native-endian value.
+            pub const fn get(self) -> $inner {
+                self.0
+            }
+        }
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Encode for $name {
+            fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+                Ok(self.0.to_le_bytes().to_vec())
+            }
+        }
+
+        impl Decode for $name {
+            fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
+                let mut data = data.into_iter();
+                Ok(Self(<$inner>::from_le_bytes(Decode::decode(&mut data)?)))
+            }
+        }
+    };
+}
+
+le_wire_type!(
+    /// A `u16` that is always sent over the wire in little-endian byte order.
+    U16Le,
+    u16
+);
+le_wire_type!(
+    /// A `u32` that is always sent over the wire in little-endian byte order.
+    U32Le,
+    u32
+);
+le_wire_type!(
+    /// An `i32` that is always sent over the wire in little-endian byte order.
+    I32Le,
+    i32
+);
+
+#[cfg(test)]
+mod tests {
+    use super::{I32Le, U16Le, U32Le};
+    use crate::{decode::Decode, encode::Encode};
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(U16Le(0x1234).encode().unwrap(), [0x34, 0x12]);
+        assert_eq!(
+            U32Le(0x12345678).encode().unwrap(),
+            [0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(I32Le(-2).encode().unwrap(), [0xFE, 0xFF, 0xFF, 0xFF]);
+
+        assert_eq!(U16Le::decode([0x34, 0x12]).unwrap(), U16Le(0x1234));
+        assert_eq!(
+            U32Le::decode([0x78, 0x56, 0x34, 0x12]).unwrap(),
+            U32Le(0x12345678)
+        );
+        assert_eq!(I32Le::decode([0xFE, 0xFF, 0xFF, 0xFF]).unwrap(), I32Le(-2));
+    }
+}
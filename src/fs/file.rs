@@ -0,0 +1,298 @@
+//! Files on the brain as byte streams.
+
+use std::{
+    io, mem,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures::future::LocalBoxFuture;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    commands::file::read_chunk,
+    connection::Connection,
+    packets::file::{
+        ExitFileTransferPacket, ExitFileTransferReplyPacket, FileExitAction, FileInitAction,
+        FileInitOption, FileTransferTarget, GetFileMetadataReplyPayload, InitFileTransferPacket,
+        InitFileTransferPayload, InitFileTransferReplyPacket,
+    },
+};
+
+use super::{UploadTarget, VexPath};
+
+/// A request to the brain that borrows the connection until it completes.
+type Pending<'a, C> = LocalBoxFuture<'a, (&'a mut C, Result<Vec<u8>, <C as Connection>::Error>)>;
+
+enum State<'a, C: Connection + ?Sized> {
+    Idle(&'a mut C),
+    Busy(Pending<'a, C>),
+    /// Only seen while switching between the other states.
+    Switching,
+}
+impl<'a, C: Connection + ?Sized + 'a> State<'a, C> {
+    /// Starts a request.
+    ///
+    /// Fails without starting it if another request is still running, or if an earlier one
+    /// panicked and never gave the connection back.
+    fn start<F>(&mut self, request: F) -> io::Result<()>
+    where
+        F: for<'c> FnOnce(&'c mut C) -> LocalBoxFuture<'c, Result<Vec<u8>, C::Error>> + 'a,
+    {
+        let connection = match mem::replace(self, State::Switching) {
+            State::Idle(connection) => connection,
+            other => {
+                *self = other;
+                return Err(io::Error::other(
+                    "a request to the brain is already running",
+                ));
+            }
+        };
+        *self = State::Busy(Box::pin(async move {
+            let result = request(&mut *connection).await;
+            (connection, result)
+        }));
+        Ok(())
+    }
+
+    /// Polls the running request, if there is one.
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Vec<u8>, C::Error>>> {
+        let State::Busy(pending) = self else {
+            return Poll::Ready(None);
+        };
+        let (connection, result) = ready!(pending.as_mut().poll(cx));
+        *self = State::Idle(connection);
+        Poll::Ready(Some(result))
+    }
+}
+
+struct Reader {
+    load_addr: u32,
+    len: u32,
+    /// How far into the file has been requested from the brain.
+    offset: u32,
+    chunk_size: u16,
+    chunk: Vec<u8>,
+    /// How much of `chunk` has been read.
+    position: usize,
+}
+
+enum Mode {
+    Read(Reader),
+    Write {
+        target: Option<UploadTarget>,
+        buffer: Vec<u8>,
+    },
+}
+
+/// An open file on the brain, created by [`VexFs::open`](super::VexFs::open) or
+/// [`VexFs::create`](super::VexFs::create).
+///
+/// Opened files implement [`AsyncRead`] and are read a chunk at a time. The file transfer is
+/// closed once the last chunk has been read, and stays open until the next transfer starts if
+/// the file is dropped before then.
+///
+/// Created files implement [`AsyncWrite`]. The brain needs a file's size and CRC before any of
+/// it is written, so writes are buffered and the file is only uploaded when the writer is shut
+/// down, for example with [`AsyncWriteExt::shutdown`](tokio::io::AsyncWriteExt::shutdown).
+/// Dropping a created file without shutting it down discards it. The whole file is held in
+/// memory until then, so a large file that is already on disk is better uploaded with
+/// [`VexFs::write`](super::VexFs::write) and [`UploadData::from_reader`], which streams it.
+///
+/// [`UploadData::from_reader`]: crate::commands::file::UploadData::from_reader
+///
+/// Requests to the brain are not `Send`, so a file has to be used from the task that opened it.
+pub struct BrainFile<'a, C: Connection + ?Sized> {
+    state: State<'a, C>,
+    mode: Mode,
+}
+impl<'a, C: Connection + ?Sized + 'a> BrainFile<'a, C> {
+    pub(super) async fn open(
+        connection: &'a mut C,
+        path: VexPath,
+        metadata: GetFileMetadataReplyPayload,
+    ) -> Result<Self, C::Error> {
+        let transfer = connection
+            .packet_handshake::<InitFileTransferReplyPacket>(InitFileTransferPacket::new(
+                InitFileTransferPayload {
+                    operation: FileInitAction::Read,
+                    target: FileTransferTarget::Qspi,
+                    vendor: path.vendor,
+                    options: FileInitOption::None,
+                    file_size: metadata.size,
+                    write_file_crc: 0,
+                    load_address: metadata.load_address,
+                    metadata: metadata.metadata,
+                    file_name: path.file_name,
+                },
+            ))
+            .await?
+            .try_into_inner()?;
+
+        let max_chunk_size = connection.config().transfer_chunk_size;
+        let chunk_size = if transfer.window_size > 0 && transfer.window_size <= max_chunk_size {
+            transfer.window_size
+        } else {
+            max_chunk_size
+        };
+
+        Ok(Self {
+            state: State::Idle(connection),
+            mode: Mode::Read(Reader {
+                load_addr: metadata.load_address,
+                len: transfer.file_size,
+                offset: 0,
+                chunk_size,
+                chunk: Vec::new(),
+                position: 0,
+            }),
+        })
+    }
+
+    pub(super) fn create(connection: &'a mut C, target: UploadTarget) -> Self {
+        Self {
+            state: State::Idle(connection),
+            mode: Mode::Write {
+                target: Some(target),
+                buffer: Vec::new(),
+            },
+        }
+    }
+
+    /// Returns the size of the file in bytes, or how much has been written to a created file.
+    pub fn len(&self) -> u32 {
+        match &self.mode {
+            Mode::Read(reader) => reader.len,
+            Mode::Write { buffer, .. } => buffer.len() as u32,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn wrong_mode(operation: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("file was not opened for {}", operation),
+    )
+}
+
+impl<'a, C> AsyncRead for BrainFile<'a, C>
+where
+    C: Connection + ?Sized + 'a,
+    C::Error: Send + Sync + 'static,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let BrainFile { state, mode } = self.get_mut();
+        let Mode::Read(reader) = mode else {
+            return Poll::Ready(Err(wrong_mode("reading")));
+        };
+
+        loop {
+            if reader.position < reader.chunk.len() {
+                let unread = &reader.chunk[reader.position..];
+                let len = unread.len().min(buf.remaining());
+                buf.put_slice(&unread[..len]);
+                reader.position += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            if let Some(result) = ready!(state.poll(cx)) {
+                let mut chunk = result.map_err(io::Error::other)?;
+                let remaining = (reader.len - reader.offset) as usize;
+                if chunk.is_empty() {
+                    // Don't ask for the same chunk forever if the brain has nothing to give.
+                    reader.offset = reader.len;
+                }
+                // The last chunk can run past the end of the file.
+                chunk.truncate(remaining);
+                reader.offset += chunk.len() as u32;
+                reader.chunk = chunk;
+                reader.position = 0;
+                continue;
+            }
+
+            if reader.offset >= reader.len {
+                return Poll::Ready(Ok(()));
+            }
+
+            let address = reader.load_addr + reader.offset;
+            let size = reader.chunk_size;
+            let last = reader.offset + u32::from(size) >= reader.len;
+            state.start(move |connection| {
+                Box::pin(async move {
+                    let chunk = read_chunk(connection, address, size).await?;
+                    if last {
+                        connection
+                            .packet_handshake::<ExitFileTransferReplyPacket>(
+                                ExitFileTransferPacket::new(FileExitAction::DoNothing),
+                            )
+                            .await?
+                            .try_into_inner()?;
+                    }
+                    Ok(chunk)
+                })
+            })?;
+        }
+    }
+}
+
+impl<'a, C> AsyncWrite for BrainFile<'a, C>
+where
+    C: Connection + ?Sized + 'a,
+    C::Error: Send + Sync + 'static,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().mode {
+            Mode::Write {
+                target: Some(_),
+                buffer,
+            } => {
+                buffer.extend_from_slice(buf);
+                Poll::Ready(Ok(buf.len()))
+            }
+            Mode::Write { target: None, .. } => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "file has already been shut down",
+            ))),
+            Mode::Read(_) => Poll::Ready(Err(wrong_mode("writing"))),
+        }
+    }
+
+    /// Does nothing, since the file is only uploaded on shutdown.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let BrainFile { state, mode } = self.get_mut();
+        let Mode::Write { target, buffer } = mode else {
+            return Poll::Ready(Err(wrong_mode("writing")));
+        };
+
+        if let Some(target) = target.take() {
+            let data = mem::take(buffer);
+            state.start(move |connection| {
+                Box::pin(async move {
+                    connection.execute_command(target.upload(data)).await?;
+                    Ok(Vec::new())
+                })
+            })?;
+        }
+
+        match ready!(state.poll(cx)) {
+            Some(result) => Poll::Ready(result.map(drop).map_err(io::Error::other)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! The brain's storage as a small remote filesystem.
+//!
+//! Files on the brain are addressed by a vendor and a file name. A [`VexPath`] writes these
+//! as `vendor/name`, such as `pros/slot_1.bin`, where the vendor can be left out for
+//! [`FileVendor::User`] files.
+
+use std::{fmt, ops::Range, str::FromStr};
+
+use futures::{stream, Stream};
+
+mod file;
+
+pub use file::BrainFile;
+
+use crate::{
+    commands::file::{
+        directory_entry, directory_file_count, DeleteFile, DownloadFile, GetFileMetadata,
+        ListFiles, UploadData, UploadFile, VerifyMode, USER_PROGRAM_LOAD_ADDR,
+    },
+    connection::Connection,
+    encode::EncodeError,
+    packets::file::{
+        ExtensionType, FileExitAction, FileMetadata, FileVendor, GetDirectoryEntryReplyPayload,
+        GetFileMetadataReplyPayload,
+    },
+    string::FixedString,
+    timestamp::J2000Timestamp,
+    version::Version,
+};
+
+/// Names of vendors in paths, and the vendors they refer to.
+const VENDOR_NAMES: &[(&str, FileVendor)] = &[
+    ("user", FileVendor::User),
+    ("sys", FileVendor::Sys),
+    ("pros", FileVendor::PROS),
+    ("mw", FileVendor::MW),
+    ("dev1", FileVendor::Dev1),
+    ("dev2", FileVendor::Dev2),
+    ("dev3", FileVendor::Dev3),
+    ("dev4", FileVendor::Dev4),
+    ("dev5", FileVendor::Dev5),
+    ("dev6", FileVendor::Dev6),
+    ("vexvm", FileVendor::VexVm),
+    ("vex", FileVendor::Vex),
+];
+
+fn vendor_from_name(name: &str) -> Option<FileVendor> {
+    VENDOR_NAMES
+        .iter()
+        .find(|(vendor_name, _)| vendor_name.eq_ignore_ascii_case(name))
+        .map(|&(_, vendor)| vendor)
+}
+
+/// The location of a file on the brain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VexPath {
+    pub vendor: FileVendor,
+    pub file_name: FixedString<23>,
+}
+impl VexPath {
+    pub fn new(vendor: FileVendor, file_name: FixedString<23>) -> Self {
+        Self { vendor, file_name }
+    }
+}
+impl FromStr for VexPath {
+    type Err = EncodeError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let (vendor, file_name) = match path.split_once('/') {
+            Some((vendor_name, file_name)) => (
+                vendor_from_name(vendor_name)
+                    .ok_or_else(|| EncodeError::InvalidPath(path.to_string()))?,
+                file_name,
+            ),
+            None => (FileVendor::User, path),
+        };
+        if file_name.is_empty() || file_name.contains('/') {
+            return Err(EncodeError::InvalidPath(path.to_string()));
+        }
+
+        Ok(Self {
+            vendor,
+            file_name: FixedString::new(file_name.to_string())?,
+        })
+    }
+}
+impl fmt::Display for VexPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match VENDOR_NAMES
+            .iter()
+            .find(|&&(_, vendor)| vendor == self.vendor)
+        {
+            Some((vendor_name, _)) => write!(f, "{}/{}", vendor_name, self.file_name),
+            // Every vendor has a name except `Undefined`.
+            None => write!(f, "{:?}/{}", self.vendor, self.file_name),
+        }
+    }
+}
+
+/// A file found by [`VexFs::read_dir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub path: VexPath,
+    pub entry: GetDirectoryEntryReplyPayload,
+}
+
+/// A path-based view of the files on a brain.
+///
+/// ```no_run
+/// # async fn example(connection: &mut vex_v5_serial::connection::serial::SerialConnection)
+/// # -> Result<(), vex_v5_serial::connection::serial::SerialError> {
+/// use vex_v5_serial::fs::VexFs;
+///
+/// let mut fs = VexFs::new(connection);
+/// for file in fs.read_dir("pros").await? {
+///     println!("{} ({} bytes)", file.path, file.entry.size);
+/// }
+/// fs.write("notes.txt", b"hello".to_vec()).await?;
+///
+/// // Files can also be streamed to and from the brain.
+/// let mut log = tokio::fs::File::create("log.txt").await?;
+/// if let Some(mut file) = VexFs::new(connection).open("log.txt").await? {
+///     tokio::io::copy(&mut file, &mut log).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct VexFs<'a, C: Connection + ?Sized> {
+    connection: &'a mut C,
+}
+impl<'a, C: Connection + ?Sized> VexFs<'a, C> {
+    pub fn new(connection: &'a mut C) -> Self {
+        Self { connection }
+    }
+
+    /// Reads a whole file, or returns `None` if it doesn't exist.
+    pub async fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>, C::Error> {
+        let path = VexPath::from_str(path)?;
+        let Some(metadata) = self.metadata_of(&path).await? else {
+            return Ok(None);
+        };
+
+        let data = self
+            .connection
+            .execute_command(DownloadFile {
+                file_name: path.file_name,
+                size: metadata.size,
+                vendor: path.vendor,
+                target: None,
+                load_addr: metadata.load_address,
+                checkpoint: None,
+                sink: None,
+                progress: None,
+            })
+            .await?;
+        Ok(Some(data))
+    }
+
+    /// Writes a whole file, replacing it if it already exists.
+    ///
+    /// An existing file keeps its load address and metadata. New files are given an
+    /// extension based on their name.
+    pub async fn write(
+        &mut self,
+        path: &str,
+        data: impl Into<UploadData<'_>>,
+    ) -> Result<(), C::Error> {
+        let path = VexPath::from_str(path)?;
+        let target = self.upload_target(path).await?;
+        self.connection.execute_command(target.upload(data)).await?;
+        Ok(())
+    }
+
+    /// Opens a file for reading, or returns `None` if it doesn't exist.
+    ///
+    /// See [`BrainFile`] for details.
+    pub async fn open(mut self, path: &str) -> Result<Option<BrainFile<'a, C>>, C::Error> {
+        let path = VexPath::from_str(path)?;
+        let Some(metadata) = self.metadata_of(&path).await? else {
+            return Ok(None);
+        };
+        BrainFile::open(self.connection, path, metadata)
+            .await
+            .map(Some)
+    }
+
+    /// Creates a file for writing, replacing it if it already exists.
+    ///
+    /// See [`BrainFile`] for details.
+    pub async fn create(mut self, path: &str) -> Result<BrainFile<'a, C>, C::Error> {
+        let path = VexPath::from_str(path)?;
+        let target = self.upload_target(path).await?;
+        Ok(BrainFile::create(self.connection, target))
+    }
+
+    /// Deletes a file.
+    pub async fn remove(&mut self, path: &str) -> Result<(), C::Error> {
+        let path = VexPath::from_str(path)?;
+        self.connection
+            .execute_command(DeleteFile {
+                file_name: path.file_name,
+                vendor: path.vendor,
+                erase_all_linked: true,
+                dry_run: None,
+            })
+            .await
+    }
+
+    /// Returns a file's metadata, or `None` if it doesn't exist.
+    pub async fn metadata(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<GetFileMetadataReplyPayload>, C::Error> {
+        let path = VexPath::from_str(path)?;
+        self.metadata_of(&path).await
+    }
+
+    /// Lists the files stored under a vendor, given by its name in paths such as `user`.
+    pub async fn read_dir(&mut self, vendor: &str) -> Result<Vec<DirEntry>, C::Error> {
+        let vendor = vendor_from_name(vendor.trim_end_matches('/'))
+            .ok_or_else(|| EncodeError::InvalidPath(vendor.to_string()))?;
+
+        let entries = self
+            .connection
+            .execute_command(ListFiles { vendor })
+            .await?;
+        entries
+            .into_iter()
+            .map(|entry| {
+                Ok(DirEntry {
+                    path: VexPath::new(vendor, FixedString::new(entry.file_name.clone())?),
+                    entry,
+                })
+            })
+            .collect()
+    }
+
+    /// Lists the files stored under a vendor one at a time, like [`read_dir`](Self::read_dir).
+    ///
+    /// Each entry is only requested from the brain when the stream is polled for it, so a
+    /// listing can be abandoned early without reading the rest. Files erased while listing
+    /// are left out. The stream ends after the first error.
+    ///
+    /// ```no_run
+    /// # async fn example(connection: &mut vex_v5_serial::connection::serial::SerialConnection)
+    /// # -> Result<(), vex_v5_serial::connection::serial::SerialError> {
+    /// use futures::{pin_mut, StreamExt};
+    /// use vex_v5_serial::fs::VexFs;
+    ///
+    /// let mut fs = VexFs::new(connection);
+    /// let files = fs.iter_files("user");
+    /// pin_mut!(files);
+    /// while let Some(file) = files.next().await {
+    ///     println!("{}", file?.path);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn iter_files(
+        &mut self,
+        vendor: &str,
+    ) -> impl Stream<Item = Result<DirEntry, C::Error>> + '_ {
+        let vendor = vendor_from_name(vendor.trim_end_matches('/'))
+            .ok_or_else(|| EncodeError::InvalidPath(vendor.to_string()));
+
+        // The remaining indices are `None` until the file count has been requested.
+        let state = Some((&mut *self.connection, vendor, None::<Range<u16>>));
+        stream::unfold(state, |state| async move {
+            let (connection, vendor, indices) = state?;
+            let vendor = match vendor {
+                Ok(vendor) => vendor,
+                Err(e) => return Some((Err(e.into()), None)),
+            };
+
+            let next = async {
+                let mut indices = match indices {
+                    Some(indices) => indices,
+                    None => 0..directory_file_count(connection, vendor).await?,
+                };
+                while let Some(index) = indices.next() {
+                    if let Some(entry) = directory_entry(connection, index).await? {
+                        let path = VexPath::new(vendor, FixedString::new(entry.file_name.clone())?);
+                        return Ok(Some((DirEntry { path, entry }, indices)));
+                    }
+                }
+                Ok::<_, C::Error>(None)
+            }
+            .await;
+
+            match next {
+                Ok(Some((entry, indices))) => {
+                    Some((Ok(entry), Some((connection, Ok(vendor), Some(indices)))))
+                }
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Works out where and with what metadata a file should be uploaded.
+    async fn upload_target(&mut self, path: VexPath) -> Result<UploadTarget, C::Error> {
+        let (load_addr, metadata) = match self.metadata_of(&path).await? {
+            Some(existing) => (
+                existing.load_address,
+                FileMetadata {
+                    timestamp: J2000Timestamp::now(),
+                    ..existing.metadata
+                },
+            ),
+            None => {
+                let extension = path
+                    .file_name
+                    .as_ref()
+                    .rsplit_once('.')
+                    .map_or("", |(_, extension)| extension);
+                (
+                    USER_PROGRAM_LOAD_ADDR,
+                    FileMetadata {
+                        extension: FixedString::new(extension.chars().take(3).collect())?,
+                        extension_type: ExtensionType::default(),
+                        timestamp: J2000Timestamp::now(),
+                        version: Version {
+                            major: 1,
+                            minor: 0,
+                            build: 0,
+                            beta: 0,
+                        },
+                    },
+                )
+            }
+        };
+        Ok(UploadTarget {
+            path,
+            load_addr,
+            metadata,
+        })
+    }
+
+    async fn metadata_of(
+        &mut self,
+        path: &VexPath,
+    ) -> Result<Option<GetFileMetadataReplyPayload>, C::Error> {
+        self.connection
+            .execute_command(GetFileMetadata {
+                file_name: path.file_name.clone(),
+                vendor: path.vendor,
+            })
+            .await
+    }
+}
+
+/// Where and how a file is written.
+struct UploadTarget {
+    path: VexPath,
+    load_addr: u32,
+    metadata: FileMetadata,
+}
+impl UploadTarget {
+    fn upload<'a>(self, data: impl Into<UploadData<'a>>) -> UploadFile<'a> {
+        UploadFile {
+            filename: self.path.file_name,
+            metadata: self.metadata,
+            vendor: Some(self.path.vendor),
+            data: data.into(),
+            target: None,
+            load_addr: self.load_addr,
+            linked_file: None,
+            after_upload: FileExitAction::DoNothing,
+            skip_identical: false,
+            verify: VerifyMode::Off,
+            checkpoint: None,
+            dry_run: None,
+            progress: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::VexPath;
+    use crate::{packets::file::FileVendor, string::FixedString};
+
+    #[test]
+    fn parses_paths() {
+        let path = VexPath::from_str("pros/slot_1.bin").unwrap();
+        assert_eq!(path.vendor, FileVendor::PROS);
+        assert_eq!(
+            path.file_name,
+            FixedString::new("slot_1.bin".to_string()).unwrap()
+        );
+        assert_eq!(path.to_string(), "pros/slot_1.bin");
+
+        assert_eq!(
+            VexPath::from_str("slot_1.ini").unwrap().vendor,
+            FileVendor::User
+        );
+        assert!(VexPath::from_str("nobody/slot_1.bin").is_err());
+        assert!(VexPath::from_str("user/").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Building malformed packets for fuzzing [`Decode`] implementations.
+//!
+//! Decoding a packet never panics, however malformed it is. A bad packet is reported as a
+//! [`DecodeError`] instead. [`ReplyFrame`] makes it easy to hold packet types defined outside
+//! this crate to the same standard. With the `arbitrary` feature enabled, it can be generated
+//! straight from fuzzer input, such as in a `cargo fuzz` target:
+//!
+//! ```ignore
+//! #![no_main]
+//! use libfuzzer_sys::fuzz_target;
+//! use vex_v5_serial::fuzz::ReplyFrame;
+//!
+//! fuzz_target!(|frame: ReplyFrame| {
+//!     let _ = frame.decode::<MyReplyPacket>(86, 200);
+//! });
+//! ```
+
+use crate::{
+    crc::VEX_CRC16,
+    decode::{Decode, DecodeError},
+    packets::HOST_BOUND_HEADER,
+};
+
+/// A host-bound packet with an arbitrary body.
+///
+/// The header and IDs are always valid, so that a decoder gets past them and into the rest
+/// of the packet. Everything after them can be as wrong as the fuzzer likes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ReplyFrame {
+    /// Whether this is a CDC2 reply, with an extended ID, an acknowledgement, and a CRC.
+    pub cdc2: bool,
+    /// The acknowledgement byte of a CDC2 reply.
+    pub ack: u8,
+    /// The size written to the packet, or `None` to write the size of the actual contents.
+    ///
+    /// Only the low 15 bits are used, since that is all the size can hold.
+    pub size: Option<u16>,
+    pub payload: Vec<u8>,
+    /// The CRC ending a CDC2 reply, or `None` to write the correct one.
+    pub crc: Option<u16>,
+}
+impl ReplyFrame {
+    /// Returns the raw bytes of this frame, as a reply to a command with the given IDs.
+    ///
+    /// `ext_id` is only used by CDC2 replies.
+    pub fn to_bytes(&self, id: u8, ext_id: u8) -> Vec<u8> {
+        let mut body = Vec::new();
+        if self.cdc2 {
+            body.push(ext_id);
+            body.push(self.ack);
+        }
+        body.extend(&self.payload);
+
+        let size = self
+            .size
+            .unwrap_or(body.len() as u16 + if self.cdc2 { 2 } else { 0 })
+            & (u16::MAX >> 1);
+
+        let mut frame = Vec::from(HOST_BOUND_HEADER);
+        frame.push(id);
+        if size > (u8::MAX >> 1) as u16 {
+            frame.extend([(size >> 8) as u8 | 0x80, size as u8]);
+        } else {
+            frame.push(size as u8);
+        }
+        frame.extend(body);
+
+        if self.cdc2 {
+            let crc = self.crc.unwrap_or_else(|| VEX_CRC16.checksum(&frame));
+            frame.extend(crc.to_be_bytes());
+        }
+        frame
+    }
+
+    /// Decodes this frame as `P`, a reply to a command with the given IDs.
+    pub fn decode<P: Decode>(&self, id: u8, ext_id: u8) -> Result<P, DecodeError> {
+        P::decode(self.to_bytes(id, ext_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReplyFrame;
+    use crate::{
+        decode::Decode,
+        packets::{
+            controller::UserFifoReplyPacket,
+            dash::{SelectDashReplyPacket, SendDashTouchReplyPacket},
+            device::GetDeviceStatusReplyPacket,
+            factory::{
+                FactoryEnableReplyPacket, GetFactoryStatusReplyPacket, GetFdtStatusReplyPacket,
+            },
+            file::*,
+            kv::{ReadKeyValueReplyPacket, WriteKeyValueReplyPacket},
+            log::{GetLogCountReplyPacket, ReadLogPageReplyPacket},
+            match_mode::SetMatchModeReplyPacket,
+            program::{Slot, SlotInfoPayload},
+            radio::{GetRadioStatusReplyPacket, SelectRadioChannelReplyPacket},
+            system::*,
+        },
+    };
+
+    /// Decodes every frame as each listed packet, which must not panic.
+    macro_rules! decode_all {
+        ($frames:expr, $($packet:ty => ($id:expr, $ext_id:expr)),* $(,)?) => {
+            for frame in $frames {
+                $(
+                    let _ = frame.decode::<$packet>($id, $ext_id);
+                )*
+                let _ = Slot::decode(frame.payload.clone());
+                let _ = SlotInfoPayload::decode(frame.payload.clone());
+            }
+        };
+    }
+
+    #[test]
+    fn malformed_replies_do_not_panic() {
+        // A small xorshift generator, so that failures can be reproduced.
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut frames = Vec::new();
+        for len in 0..64 {
+            for _ in 0..16 {
+                let random = next();
+                let mut payload: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+                // Reach past the fixed byte that starts file reads.
+                if let (Some(first), true) = (payload.first_mut(), random & 16 == 0) {
+                    *first = 0x14;
+                }
+                frames.push(ReplyFrame {
+                    cdc2: random & 1 == 0,
+                    ack: if random & 2 == 0 {
+                        0x76
+                    } else {
+                        (random >> 8) as u8
+                    },
+                    size: (random & 4 == 0).then_some((random >> 16) as u16 % 80),
+                    payload,
+                    crc: (random & 8 == 0).then_some((random >> 32) as u16),
+                });
+            }
+        }
+
+        decode_all!(
+            &frames,
+            SetMatchModeReplyPacket => (88, 193),
+            ReadKeyValueReplyPacket => (86, 46),
+            WriteKeyValueReplyPacket => (86, 47),
+            InitFileTransferReplyPacket => (86, 17),
+            ExitFileTransferReplyPacket => (86, 18),
+            WriteFileReplyPacket => (86, 19),
+            ReadFileReplyPacket => (86, 0),
+            LinkFileReplyPacket => (86, 21),
+            GetDirectoryFileCountReplyPacket => (86, 22),
+            GetDirectoryEntryReplyPacket => (86, 23),
+            LoadFileActionReplyPacket => (86, 24),
+            GetFileMetadataReplyPacket => (86, 25),
+            SetFileMetadataReplyPacket => (86, 26),
+            EraseFileReplyPacket => (86, 27),
+            FileCleanUpReplyPacket => (86, 30),
+            FileFormatReplyPacket => (86, 31),
+            SendDashTouchReplyPacket => (86, 42),
+            SelectDashReplyPacket => (86, 43),
+            GetRadioStatusReplyPacket => (86, 38),
+            SelectRadioChannelReplyPacket => (86, 16),
+            GetFdtStatusReplyPacket => (86, 35),
+            GetFactoryStatusReplyPacket => (86, 241),
+            FactoryEnableReplyPacket => (86, 255),
+            UserFifoReplyPacket => (86, 39),
+            GetLogCountReplyPacket => (86, 36),
+            ReadLogPageReplyPacket => (86, 37),
+            GetSystemFlagsReplyPacket => (86, 32),
+            GetSystemStatusReplyPacket => (86, 34),
+            GetSystemVersionReplyPacket => (164, 0),
+            Query1ReplyPacket => (33, 0),
+            GetDeviceStatusReplyPacket => (86, 33),
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Bridges polled device state into a host-side input stream.
+//!
+//! An [`InputBridge`] repeatedly executes a query command and only yields its output when it
+//! changes, which simulators and virtual gamepads can follow.
+//!
+//! No known packet reads a controller's joysticks or buttons (see
+//! [`commands::controller`](crate::commands::controller)), so a controller can't drive a
+//! bridge on its own. A user program has to relay its state, for example over a user FIFO
+//! channel, and the command that reads it back is polled here.
+
+use std::time::Duration;
+
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+use crate::{commands::Command, connection::Connection};
+
+/// A sink for input state, such as a virtual gamepad driver.
+pub trait InputSink<S> {
+    /// Called with the new state every time it changes.
+    fn update(&mut self, state: &S);
+}
+impl<S, F: FnMut(&S)> InputSink<S> for F {
+    fn update(&mut self, state: &S) {
+        self(state)
+    }
+}
+
+/// Polls a query command at a fixed rate and reports changes in its output.
+pub struct InputBridge<Q: Command> {
+    query: Q,
+    ticker: Interval,
+    last: Option<Q::Output>,
+}
+impl<Q> InputBridge<Q>
+where
+    Q: Command + Clone,
+    Q::Output: Clone + PartialEq,
+{
+    /// Creates a bridge that executes `query` every `poll_interval`.
+    pub fn new(query: Q, poll_interval: Duration) -> Self {
+        let mut ticker = interval(poll_interval);
+        // A slow link should lower the poll rate, not cause a burst of catch-up queries.
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        Self {
+            query,
+            ticker,
+            last: None,
+        }
+    }
+
+    /// Returns the most recently observed state, if any.
+    pub fn last(&self) -> Option<&Q::Output> {
+        self.last.as_ref()
+    }
+
+    /// Polls until the state differs from the last observed state, then returns it.
+    ///
+    /// The first call returns as soon as the first poll succeeds.
+    pub async fn next<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+    ) -> Result<Q::Output, C::Error> {
+        loop {
+            self.ticker.tick().await;
+
+            let state = connection.execute_command(self.query.clone()).await?;
+            if self.last.as_ref() != Some(&state) {
+                self.last = Some(state.clone());
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Forwards every state change to `sink` until polling fails.
+    pub async fn forward<C: Connection + ?Sized>(
+        &mut self,
+        connection: &mut C,
+        mut sink: impl InputSink<Q::Output>,
+    ) -> C::Error {
+        loop {
+            match self.next(connection).await {
+                Ok(state) => sink.update(&state),
+                Err(e) => return e,
+            }
+        }
+    }
+}
@@ -7,18 +7,31 @@
 //! Because manually sending and receiving packets is a chore, this library also provides high level [`Command`](commands::Command)s.
 //! These commands provide easier ways to perform complicated tasks, such as uploading a program.
 
-mod choice;
-
+pub mod capabilities;
+pub mod cobs;
 pub mod crc;
 pub mod decode;
 pub mod encode;
+pub mod endian;
+pub mod fuzz;
 pub mod packets;
+pub mod slot;
 pub mod string;
 pub mod timestamp;
 pub mod varint;
 pub mod version;
 
+#[cfg(feature = "bridge")]
+pub mod bridge;
 #[cfg(feature = "connection")]
 pub mod commands;
 #[cfg(feature = "connection")]
+pub mod config;
+#[cfg(feature = "connection")]
 pub mod connection;
+#[cfg(feature = "connection")]
+pub mod fs;
+#[cfg(feature = "input-bridge")]
+pub mod input;
+#[cfg(any(feature = "simulator", all(test, feature = "connection")))]
+pub mod simulator;
@@ -1,4 +1,80 @@
 use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
+#[cfg(feature = "image")]
+use crate::decode::DecodeError;
+
+/// The width of the brain's framebuffer in pixels, including off-screen padding.
+pub const FRAMEBUFFER_WIDTH: u32 = 512;
+/// The height of the brain's framebuffer in pixels.
+pub const FRAMEBUFFER_HEIGHT: u32 = 272;
+/// The width of the visible part of the brain's screen in pixels.
+pub const SCREEN_WIDTH: u32 = 480;
 
 pub type ScreenCapturePacket = Cdc2CommandPacket<86, 40, ()>;
 pub type ScreenCaptureReplyPacket = Cdc2ReplyPacket<86, 40, ()>;
+
+/// The visible part of the brain's screen, decoded from a raw framebuffer capture.
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot(image::RgbaImage);
+#[cfg(feature = "image")]
+impl Screenshot {
+    /// Decodes a raw framebuffer capture.
+    ///
+    /// The capture is [`FRAMEBUFFER_WIDTH`] by [`FRAMEBUFFER_HEIGHT`] pixels, stored row by row
+    /// as 4 byte little-endian `0x00RRGGBB` words. The padding to the right of the visible
+    /// screen is cropped off and every pixel is made opaque.
+    pub fn from_raw(raw: &[u8]) -> Result<Self, DecodeError> {
+        let pixels = (FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT) as usize;
+        if raw.len() < pixels * 4 {
+            return Err(DecodeError::PacketTooShort);
+        }
+
+        let image = image::RgbaImage::from_fn(SCREEN_WIDTH, FRAMEBUFFER_HEIGHT, |x, y| {
+            let offset = ((y * FRAMEBUFFER_WIDTH + x) * 4) as usize;
+            // little endian
+            let pixel = &raw[offset..offset + 4];
+            image::Rgba([pixel[2], pixel[1], pixel[0], 255])
+        });
+        Ok(Self(image))
+    }
+
+    pub fn image(&self) -> &image::RgbaImage {
+        &self.0
+    }
+
+    pub fn into_image(self) -> image::RgbaImage {
+        self.0
+    }
+
+    /// Writes the screenshot to a PNG file.
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>) -> image::ImageResult<()> {
+        self.0.save_with_format(path, image::ImageFormat::Png)
+    }
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_framebuffer_words() {
+        let mut raw = vec![0; (FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT * 4) as usize];
+        raw[..4].copy_from_slice(&0x00112233u32.to_le_bytes());
+
+        let screenshot = Screenshot::from_raw(&raw).unwrap();
+        assert_eq!(
+            screenshot.image().dimensions(),
+            (SCREEN_WIDTH, FRAMEBUFFER_HEIGHT)
+        );
+        assert_eq!(
+            screenshot.image()[(0, 0)],
+            image::Rgba([0x11, 0x22, 0x33, 255])
+        );
+        assert_eq!(screenshot.image()[(1, 0)], image::Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn rejects_short_capture() {
+        assert!(Screenshot::from_raw(&[0; 16]).is_err());
+    }
+}
@@ -6,12 +6,13 @@ use crate::{
     varint::VarU16,
 };
 
-use super::{DEVICE_BOUND_HEADER, HOST_BOUND_HEADER};
+use super::{CommandId, DEVICE_BOUND_HEADER, HOST_BOUND_HEADER};
 
 /// CDC (Simple) Command Packet
 ///
 /// Encodes a simple device-bound message over the protocol containing
 /// an ID and a payload.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CdcCommandPacket<const ID: u8, P: Encode> {
     header: [u8; 4],
     payload: P,
@@ -38,7 +39,7 @@ impl<const ID: u8, P: Encode> Encode for CdcCommandPacket<ID, P> {
 
         // We only encode the payload size if there is a payload
         if !payload_bytes.is_empty() {
-            let size = VarU16::new(payload_bytes.len() as _);
+            let size = VarU16::from_len(payload_bytes.len())?;
             encoded.extend(size.encode()?);
             encoded.extend(payload_bytes);
         }
@@ -47,6 +48,11 @@ impl<const ID: u8, P: Encode> Encode for CdcCommandPacket<ID, P> {
     }
 }
 
+impl<const ID: u8, P: Encode> CommandId for CdcCommandPacket<ID, P> {
+    const ID: u8 = ID;
+    const EXT_ID: Option<u8> = None;
+}
+
 impl<const ID: u8, P: Encode + Clone> Clone for CdcCommandPacket<ID, P> {
     fn clone(&self) -> Self {
         Self {
@@ -59,6 +65,7 @@ impl<const ID: u8, P: Encode + Clone> Clone for CdcCommandPacket<ID, P> {
 /// CDC (Simple) Command Reply Packet
 ///
 /// Encodes a reply payload to a [`CdcCommandPacket`] for a given ID.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CdcReplyPacket<const ID: u8, P: Decode> {
     /// Host-bound Packet Header
     ///
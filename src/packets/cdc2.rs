@@ -7,12 +7,17 @@ use crate::{
     varint::VarU16,
 };
 
-use super::{DEVICE_BOUND_HEADER, HOST_BOUND_HEADER};
+use super::{CommandId, DEVICE_BOUND_HEADER, HOST_BOUND_HEADER};
 use crate::decode::{Decode, DecodeError};
 
 /// CDC2 Packet Acknowledgement Codes
+///
+/// Every code except [`Ack`](Self::Ack) is a negative acknowledgement (NACK) explaining why a
+/// packet was rejected. Connection errors carry them in their `Nack` variant, so the cause can
+/// be matched on, as in `SerialError::Nack(Cdc2Ack::NackNoDirectory)`.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Error)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Cdc2Ack {
     /// Acknowledges that a packet has been received successfully.
     #[error("Packet was recieved successfully. Wait, how'd this happen??")]
@@ -53,7 +58,7 @@ pub enum Cdc2Ack {
     NackInvalidInitialization = 0xD5,
 
     /// Returned by the brain when we fail to pad a transfer to a four byte boundary.
-    #[error("File transfer was initialized incorrectly. (NACK 0xD6)")]
+    #[error("File transfer was not padded to a four byte boundary. (NACK 0xD6)")]
     NackAlignment = 0xD6,
 
     /// Returned by the brain when the addr on a file transfer does not match
@@ -121,10 +126,10 @@ impl Decode for Cdc2Ack {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cdc2CommandPacket<const ID: u8, const EXT_ID: u8, P: Encode> {
     header: [u8; 4],
     payload: P,
-    crc: crc::Crc<u16>,
 }
 
 impl<P: Encode, const ID: u8, const EXTENDED_ID: u8> Cdc2CommandPacket<ID, EXTENDED_ID, P> {
@@ -133,7 +138,6 @@ impl<P: Encode, const ID: u8, const EXTENDED_ID: u8> Cdc2CommandPacket<ID, EXTEN
         Self {
             header: DEVICE_BOUND_HEADER,
             payload,
-            crc: VEX_CRC16,
         }
     }
 }
@@ -150,13 +154,13 @@ impl<const ID: u8, const EXT_ID: u8, P: Encode> Encode for Cdc2CommandPacket<ID,
 
         // Push the payload size and encoded bytes
         let payload_bytes = self.payload.encode()?;
-        let payload_size = VarU16::new(payload_bytes.len() as u16);
+        let payload_size = VarU16::from_len(payload_bytes.len())?;
         encoded.extend(payload_size.encode()?);
         encoded.extend(payload_bytes);
 
         // The CRC32 checksum is of the whole encoded packet, meaning we need
         // to also include the header bytes.
-        let checksum = self.crc.checksum(&encoded);
+        let checksum = VEX_CRC16.checksum(&encoded);
 
         encoded.extend(checksum.to_be_bytes());
 
@@ -164,16 +168,21 @@ impl<const ID: u8, const EXT_ID: u8, P: Encode> Encode for Cdc2CommandPacket<ID,
     }
 }
 
+impl<const ID: u8, const EXT_ID: u8, P: Encode> CommandId for Cdc2CommandPacket<ID, EXT_ID, P> {
+    const ID: u8 = ID;
+    const EXT_ID: Option<u8> = Some(EXT_ID);
+}
+
 impl<const ID: u8, const EXT_ID: u8, P: Encode + Clone> Clone for Cdc2CommandPacket<ID, EXT_ID, P> {
     fn clone(&self) -> Self {
         Self {
             header: DEVICE_BOUND_HEADER,
             payload: self.payload.clone(),
-            crc: self.crc.clone(),
         }
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cdc2ReplyPacket<const ID: u8, const EXT_ID: u8, P: SizedDecode> {
     pub header: [u8; 2],
     pub ack: Cdc2Ack,
@@ -214,7 +223,7 @@ impl<const ID: u8, const EXT_ID: u8, P: SizedDecode> Decode for Cdc2ReplyPacket<
 
         let ack = Cdc2Ack::decode(&mut data)?;
 
-        let payload = P::sized_decode(&mut data, payload_size.into())?;
+        let payload = P::sized_decode(&mut data, payload_size)?;
         let crc = u16::decode(&mut data)?;
 
         Ok(Self {
@@ -9,6 +9,7 @@ pub type UserFifoPacket = Cdc2CommandPacket<86, 39, UserFifoPayload>;
 pub type UserFifoReplyPacket = Cdc2ReplyPacket<86, 39, UserFifoReplyPayload>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserFifoPayload {
     /// stdio channel is 1, other channels unknown.
     pub channel: u8,
@@ -19,10 +20,10 @@ pub struct UserFifoPayload {
 impl Encode for UserFifoPayload {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         let mut encoded = Vec::new();
-        encoded.extend(self.channel.to_le_bytes());
+        encoded.push(self.channel);
         if let Some(write) = &self.write {
             let encoded_write = write.encode()?;
-            encoded.extend((encoded_write.len() as u8).to_le_bytes());
+            encoded.push(encoded_write.len() as u8);
             encoded.extend(encoded_write);
         } else {
             encoded.extend([0]); // 0 write length
@@ -32,6 +33,7 @@ impl Encode for UserFifoPayload {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserFifoReplyPayload {
     /// stdio channel is 1, other channels unknown.
     pub channel: u8,
@@ -1,8 +1,12 @@
 use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
-use crate::encode::{Encode, EncodeError};
+use crate::{
+    encode::{Encode, EncodeError},
+    endian::U16Le,
+};
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DashScreen {
     Home = 0,
     Battery = 1,
@@ -42,10 +46,16 @@ pub enum DashScreen {
     LogData = 47,
 }
 
+/// Injects a touch on the brain's screen, as if it had been pressed at the given position.
+///
+/// This is the only known way to inject input into the brain's UI. There is no packet for
+/// the brain's physical button, so on-screen buttons (program slots, dialog prompts) have to
+/// be pressed by touching their position on the screen.
 pub type SendDashTouchPacket = Cdc2CommandPacket<86, 42, SendDashTouchPayload>;
 pub type SendDashTouchReplyPacket = Cdc2ReplyPacket<86, 42, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SendDashTouchPayload {
     pub x: u16,
     pub y: u16,
@@ -55,9 +65,9 @@ pub struct SendDashTouchPayload {
 impl Encode for SendDashTouchPayload {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         let mut encoded = Vec::new();
-        encoded.extend(self.x.to_le_bytes());
-        encoded.extend(self.y.to_le_bytes());
-        encoded.extend(self.pressing.to_le_bytes());
+        encoded.extend(U16Le(self.x).encode()?);
+        encoded.extend(U16Le(self.y).encode()?);
+        encoded.extend(U16Le(self.pressing).encode()?);
         Ok(encoded)
     }
 }
@@ -66,6 +76,7 @@ pub type SelectDashPacket = Cdc2CommandPacket<86, 43, SelectDashPayload>;
 pub type SelectDashReplyPacket = Cdc2ReplyPacket<86, 43, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SelectDashPayload {
     pub screen: DashScreen,
     /// (RESEARCH NEEDED)
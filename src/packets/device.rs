@@ -1,9 +1,12 @@
+use bitflags::bitflags;
+
 use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
 use crate::decode::{Decode, DecodeError, SizedDecode};
 
 // This is copied from vex-sdk
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DeviceType {
     NoSensor = 0,
     Motor = 2,
@@ -81,34 +84,68 @@ impl Decode for DeviceType {
     }
 }
 
-pub struct DeviceStatus {
+bitflags! {
+    /// Status bits reported for each device by [`GetDeviceStatusPacket`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct DeviceStatusFlags: u8 {
+        /// Set for devices plugged into a smart port. (UNCONFIRMED)
+        const SMART_PORT = 1 << 0;
+    }
+}
+
+/// The firmware version running on a smart device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceFirmwareVersion {
+    pub version: u16,
+    /// Non-zero for beta firmware.
+    pub beta: u8,
+}
+
+/// A device reported by [`GetDeviceStatusPacket`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SmartDevice {
     /// 1-indexed smart port number. Port 22 is the internal ADI expander and Port 23 is the battery.
     pub port: u8,
 
     /// Following V5_DeviceType
     pub device_type: DeviceType,
 
-    /// 1 = smart port device, 0 = otherwise. (UNCONFIRMED)
-    pub status: u8,
-    pub beta_version: u8,
-    pub version: u16,
+    pub status: DeviceStatusFlags,
+    pub firmware: DeviceFirmwareVersion,
     pub boot_version: u16,
 }
-impl Decode for DeviceStatus {
+impl SmartDevice {
+    /// Returns whether the device is running beta firmware.
+    pub fn is_beta_firmware(&self) -> bool {
+        self.firmware.beta != 0
+    }
+
+    /// Returns whether the device's firmware is older than `current`, usually the version
+    /// bundled with the brain's VEXos.
+    ///
+    /// VEXos updates devices with legacy firmware when they are plugged in, which makes them
+    /// unusable for a while.
+    pub fn is_legacy_firmware(&self, current: DeviceFirmwareVersion) -> bool {
+        self.firmware.version < current.version
+    }
+}
+impl Decode for SmartDevice {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
         let port = u8::decode(&mut data)?;
         let device_type = DeviceType::decode(&mut data)?;
-        let status = u8::decode(&mut data)?;
-        let beta_version = u8::decode(&mut data)?;
+        let status = DeviceStatusFlags::from_bits_retain(u8::decode(&mut data)?);
+        let beta = u8::decode(&mut data)?;
         let version = u16::decode(&mut data)?;
         let boot_version = u16::decode(&mut data)?;
         Ok(Self {
             port,
             device_type,
             status,
-            beta_version,
-            version,
+            firmware: DeviceFirmwareVersion { version, beta },
             boot_version,
         })
     }
@@ -117,10 +154,11 @@ impl Decode for DeviceStatus {
 pub type GetDeviceStatusPacket = Cdc2CommandPacket<86, 33, ()>;
 pub type GetDeviceStatusReplyPacket = Cdc2ReplyPacket<86, 33, GetDeviceStatusReplyPayload>;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetDeviceStatusReplyPayload {
     /// Number of elements in the following array.
     pub count: u8,
-    pub devices: Vec<DeviceStatus>,
+    pub devices: Vec<SmartDevice>,
 }
 impl Decode for GetDeviceStatusReplyPayload {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
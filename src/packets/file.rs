@@ -1,21 +1,26 @@
 //! Filesystem Access
 
-use std::{vec, str};
+use std::{str, vec};
 
 use super::{
     cdc::CdcReplyPacket,
     cdc2::{Cdc2Ack, Cdc2CommandPacket, Cdc2ReplyPacket},
+    HOST_BOUND_HEADER,
 };
 use crate::{
-    choice::{Choice, PrefferedChoice},
-    decode::{Decode, DecodeError, SizedDecode},
+    crc::VEX_CRC16,
+    decode::{Decode, DecodeError},
     encode::{Encode, EncodeError},
+    endian::{I32Le, U16Le, U32Le},
     string::FixedString,
+    timestamp::J2000Timestamp,
+    varint::VarU16,
     version::Version,
 };
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileInitAction {
     Write = 1,
     Read = 2,
@@ -23,13 +28,20 @@ pub enum FileInitAction {
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileInitOption {
     None = 0,
     Overwrite = 1,
 }
 
+/// The memory a file transfer reads from or writes to.
+///
+/// VEXos does not expose the brain's microSD card over the serial protocol, and there is no
+/// known target or vendor that addresses it. Only user programs running on the brain can
+/// read and write the card.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileTransferTarget {
     Ddr = 0,
     Qspi = 1,
@@ -45,7 +57,8 @@ pub enum FileTransferTarget {
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileVendor {
     User = 1,
     Sys = 15,
@@ -59,6 +72,12 @@ pub enum FileVendor {
     Vex = 240,
     Undefined = 241,
 }
+impl FileVendor {
+    /// The vendor that PROS uploads its programs under.
+    pub const PROS: Self = Self::Dev2;
+    /// The vendor that MW uploads its programs under.
+    pub const MW: Self = Self::Dev3;
+}
 impl Decode for FileVendor {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let this = u8::decode(data)?;
@@ -86,6 +105,7 @@ impl Decode for FileVendor {
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileLoadAction {
     Run = 0,
     Stop = 128,
@@ -93,6 +113,7 @@ pub enum FileLoadAction {
 
 #[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
 #[repr(u8)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExtensionType {
     /// Regular unencrypted file.
     #[default]
@@ -125,10 +146,12 @@ impl Decode for ExtensionType {
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileMetadata {
     pub extension: FixedString<3>,
     pub extension_type: ExtensionType,
-    pub timestamp: i32,
+    /// When the file was last written.
+    pub timestamp: J2000Timestamp,
     pub version: Version,
 }
 
@@ -138,7 +161,7 @@ impl Encode for FileMetadata {
         // extension is not null terminated and is fixed length
         data[..self.extension.as_ref().len()].copy_from_slice(self.extension.as_ref().as_bytes());
         data.push(self.extension_type as _);
-        data.extend(self.timestamp.to_le_bytes());
+        data.extend(I32Le(self.timestamp.0).encode()?);
         data.extend(self.version.encode()?);
 
         Ok(data)
@@ -159,8 +182,8 @@ impl Decode for FileMetadata {
                     str::from_utf8(&<[u8; 3]>::decode(&mut data)?)?.to_string(),
                 )
             },
-            extension_type: Decode::decode(&mut data).unwrap(),
-            timestamp: i32::decode(&mut data)?,
+            extension_type: Decode::decode(&mut data)?,
+            timestamp: J2000Timestamp(i32::decode(&mut data)?),
             version: Version::decode(&mut data)?,
         })
     }
@@ -171,6 +194,7 @@ pub type InitFileTransferPacket = Cdc2CommandPacket<86, 17, InitFileTransferPayl
 pub type InitFileTransferReplyPacket = Cdc2ReplyPacket<86, 17, InitFileTransferReplyPayload>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InitFileTransferPayload {
     pub operation: FileInitAction,
     pub target: FileTransferTarget,
@@ -191,9 +215,9 @@ impl Encode for InitFileTransferPayload {
             self.vendor as _,
             self.options as _,
         ];
-        encoded.extend(self.file_size.to_le_bytes());
-        encoded.extend(self.load_address.to_le_bytes());
-        encoded.extend(self.write_file_crc.to_le_bytes());
+        encoded.extend(U32Le(self.file_size).encode()?);
+        encoded.extend(U32Le(self.load_address).encode()?);
+        encoded.extend(U32Le(self.write_file_crc).encode()?);
         encoded.extend(self.metadata.encode()?);
         encoded.extend(self.file_name.encode()?);
 
@@ -201,6 +225,7 @@ impl Encode for InitFileTransferPayload {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InitFileTransferReplyPayload {
     /// The amount of receive data (in bytes) that can be sent in every packet.
     pub window_size: u16,
@@ -238,6 +263,7 @@ pub type ExitFileTransferReplyPacket = Cdc2ReplyPacket<86, 18, ()>;
 /// The action to run when a file transfer is completed.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileExitAction {
     DoNothing = 0,
     RunProgram = 1,
@@ -254,18 +280,23 @@ pub type WriteFilePacket = Cdc2CommandPacket<86, 19, WriteFilePayload>;
 pub type WriteFileReplyPacket = Cdc2ReplyPacket<86, 19, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WriteFilePayload {
     /// Memory address to write to.
     pub address: i32,
 
     /// A sequence of bytes to write. Must be 4-byte aligned.
+    #[cfg_attr(
+        all(feature = "serde", feature = "serde_bytes"),
+        serde(with = "serde_bytes")
+    )]
     pub chunk_data: Vec<u8>,
 }
 impl Encode for WriteFilePayload {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         let mut encoded = Vec::new();
 
-        encoded.extend(self.address.to_le_bytes());
+        encoded.extend(I32Le(self.address).encode()?);
         encoded.extend(&self.chunk_data);
 
         Ok(encoded)
@@ -278,6 +309,7 @@ pub type ReadFilePacket = Cdc2CommandPacket<86, 20, ReadFilePayload>;
 pub type ReadFileReplyPacket = CdcReplyPacket<86, ReadFileReplyPayload>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReadFilePayload {
     /// Memory address to read from.
     pub address: u32,
@@ -288,12 +320,13 @@ pub struct ReadFilePayload {
 impl Encode for ReadFilePayload {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         let mut encoded = Vec::new();
-        encoded.extend(self.address.to_le_bytes());
-        encoded.extend(self.size.to_le_bytes());
+        encoded.extend(U32Le(self.address).encode()?);
+        encoded.extend(U16Le(self.size).encode()?);
         Ok(encoded)
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReadFileReplyContents {
     Failure {
         nack: Cdc2Ack,
@@ -302,65 +335,35 @@ pub enum ReadFileReplyContents {
     Success {
         /// Memory address to read from.
         address: u32,
+        #[cfg_attr(
+            all(feature = "serde", feature = "serde_bytes"),
+            serde(with = "serde_bytes")
+        )]
         data: Vec<u8>,
         crc: u16,
     },
 }
 impl Decode for ReadFileReplyContents {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
-        struct Success {
-            address: u32,
-            data: Vec<u8>,
-            crc: u16,
-        }
-        impl Decode for Success {
-            fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
-                let mut data = data.into_iter();
-                let address = u32::decode(&mut data)?;
-
-                // This is a cursed way to get the number of bytes in chunk_data.
-                let data_vec = data.collect::<Vec<_>>();
-                // The last two bytes are the CRC checksum.
-                let num_bytes = data_vec.len() - 2;
-                let mut data = data_vec.into_iter();
-
-                let chunk_data = Vec::sized_decode(&mut data, num_bytes as _)?;
-                let crc = u16::decode(&mut data)?.swap_bytes();
-                Ok(Self {
-                    address,
-                    data: chunk_data,
-                    crc,
-                })
-            }
-        }
-        struct Failure {
-            nack: Cdc2Ack,
-            crc: u16,
-        }
-        impl Decode for Failure {
-            fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
-                let mut data = data.into_iter();
-                let nack = Cdc2Ack::decode(&mut data)?;
-                let crc = u16::decode(&mut data)?.swap_bytes();
-                Ok(Self { nack, crc })
-            }
+        let mut data = data.into_iter().collect::<Vec<_>>();
+        // A failure is only a NACK and a CRC, so anything long enough to hold
+        // an address and a CRC is a successful read.
+        if data.len() >= 6 {
+            // The last two bytes are the CRC checksum. The chunk is what's left once the
+            // address has been taken off the front.
+            let crc = u16::decode(data.drain(data.len() - 2..))?.swap_bytes();
+            let address = u32::decode(data.drain(..4))?;
+            Ok(Self::Success { address, data, crc })
+        } else {
+            let mut data = data.into_iter();
+            let nack = Cdc2Ack::decode(&mut data)?;
+            let crc = u16::decode(&mut data)?.swap_bytes();
+            Ok(Self::Failure { nack, crc })
         }
-
-        let result = Choice::<Success, Failure>::decode(data)?.prefer_left();
-        Ok(match result {
-            PrefferedChoice::Left(success) => Self::Success {
-                address: success.address,
-                data: success.data,
-                crc: success.crc,
-            },
-            PrefferedChoice::Right(failure) => Self::Failure {
-                nack: failure.nack,
-                crc: failure.crc,
-            },
-        })
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReadFileReplyPayload {
     pub contents: ReadFileReplyContents,
 }
@@ -381,6 +384,7 @@ impl Decode for ReadFileReplyPayload {
         Ok(Self { contents })
     }
 }
+
 impl ReadFileReplyPayload {
     pub fn unwrap(self) -> Result<(u32, Vec<u8>), Cdc2Ack> {
         match self.contents {
@@ -394,6 +398,39 @@ impl ReadFileReplyPayload {
     }
 }
 
+impl ReadFileReplyPacket {
+    /// Checks the CRC that ends the reply against the rest of the packet.
+    ///
+    /// Unlike CDC2 replies, reads put their data in a simple CDC packet, so a corrupted chunk
+    /// would otherwise decode successfully.
+    pub fn verify_crc(&self) -> Result<(), DecodeError> {
+        let mut packet = Vec::from(HOST_BOUND_HEADER);
+        packet.push(86);
+        packet.extend(VarU16::new(self.payload_size).encode().unwrap());
+        packet.push(0x14);
+        let expected = match &self.payload.contents {
+            ReadFileReplyContents::Success { address, data, crc } => {
+                packet.extend(address.to_le_bytes());
+                packet.extend(data);
+                *crc
+            }
+            ReadFileReplyContents::Failure { nack, crc } => {
+                packet.push(*nack as u8);
+                *crc
+            }
+        };
+
+        let actual = VEX_CRC16.checksum(&packet);
+        if actual != expected {
+            return Err(DecodeError::ChecksumMismatch {
+                expected: expected.into(),
+                actual: actual.into(),
+            });
+        }
+        Ok(())
+    }
+}
+
 /// File linking means allowing one file to be loaded after another file first (its parent).
 ///
 /// This is used in PROS for the hot/cold linking.
@@ -401,6 +438,7 @@ pub type LinkFilePacket = Cdc2CommandPacket<86, 21, LinkFilePayload>;
 pub type LinkFileReplyPacket = Cdc2ReplyPacket<86, 21, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LinkFilePayload {
     pub vendor: FileVendor,
     /// 0 = default. (RESEARCH NEEDED)
@@ -421,6 +459,7 @@ pub type GetDirectoryFileCountPacket = Cdc2CommandPacket<86, 22, GetDirectoryFil
 pub type GetDirectoryFileCountReplyPacket = Cdc2ReplyPacket<86, 22, u16>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetDirectoryFileCountPayload {
     pub vendor: FileVendor,
     /// 0 = default. (RESEARCH NEEDED)
@@ -437,6 +476,7 @@ pub type GetDirectoryEntryReplyPacket =
     Cdc2ReplyPacket<86, 23, Option<GetDirectoryEntryReplyPayload>>;
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetDirectoryEntryPayload {
     pub file_index: u8,
     /// 0 = default. (RESEARCH NEEDED)
@@ -449,6 +489,7 @@ impl Encode for GetDirectoryEntryPayload {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetDirectoryEntryReplyPayload {
     pub file_index: u8,
     pub size: u32,
@@ -497,6 +538,7 @@ pub type LoadFileActionPacket = Cdc2CommandPacket<86, 24, LoadFileActionPayload>
 pub type LoadFileActionReplyPacket = Cdc2ReplyPacket<86, 24, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LoadFileActionPayload {
     pub vendor: FileVendor,
     pub action: FileLoadAction,
@@ -515,6 +557,7 @@ pub type GetFileMetadataPacket = Cdc2CommandPacket<86, 25, GetFileMetadataPayloa
 pub type GetFileMetadataReplyPacket = Cdc2ReplyPacket<86, 25, Option<GetFileMetadataReplyPayload>>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetFileMetadataPayload {
     pub vendor: FileVendor,
     /// 0 = default. (RESEARCH NEEDED)
@@ -531,6 +574,8 @@ impl Encode for GetFileMetadataPayload {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetFileMetadataReplyPayload {
     /// RESEARCH NEEDED: Unknown what this is if there is no link to the file.
     pub linked_vendor: Option<FileVendor>,
@@ -543,7 +588,7 @@ pub struct GetFileMetadataReplyPayload {
 impl Decode for Option<GetFileMetadataReplyPayload> {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
-        let maybe_vid = u8::decode(&mut data).unwrap();
+        let maybe_vid = u8::decode(&mut data)?;
 
         let linked_vendor = match maybe_vid {
             // 0 is returned if there is no linked file.
@@ -556,7 +601,7 @@ impl Decode for Option<GetFileMetadataReplyPayload> {
         };
 
         let size = u32::decode(&mut data)?;
-        
+
         // This happens when we try to read a system file from the
         // `/vex_/*` VID. In this case, all of bytes after the vendor
         // will be returned as 0xff or 0x0, making this packet useless,
@@ -583,6 +628,7 @@ pub type SetFileMetadataPacket = Cdc2CommandPacket<86, 26, SetFileMetadataPayloa
 pub type SetFileMetadataReplyPacket = Cdc2ReplyPacket<86, 26, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetFileMetadataPayload {
     pub vendor: FileVendor,
     /// 0 = default. (RESEARCH NEEDED)
@@ -595,7 +641,7 @@ pub struct SetFileMetadataPayload {
 impl Encode for SetFileMetadataPayload {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         let mut encoded = vec![self.vendor as _, self.option];
-        encoded.extend(self.load_address.to_le_bytes());
+        encoded.extend(U32Le(self.load_address).encode()?);
         encoded.extend(self.metadata.encode()?);
         encoded.extend(self.file_name.encode()?);
         Ok(encoded)
@@ -606,12 +652,17 @@ pub type EraseFilePacket = Cdc2CommandPacket<86, 27, EraseFilePayload>;
 pub type EraseFileReplyPacket = Cdc2ReplyPacket<86, 27, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EraseFilePayload {
     pub vendor: FileVendor,
-    /// 128 = default. (RESEARCH NEEDED)
+    /// 0, or [`Self::ERASE_ALL_LINKED`].
     pub option: u8,
     pub file_name: FixedString<23>,
 }
+impl EraseFilePayload {
+    /// Erases the files linked to this one as well.
+    pub const ERASE_ALL_LINKED: u8 = 0x80;
+}
 impl Encode for EraseFilePayload {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         let mut encoded = vec![self.vendor as _, self.option];
@@ -622,9 +673,10 @@ impl Encode for EraseFilePayload {
 }
 
 pub type FileCleanUpPacket = Cdc2CommandPacket<86, 30, FileCleanUpPayload>;
-pub type FileCleanUpReplyPacket = Cdc2CommandPacket<86, 30, FileCleanUpResult>;
+pub type FileCleanUpReplyPacket = Cdc2ReplyPacket<86, 30, FileCleanUpResult>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileCleanUpPayload {
     pub vendor: FileVendor,
     /// 0 = default. (RESEARCH NEEDED)
@@ -639,6 +691,7 @@ impl Encode for FileCleanUpPayload {
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
 /// (RESEARCH NEEDED)
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileCleanUpResult {
     /// No file deleted
     None = 0,
@@ -674,9 +727,10 @@ impl Decode for FileCleanUpResult {
 
 /// Same as "File Clear Up", but takes longer
 pub type FileFormatPacket = Cdc2CommandPacket<86, 31, FileFormatConfirmation>;
-pub type FileFormatReplyPacket = Cdc2CommandPacket<86, 31, ()>;
+pub type FileFormatReplyPacket = Cdc2ReplyPacket<86, 31, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileFormatConfirmation {
     /// Must be [0x44, 0x43, 0x42, 0x41].
     pub confirmation_code: [u8; 4],
@@ -686,3 +740,54 @@ impl Encode for FileFormatConfirmation {
         Ok(self.confirmation_code.to_vec())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadFileReplyContents, ReadFileReplyPacket, ReadFileReplyPayload};
+    use crate::{
+        crc::VEX_CRC16,
+        decode::{Decode, DecodeError},
+        packets::cdc2::Cdc2Ack,
+    };
+
+    #[test]
+    fn read_file_reply_chunk() {
+        let packet = [
+            0x14, 0x00, 0x00, 0x80, 0x03, 0xDE, 0xAD, 0xBE, 0xEF, 0x12, 0x34,
+        ];
+        let payload = ReadFileReplyPayload::decode(packet).unwrap();
+
+        let ReadFileReplyContents::Success { address, data, crc } = payload.contents else {
+            panic!("expected a successful read");
+        };
+        assert_eq!(address, 0x03800000);
+        assert_eq!(data, [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(crc, 0x1234);
+
+        let payload = ReadFileReplyPayload::decode([0x14, 0xD1, 0x12, 0x34]).unwrap();
+        assert!(matches!(
+            payload.contents,
+            ReadFileReplyContents::Failure {
+                nack: Cdc2Ack::NackTransferSize,
+                crc: 0x1234
+            }
+        ));
+    }
+
+    #[test]
+    fn read_file_reply_crc() {
+        let mut packet = vec![
+            0xAA, 0x55, 0x56, 0x0B, 0x14, 0x00, 0x00, 0x80, 0x03, 0xDE, 0xAD, 0xBE, 0xEF,
+        ];
+        packet.extend(VEX_CRC16.checksum(&packet).to_be_bytes());
+        let reply = ReadFileReplyPacket::decode(packet.clone()).unwrap();
+        assert!(reply.verify_crc().is_ok());
+
+        packet[9] ^= 0xFF;
+        let reply = ReadFileReplyPacket::decode(packet).unwrap();
+        assert!(matches!(
+            reply.verify_crc(),
+            Err(DecodeError::ChecksumMismatch { .. })
+        ));
+    }
+}
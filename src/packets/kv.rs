@@ -13,6 +13,7 @@ pub type WriteKeyValuePacket = Cdc2CommandPacket<86, 47, WriteKeyValuePayload>;
 pub type WriteKeyValueReplyPacket = Cdc2ReplyPacket<86, 47, ()>;
 
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WriteKeyValuePayload {
     pub key: FixedString<31>,
     pub value: FixedString<255>,
@@ -2,9 +2,11 @@ use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
 use crate::{
     decode::{Decode, DecodeError, SizedDecode},
     encode::{Encode, EncodeError},
+    endian::U32Le,
 };
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Log {
     /// (RESEARCH NEEDED)
     pub code: u8,
@@ -42,6 +44,8 @@ impl Decode for Log {
 pub type GetLogCountPacket = Cdc2CommandPacket<86, 36, ()>;
 pub type GetLogCountReplyPacket = Cdc2ReplyPacket<86, 36, GetLogCountReplyPayload>;
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetLogCountReplyPayload {
     pub unknown: u8,
     pub count: u32,
@@ -60,6 +64,7 @@ pub type ReadLogPagePacket = Cdc2CommandPacket<86, 37, ReadLogPagePayload>;
 pub type ReadLogPageReplyPacket = Cdc2ReplyPacket<86, 37, ReadLogPageReplyPayload>;
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReadLogPagePayload {
     pub offset: u32,
     pub count: u32,
@@ -67,13 +72,14 @@ pub struct ReadLogPagePayload {
 impl Encode for ReadLogPagePayload {
     fn encode(&self) -> Result<Vec<u8>, EncodeError> {
         let mut encoded = Vec::new();
-        encoded.extend(self.offset.to_le_bytes());
-        encoded.extend(self.count.to_le_bytes());
+        encoded.extend(U32Le(self.offset).encode()?);
+        encoded.extend(U32Le(self.count).encode()?);
         Ok(encoded)
     }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReadLogPageReplyPayload {
     /// Size of each log item in bytes.
     pub log_size: u8,
@@ -1,14 +1,16 @@
-use crate::encode::Encode;
+use crate::{encode::Encode, endian::U32Le};
 
 use super::cdc2::{Cdc2CommandPacket, Cdc2ReplyPacket};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MatchMode {
     Driver = 8,
     Auto = 10,
     Disabled = 11,
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetMatchModePayload {
     pub match_mode: MatchMode,
     /// Time in seconds that should be displayed on the controller
@@ -18,7 +20,7 @@ impl Encode for SetMatchModePayload {
     fn encode(&self) -> Result<Vec<u8>, crate::encode::EncodeError> {
         let mut encoded = Vec::new();
         encoded.push(self.match_mode as u8);
-        encoded.extend(self.match_time.to_le_bytes());
+        encoded.extend(U32Le(self.match_time).encode()?);
         Ok(encoded)
     }
 }
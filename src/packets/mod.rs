@@ -20,3 +20,11 @@ pub const DEVICE_BOUND_HEADER: [u8; 4] = [0xC9, 0x36, 0xB8, 0x47];
 
 /// Header byte sequence used for all host-bound packets.
 pub const HOST_BOUND_HEADER: [u8; 2] = [0xAA, 0x55];
+
+/// The command IDs of a device-bound packet type.
+pub trait CommandId {
+    /// The packet's command ID.
+    const ID: u8;
+    /// The packet's extended command ID, or `None` for simple CDC packets.
+    const EXT_ID: Option<u8>;
+}
@@ -7,6 +7,7 @@ use crate::{
     encode::{Encode, EncodeError},
 };
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Slot {
     /// The number in the file icon: 'USER???x.bmp'.
     pub icon_number: u16,
@@ -18,7 +19,7 @@ impl Decode for Slot {
         let mut data = data.into_iter();
         let icon_number = u16::decode(&mut data)?;
         let name_length = u8::decode(&mut data)?;
-        let name = String::sized_decode(&mut data, (name_length - 1) as _)?;
+        let name = String::sized_decode(&mut data, name_length.saturating_sub(1) as _)?;
 
         Ok(Self {
             icon_number,
@@ -32,6 +33,7 @@ pub type GetProgramInfoPacket = Cdc2CommandPacket<86, 28, GetProgramInfoPayload>
 pub type GetProgramInfoReplyPacket = Cdc2ReplyPacket<86, 28, GetProgramInfoReplyPayload>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetProgramInfoPayload {
     pub vendor: FileVendor,
     /// 0 = default. (RESEARCH NEEDED)
@@ -49,6 +51,7 @@ impl Encode for GetProgramInfoPayload {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetProgramInfoReplyPayload {
     /// A zero-based slot number.
     pub slot: u8,
@@ -62,6 +65,7 @@ pub type GetSlot1To4InfoReplyPacket = Cdc2CommandPacket<86, 49, SlotInfoPayload>
 pub type GetSlot5To8InfoPacket = Cdc2CommandPacket<86, 50, ()>;
 pub type GetSlot5To8InfoReplyPacket = Cdc2CommandPacket<86, 50, SlotInfoPayload>;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SlotInfoPayload {
     /// Bit Mask.
     ///
@@ -5,6 +5,8 @@ use super::{
     Decode,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RadioStatus {
     /// 0 = No controller, 4 = Controller connected (UNCONFIRMED)
     pub device: u8,
@@ -38,7 +40,8 @@ pub type GetRadioStatusPacket = Cdc2CommandPacket<86, 38, ()>;
 pub type GetRadioStatusReplyPacket = Cdc2ReplyPacket<86, 38, RadioStatus>;
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RadioChannel {
     // NOTE: There's probably a secret third channel for matches, but that's not known.
     /// Used when controlling the robot outside of a competition match.
@@ -58,6 +61,7 @@ pub type SelectRadioChannelPacket = Cdc2CommandPacket<86, 16, SelectRadioChannel
 pub type SelectRadioChannelReplyPacket = Cdc2ReplyPacket<86, 16, ()>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SelectRadioChannelPayload {
     pub channel: RadioChannel,
 }
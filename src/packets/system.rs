@@ -10,6 +10,7 @@ use bitflags::bitflags;
 
 #[repr(u16)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ProductType {
     Brain = 0x10,
     Controller = 0x11,
@@ -32,6 +33,7 @@ impl Decode for ProductType {
 
 bitflags! {
     #[derive(Debug, Clone, Copy)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct ProductFlags: u8 {
         /// Bit 1 is set when the controller is connected over a cable to the V5 Brain
         const CONNECTED_CABLE = 1 << 0; // From testing, this appears to be how it works.
@@ -41,23 +43,59 @@ bitflags! {
     }
 }
 
-pub struct SystemFlags {
-    /// Bit mask.
-    /// From left to right:
-    /// no.1 to no.8 bit - Page index
-    /// no.12 bit = Radio Data mode on
-    /// no.14 bit = Brain button double clicked
-    /// no.15 bit = Battery is charging
-    /// no.17 bit = Brain button clicked
-    /// no.18 bit = Is VexNet mode
-    /// no.19 bit = Has partner controller
-    /// no.22 bit = Radio connected
-    /// no.23 bit = Radio available
-    /// no.24 bit = Controller tethered
-    /// no.30 bit = Page changed
-    /// no.32 bit = Device added/removed
-    /// (RESEARCH NEEDED)
-    pub flags: u32,
+bitflags! {
+    /// The brain's status flag word.
+    ///
+    /// VEXos publishes no documentation for this word. The bit numbers follow what has been
+    /// observed in the traffic of vexcom and the PROS CLI, counting from the most significant
+    /// bit, so the flag for "bit n" is `1 << (32 - n)`. Bits that are not named here are kept
+    /// as-is. (RESEARCH NEEDED)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct SystemFlags: u32 {
+        /// Bit 12: the radio is in its high-bandwidth data (download) mode.
+        const RADIO_DATA_MODE = 1 << (32 - 12);
+        /// Bit 14: the brain's button was double clicked.
+        const BRAIN_BUTTON_DOUBLE_CLICKED = 1 << (32 - 14);
+        /// Bit 15: the battery is charging.
+        const BATTERY_CHARGING = 1 << (32 - 15);
+        /// Bit 17: the brain's button was clicked.
+        const BRAIN_BUTTON_CLICKED = 1 << (32 - 17);
+        /// Bit 18: the radio is in VEXnet mode.
+        const VEXNET_MODE = 1 << (32 - 18);
+        /// Bit 19: a partner controller is connected.
+        const PARTNER_CONTROLLER = 1 << (32 - 19);
+        /// Bit 22: the radio is linked to a controller.
+        const RADIO_CONNECTED = 1 << (32 - 22);
+        /// Bit 23: a radio is plugged into the brain.
+        const RADIO_AVAILABLE = 1 << (32 - 23);
+        /// Bit 24: a controller is tethered to the brain with a cable.
+        const CONTROLLER_TETHERED = 1 << (32 - 24);
+        /// Bit 30: the brain's screen changed to another page.
+        const PAGE_CHANGED = 1 << (32 - 30);
+        /// Bit 32: a smart device was plugged in or unplugged.
+        const DEVICE_CHANGED = 1;
+    }
+}
+
+/// How the controller is linked to the brain, as guessed by
+/// [`GetSystemFlagsReplyPayload::controller_link`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ControllerLink {
+    /// Connected to the brain with a smart cable.
+    Tethered,
+    /// Linked over the radio in VEXnet mode.
+    Vexnet,
+    /// Linked over the radio, but not in VEXnet mode. Assumed to be Bluetooth; unverified.
+    Bluetooth,
+    /// Not linked to a brain.
+    Disconnected,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetSystemFlagsReplyPayload {
+    pub flags: SystemFlags,
 
     /// Battery percent = First four bits * 8
     /// Controller battery percent = Last four bits * 8
@@ -72,10 +110,60 @@ pub struct SystemFlags {
     /// 145 = Driver program
     pub current_program: u8,
 }
-impl Decode for SystemFlags {
+impl GetSystemFlagsReplyPayload {
+    /// Returns whether the radio link is currently on the download (data) channel.
+    pub fn is_radio_data_mode(&self) -> bool {
+        self.flags.contains(SystemFlags::RADIO_DATA_MODE)
+    }
+
+    /// Returns whether a program is running.
+    pub fn is_program_running(&self) -> bool {
+        self.current_program != 0
+    }
+
+    /// The brain's battery capacity in percent, in steps of 8.
+    pub fn battery_percent(&self) -> u8 {
+        ((self.byte_1 >> 4) * 8).min(100)
+    }
+
+    /// The primary controller's battery capacity in percent, in steps of 8.
+    pub fn controller_battery_percent(&self) -> u8 {
+        ((self.byte_1 & 0x0F) * 8).min(100)
+    }
+
+    /// The partner controller's battery capacity in percent, or `None` if there is no
+    /// partner controller.
+    pub fn partner_battery_percent(&self) -> Option<u8> {
+        self.flags
+            .contains(SystemFlags::PARTNER_CONTROLLER)
+            .then(|| ((self.byte_2 & 0x0F) * 8).min(100))
+    }
+
+    /// Returns how the primary controller is linked to the brain.
+    ///
+    /// This is unverified: no flag is known to mean Bluetooth, so a radio link that is not in
+    /// VEXnet mode is assumed to be Bluetooth. The raw bits are available in [`Self::flags`].
+    pub fn controller_link(&self) -> ControllerLink {
+        if self.flags.contains(SystemFlags::CONTROLLER_TETHERED) {
+            ControllerLink::Tethered
+        } else if !self.flags.contains(SystemFlags::RADIO_CONNECTED) {
+            ControllerLink::Disconnected
+        } else if self.flags.contains(SystemFlags::VEXNET_MODE) {
+            ControllerLink::Vexnet
+        } else {
+            ControllerLink::Bluetooth
+        }
+    }
+
+    /// The index of the page shown on the brain's screen, stored in bits 1 through 8.
+    pub fn page_index(&self) -> u8 {
+        (self.flags.bits() >> 24) as u8
+    }
+}
+impl Decode for GetSystemFlagsReplyPayload {
     fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
         let mut data = data.into_iter();
-        let flags = u32::decode(&mut data)?;
+        let flags = SystemFlags::from_bits_retain(u32::decode(&mut data)?);
         let byte_1 = u8::decode(&mut data)?;
         let byte_2 = u8::decode(&mut data)?;
         let current_program = u8::decode(&mut data)?;
@@ -89,6 +177,7 @@ impl Decode for SystemFlags {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SystemStatus {
     pub unknown: u8,
     pub system_version: Version,
@@ -130,6 +219,7 @@ impl Decode for SystemStatus {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SystemDetails {
     pub unique_id: u32,
 
@@ -184,7 +274,7 @@ impl Decode for SystemDetails {
 }
 
 pub type GetSystemFlagsPacket = Cdc2CommandPacket<86, 32, ()>;
-pub type GetSystemFlagsReplyPacket = Cdc2ReplyPacket<86, 32, SystemFlags>;
+pub type GetSystemFlagsReplyPacket = Cdc2ReplyPacket<86, 32, GetSystemFlagsReplyPayload>;
 
 pub type GetSystemStatusPacket = Cdc2CommandPacket<86, 34, ()>;
 pub type GetSystemStatusReplyPacket = Cdc2ReplyPacket<86, 34, SystemStatus>;
@@ -193,6 +283,7 @@ pub type GetSystemVersionPacket = CdcCommandPacket<164, ()>;
 pub type GetSystemVersionReplyPacket = CdcReplyPacket<164, GetSystemVersionReplyPayload>;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetSystemVersionReplyPayload {
     pub version: Version,
     pub product_type: ProductType,
@@ -216,6 +307,8 @@ impl Decode for GetSystemVersionReplyPayload {
 pub type Query1Packet = CdcCommandPacket<33, ()>;
 pub type Query1ReplyPacket = CdcReplyPacket<33, Query1ReplyPayload>;
 
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Query1ReplyPayload {
     pub unknown_1: [u8; 4],
     /// bytes 0-3 unknown
@@ -228,3 +321,27 @@ pub struct Query1ReplyPayload {
     pub bootload_flag_1: u8,
     pub bootload_flag_2: u8,
 }
+impl Decode for Query1ReplyPayload {
+    fn decode(data: impl IntoIterator<Item = u8>) -> Result<Self, DecodeError> {
+        let mut data = data.into_iter();
+        let unknown_1 = Decode::decode(&mut data)?;
+        let joystick_flag_1 = u8::decode(&mut data)?;
+        let joystick_flag_2 = u8::decode(&mut data)?;
+        let brain_flag_1 = u8::decode(&mut data)?;
+        let brain_flag_2 = u8::decode(&mut data)?;
+        let unknown_2 = Decode::decode(&mut data)?;
+        let bootload_flag_1 = u8::decode(&mut data)?;
+        let bootload_flag_2 = u8::decode(&mut data)?;
+
+        Ok(Self {
+            unknown_1,
+            joystick_flag_1,
+            joystick_flag_2,
+            brain_flag_1,
+            brain_flag_2,
+            unknown_2,
+            bootload_flag_1,
+            bootload_flag_2,
+        })
+    }
+}
@@ -0,0 +1,1363 @@
+//! A simulated V5 brain, for running tools and tests without hardware.
+//!
+//! A [`SimulatedBrain`] answers device-bound packets the way a brain would. It handles
+//! version and status queries, file transfers into an in-memory filesystem, directory
+//! listings, smart device status, and user program I/O through the user FIFO. Any other CDC2
+//! command is answered with a NACK.
+//!
+//! The brain can be served over any byte stream, such as one end of a
+//! [`tokio::io::duplex`] pipe wrapped in a
+//! [`TransportConnection`](crate::connection::transport::TransportConnection), or over TCP for a
+//! [`TcpConnection`](crate::connection::tcp::TcpConnection) to connect to.
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use vex_v5_serial::simulator::SimulatedBrain;
+//!
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:3333").await?;
+//! SimulatedBrain::new().serve_tcp(listener).await
+//! # }
+//! ```
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+};
+
+use log::{debug, info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::net::TcpListener;
+
+use crate::{
+    connection::reply::{cdc2_reply, cdc_reply, crc_reply},
+    crc::{VEX_CRC16, VEX_CRC32},
+    decode::{Decode, SizedDecode},
+    encode::Encode,
+    packets::{
+        cdc2::Cdc2Ack,
+        device::SmartDevice,
+        file::{FileMetadata, FileVendor},
+        DEVICE_BOUND_HEADER,
+    },
+    varint::VarU16,
+    version::Version,
+};
+
+/// The file size a brain reports when a write transfer is started.
+const WRITE_CAPACITY: u32 = 3145728;
+/// The most user program output returned by one user FIFO read.
+const MAX_FIFO_READ: usize = 224;
+
+/// A file stored on a [`SimulatedBrain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedFile {
+    pub data: Vec<u8>,
+    pub load_address: u32,
+    pub metadata: FileMetadata,
+    /// The vendor and name of the file this one is linked to.
+    pub linked_file: Option<(FileVendor, String)>,
+}
+
+/// Files are keyed by their vendor's ID, since [`FileVendor`] isn't ordered.
+type FileKey = (u8, String);
+
+/// A file transfer that has been started but not exited.
+#[derive(Debug)]
+struct Transfer {
+    write: bool,
+    key: FileKey,
+    size: u32,
+    crc: u32,
+    file: SimulatedFile,
+}
+
+/// Answers device-bound packets like a V5 brain. See the [module docs](self) for details.
+///
+/// All file transfer targets share the same filesystem, and uploaded files are checked
+/// against the CRC they were announced with.
+#[derive(Debug)]
+pub struct SimulatedBrain {
+    version: Version,
+    window_size: u16,
+    files: BTreeMap<FileKey, SimulatedFile>,
+    transfer: Option<Transfer>,
+    /// The files a directory listing is read from, taken when the file count is requested.
+    listing: Vec<FileKey>,
+    running_program: Option<FileKey>,
+    devices: Vec<SmartDevice>,
+    stdout: VecDeque<u8>,
+    stdin: Vec<u8>,
+}
+impl Default for SimulatedBrain {
+    fn default() -> Self {
+        Self {
+            version: Version {
+                major: 1,
+                minor: 1,
+                build: 4,
+                beta: 0,
+            },
+            window_size: 4096,
+            files: BTreeMap::new(),
+            transfer: None,
+            listing: Vec::new(),
+            running_program: None,
+            devices: Vec::new(),
+            stdout: VecDeque::new(),
+            stdin: Vec::new(),
+        }
+    }
+}
+impl SimulatedBrain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the VEXos version the brain reports.
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Sets how many bytes of a file the brain lets the host transfer per packet.
+    pub fn with_window_size(mut self, window_size: u16) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    /// Plugs in a smart device, replacing any device on the same port.
+    pub fn with_device(mut self, device: SmartDevice) -> Self {
+        self.devices.retain(|plugged| plugged.port != device.port);
+        self.devices.push(device);
+        self.devices.sort_by_key(|device| device.port);
+        self
+    }
+
+    /// Stores a file, replacing any file with the same vendor and name.
+    ///
+    /// Names longer than the 24 bytes a packet has room for can be stored, but listing a
+    /// directory that holds one fails.
+    pub fn insert_file(&mut self, vendor: FileVendor, name: &str, file: SimulatedFile) {
+        self.files.insert((vendor as u8, name.to_string()), file);
+    }
+
+    /// Returns a stored file.
+    pub fn file(&self, vendor: FileVendor, name: &str) -> Option<&SimulatedFile> {
+        self.files.get(&(vendor as u8, name.to_string()))
+    }
+
+    /// Returns every stored file along with its vendor and name.
+    pub fn files(&self) -> impl Iterator<Item = (FileVendor, &str, &SimulatedFile)> {
+        self.files.iter().filter_map(|((vendor, name), file)| {
+            Some((FileVendor::decode([*vendor]).ok()?, name.as_str(), file))
+        })
+    }
+
+    /// Returns the vendor and name of the program that was last run, if it hasn't been stopped.
+    pub fn running_program(&self) -> Option<(FileVendor, &str)> {
+        let (vendor, name) = self.running_program.as_ref()?;
+        Some((FileVendor::decode([*vendor]).ok()?, name.as_str()))
+    }
+
+    /// Queues output from the user program, to be read through the user FIFO.
+    pub fn push_stdout(&mut self, output: &[u8]) {
+        self.stdout.extend(output);
+    }
+
+    /// Returns everything written to the user program's stdin so far.
+    pub fn stdin(&self) -> &[u8] {
+        &self.stdin
+    }
+
+    /// Answers a single device-bound packet, returning the raw reply.
+    ///
+    /// Returns `None` for packets a brain wouldn't reply to, such as simple CDC commands that
+    /// aren't simulated.
+    pub fn handle_packet(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        let rest = packet.strip_prefix(&DEVICE_BOUND_HEADER)?;
+        let (&id, rest) = rest.split_first()?;
+
+        if !is_cdc2(id) {
+            return match id {
+                // Get system version
+                164 => {
+                    let mut payload = self.version.encode().ok()?;
+                    // The product type is a brain, with no product flags set.
+                    payload.extend([0x00, 0x10, 0x00]);
+                    Some(cdc_reply(id, &payload))
+                }
+                _ => {
+                    warn!("Ignoring unsupported CDC command {:#x}", id);
+                    None
+                }
+            };
+        }
+
+        let (&ext_id, rest) = rest.split_first()?;
+        let (body, crc) = packet.split_at(packet.len().checked_sub(2)?);
+        if VEX_CRC16.checksum(body) != u16::from_be_bytes([crc[0], crc[1]]) {
+            warn!("Packet {:#x}/{:#x} failed its CRC check", id, ext_id);
+            return Some(cdc2_reply(id, ext_id, Cdc2Ack::NackPacketCrc, &[]));
+        }
+        let size_len = if VarU16::check_wide(*rest.first()?) {
+            2
+        } else {
+            1
+        };
+        let payload = rest.get(size_len..rest.len().checked_sub(2)?)?;
+
+        if ext_id == 20 {
+            return Some(self.read_file(id, &mut Payload(payload)));
+        }
+        Some(match self.handle_cdc2(ext_id, &mut Payload(payload)) {
+            Ok(payload) => cdc2_reply(id, ext_id, Cdc2Ack::Ack, &payload),
+            Err(nack) => cdc2_reply(id, ext_id, nack, &[]),
+        })
+    }
+
+    /// Answers packets from `stream` until it is closed.
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        mut stream: S,
+    ) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        let mut read = [0u8; 1024];
+        loop {
+            while let Some(packet) = next_packet(&mut buffer) {
+                if let Some(reply) = self.handle_packet(&packet) {
+                    stream.write_all(&reply).await?;
+                }
+            }
+            stream.flush().await?;
+
+            match stream.read(&mut read).await? {
+                0 => return Ok(()),
+                n => buffer.extend_from_slice(&read[..n]),
+            }
+        }
+    }
+
+    /// Accepts clients on `listener` and serves them one at a time.
+    ///
+    /// This only returns if accepting a client fails. Errors from individual clients are
+    /// logged and end that client's session.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn serve_tcp(&mut self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (socket, address) = listener.accept().await?;
+            socket.set_nodelay(true)?;
+            info!("Simulator client connected from {}", address);
+
+            match self.serve(socket).await {
+                Ok(()) => info!("Simulator client {} disconnected", address),
+                Err(e) => warn!("Simulator client {} failed: {}", address, e),
+            }
+        }
+    }
+
+    fn handle_cdc2(&mut self, ext_id: u8, payload: &mut Payload<'_>) -> Result<Vec<u8>, Cdc2Ack> {
+        match ext_id {
+            // Init file transfer
+            17 => {
+                let operation = payload.u8()?;
+                let _target = payload.u8()?;
+                let vendor = payload.vendor()?;
+                let overwrite = payload.u8()? != 0;
+                let size = payload.u32()?;
+                let load_address = payload.u32()?;
+                let crc = payload.u32()?;
+                let metadata = payload.metadata()?;
+                let key = (vendor as u8, payload.file_name()?);
+
+                let (reply_size, reply_crc, transfer) = match operation {
+                    1 => {
+                        if !overwrite && self.files.contains_key(&key) {
+                            return Err(Cdc2Ack::NackFileAlreadyExists);
+                        }
+                        let file = SimulatedFile {
+                            data: Vec::with_capacity(size as usize),
+                            load_address,
+                            metadata,
+                            linked_file: None,
+                        };
+                        (WRITE_CAPACITY, crc, (true, size, crc, file))
+                    }
+                    2 => {
+                        let file = self
+                            .files
+                            .get(&key)
+                            .ok_or(Cdc2Ack::NackProgramFile)?
+                            .clone();
+                        let size = file.data.len() as u32;
+                        let crc = VEX_CRC32.checksum(&file.data);
+                        (size, crc, (false, size, crc, file))
+                    }
+                    _ => return Err(Cdc2Ack::NackInvalidInitialization),
+                };
+                debug!("Started transfer of {:?}", key);
+
+                let (write, size, crc, file) = transfer;
+                self.transfer = Some(Transfer {
+                    write,
+                    key,
+                    size,
+                    crc,
+                    file,
+                });
+
+                let mut reply = self.window_size.to_le_bytes().to_vec();
+                reply.extend(reply_size.to_le_bytes());
+                reply.extend(reply_crc.to_be_bytes());
+                Ok(reply)
+            }
+            // Exit file transfer
+            18 => {
+                let action = payload.u8()?;
+                let mut transfer = self
+                    .transfer
+                    .take()
+                    .ok_or(Cdc2Ack::NackUninitializedTransfer)?;
+
+                if transfer.write {
+                    let data = &mut transfer.file.data;
+                    if data.len() < transfer.size as usize {
+                        return Err(Cdc2Ack::NackIncomplete);
+                    }
+                    // The last write is padded to a multiple of four bytes.
+                    data.truncate(transfer.size as usize);
+                    if VEX_CRC32.checksum(data) != transfer.crc {
+                        return Err(Cdc2Ack::NackProgramCrc);
+                    }
+                    debug!("Stored {:?} ({} bytes)", transfer.key, transfer.size);
+                    self.files.insert(transfer.key.clone(), transfer.file);
+                }
+                // Run program
+                if action == 1 {
+                    self.running_program = Some(transfer.key);
+                }
+                Ok(Vec::new())
+            }
+            // Write file
+            19 => {
+                let address = payload.u32()?;
+                let data = payload.rest();
+                let transfer = self
+                    .transfer
+                    .as_mut()
+                    .filter(|transfer| transfer.write)
+                    .ok_or(Cdc2Ack::NackUninitializedTransfer)?;
+
+                let offset = address
+                    .checked_sub(transfer.file.load_address)
+                    .ok_or(Cdc2Ack::NackAddress)? as usize;
+                let end = offset + data.len();
+                if end > (transfer.size as usize).next_multiple_of(4) {
+                    return Err(Cdc2Ack::NackTransferSize);
+                }
+                let file_data = &mut transfer.file.data;
+                if file_data.len() < end {
+                    file_data.resize(end, 0);
+                }
+                file_data[offset..end].copy_from_slice(data);
+                Ok(Vec::new())
+            }
+            // Link file
+            21 => {
+                let vendor = payload.vendor()?;
+                let _option = payload.u8()?;
+                let name = payload.file_name()?;
+                let transfer = self
+                    .transfer
+                    .as_mut()
+                    .filter(|transfer| transfer.write)
+                    .ok_or(Cdc2Ack::NackUninitializedTransfer)?;
+                transfer.file.linked_file = Some((vendor, name));
+                Ok(Vec::new())
+            }
+            // Get directory file count
+            22 => {
+                let vendor = payload.vendor()? as u8;
+                self.listing = self
+                    .files
+                    .keys()
+                    .filter(|(file_vendor, _)| *file_vendor == vendor)
+                    .cloned()
+                    .collect();
+                Ok((self.listing.len() as u16).to_le_bytes().to_vec())
+            }
+            // Get directory entry
+            23 => {
+                let index = payload.u8()?;
+                let key = self.listing.get(index as usize).ok_or(Cdc2Ack::Nack)?;
+                let file = self.files.get(key).ok_or(Cdc2Ack::Nack)?;
+
+                let mut reply = vec![index];
+                reply.extend((file.data.len() as u32).to_le_bytes());
+                reply.extend(file.load_address.to_le_bytes());
+                reply.extend(VEX_CRC32.checksum(&file.data).to_le_bytes());
+                reply.extend(encode_metadata(&file.metadata)?);
+                reply.extend(encode_name(&key.1)?);
+                Ok(reply)
+            }
+            // Load file action
+            24 => {
+                let vendor = payload.vendor()?;
+                let action = payload.u8()?;
+                let key = (vendor as u8, payload.file_name()?);
+                // Stop
+                if action == 128 {
+                    self.running_program = None;
+                } else if self.files.contains_key(&key) {
+                    self.running_program = Some(key);
+                } else {
+                    return Err(Cdc2Ack::NackProgramFile);
+                }
+                Ok(Vec::new())
+            }
+            // Get file metadata
+            25 => {
+                let vendor = payload.vendor()?;
+                let _option = payload.u8()?;
+                let key = (vendor as u8, payload.file_name()?);
+                let Some(file) = self.files.get(&key) else {
+                    // No such file
+                    return Ok(vec![255]);
+                };
+
+                let linked_vendor = file
+                    .linked_file
+                    .as_ref()
+                    .map_or(0, |(vendor, _)| *vendor as u8);
+                let mut reply = vec![linked_vendor];
+                reply.extend((file.data.len() as u32).to_le_bytes());
+                reply.extend(file.load_address.to_le_bytes());
+                reply.extend(VEX_CRC32.checksum(&file.data).to_le_bytes());
+                reply.extend(encode_metadata(&file.metadata)?);
+                Ok(reply)
+            }
+            // Set file metadata
+            26 => {
+                let vendor = payload.vendor()?;
+                let _option = payload.u8()?;
+                let load_address = payload.u32()?;
+                let metadata = payload.metadata()?;
+                let key = (vendor as u8, payload.file_name()?);
+                let file = self.files.get_mut(&key).ok_or(Cdc2Ack::NackProgramFile)?;
+                file.load_address = load_address;
+                file.metadata = metadata;
+                Ok(Vec::new())
+            }
+            // Erase file
+            27 => {
+                let vendor = payload.vendor()?;
+                let _option = payload.u8()?;
+                let key = (vendor as u8, payload.file_name()?);
+                let mut file = self.files.remove(&key).ok_or(Cdc2Ack::NackProgramFile)?;
+                if self.running_program.as_ref() == Some(&key) {
+                    self.running_program = None;
+                }
+                // Erasing opens a transfer, which the host closes with an exit. There is
+                // nothing left in it to read.
+                file.data.clear();
+                self.transfer = Some(Transfer {
+                    write: false,
+                    key,
+                    size: 0,
+                    crc: 0,
+                    file,
+                });
+                Ok(Vec::new())
+            }
+            // Format filesystem
+            31 => {
+                self.files.clear();
+                self.running_program = None;
+                Ok(Vec::new())
+            }
+            // Get system flags
+            32 => {
+                let mut reply = 0u32.to_le_bytes().to_vec();
+                // Brain battery at 96%, no controllers.
+                reply.extend([0xC0, 0x00]);
+                // The slot of the running program, taken from its name. Any other program
+                // counts as slot 1.
+                reply.push(self.running_program.as_ref().map_or(0, |(_, name)| {
+                    name.strip_prefix("slot_")
+                        .and_then(|name| name.strip_suffix(".bin"))
+                        .and_then(|number| number.parse().ok())
+                        .unwrap_or(1)
+                }));
+                Ok(reply)
+            }
+            // Get device status
+            33 => {
+                let mut reply = vec![self.devices.len() as u8];
+                for device in &self.devices {
+                    reply.extend([
+                        device.port,
+                        device.device_type as u8,
+                        device.status.bits(),
+                        device.firmware.beta,
+                    ]);
+                    reply.extend(device.firmware.version.to_le_bytes());
+                    reply.extend(device.boot_version.to_le_bytes());
+                }
+                Ok(reply)
+            }
+            // Get system status
+            34 => {
+                let version = encode_version(&self.version)?;
+                let mut reply = vec![0];
+                // System, CPU0 and CPU1 versions.
+                for _ in 0..3 {
+                    reply.extend(&version);
+                }
+                // The touch controller's version is little endian.
+                reply.extend(version.iter().rev());
+                // Details: unique ID, three flag fields and an unknown field.
+                reply.extend([0; 12]);
+                // Golden and NXP versions.
+                reply.extend(&version);
+                reply.extend(&version);
+                Ok(reply)
+            }
+            // User FIFO
+            39 => {
+                let channel = payload.u8()?;
+                let write_len = payload.u8()?;
+                if write_len > 0 {
+                    let write = payload.bytes(write_len as usize)?;
+                    let end = write
+                        .iter()
+                        .position(|&byte| byte == 0)
+                        .unwrap_or(write.len());
+                    self.stdin.extend_from_slice(&write[..end]);
+                    return Ok(vec![channel]);
+                }
+
+                let mut reply = vec![channel];
+                // Only the stdio channel carries output.
+                if channel == 1 && !self.stdout.is_empty() {
+                    let max_len = self.stdout.len().min(MAX_FIFO_READ);
+                    let mut len = max_len;
+                    // Don't split a UTF-8 character between reads.
+                    while len > 0 && len < self.stdout.len() && self.stdout[len] & 0xC0 == 0x80 {
+                        len -= 1;
+                    }
+                    // Output that isn't UTF-8 may have nowhere to split.
+                    if len == 0 {
+                        len = max_len;
+                    }
+                    reply.extend(self.stdout.drain(..len));
+                    reply.push(0);
+                }
+                Ok(reply)
+            }
+            _ => {
+                warn!("Ignoring unsupported CDC2 command {:#x}", ext_id);
+                Err(Cdc2Ack::Nack)
+            }
+        }
+    }
+
+    /// Answers a file read, whose reply is laid out differently from other CDC2 replies.
+    fn read_file(&self, id: u8, payload: &mut Payload<'_>) -> Vec<u8> {
+        let mut body = vec![0x14];
+        match self.read_chunk(payload) {
+            Ok(chunk) => body.extend(chunk),
+            Err(nack) => body.push(nack as u8),
+        }
+        crc_reply(id, &body)
+    }
+
+    /// Returns the address of a read followed by the data read.
+    fn read_chunk(&self, payload: &mut Payload<'_>) -> Result<Vec<u8>, Cdc2Ack> {
+        let address = payload.u32()?;
+        let size = payload.u16()? as usize;
+        let transfer = self
+            .transfer
+            .as_ref()
+            .filter(|transfer| !transfer.write)
+            .ok_or(Cdc2Ack::NackUninitializedTransfer)?;
+
+        let offset = address
+            .checked_sub(transfer.file.load_address)
+            .ok_or(Cdc2Ack::NackAddress)? as usize;
+        let mut chunk = address.to_le_bytes().to_vec();
+        chunk.extend(transfer.file.data.iter().skip(offset).take(size));
+        // Reading past the end of the file returns zeros.
+        chunk.resize(4 + size, 0);
+        Ok(chunk)
+    }
+}
+
+/// The fields of a device-bound payload, read in order.
+///
+/// Running out of bytes is answered with [`Cdc2Ack::NackPacketLength`].
+struct Payload<'a>(&'a [u8]);
+impl<'a> Payload<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Cdc2Ack> {
+        if self.0.len() < len {
+            return Err(Cdc2Ack::NackPacketLength);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+
+    fn u8(&mut self) -> Result<u8, Cdc2Ack> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Cdc2Ack> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Cdc2Ack> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn vendor(&mut self) -> Result<FileVendor, Cdc2Ack> {
+        FileVendor::decode([self.u8()?]).map_err(|_| Cdc2Ack::NackNoDirectory)
+    }
+
+    fn metadata(&mut self) -> Result<FileMetadata, Cdc2Ack> {
+        FileMetadata::decode(self.bytes(12)?.iter().copied()).map_err(|_| Cdc2Ack::NackPacketLength)
+    }
+
+    /// Reads a file name, which is always sent nul-padded to 24 bytes.
+    fn file_name(&mut self) -> Result<String, Cdc2Ack> {
+        String::sized_decode(self.bytes(24)?.iter().copied(), 24)
+            .map_err(|_| Cdc2Ack::NackPacketLength)
+    }
+}
+
+fn encode_metadata(metadata: &FileMetadata) -> Result<Vec<u8>, Cdc2Ack> {
+    metadata.encode().map_err(|_| Cdc2Ack::WriteError)
+}
+
+fn encode_version(version: &Version) -> Result<Vec<u8>, Cdc2Ack> {
+    version.encode().map_err(|_| Cdc2Ack::WriteError)
+}
+
+fn encode_name(name: &str) -> Result<[u8; 24], Cdc2Ack> {
+    let mut encoded = [0; 24];
+    encoded
+        .get_mut(..name.len())
+        .ok_or(Cdc2Ack::WriteError)?
+        .copy_from_slice(name.as_bytes());
+    Ok(encoded)
+}
+
+fn is_cdc2(id: u8) -> bool {
+    id == 0x56 || id == 0x58
+}
+
+/// Takes the next complete device-bound packet out of `buffer`, skipping bytes before it
+/// that don't start a packet.
+///
+/// Simple CDC commands are assumed to have no payload, which holds for all that are
+/// simulated.
+fn next_packet(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let start = buffer
+        .windows(DEVICE_BOUND_HEADER.len())
+        .position(|window| window == DEVICE_BOUND_HEADER)
+        .unwrap_or_else(|| {
+            // Keep what could be the start of a header that hasn't fully arrived.
+            buffer.len().saturating_sub(DEVICE_BOUND_HEADER.len() - 1)
+        });
+    if start > 0 {
+        warn!("Skipping {} bytes without a valid header", start);
+        buffer.drain(..start);
+    }
+
+    let id = *buffer.get(DEVICE_BOUND_HEADER.len())?;
+    let len = if is_cdc2(id) {
+        let size_start = DEVICE_BOUND_HEADER.len() + 2;
+        let size_len = if VarU16::check_wide(*buffer.get(size_start)?) {
+            2
+        } else {
+            1
+        };
+        let size = VarU16::decode(
+            buffer
+                .get(size_start..size_start + size_len)?
+                .iter()
+                .copied(),
+        )
+        .ok()?
+        .into_inner() as usize;
+        size_start + size_len + size + 2
+    } else {
+        DEVICE_BOUND_HEADER.len() + 1
+    };
+
+    if buffer.len() < len {
+        return None;
+    }
+    Some(buffer.drain(..len).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use super::{SimulatedBrain, SimulatedFile, MAX_FIFO_READ};
+    use crate::{
+        commands::{
+            file::{
+                DeleteFile, DownloadFile, DownloadProgram, GetFileMetadata, LinkStrategy,
+                ListFiles, ProgramData, RenameFile, TransferCheckpoint, UploadFile,
+                UploadVerification, VerifyMode,
+            },
+            program::{GetRunningProgram, RunProgram},
+            terminal::ReadStdout,
+            CommandError,
+        },
+        config::Config,
+        connection::{
+            transport::{TransportConnection, TransportError},
+            Connection, ConnectionType,
+        },
+        encode::Encode,
+        fs::VexFs,
+        packets::{
+            cdc2::Cdc2Ack,
+            controller::{UserFifoPacket, UserFifoPayload},
+            device::{
+                DeviceFirmwareVersion, DeviceStatusFlags, DeviceType, GetDeviceStatusPacket,
+                GetDeviceStatusReplyPacket, SmartDevice,
+            },
+            file::{
+                ExtensionType, FileExitAction, FileMetadata, FileVendor, GetDirectoryEntryPacket,
+                GetDirectoryEntryPayload, GetDirectoryFileCountPacket,
+                GetDirectoryFileCountPayload,
+            },
+            system::{GetSystemVersionPacket, GetSystemVersionReplyPacket},
+        },
+        slot::Slot,
+        string::FixedString,
+        timestamp::J2000Timestamp,
+        version::Version,
+    };
+
+    /// Serves `stream` like [`SimulatedBrain::serve`], but shows every packet to `intercept`
+    /// first, which can change it. The brain hangs up when `intercept` returns false.
+    async fn serve_intercepted(
+        brain: &mut SimulatedBrain,
+        mut stream: DuplexStream,
+        mut intercept: impl FnMut(&SimulatedBrain, &mut Vec<u8>) -> bool,
+    ) {
+        let mut buffer = Vec::new();
+        let mut read = [0u8; 1024];
+        loop {
+            while let Some(mut packet) = super::next_packet(&mut buffer) {
+                if !intercept(brain, &mut packet) {
+                    return;
+                }
+                if let Some(reply) = brain.handle_packet(&packet) {
+                    stream.write_all(&reply).await.unwrap();
+                }
+            }
+            match stream.read(&mut read).await.unwrap() {
+                0 => return,
+                n => buffer.extend_from_slice(&read[..n]),
+            }
+        }
+    }
+
+    /// Whether `packet` is a file write.
+    fn is_write(packet: &[u8]) -> bool {
+        packet.get(4..6) == Some(&[0x56, 19])
+    }
+
+    /// A small file with plain metadata, for preloading a brain.
+    fn text_file(data: &[u8]) -> SimulatedFile {
+        SimulatedFile {
+            data: data.to_vec(),
+            load_address: 0x03800000,
+            metadata: FileMetadata {
+                extension: FixedString::new("txt".to_string()).unwrap(),
+                extension_type: ExtensionType::default(),
+                timestamp: J2000Timestamp::EPOCH,
+                version: Version {
+                    major: 1,
+                    minor: 0,
+                    build: 0,
+                    beta: 0,
+                },
+            },
+            linked_file: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn transfers_files() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new().with_window_size(64);
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        let metadata = FileMetadata {
+            extension: FixedString::new("bin".to_string()).unwrap(),
+            extension_type: ExtensionType::Binary,
+            timestamp: J2000Timestamp::EPOCH,
+            version: Version {
+                major: 1,
+                minor: 0,
+                build: 0,
+                beta: 0,
+            },
+        };
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+
+            let version = connection
+                .packet_handshake::<GetSystemVersionReplyPacket>(GetSystemVersionPacket::new(()))
+                .await
+                .unwrap();
+            assert_eq!(version.payload.version.minor, 1);
+
+            connection
+                .execute_command(UploadFile {
+                    filename: FixedString::new("slot_1.bin".to_string()).unwrap(),
+                    metadata: metadata.clone(),
+                    vendor: None,
+                    data: data.clone().into(),
+                    target: None,
+                    load_addr: 0x03800000,
+                    linked_file: None,
+                    after_upload: FileExitAction::RunProgram,
+                    skip_identical: false,
+                    verify: VerifyMode::Off,
+                    checkpoint: None,
+                    dry_run: None,
+                    progress: None,
+                })
+                .await
+                .unwrap();
+
+            let files = connection
+                .execute_command(ListFiles {
+                    vendor: FileVendor::User,
+                })
+                .await
+                .unwrap();
+            assert_eq!(files.len(), 1);
+            assert_eq!(files[0].file_name, "slot_1.bin");
+            assert_eq!(files[0].size, 1000);
+
+            let downloaded = connection
+                .execute_command(DownloadFile {
+                    file_name: FixedString::new("slot_1.bin".to_string()).unwrap(),
+                    size: 1000,
+                    vendor: FileVendor::User,
+                    target: None,
+                    load_addr: 0x03800000,
+                    checkpoint: None,
+                    sink: None,
+                    progress: None,
+                })
+                .await
+                .unwrap();
+            assert_eq!(downloaded, data);
+
+            connection
+                .execute_command(DeleteFile {
+                    file_name: FixedString::new("slot_1.bin".to_string()).unwrap(),
+                    vendor: FileVendor::User,
+                    erase_all_linked: true,
+                    dry_run: None,
+                })
+                .await
+                .unwrap();
+            let metadata = connection
+                .execute_command(GetFileMetadata {
+                    file_name: FixedString::new("slot_1.bin".to_string()).unwrap(),
+                    vendor: FileVendor::User,
+                })
+                .await
+                .unwrap();
+            assert!(metadata.is_none());
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+        assert!(brain.files().next().is_none());
+        assert_eq!(
+            brain.running_program(),
+            None,
+            "deleting the running program stops it"
+        );
+    }
+
+    #[tokio::test]
+    async fn verifies_uploads() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        let data: Vec<u8> = (0..=255).cycle().take(300).collect();
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            for (verify, after_upload) in [
+                (VerifyMode::Crc, FileExitAction::DoNothing),
+                (VerifyMode::ReadBack, FileExitAction::RunProgram),
+            ] {
+                let summary = connection
+                    .execute_command(UploadFile {
+                        filename: FixedString::new("slot_2.bin".to_string()).unwrap(),
+                        metadata: FileMetadata {
+                            extension: FixedString::new("bin".to_string()).unwrap(),
+                            extension_type: ExtensionType::Binary,
+                            timestamp: J2000Timestamp::EPOCH,
+                            version: Version {
+                                major: 1,
+                                minor: 0,
+                                build: 0,
+                                beta: 0,
+                            },
+                        },
+                        vendor: None,
+                        data: data.clone().into(),
+                        target: None,
+                        load_addr: 0x03800000,
+                        linked_file: None,
+                        after_upload,
+                        skip_identical: false,
+                        verify,
+                        checkpoint: None,
+                        dry_run: None,
+                        progress: None,
+                    })
+                    .await
+                    .unwrap();
+                assert_eq!(summary.verification, UploadVerification::Passed);
+            }
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+        assert_eq!(
+            brain.running_program(),
+            Some((FileVendor::User, "slot_2.bin")),
+            "the program runs once it has been verified"
+        );
+    }
+
+    #[tokio::test]
+    async fn iterates_files() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            brain.insert_file(FileVendor::User, name, text_file(name.as_bytes()));
+        }
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            let mut fs = VexFs::new(&mut connection);
+            let names: Vec<String> = fs
+                .iter_files("user")
+                .map(|file| file.unwrap().path.to_string())
+                .collect()
+                .await;
+            assert_eq!(names, ["user/a.txt", "user/b.txt", "user/c.txt"]);
+
+            let first = Box::pin(fs.iter_files("user")).next().await;
+            assert_eq!(first.unwrap().unwrap().entry.size, 5);
+            assert!(Box::pin(fs.iter_files("nobody"))
+                .next()
+                .await
+                .unwrap()
+                .is_err());
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn renaming_onto_itself_keeps_the_file() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        brain.insert_file(FileVendor::User, "notes.txt", text_file(b"hello"));
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            let renamed = connection
+                .execute_command(RenameFile {
+                    from: FixedString::new("notes.txt".to_string()).unwrap(),
+                    to: FixedString::new("notes.txt".to_string()).unwrap(),
+                    vendor: FileVendor::User,
+                    overwrite: true,
+                })
+                .await
+                .unwrap();
+            assert!(renamed);
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+        assert_eq!(
+            brain.file(FileVendor::User, "notes.txt").unwrap().data,
+            b"hello"
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_only_identical_uploads() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        brain.insert_file(FileVendor::User, "notes.txt", text_file(b"hello"));
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            for (load_addr, skipped) in [(0x03800000, true), (0x07800000, false)] {
+                let summary = connection
+                    .execute_command(UploadFile {
+                        filename: FixedString::new("notes.txt".to_string()).unwrap(),
+                        metadata: text_file(b"").metadata,
+                        vendor: None,
+                        data: b"hello".to_vec().into(),
+                        target: None,
+                        load_addr,
+                        linked_file: None,
+                        after_upload: FileExitAction::DoNothing,
+                        skip_identical: true,
+                        verify: VerifyMode::Off,
+                        checkpoint: None,
+                        dry_run: None,
+                        progress: None,
+                    })
+                    .await
+                    .unwrap();
+                assert_eq!(summary.skipped, skipped, "loaded at {load_addr:#x}");
+            }
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+        assert_eq!(
+            brain
+                .file(FileVendor::User, "notes.txt")
+                .unwrap()
+                .load_address,
+            0x07800000
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_programs_with_unreadable_ini_files() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        brain.insert_file(FileVendor::User, "slot_2.bin", text_file(b"program"));
+        brain.insert_file(FileVendor::User, "slot_2.ini", text_file(b"[program\nname"));
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            let program = connection
+                .execute_command(DownloadProgram {
+                    slot: Slot::new(2).unwrap(),
+                    link_strategy: LinkStrategy::default(),
+                    progress: None,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(program.config.is_none());
+            assert_eq!(program.ini.as_deref(), Some(&b"[program\nname"[..]));
+            assert!(matches!(program.data, ProgramData::Monolith(data) if data == b"program"));
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deletes_files() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        brain.insert_file(FileVendor::User, "notes.txt", text_file(b"hello"));
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            connection
+                .execute_command(DeleteFile {
+                    file_name: FixedString::new("notes.txt".to_string()).unwrap(),
+                    vendor: FileVendor::User,
+                    erase_all_linked: false,
+                    dry_run: None,
+                })
+                .await
+                .unwrap();
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+        assert!(brain.files().next().is_none());
+        assert!(brain.transfer.is_none(), "the erase's transfer is closed");
+    }
+
+    #[tokio::test]
+    async fn rejects_checkpoints_past_the_end_of_a_download() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        brain.insert_file(FileVendor::User, "notes.txt", text_file(b"hello"));
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            let mut sink = Vec::new();
+            let error = connection
+                .execute_command(DownloadFile {
+                    file_name: FixedString::new("notes.txt".to_string()).unwrap(),
+                    size: 5,
+                    vendor: FileVendor::User,
+                    target: None,
+                    load_addr: 0x03800000,
+                    checkpoint: Some(TransferCheckpoint::resume_from(100)),
+                    sink: Some(Box::new(&mut sink)),
+                    progress: None,
+                })
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error,
+                TransportError::CommandError(CommandError::CheckpointMismatch { .. })
+            ));
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_slot_to_run() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        brain.insert_file(FileVendor::User, "slot_2.bin", text_file(b"program"));
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            connection
+                .execute_command(RunProgram {
+                    slot: Slot::new(2).unwrap(),
+                    wait: Some(Duration::from_secs(1)),
+                })
+                .await
+                .unwrap();
+            let running = connection.execute_command(GetRunningProgram).await.unwrap();
+            assert_eq!(running, Some(2));
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_uploads_mid_file() {
+        let mut brain = SimulatedBrain::new().with_window_size(64);
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let checkpoint = TransferCheckpoint::new();
+        let upload = |data: &[u8]| UploadFile {
+            filename: FixedString::new("slot_1.bin".to_string()).unwrap(),
+            metadata: text_file(&[]).metadata,
+            vendor: None,
+            data: data.to_vec().into(),
+            target: None,
+            load_addr: 0x03800000,
+            linked_file: None,
+            after_upload: FileExitAction::DoNothing,
+            skip_identical: false,
+            verify: VerifyMode::Off,
+            checkpoint: Some(checkpoint.clone()),
+            dry_run: None,
+            progress: None,
+        };
+
+        // The link drops partway through the file.
+        let (device, host) = duplex(1024);
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            assert!(connection.execute_command(upload(&data)).await.is_err());
+        };
+        let serve = serve_intercepted(&mut brain, device, |brain, _| {
+            brain
+                .transfer
+                .as_ref()
+                .is_none_or(|transfer| transfer.file.data.len() < 512)
+        });
+        tokio::join!(serve, host);
+        let resumed_at = checkpoint.offset();
+        assert!(resumed_at > 0 && resumed_at < 1000, "{resumed_at}");
+
+        let (device, host) = duplex(1024);
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+
+            let mut other = data.clone();
+            other[0] ^= 0xFF;
+            let error = connection
+                .execute_command(upload(&other))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error,
+                TransportError::CommandError(CommandError::CheckpointMismatch { .. })
+            ));
+
+            let summary = connection.execute_command(upload(&data)).await.unwrap();
+            assert_eq!(summary.bytes_sent, 1000 - resumed_at as usize);
+        };
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+
+        assert_eq!(
+            brain.file(FileVendor::User, "slot_1.bin").unwrap().data,
+            data
+        );
+        assert_eq!(checkpoint.offset(), 0);
+    }
+
+    #[tokio::test]
+    async fn resends_rejected_pipelined_writes() {
+        let mut brain = SimulatedBrain::new().with_window_size(64);
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        let (device, host) = duplex(4096);
+        let host = async {
+            let config = Config {
+                write_window: 4,
+                ..Config::default()
+            };
+            let mut connection =
+                TransportConnection::with_config(host, ConnectionType::Wired, config);
+            let summary = connection
+                .execute_command(UploadFile {
+                    filename: FixedString::new("slot_1.bin".to_string()).unwrap(),
+                    metadata: text_file(&[]).metadata,
+                    vendor: None,
+                    data: data.clone().into(),
+                    target: None,
+                    load_addr: 0x03800000,
+                    linked_file: None,
+                    after_upload: FileExitAction::DoNothing,
+                    skip_identical: false,
+                    verify: VerifyMode::Off,
+                    checkpoint: None,
+                    dry_run: None,
+                    progress: None,
+                })
+                .await
+                .unwrap();
+            assert!(summary.retries > 0);
+        };
+
+        // Break the CRC of the third write, so that the brain rejects it while the writes
+        // after it are already on their way.
+        let mut writes = 0;
+        let serve = serve_intercepted(&mut brain, device, |_, packet| {
+            if is_write(packet) {
+                writes += 1;
+                if writes == 3 {
+                    *packet.last_mut().unwrap() ^= 0xFF;
+                }
+            }
+            true
+        });
+        tokio::join!(serve, host);
+
+        assert_eq!(
+            brain.file(FileVendor::User, "slot_1.bin").unwrap().data,
+            data
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_devices() {
+        let (device, host) = duplex(1024);
+        let motor = SmartDevice {
+            port: 3,
+            device_type: DeviceType::Motor,
+            status: DeviceStatusFlags::SMART_PORT,
+            firmware: DeviceFirmwareVersion {
+                version: 0x0123,
+                beta: 0,
+            },
+            boot_version: 0x0405,
+        };
+        let mut brain = SimulatedBrain::new().with_device(motor);
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            let status = connection
+                .packet_handshake::<GetDeviceStatusReplyPacket>(GetDeviceStatusPacket::new(()))
+                .await
+                .unwrap()
+                .try_into_inner()
+                .unwrap();
+            assert_eq!(status.count, 1);
+            assert_eq!(status.devices, [motor]);
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn splits_stdout_between_characters() {
+        let (device, host) = duplex(1024);
+        let mut brain = SimulatedBrain::new();
+        let mut text = "a".repeat(MAX_FIFO_READ - 1);
+        text.push('é');
+        brain.push_stdout(text.as_bytes());
+
+        let host = async {
+            let mut connection = TransportConnection::new(host, ConnectionType::Wired);
+            let mut reads = Vec::new();
+            while let Some(output) = connection.execute_command(ReadStdout).await.unwrap() {
+                reads.push(output.len());
+            }
+            assert_eq!(reads, [MAX_FIFO_READ - 1, 2]);
+        };
+
+        let (served, ()) = tokio::join!(brain.serve(device), host);
+        served.unwrap();
+
+        // Continuation bytes with no character to belong to can't be split anywhere.
+        brain.push_stdout(&[0x80; MAX_FIFO_READ + 1]);
+        let poll = UserFifoPacket::new(UserFifoPayload {
+            channel: 1,
+            write: None,
+        });
+        assert!(brain.handle_packet(&poll.encode().unwrap()).is_some());
+        assert_eq!(brain.stdout.len(), 1);
+    }
+
+    #[test]
+    fn rejects_listing_long_names() {
+        let mut brain = SimulatedBrain::new();
+        let name = "a_file_name_that_is_far_too_long.txt";
+        brain.insert_file(FileVendor::User, name, text_file(b"hello"));
+
+        let count = GetDirectoryFileCountPacket::new(GetDirectoryFileCountPayload {
+            vendor: FileVendor::User,
+            option: 0,
+        });
+        brain.handle_packet(&count.encode().unwrap()).unwrap();
+        let entry = GetDirectoryEntryPacket::new(GetDirectoryEntryPayload {
+            file_index: 0,
+            unknown: 0,
+        });
+        let reply = brain.handle_packet(&entry.encode().unwrap()).unwrap();
+        // The ack follows the header, command ID, size and extended command ID.
+        assert_eq!(reply[5], Cdc2Ack::WriteError as u8);
+    }
+}
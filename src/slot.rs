@@ -0,0 +1,93 @@
+//! The brain's program slots.
+//!
+//! VEXos numbers slots differently depending on where they appear. File names and the
+//! brain's screen count from 1 (`slot_1.bin` to `slot_8.bin`), while a program's ini file
+//! stores a zero-based index. [`Slot`] holds a slot that is known to exist and converts
+//! between the two, so neither has to be adjusted by hand.
+
+use std::fmt;
+
+use crate::encode::EncodeError;
+
+/// One of the brain's eight program slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
+pub struct Slot(u8);
+impl Slot {
+    /// The number of program slots on the brain.
+    pub const COUNT: u8 = 8;
+
+    /// Creates a slot from its number, counting from 1.
+    ///
+    /// Fails with [`EncodeError::SlotOutOfRange`] if the number isn't between 1 and 8.
+    pub const fn new(number: u8) -> Result<Self, EncodeError> {
+        if number >= 1 && number <= Self::COUNT {
+            Ok(Self(number))
+        } else {
+            Err(EncodeError::SlotOutOfRange(number))
+        }
+    }
+
+    /// Creates a slot from its zero-based index, as stored in a program's ini file.
+    pub const fn from_index(index: u8) -> Result<Self, EncodeError> {
+        Self::new(index.saturating_add(1))
+    }
+
+    /// Returns the slot's number, counting from 1.
+    pub const fn number(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the slot's zero-based index.
+    pub const fn index(self) -> u8 {
+        self.0 - 1
+    }
+
+    /// Returns every slot, in order.
+    pub fn all() -> impl Iterator<Item = Self> {
+        (1..=Self::COUNT).map(Self)
+    }
+
+    /// Returns the name shared by the slot's files, such as `slot_1`.
+    pub fn file_stem(self) -> String {
+        format!("slot_{}", self.0)
+    }
+}
+impl TryFrom<u8> for Slot {
+    type Error = EncodeError;
+
+    fn try_from(number: u8) -> Result<Self, Self::Error> {
+        Self::new(number)
+    }
+}
+impl From<Slot> for u8 {
+    fn from(slot: Slot) -> Self {
+        slot.number()
+    }
+}
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Slot;
+
+    #[test]
+    fn converts_between_numbers_and_indices() {
+        let slot = Slot::new(1).unwrap();
+        assert_eq!(slot.index(), 0);
+        assert_eq!(Slot::from_index(0).unwrap(), slot);
+        assert_eq!(Slot::from_index(7).unwrap().number(), 8);
+        assert_eq!(slot.file_stem(), "slot_1");
+
+        assert!(Slot::new(0).is_err());
+        assert!(Slot::new(9).is_err());
+        assert!(Slot::from_index(8).is_err());
+        assert!(Slot::from_index(u8::MAX).is_err());
+        assert_eq!(Slot::all().count(), Slot::COUNT as usize);
+    }
+}
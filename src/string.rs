@@ -6,18 +6,31 @@ use crate::{
 };
 
 /// A string with a maximum capacity of `len <= N`.
+///
+/// With the `serde` feature, this is (de)serialized as a plain string, and deserializing a
+/// string that is too long fails.
 #[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
 pub struct FixedString<const N: usize>(String);
 
 impl<const N: usize> FixedString<N> {
     pub fn new(string: String) -> Result<Self, EncodeError> {
-        if string.as_bytes().len() > N {
+        if string.len() > N {
             return Err(EncodeError::StringTooLong);
         }
 
         Ok(Self(string))
     }
 
+    /// Creates a new [`FixedString`] without checking its length.
+    ///
+    /// # Safety
+    ///
+    /// The string's UTF-8 representation must be no longer than `N` bytes.
     pub unsafe fn new_unchecked(string: String) -> Self {
         Self(string)
     }
@@ -35,6 +48,20 @@ impl<const N: usize> TryFrom<&str> for FixedString<N> {
     }
 }
 
+impl<const N: usize> TryFrom<String> for FixedString<N> {
+    type Error = EncodeError;
+
+    fn try_from(value: String) -> Result<FixedString<N>, EncodeError> {
+        Self::new(value)
+    }
+}
+
+impl<const N: usize> From<FixedString<N>> for String {
+    fn from(value: FixedString<N>) -> String {
+        value.0
+    }
+}
+
 impl<const N: usize> FromStr for FixedString<N> {
     type Err = EncodeError;
 
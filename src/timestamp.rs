@@ -1,12 +1,223 @@
+//! Times in the serial protocol, which count from the J2000 epoch (2000-01-01 00:00:00 UTC).
+//!
+//! File metadata counts whole seconds, but not every packet agrees, so each conversion comes
+//! in a second and a millisecond flavor. Times that don't fit are clamped to the nearest
+//! one that does, rather than wrapping around or panicking.
+
 use std::time::SystemTime;
 
 /// The epoch of the serial protocols timestamps
 pub const J2000_EPOCH: u32 = 946684800;
 
+/// Returns the number of milliseconds between the Unix epoch and `time`, which is negative
+/// for times before it.
+fn unix_millis(time: SystemTime) -> i128 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i128,
+        Err(before) => -(before.duration().as_millis() as i128),
+    }
+}
+
+/// Converts milliseconds since the Unix epoch to milliseconds since the J2000 epoch.
+fn unix_to_j2000_millis(millis: i128) -> i128 {
+    millis - J2000_EPOCH as i128 * 1000
+}
+
+fn clamp_i32(value: i128) -> i32 {
+    value.clamp(i32::MIN as i128, i32::MAX as i128) as i32
+}
+
+fn clamp_i64(value: i128) -> i64 {
+    value.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Returns the current time in seconds since the J2000 epoch.
 pub fn j2000_timestamp() -> i32 {
-    (SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis()
-        - J2000_EPOCH as u128) as i32
+    clamp_i32(unix_to_j2000_millis(unix_millis(SystemTime::now())).div_euclid(1000))
+}
+
+/// Returns the current time in milliseconds since the J2000 epoch.
+pub fn j2000_timestamp_millis() -> i64 {
+    clamp_i64(unix_to_j2000_millis(unix_millis(SystemTime::now())))
+}
+
+/// Converts a time to seconds since the J2000 epoch, rounding down.
+#[cfg(feature = "chrono")]
+pub fn to_j2000<Tz: chrono::TimeZone>(time: chrono::DateTime<Tz>) -> i32 {
+    clamp_i32(unix_to_j2000_millis(time.timestamp_millis() as i128).div_euclid(1000))
+}
+
+/// Converts a time to milliseconds since the J2000 epoch, rounding down.
+#[cfg(feature = "chrono")]
+pub fn to_j2000_millis<Tz: chrono::TimeZone>(time: chrono::DateTime<Tz>) -> i64 {
+    clamp_i64(unix_to_j2000_millis(time.timestamp_millis() as i128))
+}
+
+/// Converts seconds since the J2000 epoch to a time.
+#[cfg(feature = "chrono")]
+pub fn from_j2000(seconds: i32) -> chrono::DateTime<chrono::Utc> {
+    from_j2000_millis(seconds as i64 * 1000)
+}
+
+/// Converts milliseconds since the J2000 epoch to a time.
+#[cfg(feature = "chrono")]
+pub fn from_j2000_millis(millis: i64) -> chrono::DateTime<chrono::Utc> {
+    use chrono::DateTime;
+
+    let unix_millis = millis.saturating_add(J2000_EPOCH as i64 * 1000);
+    DateTime::from_timestamp_millis(unix_millis).unwrap_or(if unix_millis < 0 {
+        DateTime::<chrono::Utc>::MIN_UTC
+    } else {
+        DateTime::<chrono::Utc>::MAX_UTC
+    })
+}
+
+/// A point in time as stored in file metadata, in whole seconds since the J2000 epoch
+/// (2000-01-01 00:00:00 UTC).
+///
+/// With the `chrono` or `time` feature enabled, this converts to and from
+/// `chrono::DateTime<Utc>` and `time::OffsetDateTime`. Times outside of the range that fits
+/// are clamped to its ends.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct J2000Timestamp(pub i32);
+impl J2000Timestamp {
+    /// The J2000 epoch itself.
+    pub const EPOCH: Self = Self(0);
+
+    /// Returns the current time.
+    pub fn now() -> Self {
+        Self(j2000_timestamp())
+    }
+
+    /// Returns the number of seconds since the J2000 epoch.
+    pub const fn seconds(self) -> i32 {
+        self.0
+    }
+
+    /// Returns the number of seconds since the Unix epoch.
+    pub const fn unix_seconds(self) -> i64 {
+        self.0 as i64 + J2000_EPOCH as i64
+    }
+
+    /// Creates a timestamp from a number of seconds since the Unix epoch, clamped to the range
+    /// that fits.
+    pub fn from_unix_seconds(seconds: i64) -> Self {
+        Self(clamp_i32(seconds as i128 - J2000_EPOCH as i128))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<J2000Timestamp> for chrono::DateTime<chrono::Utc> {
+    fn from(timestamp: J2000Timestamp) -> Self {
+        from_j2000(timestamp.0)
+    }
+}
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> From<chrono::DateTime<Tz>> for J2000Timestamp {
+    fn from(time: chrono::DateTime<Tz>) -> Self {
+        Self(to_j2000(time))
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<J2000Timestamp> for time::OffsetDateTime {
+    fn from(timestamp: J2000Timestamp) -> Self {
+        // Every i32 number of seconds from 2000 is well within time's range.
+        time::OffsetDateTime::from_unix_timestamp(timestamp.unix_seconds())
+            .unwrap_or(time::OffsetDateTime::UNIX_EPOCH)
+    }
+}
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for J2000Timestamp {
+    fn from(time: time::OffsetDateTime) -> Self {
+        Self::from_unix_seconds(time.unix_timestamp())
+    }
+}
+
+impl From<J2000Timestamp> for SystemTime {
+    fn from(timestamp: J2000Timestamp) -> Self {
+        let unix_seconds = timestamp.unix_seconds();
+        let offset = std::time::Duration::from_secs(unix_seconds.unsigned_abs());
+        if unix_seconds < 0 {
+            SystemTime::UNIX_EPOCH - offset
+        } else {
+            SystemTime::UNIX_EPOCH + offset
+        }
+    }
+}
+impl From<SystemTime> for J2000Timestamp {
+    fn from(time: SystemTime) -> Self {
+        Self(clamp_i32(
+            unix_to_j2000_millis(unix_millis(time)).div_euclid(1000),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{j2000_timestamp, j2000_timestamp_millis, J2000Timestamp};
+
+    #[test]
+    fn counts_from_j2000() {
+        assert_eq!(J2000Timestamp::EPOCH.unix_seconds(), 946684800);
+        assert_eq!(J2000Timestamp::from_unix_seconds(0).seconds(), -946684800);
+        assert_eq!(
+            J2000Timestamp::from_unix_seconds(i64::MAX),
+            J2000Timestamp(i32::MAX)
+        );
+    }
+
+    #[test]
+    fn reads_the_clock_in_seconds() {
+        let seconds = j2000_timestamp();
+        let millis = j2000_timestamp_millis();
+        // A millisecond count would have overflowed an i32 long ago.
+        assert!(seconds > 820_000_000, "{seconds} is before 2026");
+        assert!((millis / 1000 - seconds as i64).abs() <= 1);
+    }
+
+    #[test]
+    fn converts_to_system_time() {
+        let before_unix = SystemTime::UNIX_EPOCH - Duration::from_secs(10);
+        let timestamp = J2000Timestamp::from(before_unix);
+        assert_eq!(timestamp.seconds(), -946684810);
+        assert_eq!(SystemTime::from(timestamp), before_unix);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn converts_to_chrono() {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        let time = Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap();
+        let timestamp = J2000Timestamp::from(time);
+        assert_eq!(timestamp.seconds(), 762611400);
+        assert_eq!(DateTime::<Utc>::from(timestamp), time);
+
+        assert_eq!(super::to_j2000_millis(time), 762611400000);
+        assert_eq!(
+            super::from_j2000_millis(-1),
+            Utc.with_ymd_and_hms(1999, 12, 31, 23, 59, 59).unwrap()
+                + chrono::Duration::milliseconds(999)
+        );
+        assert_eq!(super::to_j2000(super::from_j2000_millis(-1)), -1);
+        assert_eq!(super::from_j2000_millis(i64::MAX), DateTime::<Utc>::MAX_UTC);
+        assert_eq!(super::to_j2000(DateTime::<Utc>::MAX_UTC), i32::MAX);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn converts_to_time() {
+        use time::OffsetDateTime;
+
+        // 2024-03-01 12:30:00 UTC.
+        let time = OffsetDateTime::from_unix_timestamp(1709296200).unwrap();
+        let timestamp = J2000Timestamp::from(time);
+        assert_eq!(timestamp.seconds(), 762611400);
+        assert_eq!(OffsetDateTime::from(timestamp), time);
+    }
 }
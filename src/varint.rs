@@ -6,15 +6,19 @@ use crate::encode::{Encode, EncodeError};
 /// Variable-width u16 type.
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct VarU16(u16);
 impl VarU16 {
     /// Creates a new variable length u16.
     ///
-    /// # Panics
-    ///
-    /// Panics if the value is too large to be encoded as a variable length u16.
-    pub fn new(value: u16) -> Self {
-        Self::try_new(value).expect("Value too large for variable-length u16")
+    /// This never panics. A value too large to be encoded as a variable length u16 is
+    /// reported as [`EncodeError::VarShortTooLarge`] once it is encoded.
+    pub const fn new(value: u16) -> Self {
+        Self(value)
     }
 
     /// Creates a new variable length u16.
@@ -26,6 +30,14 @@ impl VarU16 {
         }
     }
 
+    /// Creates a variable length u16 holding the length of a payload.
+    pub fn from_len(len: usize) -> Result<Self, EncodeError> {
+        u16::try_from(len)
+            .ok()
+            .and_then(|len| Self::try_new(len).ok())
+            .ok_or(EncodeError::VarShortTooLarge)
+    }
+
     pub fn into_inner(self) -> u16 {
         self.0
     }
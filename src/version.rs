@@ -1,7 +1,10 @@
+use std::fmt;
+
 use crate::decode::{Decode, DecodeError};
 use crate::encode::{Encode, EncodeError};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
@@ -28,3 +31,12 @@ impl Decode for Version {
         })
     }
 }
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)?;
+        if self.beta != 0 {
+            write!(f, "b{}", self.beta)?;
+        }
+        Ok(())
+    }
+}